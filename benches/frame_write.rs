@@ -0,0 +1,113 @@
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ws_tool::{
+    codec::{FrameConfig, FrameWriteState},
+    frame::{ctor_header, OpCode},
+};
+
+/// connected tcp stream, data written to it is read and dropped by another thread
+fn drain() -> TcpStream {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (mut peer, _) = listener.accept().unwrap();
+    std::thread::spawn(move || {
+        let mut buf = vec![0; 1 << 16];
+        while matches!(peer.read(&mut buf), Ok(n) if n > 0) {}
+    });
+    stream.set_nodelay(true).unwrap();
+    stream
+}
+
+/// previous write path, header and payload are copied into one buffer
+fn send_copy(stream: &mut TcpStream, buf: &mut Vec<u8>, code: OpCode, payload: &[u8]) {
+    let mut header_buf = [0; 14];
+    let header = ctor_header(
+        &mut header_buf,
+        true,
+        false,
+        false,
+        false,
+        None,
+        code,
+        payload.len() as u64,
+    );
+    buf.clear();
+    buf.extend_from_slice(header);
+    buf.extend_from_slice(payload);
+    stream.write_all(buf).unwrap();
+}
+
+fn server_state() -> FrameWriteState {
+    FrameWriteState::with_config(FrameConfig {
+        mask_send_frame: false,
+        ..Default::default()
+    })
+}
+
+fn send(c: &mut Criterion) {
+    let mut group = c.benchmark_group("send");
+    for size in [128, 4096, 65536] {
+        let payload = vec![1; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("copy", size), &payload, |b, payload| {
+            let mut stream = drain();
+            let mut buf = vec![];
+            b.iter(|| send_copy(&mut stream, &mut buf, OpCode::Binary, payload));
+        });
+        group.bench_with_input(
+            BenchmarkId::new("vectored", size),
+            &payload,
+            |b, payload| {
+                let mut stream = drain();
+                let mut state = server_state();
+                b.iter(|| state.send(&mut stream, OpCode::Binary, payload).unwrap());
+            },
+        );
+    }
+    group.finish();
+}
+
+fn send_many(c: &mut Criterion) {
+    let mut group = c.benchmark_group("send_many");
+    let payload = [1; 64];
+    for count in [4, 16, 64] {
+        let frames: Vec<(OpCode, &[u8])> =
+            (0..count).map(|_| (OpCode::Text, &payload[..])).collect();
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("copy", count), &frames, |b, frames| {
+            let mut stream = drain();
+            let mut buf = vec![];
+            b.iter(|| {
+                for (code, payload) in frames {
+                    send_copy(&mut stream, &mut buf, *code, payload);
+                }
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("send", count), &frames, |b, frames| {
+            let mut stream = drain();
+            let mut state = server_state();
+            b.iter(|| {
+                for (code, payload) in frames {
+                    state.send(&mut stream, *code, payload).unwrap();
+                }
+            });
+        });
+        group.bench_with_input(
+            BenchmarkId::new("send_many", count),
+            &frames,
+            |b, frames| {
+                let mut stream = drain();
+                let mut state = server_state();
+                b.iter(|| state.send_many(&mut stream, frames).unwrap());
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, send, send_many);
+criterion_main!(benches);
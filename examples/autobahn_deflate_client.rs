@@ -2,9 +2,10 @@ use rand::random;
 use tracing::*;
 use tracing_subscriber::util::SubscriberInitExt;
 use ws_tool::{
-    codec::{StringCodec, WindowBit},
+    codec::{PMDConfig, StringCodec, WindowBit},
     errors::WsError,
     frame::{OpCode, OwnedFrame},
+    protocol::CloseCode,
     ClientConfig,
 };
 
@@ -27,7 +28,11 @@ fn run_test(case: usize) -> Result<(), WsError> {
     info!("running test case {}", case);
     let url = format!("ws://localhost:9002/runCase?case={}&agent={}", case, AGENT);
     let (mut read, mut write) = ClientConfig {
-        window: Some(WindowBit::Nine),
+        deflate: Some(PMDConfig {
+            client_max_window_bits: WindowBit::Nine,
+            server_max_window_bits: WindowBit::Nine,
+            ..Default::default()
+        }),
         ..Default::default()
     }
     .connect(url)
@@ -70,7 +75,11 @@ fn run_test(case: usize) -> Result<(), WsError> {
                 e => {
                     tracing::warn!("{e}");
                     write
-                        .send_owned_frame(OwnedFrame::close_frame(mask_key(), 1000, &[]))
+                        .send_owned_frame(OwnedFrame::close_frame(
+                            mask_key(),
+                            CloseCode::Normal,
+                            &[],
+                        ))
                         .ok();
                     break;
                 }
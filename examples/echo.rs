@@ -1,4 +1,4 @@
-use std::{io::Write, path::PathBuf};
+use std::{io::Write, path::PathBuf, time::Duration};
 
 use clap::Parser;
 use tracing::Level;
@@ -59,6 +59,9 @@ async fn run() -> Result<(), ()> {
             }
         }
     }
-    client.send((1000u16, "done")).await.ok();
+    client
+        .graceful_close(1000, "done", Duration::from_secs(3))
+        .await
+        .ok();
     Ok(())
 }
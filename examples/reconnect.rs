@@ -0,0 +1,65 @@
+use clap::Parser;
+use tracing::Level;
+use tracing_subscriber::util::SubscriberInitExt;
+use ws_tool::{
+    frame::OpCode,
+    reconnect::{AsyncReconnectingClient, Backoff, ConnectionEvent},
+    ClientConfig,
+};
+
+/// binance futures client which resubscribe channels after reconnecting
+#[derive(Parser)]
+struct Args {
+    /// channel name, such as btcusdt@depth20
+    channels: Vec<String>,
+
+    /// max retry times before give up
+    #[arg(long)]
+    max_retries: Option<u32>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), ()> {
+    tracing_subscriber::fmt::fmt()
+        .with_max_level(Level::INFO)
+        .finish()
+        .try_init()
+        .expect("failed to init log");
+    let args = Args::parse();
+    let sub = serde_json::json!({
+        "method": "SUBSCRIBE",
+        "params": args.channels,
+        "id": 1
+    })
+    .to_string();
+    let mut client =
+        AsyncReconnectingClient::new("wss://fstream.binance.com/ws", ClientConfig::buffered())
+            .unwrap()
+            .backoff(Backoff {
+                max_retries: args.max_retries,
+                ..Default::default()
+            })
+            .on_connected(move |codec| {
+                let sub = sub.clone();
+                Box::pin(async move {
+                    codec.text(&sub).await?;
+                    codec.flush().await
+                })
+            })
+            .on_event(|event| match event {
+                ConnectionEvent::Connected { attempt } => {
+                    tracing::info!("connected after {attempt} attempt(s)")
+                }
+                ConnectionEvent::Disconnected(reason) => tracing::warn!("disconnected {reason:?}"),
+                ConnectionEvent::GaveUp(e) => tracing::error!("gave up {e}"),
+            });
+
+    while let Ok((header, data)) = client.receive().await {
+        match header.code {
+            OpCode::Text => println!("{}", String::from_utf8_lossy(&data).trim()),
+            OpCode::Ping => client.pong(&data).await.unwrap(),
+            _ => {}
+        }
+    }
+    Ok(())
+}
@@ -1,16 +1,18 @@
 use http;
 use crate::{
     codec::{
-        FrameCodec, FrameConfig, FrameReadState, FrameRecv, FrameSend, FrameWriteState, Split,
+        ConnectionState, FrameCodec, FrameConfig, FrameReadState, FrameRecv, FrameSend,
+        FrameWriteState, MessageWriter, Shutdown, Split,
     },
     errors::WsError,
     frame::OpCode,
-    protocol::standard_handshake_resp_check,
-    Message,
+    protocol::{standard_handshake_resp_check, CloseCode},
+    Message, WsMessage,
 };
 use bytes::Buf;
 use std::borrow::Cow;
 use std::io::{Read, Write};
+use std::time::Duration;
 
 macro_rules! impl_recv {
     () => {
@@ -23,7 +25,7 @@ macro_rules! impl_recv {
                 } else {
                     1000
                 };
-                Some(code)
+                Some(code.into())
             } else {
                 None
             };
@@ -33,6 +35,11 @@ macro_rules! impl_recv {
                 close_code,
             })
         }
+
+        /// receive a complete owned message
+        pub fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+            self.frame_codec.receive_message()
+        }
     };
 }
 
@@ -49,8 +56,12 @@ macro_rules! impl_send {
         }
 
         /// helper method to send close message
-        pub fn close<'a>(&mut self, code: u16, msg: &'a [u8]) -> Result<(), WsError> {
-            self.send((code, msg))
+        pub fn close<'a>(
+            &mut self,
+            code: impl Into<CloseCode>,
+            msg: &'a [u8],
+        ) -> Result<(), WsError> {
+            self.send((u16::from(code.into()), msg))
         }
 
         /// send a message
@@ -58,7 +69,7 @@ macro_rules! impl_send {
             let msg: Message<Cow<'a, [u8]>> = msg.into();
             if let Some(close_code) = msg.close_code {
                 if msg.code == OpCode::Close {
-                    let mut data = close_code.to_be_bytes().to_vec();
+                    let mut data = u16::from(close_code).to_be_bytes().to_vec();
                     data.extend_from_slice(msg.data.as_ref());
                     self.frame_codec.send(msg.code, &data)
                 } else {
@@ -69,6 +80,16 @@ macro_rules! impl_send {
             }
         }
 
+        /// send owned message
+        pub fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+            self.frame_codec.send_message(msg)
+        }
+
+        /// start a text or binary message written fragment by fragment, see [MessageWriter]
+        pub fn begin_message(&mut self, code: OpCode) -> Result<MessageWriter<'_, S>, WsError> {
+            self.frame_codec.begin_message(code)
+        }
+
         /// flush underlying stream
         pub fn flush(&mut self) -> Result<(), WsError> {
             self.frame_codec.flush()
@@ -148,11 +169,31 @@ impl<S: Read + Write> BytesCodec<S> {
         self.frame_codec.stream_mut()
     }
 
+    /// current state of close handshake
+    pub fn state(&self) -> ConnectionState {
+        self.frame_codec.state()
+    }
+
     impl_recv! {}
 
     impl_send! {}
 }
 
+impl<S: Read + Write + Shutdown> BytesCodec<S> {
+    /// send close frame if not sent, then wait for close frame from peer until timeout
+    /// and shutdown underlying stream, return true if close frame from peer is received
+    ///
+    /// read timeout of underlying stream is set to remaining time before each read
+    pub fn graceful_close(
+        &mut self,
+        code: impl Into<CloseCode>,
+        reason: &[u8],
+        timeout: Duration,
+    ) -> Result<bool, WsError> {
+        self.frame_codec.graceful_close(code, reason, timeout)
+    }
+}
+
 impl<R, W, S> BytesCodec<S>
 where
     R: Read,
@@ -1,16 +1,16 @@
 use http;
 use crate::{
     codec::{
-        AsyncFrameCodec, AsyncFrameRecv, AsyncFrameSend, FrameConfig, FrameReadState,
-        FrameWriteState, Split,
+        AsyncFrameCodec, AsyncFrameRecv, AsyncFrameSend, AsyncMessageWriter, ConnectionState,
+        FrameConfig, FrameReadState, FrameWriteState, Split,
     },
     errors::WsError,
     frame::OpCode,
-    protocol::standard_handshake_resp_check,
-    Message,
+    protocol::{standard_handshake_resp_check, CloseCode},
+    Message, WsMessage,
 };
 use bytes::Buf;
-use std::borrow::Cow;
+use std::{borrow::Cow, time::Duration};
 use tokio::io::{AsyncRead, AsyncWrite};
 
 macro_rules! impl_recv {
@@ -24,7 +24,7 @@ macro_rules! impl_recv {
                 } else {
                     1000
                 };
-                Some(code)
+                Some(code.into())
             } else {
                 None
             };
@@ -34,6 +34,11 @@ macro_rules! impl_recv {
                 close_code,
             })
         }
+
+        /// receive a complete owned message
+        pub async fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+            self.frame_codec.receive_message().await
+        }
     };
 }
 
@@ -50,8 +55,12 @@ macro_rules! impl_send {
         }
 
         /// helper method to send close message
-        pub async fn close<'a>(&mut self, code: u16, msg: &'a [u8]) -> Result<(), WsError> {
-            self.send((code, msg)).await
+        pub async fn close<'a>(
+            &mut self,
+            code: impl Into<CloseCode>,
+            msg: &'a [u8],
+        ) -> Result<(), WsError> {
+            self.send((u16::from(code.into()), msg)).await
         }
 
         /// send a message
@@ -62,7 +71,7 @@ macro_rules! impl_send {
             let msg: Message<Cow<'a, [u8]>> = msg.into();
             if let Some(close_code) = msg.close_code {
                 if msg.code == OpCode::Close {
-                    let mut data = close_code.to_be_bytes().to_vec();
+                    let mut data = u16::from(close_code).to_be_bytes().to_vec();
                     data.extend_from_slice(msg.data.as_ref());
                     self.frame_codec.send(msg.code, &data).await
                 } else {
@@ -73,6 +82,19 @@ macro_rules! impl_send {
             }
         }
 
+        /// send owned message
+        pub async fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+            self.frame_codec.send_message(msg).await
+        }
+
+        /// start a text or binary message written fragment by fragment, see [AsyncMessageWriter]
+        pub fn begin_message(
+            &mut self,
+            code: OpCode,
+        ) -> Result<AsyncMessageWriter<'_, S>, WsError> {
+            self.frame_codec.begin_message(code)
+        }
+
         /// flush underlying stream
         pub async fn flush(&mut self) -> Result<(), WsError> {
             self.frame_codec.flush().await
@@ -152,6 +174,22 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncBytesCodec<S> {
         self.frame_codec.stream_mut()
     }
 
+    /// send close frame if not sent, then wait for close frame from peer until timeout
+    /// and shutdown underlying stream, return true if close frame from peer is received
+    pub async fn graceful_close(
+        &mut self,
+        code: impl Into<CloseCode>,
+        reason: &[u8],
+        timeout: Duration,
+    ) -> Result<bool, WsError> {
+        self.frame_codec.graceful_close(code, reason, timeout).await
+    }
+
+    /// current state of close handshake
+    pub fn state(&self) -> ConnectionState {
+        self.frame_codec.state()
+    }
+
     impl_recv! {}
 
     impl_send! {}
@@ -177,3 +215,71 @@ where
         )
     }
 }
+
+#[cfg(feature = "stream")]
+macro_rules! impl_stream {
+    ($name:ident, $($bound:tt)+) => {
+        impl<S: $($bound)+ + Unpin> futures_core::Stream for $name<S> {
+            type Item = Result<Message<Vec<u8>>, WsError>;
+
+            fn poll_next(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<Option<Self::Item>> {
+                std::pin::Pin::new(&mut self.get_mut().frame_codec).poll_next(cx)
+            }
+        }
+    };
+}
+
+#[cfg(feature = "stream")]
+macro_rules! impl_sink {
+    ($name:ident, $($bound:tt)+) => {
+        impl<S: $($bound)+ + Unpin, T: AsRef<[u8]>> futures_sink::Sink<Message<T>> for $name<S> {
+            type Error = WsError;
+
+            fn poll_ready(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<Result<(), WsError>> {
+                futures_sink::Sink::<Message<T>>::poll_ready(
+                    std::pin::Pin::new(&mut self.get_mut().frame_codec),
+                    cx,
+                )
+            }
+
+            fn start_send(self: std::pin::Pin<&mut Self>, item: Message<T>) -> Result<(), WsError> {
+                std::pin::Pin::new(&mut self.get_mut().frame_codec).start_send(item)
+            }
+
+            fn poll_flush(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<Result<(), WsError>> {
+                futures_sink::Sink::<Message<T>>::poll_flush(
+                    std::pin::Pin::new(&mut self.get_mut().frame_codec),
+                    cx,
+                )
+            }
+
+            fn poll_close(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<Result<(), WsError>> {
+                futures_sink::Sink::<Message<T>>::poll_close(
+                    std::pin::Pin::new(&mut self.get_mut().frame_codec),
+                    cx,
+                )
+            }
+        }
+    };
+}
+
+#[cfg(feature = "stream")]
+impl_stream!(AsyncBytesCodec, AsyncRead + AsyncWrite);
+#[cfg(feature = "stream")]
+impl_stream!(AsyncBytesRecv, AsyncRead);
+#[cfg(feature = "stream")]
+impl_sink!(AsyncBytesCodec, AsyncRead + AsyncWrite);
+#[cfg(feature = "stream")]
+impl_sink!(AsyncBytesSend, AsyncWrite);
@@ -1,72 +1,44 @@
-use std::io::{Read, Write};
+use std::{
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
 
 use http;
 use crate::{
-    codec::{apply_mask, FrameConfig, Split},
-    errors::{ProtocolError, WsError},
+    codec::{
+        apply_mask, echo_close_payload, io_timeout, is_timeout, ConnectionState, FrameConfig,
+        KeepaliveConfig, PreparedFrame, Shutdown, Split, StreamRead,
+    },
+    errors::{ProtocolError, TimeoutPhase, WsError},
     frame::{ctor_header, OpCode, OwnedFrame, SimplifiedHeader},
-    protocol::standard_handshake_resp_check,
+    protocol::{standard_handshake_resp_check, CloseCode},
+    WsMessage,
 };
-use bytes::BytesMut;
 use rand::random;
 
-use super::{DeflateReadState, DeflateWriteState, PMDConfig};
+use super::{
+    client_config, server_config, CompressPolicy, CompressStats, DeflateReadState,
+    DeflateWriteState, PMDConfig,
+};
 
 impl DeflateWriteState {
     /// send a read frame, **this method will not check validation of frame and do not fragment**
     pub fn send_owned_frame<S: Write>(
         &mut self,
         stream: &mut S,
-        mut frame: OwnedFrame,
+        frame: OwnedFrame,
     ) -> Result<(), WsError> {
-        if !frame.header().opcode().is_data() {
-            return self
-                .write_state
-                .send_owned_frame(stream, frame)
-                .map_err(WsError::IOError);
-        }
-        let prev_mask = frame.unmask();
-        let header = frame.header();
-        let frame: Result<OwnedFrame, WsError> = header
-            .opcode()
-            .is_data()
-            .then(|| self.com.as_mut())
-            .flatten()
-            .map(|handler| {
-                let mut compressed = Vec::with_capacity(frame.payload().len());
-                handler
-                    .com
-                    .compress(&[frame.payload()], &mut compressed)
-                    .map_err(|code| WsError::CompressFailed(code.to_string()))?;
-                compressed.truncate(compressed.len() - 4);
-                let mut new = OwnedFrame::new(header.opcode(), prev_mask, &compressed);
-                let header = new.header_mut();
-                header.set_rsv1(true);
-                header.set_fin(header.fin());
-
-                if (self.is_server && handler.config.server_no_context_takeover)
-                    || (!self.is_server && handler.config.client_no_context_takeover)
-                {
-                    handler
-                        .com
-                        .reset()
-                        .map_err(|code| WsError::CompressFailed(code.to_string()))?;
-                    tracing::trace!("reset compressor");
-                }
-                Ok(new)
-            })
-            .unwrap_or_else(|| {
-                if let Some(mask) = prev_mask {
-                    frame.mask(mask);
-                }
-                Ok(frame)
-            });
+        let frame = if frame.header().opcode().is_data() {
+            self.compress_owned(frame)?
+        } else {
+            frame
+        };
         self.write_state
-            .send_owned_frame(stream, frame?)
-            .map_err(WsError::IOError)
+            .send_owned_frame(stream, frame)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
     }
 
-    /// send payload
+    /// send payload, data message is compressed if compress policy allows
     ///
     /// will auto fragment **before compression** if auto_fragment_size > 0
     pub fn send<S: Write>(
@@ -75,87 +47,83 @@ impl DeflateWriteState {
         code: OpCode,
         payload: &[u8],
     ) -> Result<(), WsError> {
-        let mask_send = self.config.mask_send_frame;
-        let mask_fn = || {
-            if mask_send {
-                Some(random())
-            } else {
-                None
-            }
-        };
-        if payload.is_empty() {
-            let mask = mask_fn();
-            let frame = OwnedFrame::new(code, mask, &[]);
-            return self.send_owned_frame(stream, frame);
-        }
+        self.send_with(stream, code, payload, true)
+    }
 
-        let chunk_size = if self.config.auto_fragment_size > 0 {
+    /// send payload without compression, rsv1 of frame is cleared
+    pub fn send_uncompressed<S: Write>(
+        &mut self,
+        stream: &mut S,
+        code: OpCode,
+        payload: &[u8],
+    ) -> Result<(), WsError> {
+        self.send_with(stream, code, payload, false)
+    }
+
+    fn send_with<S: Write>(
+        &mut self,
+        stream: &mut S,
+        code: OpCode,
+        payload: &[u8],
+        compress: bool,
+    ) -> Result<(), WsError> {
+        let mask_send = self.config.mask_send_frame;
+        let chunk_size = if self.config.auto_fragment_size > 0 && code.is_data() {
             self.config.auto_fragment_size
         } else {
-            payload.len()
+            payload.len().max(1)
         };
-        let parts: Vec<&[u8]> = payload.chunks(chunk_size).collect();
+        let mut parts: Vec<&[u8]> = payload.chunks(chunk_size).collect();
+        if parts.is_empty() {
+            parts.push(&[]);
+        }
+        self.begin(code, Some(payload.len()), compress);
         let total = parts.len();
         for (idx, chunk) in parts.into_iter().enumerate() {
-            let fin = idx + 1 == total;
-            let mask = mask_fn();
-            match (self.com.as_mut(), code.is_data()) {
-                (Some(handler), true) => {
-                    let mut output = vec![];
-                    handler
-                        .com
-                        .compress(&[chunk], &mut output)
-                        .map_err(|code| WsError::CompressFailed(code.to_string()))?;
-                    output.truncate(output.len() - 4);
-                    let header = ctor_header(
-                        &mut self.header_buf,
-                        fin,
-                        true,
-                        false,
-                        false,
-                        mask,
-                        code,
-                        output.len() as u64,
-                    );
-                    stream.write_all(header)?;
-                    if let Some(mask) = mask {
-                        apply_mask(&mut output, mask)
-                    };
-                    stream.write_all(&output)?;
-                    if (self.is_server && handler.config.server_no_context_takeover)
-                        || (!self.is_server && handler.config.client_no_context_takeover)
-                    {
-                        handler
-                            .com
-                            .reset()
-                            .map_err(|code| WsError::CompressFailed(code.to_string()))?;
-                        tracing::trace!("reset compressor");
-                    }
-                }
-                _ => {
-                    let header = ctor_header(
-                        &mut self.header_buf,
-                        fin,
-                        false,
-                        false,
-                        false,
-                        mask,
-                        code,
-                        chunk.len() as u64,
-                    );
-                    stream.write_all(header)?;
-                    if let Some(mask) = mask {
-                        let mut data = BytesMut::from_iter(chunk);
-                        apply_mask(&mut data, mask);
-                        stream.write_all(&data)?;
-                    } else {
-                        stream.write_all(chunk)?;
-                    }
-                }
+            let (first, fin) = (idx == 0, idx + 1 == total);
+            let mask = if mask_send { Some(random()) } else { None };
+            let (rsv1, data) = self.fragment(first, fin, chunk)?;
+            let header = ctor_header(
+                &mut self.header_buf,
+                fin,
+                rsv1,
+                false,
+                false,
+                mask,
+                if first { code } else { OpCode::Continue },
+                data.len() as u64,
+            );
+            stream.write_all(header)?;
+            if let Some(mask) = mask {
+                let mut data = data.into_owned();
+                apply_mask(&mut data, mask);
+                stream.write_all(&data)?;
+            } else {
+                stream.write_all(&data)?;
             }
         }
+        self.write_state.set_sent(code);
         Ok(())
     }
+
+    /// send prepared frame, shared compressed frame is used if context takeover of
+    /// sending side is disabled, else payload is compressed by this connection
+    pub fn send_prepared<S: Write>(
+        &mut self,
+        stream: &mut S,
+        frame: &PreparedFrame,
+    ) -> Result<(), WsError> {
+        match self.prepared(frame)? {
+            Some(encoded) => {
+                stream
+                    .write_all(encoded)
+                    .map_err(|e| io_timeout(e, TimeoutPhase::Write))?;
+                self.write_state.set_sent(frame.opcode());
+                Ok(())
+            }
+            None => self.send(stream, frame.opcode(), frame.payload()),
+        }
+    }
 }
 
 impl DeflateReadState {
@@ -165,66 +133,27 @@ impl DeflateReadState {
     ) -> Result<(SimplifiedHeader, Vec<u8>), WsError> {
         let (mut header, data) = self.read_state.receive(stream)?;
         let data = data.to_vec();
-        let compressed = header.rsv1;
-        let is_data_frame = header.code.is_data();
-        if compressed && !is_data_frame {
-            return Err(WsError::ProtocolError {
-                close_code: 1002,
-                error: ProtocolError::CompressedControlFrame,
-            });
-        }
-        if !is_data_frame || !compressed {
-            return Ok((header, data));
-        }
-        let frame = match self.de.as_mut() {
-            Some(handler) => {
-                let mut de_data = vec![];
-                handler
-                    .de
-                    .de_compress(&[&data, &[0, 0, 255, 255]], &mut de_data)
-                    .map_err(|code| WsError::DeCompressFailed(code.to_string()))?;
-                if (self.is_server && handler.config.server_no_context_takeover)
-                    || (!self.is_server && handler.config.client_no_context_takeover)
-                {
-                    handler
-                        .de
-                        .reset()
-                        .map_err(|code| WsError::DeCompressFailed(code.to_string()))?;
-                    tracing::trace!("reset decompressor state");
-                }
-                de_data
-            }
-            None => {
-                if header.rsv1 {
-                    return Err(WsError::DeCompressFailed(
-                        "extension not enabled but got compressed frame".into(),
-                    ));
-                } else {
-                    data
-                }
-            }
-        };
-        header.rsv1 = false;
-        Ok((header, frame))
+        let data = self.inflate_frame(&mut header, data)?;
+        Ok((header, data))
     }
 
-    /// receive a message
-    pub fn receive<S: Read>(
+    /// read a message, return true if payload is in control frame buffer
+    fn next_message<S: Read>(
         &mut self,
         stream: &mut S,
-    ) -> Result<(SimplifiedHeader, &[u8]), WsError> {
+    ) -> Result<(SimplifiedHeader, bool), WsError> {
         loop {
             let (mut header, mut data) = self.receive_one(stream)?;
             if !self.config.merge_frame {
                 self.fragmented_data.clear();
                 self.fragmented_data.append(&mut data);
-                break Ok((header, &self.fragmented_data));
+                break Ok((header, false));
             }
             match header.code {
                 OpCode::Continue => {
                     if !self.fragmented {
                         return Err(WsError::ProtocolError {
-                            close_code: 1002,
+                            close_code: CloseCode::ProtocolError,
                             error: ProtocolError::MissInitialFragmentedFrame,
                         });
                     }
@@ -233,7 +162,7 @@ impl DeflateReadState {
                     if fin {
                         self.fragmented = false;
                         header.code = self.fragmented_type;
-                        break Ok((header, &self.fragmented_data));
+                        break Ok((header, false));
                     } else {
                         continue;
                     }
@@ -241,7 +170,7 @@ impl DeflateReadState {
                 OpCode::Text | OpCode::Binary => {
                     if self.fragmented {
                         return Err(WsError::ProtocolError {
-                            close_code: 1002,
+                            close_code: CloseCode::ProtocolError,
                             error: ProtocolError::NotContinueFrameAfterFragmented,
                         });
                     }
@@ -253,7 +182,7 @@ impl DeflateReadState {
                             && simdutf8::basic::from_utf8(&data).is_err()
                         {
                             return Err(WsError::ProtocolError {
-                                close_code: 1007,
+                                close_code: CloseCode::InvalidPayload,
                                 error: ProtocolError::InvalidUtf8,
                             });
                         }
@@ -266,96 +195,71 @@ impl DeflateReadState {
                             && simdutf8::basic::from_utf8(&data).is_err()
                         {
                             return Err(WsError::ProtocolError {
-                                close_code: 1007,
+                                close_code: CloseCode::InvalidPayload,
                                 error: ProtocolError::InvalidUtf8,
                             });
                         }
                         self.fragmented_data.clear();
                         self.fragmented_data.extend_from_slice(&data);
-                        break Ok((header, &self.fragmented_data));
+                        break Ok((header, false));
                     }
                 }
                 OpCode::Close | OpCode::Ping | OpCode::Pong => {
                     self.control_buf = data;
-                    break Ok((header, &self.control_buf));
+                    break Ok((header, true));
                 }
                 _ => break Err(WsError::UnsupportedFrame(header.code)),
             }
         }
     }
 
+    /// receive a message
+    pub fn receive<S: Read>(
+        &mut self,
+        stream: &mut S,
+    ) -> Result<(SimplifiedHeader, &[u8]), WsError> {
+        let (header, is_control) = self.next_message(stream)?;
+        Ok((header, self.payload(is_control)))
+    }
+
     /// receive a message, data as mut
     pub fn receive_mut<S: Read>(
         &mut self,
         stream: &mut S,
     ) -> Result<(SimplifiedHeader, &mut [u8]), WsError> {
+        let (header, is_control) = self.next_message(stream)?;
+        Ok((header, self.payload_mut(is_control)))
+    }
+
+    /// read next chunk of streamed message into `buf`, compressed payload is inflated
+    /// incrementally, new message is started if no message is being streamed
+    pub(crate) fn stream_read<S: Read>(
+        &mut self,
+        stream: &mut S,
+        buf: &mut [u8],
+    ) -> Result<StreamRead, WsError> {
         loop {
-            let (mut header, mut data) = self.receive_one(stream)?;
-            if !self.config.merge_frame {
-                self.fragmented_data.clear();
-                self.fragmented_data.append(&mut data);
-                break Ok((header, &mut self.fragmented_data));
+            if let Some(ret) = self.stream_output(buf) {
+                return Ok(ret);
             }
-            match header.code {
-                OpCode::Continue => {
-                    if !self.fragmented {
-                        return Err(WsError::ProtocolError {
-                            close_code: 1002,
-                            error: ProtocolError::MissInitialFragmentedFrame,
-                        });
-                    }
-                    let fin = header.fin;
-                    self.fragmented_data.extend_from_slice(&data);
-                    if fin {
-                        self.fragmented = false;
-                        header.code = self.fragmented_type;
-                        break Ok((header, &mut self.fragmented_data));
-                    } else {
-                        continue;
-                    }
+            let ret = match self.stream_input_size(buf) {
+                (_, true) => {
+                    let ret = self.read_state.stream_read(stream, buf)?;
+                    self.stream_input(ret, buf)?
                 }
-                OpCode::Text | OpCode::Binary => {
-                    if self.fragmented {
-                        return Err(WsError::ProtocolError {
-                            close_code: 1002,
-                            error: ProtocolError::NotContinueFrameAfterFragmented,
-                        });
-                    }
-                    if !header.fin {
-                        self.fragmented = true;
-                        self.fragmented_type = header.code;
-                        if header.code == OpCode::Text
-                            && self.config.validate_utf8.is_fast_fail()
-                            && simdutf8::basic::from_utf8(&data).is_err()
-                        {
-                            return Err(WsError::ProtocolError {
-                                close_code: 1007,
-                                error: ProtocolError::InvalidUtf8,
-                            });
-                        }
-                        self.fragmented_data.clear();
-                        self.fragmented_data.extend_from_slice(&data);
-                        continue;
-                    } else {
-                        if header.code == OpCode::Text
-                            && self.config.validate_utf8.should_check()
-                            && simdutf8::basic::from_utf8(&data).is_err()
-                        {
-                            return Err(WsError::ProtocolError {
-                                close_code: 1007,
-                                error: ProtocolError::InvalidUtf8,
-                            });
-                        }
-                        self.fragmented_data.clear();
-                        self.fragmented_data.extend_from_slice(&data);
-                        break Ok((header, &mut self.fragmented_data));
-                    }
-                }
-                OpCode::Close | OpCode::Ping | OpCode::Pong => {
-                    self.control_buf = data;
-                    break Ok((header, &mut self.control_buf));
+                (size, false) => {
+                    let mut raw = std::mem::take(&mut self.stream_buf);
+                    raw.resize(size, 0);
+                    let ret = self
+                        .read_state
+                        .stream_read(stream, &mut raw)
+                        .and_then(|ret| self.stream_input(ret, &raw));
+                    self.stream_buf = raw;
+                    ret?
                 }
-                _ => break Err(WsError::UnsupportedFrame(header.code)),
+            };
+            if let Some(ret) = ret {
+                return Ok(ret);
             }
         }
     }
@@ -386,62 +290,36 @@ impl<S: Read + Write> DeflateCodec<S> {
         }
     }
 
-    /// used for server side to construct a new server
+    /// used for server side to construct a new server, params negotiated by
+    /// [deflate_handshake_handler_with](crate::codec::deflate_handshake_handler_with) are used
     pub fn factory(req: http::Request<()>, stream: S) -> Result<Self, WsError> {
-        let mut pmd_confs: Vec<PMDConfig> = vec![];
-        for (k, v) in req.headers() {
-            if k.as_str().to_lowercase() == "sec-websocket-extensions" {
-                if let Ok(s) = v.to_str() {
-                    match PMDConfig::parse_str(s) {
-                        Ok(mut conf) => {
-                            pmd_confs.append(&mut conf);
-                        }
-                        Err(e) => return Err(WsError::HandShakeFailed(e)),
-                    }
-                }
-            }
-        }
-        let mut pmd_conf = pmd_confs.pop();
-        if let Some(conf) = pmd_conf.as_mut() {
-            let min = conf.client_max_window_bits.min(conf.server_max_window_bits);
-            conf.client_max_window_bits = min;
-            conf.server_max_window_bits = min;
-        }
-        tracing::debug!("use deflate config {:?}", pmd_conf);
-
+        let pmd_config = server_config(&req)?;
+        tracing::debug!("use deflate config {:?}", pmd_config);
         let frame_conf = FrameConfig {
             mask_send_frame: false,
             ..Default::default()
         };
-        let codec = DeflateCodec::new(stream, frame_conf, pmd_conf, true);
+        let codec = DeflateCodec::new(stream, frame_conf, pmd_config, true);
         Ok(codec)
     }
 
-    /// used for client side to construct a new client
+    /// used for client side to construct a new client, params accepted by server are used,
+    /// response is validated against default [PMDConfig] offer
     pub fn check_fn(key: String, resp: http::Response<()>, stream: S) -> Result<Self, WsError> {
-        standard_handshake_resp_check(key.as_bytes(), &resp)?;
-        let mut pmd_confs: Vec<PMDConfig> = vec![];
-        for (k, v) in resp.headers() {
-            if k.as_str().to_lowercase() == "sec-websocket-extensions" {
-                if let Ok(s) = v.to_str() {
-                    match PMDConfig::parse_str(s) {
-                        Ok(mut conf) => {
-                            pmd_confs.append(&mut conf);
-                        }
-                        Err(e) => return Err(WsError::HandShakeFailed(e)),
-                    }
-                }
-            }
-        }
-        let mut pmd_conf = pmd_confs.pop();
-        if let Some(conf) = pmd_conf.as_mut() {
-            let min = conf.client_max_window_bits.min(conf.server_max_window_bits);
-            conf.client_max_window_bits = min;
-            conf.server_max_window_bits = min;
+        Self::check_fn_with(Some(Default::default()))(key, resp, stream)
+    }
+
+    /// client side check fn which validates response against `offer`, `None` if
+    /// deflate is not offered
+    pub fn check_fn_with(
+        offer: Option<PMDConfig>,
+    ) -> impl FnMut(String, http::Response<()>, S) -> Result<Self, WsError> {
+        move |key, resp, stream| {
+            standard_handshake_resp_check(key.as_bytes(), &resp)?;
+            let pmd_config = client_config(offer.as_ref(), &resp)?;
+            let codec = DeflateCodec::new(stream, Default::default(), pmd_config, false);
+            Ok(codec)
         }
-        tracing::debug!("use deflate config: {:?}", pmd_conf);
-        let codec = DeflateCodec::new(stream, Default::default(), pmd_conf, false);
-        Ok(codec)
     }
 
     /// get mutable underlying stream
@@ -450,12 +328,141 @@ impl<S: Read + Write> DeflateCodec<S> {
     }
 
     /// receive a message
+    ///
+    /// close frame from peer is echoed automatically if close frame has not been sent,
+    /// after that, [WsError::ConnectionClosed] is returned
+    ///
+    /// if keepalive is enabled, ping frame is replied automatically and read timeout
+    /// of underlying stream is used to send ping & detect dead peer
     pub fn receive(&mut self) -> Result<(SimplifiedHeader, &[u8]), WsError> {
-        self.read_state.receive(&mut self.stream)
+        self.discard_stream()?;
+        loop {
+            match self.read_state.next_message(&mut self.stream) {
+                Ok((header, is_control)) => {
+                    if header.code == OpCode::Close {
+                        self.echo_close();
+                    }
+                    if !self.read_state.keepalive.is_enabled() {
+                        return Ok((header, self.read_state.payload(is_control)));
+                    }
+                    let need_pong = self.read_state.keepalive.on_frame(header.code);
+                    if !self.write_state.is_close_sent() && self.read_state.keepalive.on_tick()? {
+                        self.ping(&[])?;
+                        self.flush()?;
+                    }
+                    if need_pong && !self.write_state.is_close_sent() {
+                        let payload = self.read_state.payload(is_control).to_vec();
+                        self.pong(&payload)?;
+                        self.flush()?;
+                        continue;
+                    }
+                    return Ok((header, self.read_state.payload(is_control)));
+                }
+                Err(e) if is_timeout(&e) && self.read_state.keepalive.is_enabled() => {
+                    if !self.write_state.is_close_sent() && self.read_state.keepalive.on_tick()? {
+                        self.ping(&[])?;
+                        self.flush()?;
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive()?;
+        WsMessage::from_header(&header, data)
+    }
+
+    /// receive a text or binary message as [DeflateMessageReader], compressed payload
+    /// is inflated on demand instead of being buffered, see [FrameCodec::receive_stream](crate::codec::FrameCodec::receive_stream)
+    pub fn receive_stream(&mut self) -> Result<DeflateMessageReader<'_, S>, WsError> {
+        self.discard_stream()?;
+        let (header, done) = match self.stream_read(&mut [])? {
+            StreamRead::End(header) => (header, true),
+            _ => match self.read_state.streaming_header() {
+                Some(header) => (header, false),
+                None => unreachable!("message stream is not started"),
+            },
+        };
+        Ok(DeflateMessageReader {
+            codec: self,
+            header,
+            done,
+        })
+    }
+
+    /// read streamed message, control frames are handled, return [StreamRead::Data]
+    /// or [StreamRead::End]
+    fn stream_read(&mut self, buf: &mut [u8]) -> Result<StreamRead, WsError> {
+        loop {
+            match self.read_state.stream_read(&mut self.stream, buf)? {
+                StreamRead::Control(header, range) => {
+                    self.read_state.keepalive.on_frame(header.code);
+                    let payload = self.read_state.read_state.payload(Some(range));
+                    match header.code {
+                        OpCode::Ping if !self.write_state.is_close_sent() => {
+                            let payload = payload.to_vec();
+                            self.pong(&payload)?;
+                            self.flush()?;
+                        }
+                        OpCode::Close => {
+                            self.read_state.control_buf = payload.to_vec();
+                            self.echo_close();
+                            return Err(WsError::ConnectionClosed);
+                        }
+                        _ => {}
+                    }
+                }
+                ret => return Ok(ret),
+            }
+        }
+    }
+
+    /// discard unread payload of streamed message
+    fn discard_stream(&mut self) -> Result<(), WsError> {
+        let mut buf = [0; 4096];
+        while self.read_state.streaming_header().is_some() {
+            self.stream_read(&mut buf)?;
+        }
+        Ok(())
+    }
+
+    fn echo_close(&mut self) {
+        if self.write_state.is_close_sent() {
+            return;
+        }
+        let payload = echo_close_payload(self.read_state.payload(true)).to_vec();
+        if let Err(e) = self
+            .send(OpCode::Close, &payload)
+            .and_then(|_| self.flush())
+        {
+            tracing::debug!("failed to echo close frame {e}");
+        }
+    }
+
+    /// current state of close handshake
+    pub fn state(&self) -> ConnectionState {
+        ConnectionState::new(
+            self.write_state.is_close_sent(),
+            self.read_state.is_close_received(),
+        )
+    }
+
+    /// last time pong frame is received
+    pub fn last_pong(&self) -> Option<std::time::Instant> {
+        self.read_state.keepalive.last_pong()
+    }
+
+    /// update keepalive config and reset keepalive timer
+    pub fn set_keepalive(&mut self, config: KeepaliveConfig) {
+        self.read_state.set_keepalive(config)
     }
 
     /// send a read frame, **this method will not check validation of frame and do not fragment**
     pub fn send_owned_frame(&mut self, frame: OwnedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.header().opcode())?;
         self.write_state.send_owned_frame(&mut self.stream, frame)
     }
 
@@ -463,13 +470,49 @@ impl<S: Read + Write> DeflateCodec<S> {
     ///
     /// will auto fragment **before compression** if auto_fragment_size > 0
     pub fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
-        self.write_state.send(&mut self.stream, code, payload)
+        self.write_state.check_send(code)?;
+        self.write_state
+            .send(&mut self.stream, code, payload)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    /// send payload without compression, rsv1 of frame is cleared
+    pub fn send_uncompressed(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        self.write_state.check_send(code)?;
+        self.write_state
+            .send_uncompressed(&mut self.stream, code, payload)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    /// set policy to decide which data message is compressed
+    pub fn set_compress_policy(&mut self, policy: impl CompressPolicy + 'static) {
+        self.write_state.set_compress_policy(policy)
+    }
+
+    /// compression counters of sent messages
+    pub fn compress_stats(&self) -> CompressStats {
+        self.write_state.compress_stats()
+    }
+
+    /// send owned message
+    pub fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload())
+    }
+
+    /// send prepared frame, see [DeflateWriteState::send_prepared]
+    pub fn send_prepared(&mut self, frame: &PreparedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.opcode())?;
+        self.write_state.send_prepared(&mut self.stream, frame)
+    }
+
+    /// start a text or binary message, see [DeflateMessageWriter]
+    pub fn begin_message(&mut self, code: OpCode) -> Result<DeflateMessageWriter<'_, S>, WsError> {
+        DeflateMessageWriter::new(&mut self.stream, &mut self.write_state, code)
     }
 
     /// helper function to send text message
     pub fn text(&mut self, text: &str) -> Result<(), WsError> {
-        self.write_state
-            .send(&mut self.stream, OpCode::Text, text.as_bytes())
+        self.send(OpCode::Text, text.as_bytes())
     }
 
     /// helper function to send binary message
@@ -488,15 +531,167 @@ impl<S: Read + Write> DeflateCodec<S> {
     }
 
     /// helper method to send close message
-    pub fn close(&mut self, code: u16, msg: &[u8]) -> Result<(), WsError> {
-        let mut data = code.to_be_bytes().to_vec();
+    pub fn close(&mut self, code: impl Into<CloseCode>, msg: &[u8]) -> Result<(), WsError> {
+        let mut data = u16::from(code.into()).to_be_bytes().to_vec();
         data.extend_from_slice(msg);
         self.send(OpCode::Close, &data)
     }
 
     /// flush stream to ensure all data are send
     pub fn flush(&mut self) -> Result<(), WsError> {
-        self.stream.flush().map_err(WsError::IOError)
+        self.stream
+            .flush()
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+}
+
+/// message received by [DeflateCodec::receive_stream], compressed payload is read
+/// and inflated on demand
+pub struct DeflateMessageReader<'a, S: Read + Write> {
+    codec: &'a mut DeflateCodec<S>,
+    header: SimplifiedHeader,
+    done: bool,
+}
+
+impl<'a, S: Read + Write> DeflateMessageReader<'a, S> {
+    /// opcode of message, text or binary
+    pub fn opcode(&self) -> OpCode {
+        self.header.code
+    }
+
+    /// check if whole message has been read
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+impl<'a, S: Read + Write> Read for DeflateMessageReader<'a, S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        match self.codec.stream_read(buf)? {
+            StreamRead::Data(count) => Ok(count),
+            _ => {
+                self.done = true;
+                Ok(0)
+            }
+        }
+    }
+}
+
+/// message written fragment by fragment, each non-empty write is compressed and
+/// sent as a frame immediately, see [MessageWriter](crate::codec::MessageWriter)
+///
+/// only [DeflateMessageWriter::finish] or [DeflateMessageWriter::finish_with] sends
+/// the final frame, if writer is dropped after some fragments are sent, close frame
+/// 1011 is sent and following data frames of codec fail with
+/// [WsError::UnfinishedMessage]
+pub struct DeflateMessageWriter<'a, S: Write> {
+    stream: &'a mut S,
+    write_state: &'a mut DeflateWriteState,
+    code: OpCode,
+    started: bool,
+    finished: bool,
+}
+
+impl<'a, S: Write> DeflateMessageWriter<'a, S> {
+    pub(crate) fn new(
+        stream: &'a mut S,
+        write_state: &'a mut DeflateWriteState,
+        code: OpCode,
+    ) -> Result<Self, WsError> {
+        if !code.is_data() {
+            return Err(WsError::UnsupportedFrame(code));
+        }
+        write_state.check_send(code)?;
+        write_state.begin(code, None, true);
+        Ok(Self {
+            stream,
+            write_state,
+            code,
+            started: false,
+            finished: false,
+        })
+    }
+
+    /// opcode of message, text or binary
+    pub fn opcode(&self) -> OpCode {
+        self.code
+    }
+
+    /// send a control frame between fragments
+    pub fn control(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        if code.is_data() {
+            return Err(WsError::UnsupportedFrame(code));
+        }
+        self.write_state.check_send(code)?;
+        self.write_state
+            .write_state
+            .send_frame(self.stream, true, false, code, payload)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    fn send_fragment(&mut self, fin: bool, payload: &[u8]) -> Result<(), WsError> {
+        let code = if self.started {
+            OpCode::Continue
+        } else {
+            self.code
+        };
+        self.write_state.check_send(code)?;
+        let (rsv1, data) = self.write_state.fragment(!self.started, fin, payload)?;
+        self.write_state
+            .write_state
+            .send_frame(self.stream, fin, rsv1, code, &data)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))?;
+        self.started = true;
+        Ok(())
+    }
+
+    /// send empty final frame and flush stream
+    pub fn finish(self) -> Result<(), WsError> {
+        self.finish_with(&[])
+    }
+
+    /// send last chunk of message as final frame and flush stream
+    pub fn finish_with(mut self, payload: &[u8]) -> Result<(), WsError> {
+        self.send_fragment(true, payload)?;
+        self.finished = true;
+        self.stream
+            .flush()
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+}
+
+impl<'a, S: Write> Write for DeflateMessageWriter<'a, S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.send_fragment(false, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl<'a, S: Write> Drop for DeflateMessageWriter<'a, S> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if !self.started {
+            return;
+        }
+        let write_state = &mut self.write_state.write_state;
+        if let Some(payload) = write_state.set_unfinished() {
+            let ret = write_state.send_frame(self.stream, true, false, OpCode::Close, &payload);
+            if let Err(e) = ret {
+                tracing::debug!("failed to send close frame {e}");
+            }
+        }
     }
 }
 
@@ -522,6 +717,12 @@ impl<S: Read> DeflateRecv<S> {
         self.read_state.receive(&mut self.stream)
     }
 
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive()?;
+        WsMessage::from_header(&header, data)
+    }
+
     /// receive a mutable frame
     pub fn receive_mut(&mut self) -> Result<(SimplifiedHeader, &mut [u8]), WsError> {
         self.read_state.receive_mut(&mut self.stream)
@@ -550,6 +751,7 @@ impl<S: Write> DeflateSend<S> {
 
     /// send a read frame, **this method will not check validation of frame and do not fragment**
     pub fn send_owned_frame(&mut self, frame: OwnedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.header().opcode())?;
         self.write_state.send_owned_frame(&mut self.stream, frame)
     }
 
@@ -557,13 +759,49 @@ impl<S: Write> DeflateSend<S> {
     ///
     /// will auto fragment **before compression** if auto_fragment_size > 0
     pub fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
-        self.write_state.send(&mut self.stream, code, payload)
+        self.write_state.check_send(code)?;
+        self.write_state
+            .send(&mut self.stream, code, payload)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    /// send payload without compression, rsv1 of frame is cleared
+    pub fn send_uncompressed(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        self.write_state.check_send(code)?;
+        self.write_state
+            .send_uncompressed(&mut self.stream, code, payload)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    /// set policy to decide which data message is compressed
+    pub fn set_compress_policy(&mut self, policy: impl CompressPolicy + 'static) {
+        self.write_state.set_compress_policy(policy)
+    }
+
+    /// compression counters of sent messages
+    pub fn compress_stats(&self) -> CompressStats {
+        self.write_state.compress_stats()
+    }
+
+    /// send owned message
+    pub fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload())
+    }
+
+    /// send prepared frame, see [DeflateWriteState::send_prepared]
+    pub fn send_prepared(&mut self, frame: &PreparedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.opcode())?;
+        self.write_state.send_prepared(&mut self.stream, frame)
+    }
+
+    /// start a text or binary message, see [DeflateMessageWriter]
+    pub fn begin_message(&mut self, code: OpCode) -> Result<DeflateMessageWriter<'_, S>, WsError> {
+        DeflateMessageWriter::new(&mut self.stream, &mut self.write_state, code)
     }
 
     /// helper function to send text message
     pub fn text(&mut self, text: &str) -> Result<(), WsError> {
-        self.write_state
-            .send(&mut self.stream, OpCode::Text, text.as_bytes())
+        self.send(OpCode::Text, text.as_bytes())
     }
 
     /// helper function to send binary message
@@ -582,15 +820,61 @@ impl<S: Write> DeflateSend<S> {
     }
 
     /// helper method to send close message
-    pub fn close(&mut self, code: u16, msg: &[u8]) -> Result<(), WsError> {
-        let mut data = code.to_be_bytes().to_vec();
+    pub fn close(&mut self, code: impl Into<CloseCode>, msg: &[u8]) -> Result<(), WsError> {
+        let mut data = u16::from(code.into()).to_be_bytes().to_vec();
         data.extend_from_slice(msg);
         self.send(OpCode::Close, &data)
     }
 
     /// flush stream to ensure all data are send
     pub fn flush(&mut self) -> Result<(), WsError> {
-        self.stream.flush().map_err(WsError::IOError)
+        self.stream
+            .flush()
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+}
+
+impl<S: Read + Write + Shutdown> DeflateCodec<S> {
+    /// send close frame if not sent, then wait for close frame from peer until timeout
+    /// and shutdown underlying stream, return true if close frame from peer is received
+    ///
+    /// messages received before close frame are dropped, read timeout of underlying
+    /// stream is set to remaining time before each read
+    pub fn graceful_close(
+        &mut self,
+        code: impl Into<CloseCode>,
+        reason: &[u8],
+        timeout: Duration,
+    ) -> Result<bool, WsError> {
+        if !self.write_state.is_close_sent() {
+            self.close(code, reason)?;
+            self.flush()?;
+        }
+        let deadline = Instant::now() + timeout;
+        let acked = loop {
+            if self.read_state.is_close_received() {
+                break true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break false;
+            }
+            if let Err(e) = self.stream.set_read_timeout(Some(deadline - now)) {
+                tracing::debug!("failed to set read timeout {e}");
+            }
+            match self.read_state.next_message(&mut self.stream) {
+                Ok(_) => {}
+                Err(e) if is_timeout(&e) => {}
+                Err(e) => {
+                    tracing::debug!("failed to wait close frame {e}");
+                    break false;
+                }
+            }
+        };
+        if let Err(e) = self.stream.shutdown() {
+            tracing::debug!("failed to shutdown stream {e}");
+        }
+        Ok(acked)
     }
 }
 
@@ -614,3 +898,291 @@ where
         )
     }
 }
+
+#[test]
+fn test_deflate_receive_stream() {
+    use crate::codec::{CompressStream, Duplex, WindowBit};
+
+    let text = "hello world ".repeat(1000);
+    let mut compressed = vec![];
+    CompressStream::new(WindowBit::Fifteen)
+        .compress(&[text.as_bytes()], &mut compressed)
+        .unwrap();
+    compressed.truncate(compressed.len() - 4);
+    let (first, second) = compressed.split_at(compressed.len() / 2);
+    let frame = |code, fin, rsv1, payload: &[u8]| {
+        let mut frame = OwnedFrame::new(code, None, payload);
+        frame.header_mut().set_fin(fin);
+        frame.header_mut().set_rsv1(rsv1);
+        [&frame.header().0[..], frame.payload()].concat()
+    };
+    let input = [
+        frame(OpCode::Text, false, true, first),
+        frame(OpCode::Ping, true, false, b"p"),
+        frame(OpCode::Continue, true, false, second),
+        frame(OpCode::Binary, true, false, b"next"),
+    ]
+    .concat();
+    let stream = Duplex::new(input);
+    let mut client = DeflateCodec::new(stream, Default::default(), Some(Default::default()), false);
+    let mut reader = client.receive_stream().unwrap();
+    assert_eq!(reader.opcode(), OpCode::Text);
+    let mut data = vec![];
+    let mut buf = [0; 7];
+    loop {
+        let count = reader.read(&mut buf).unwrap();
+        if count == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..count]);
+    }
+    assert_eq!(String::from_utf8(data).unwrap(), text);
+    assert_eq!(client.stream_mut().output[0], 0x8a);
+    assert_eq!(
+        client.receive_message().unwrap(),
+        WsMessage::Binary("next".into())
+    );
+}
+
+#[test]
+fn test_deflate_message_writer() {
+    use crate::codec::Duplex;
+
+    let text = "hello world ".repeat(100);
+    let stream = Duplex::new(vec![]);
+    let mut codec = DeflateCodec::new(stream, Default::default(), Some(Default::default()), false);
+    for _ in 0..2 {
+        let mut writer = codec.begin_message(OpCode::Text).unwrap();
+        for chunk in text.as_bytes().chunks(500) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.control(OpCode::Ping, b"p").unwrap();
+        writer.finish().unwrap();
+    }
+
+    let output = std::mem::take(&mut codec.stream_mut().output);
+    assert!(output.len() < text.len());
+    let stream = Duplex::new(output);
+    let mut peer = DeflateCodec::new(stream, Default::default(), Some(Default::default()), true);
+    for _ in 0..2 {
+        let mut data = String::new();
+        peer.receive_stream()
+            .unwrap()
+            .read_to_string(&mut data)
+            .unwrap();
+        assert_eq!(data, text);
+    }
+}
+
+#[test]
+fn test_deflate_negotiated_params() {
+    use crate::codec::{Duplex, WindowBit};
+
+    // repetition distance is larger than 9 bits window
+    let mut block = vec![0; 1000];
+    fastrand::Rng::with_seed(9).fill(&mut block);
+    let data = block.repeat(3);
+    let conf = PMDConfig {
+        client_no_context_takeover: true,
+        client_max_window_bits: WindowBit::Nine,
+        ..Default::default()
+    };
+    let frame_sizes = |mut output: &[u8]| {
+        let mut sizes = vec![];
+        while !output.is_empty() {
+            let len = output[1] as usize & 0x7f;
+            let (header_len, len) = match len {
+                126 => (4, u16::from_be_bytes([output[2], output[3]]) as usize),
+                _ => (2, len),
+            };
+            let header_len = header_len + if output[1] & 0x80 != 0 { 4 } else { 0 };
+            sizes.push(len);
+            output = &output[header_len + len..];
+        }
+        sizes
+    };
+
+    let mut client = DeflateCodec::new(
+        Duplex::new(vec![]),
+        Default::default(),
+        Some(conf.clone()),
+        false,
+    );
+    let mut server = DeflateCodec::new(
+        Duplex::new(vec![]),
+        FrameConfig {
+            mask_send_frame: false,
+            ..Default::default()
+        },
+        Some(conf.clone()),
+        true,
+    );
+    for _ in 0..2 {
+        client.binary(&data).unwrap();
+        server.binary(&data).unwrap();
+    }
+    let client_output = std::mem::take(&mut client.stream_mut().output);
+    let server_output = std::mem::take(&mut server.stream_mut().output);
+    // client compress with limited window and without context takeover
+    let sizes = frame_sizes(&client_output);
+    assert!(sizes[0] > 2000 && sizes[0] == sizes[1], "{sizes:?}");
+    // server compress with full window and context takeover
+    let sizes = frame_sizes(&server_output);
+    assert!(sizes[0] < 1100 && sizes[1] < 100, "{sizes:?}");
+
+    let mut server = DeflateCodec::new(
+        Duplex::new(client_output),
+        Default::default(),
+        Some(conf.clone()),
+        true,
+    );
+    let mut client = DeflateCodec::new(
+        Duplex::new(server_output),
+        Default::default(),
+        Some(conf),
+        false,
+    );
+    for _ in 0..2 {
+        assert_eq!(server.receive().unwrap().1, data);
+        assert_eq!(client.receive().unwrap().1, data);
+    }
+}
+
+#[test]
+fn test_deflate_compress_policy() {
+    use super::CompressRule;
+    use crate::codec::{Duplex, ValidateUtf8Policy};
+
+    let text = "hello world ".repeat(100);
+    let config = FrameConfig {
+        auto_fragment_size: 256,
+        ..Default::default()
+    };
+    let mut client =
+        DeflateCodec::new(Duplex::new(vec![]), config, Some(Default::default()), false);
+    client.set_compress_policy(CompressRule {
+        min_size: 16,
+        binary: false,
+        ..Default::default()
+    });
+    client.text("ack").unwrap();
+    client.text(&text).unwrap();
+    client.binary(text.as_bytes()).unwrap();
+    client
+        .send_uncompressed(OpCode::Text, text.as_bytes())
+        .unwrap();
+    let mut writer = client.begin_message(OpCode::Text).unwrap();
+    writer.write_all(text.as_bytes()).unwrap();
+    writer.finish().unwrap();
+    let stats = client.compress_stats();
+    assert_eq!(stats.compressed_messages, 2);
+    assert_eq!(stats.input_bytes, 2 * text.len() as u64);
+    assert_eq!(stats.skipped_messages, 3);
+    assert_eq!(stats.skipped_bytes, 3 + 2 * text.len() as u64);
+    assert!(stats.ratio() < 0.1);
+
+    let output = std::mem::take(&mut client.stream_mut().output);
+    let config = FrameConfig {
+        check_rsv: false,
+        merge_frame: false,
+        validate_utf8: ValidateUtf8Policy::Off,
+        ..Default::default()
+    };
+    let mut frames = crate::codec::FrameCodec::new_with(Duplex::new(output.clone()), config);
+    let mut server = DeflateCodec::new(
+        Duplex::new(output),
+        Default::default(),
+        Some(Default::default()),
+        true,
+    );
+    let expected = [
+        (false, OpCode::Text, "ack", 1),
+        // fragmented before compression
+        (true, OpCode::Text, text.as_str(), 5),
+        (false, OpCode::Binary, text.as_str(), 5),
+        (false, OpCode::Text, text.as_str(), 5),
+        // message writer sends an empty final fragment
+        (true, OpCode::Text, text.as_str(), 2),
+    ];
+    for (compressed, code, payload, count) in expected {
+        let mut headers = vec![];
+        loop {
+            let (header, _) = frames.receive().unwrap();
+            headers.push((header.code, header.rsv1));
+            if header.fin {
+                break;
+            }
+        }
+        assert_eq!(headers[0], (code, compressed));
+        assert!(headers[1..].iter().all(|h| *h == (OpCode::Continue, false)));
+        assert_eq!(headers.len(), count);
+        let (header, data) = server.receive().unwrap();
+        assert_eq!((header.code, data), (code, payload.as_bytes()));
+    }
+}
+
+#[test]
+fn test_deflate_send_owned_frame() {
+    use super::CompressRule;
+    use crate::codec::{Duplex, ValidateUtf8Policy};
+
+    let text = "hello world ".repeat(100);
+    let mut client = DeflateCodec::new(
+        Duplex::new(vec![]),
+        Default::default(),
+        Some(Default::default()),
+        false,
+    );
+    client.set_compress_policy(CompressRule {
+        binary: false,
+        ..Default::default()
+    });
+    for code in [OpCode::Text, OpCode::Binary] {
+        let chunks = text.as_bytes().chunks(500).collect::<Vec<_>>();
+        for (idx, chunk) in chunks.iter().enumerate() {
+            let code = if idx == 0 { code } else { OpCode::Continue };
+            let mut frame = OwnedFrame::new(code, [1, 2, 3, 4], chunk);
+            frame.header_mut().set_fin(idx == chunks.len() - 1);
+            client.send_owned_frame(frame).unwrap();
+        }
+    }
+    client.flush().unwrap();
+    let stats = client.compress_stats();
+    assert_eq!((stats.compressed_messages, stats.skipped_messages), (1, 1));
+
+    let output = std::mem::take(&mut client.stream_mut().output);
+    let config = FrameConfig {
+        check_rsv: false,
+        merge_frame: false,
+        validate_utf8: ValidateUtf8Policy::Off,
+        ..Default::default()
+    };
+    let mut frames = crate::codec::FrameCodec::new_with(Duplex::new(output.clone()), config);
+    for code in [OpCode::Text, OpCode::Binary] {
+        let headers = (0..3)
+            .map(|_| {
+                let (header, _) = frames.receive().unwrap();
+                (header.code, header.rsv1, header.fin)
+            })
+            .collect::<Vec<_>>();
+        let compressed = code == OpCode::Text;
+        assert_eq!(
+            headers,
+            [
+                (code, compressed, false),
+                (OpCode::Continue, false, false),
+                (OpCode::Continue, false, true)
+            ]
+        );
+    }
+    let mut server = DeflateCodec::new(
+        Duplex::new(output),
+        Default::default(),
+        Some(Default::default()),
+        true,
+    );
+    for code in [OpCode::Text, OpCode::Binary] {
+        let (header, data) = server.receive().unwrap();
+        assert_eq!((header.code, data), (code, text.as_bytes()));
+    }
+}
@@ -1,9 +1,5 @@
 use http;
-use core::slice;
-use std::{
-    ffi::{c_char, c_int, c_uint},
-    mem::{self, transmute, MaybeUninit},
-};
+use std::{borrow::Cow, mem::transmute};
 /// permessage-deflate id
 pub const EXT_ID: &str = "permessage-deflate";
 /// server_no_context_takeover param
@@ -15,25 +11,46 @@ pub const SERVER_MAX_WINDOW_BITS: &str = "server_max_window_bits";
 /// client_max_window_bits param
 pub const CLIENT_MAX_WINDOW_BITS: &str = "client_max_window_bits";
 
-/// zlib version
-pub const ZLIB_VERSION: &str = "1.2.13\0";
-
 #[cfg(feature = "sync")]
 mod blocking;
 #[cfg(feature = "sync")]
 pub use blocking::*;
-use libz_sys::{Z_BUF_ERROR, Z_NO_FLUSH, Z_OK, Z_SYNC_FLUSH};
 
 #[cfg(feature = "async")]
 mod non_blocking;
 #[cfg(feature = "async")]
 pub use non_blocking::*;
 
-use crate::{errors::WsError, frame::OpCode};
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static"
+))]
+mod zlib;
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static"
+))]
+pub use zlib::*;
+
+#[cfg(feature = "deflate_rust")]
+mod rust_backend;
+#[cfg(feature = "deflate_rust")]
+pub use rust_backend::*;
+
+use crate::{
+    errors::WsError,
+    frame::{OpCode, OwnedFrame},
+    protocol::CloseCode,
+};
 
 use super::{
-    default_handshake_handler, FrameConfig, FrameReadState, FrameWriteState, ValidateUtf8Policy,
+    default_handshake_handler, Extension, ExtensionParams, FrameConfig, FrameReadState,
+    FrameWriteState, KeepaliveConfig, KeepaliveState, PreparedFrame, StreamRead, Utf8Check,
+    ValidateUtf8Policy, RSV1,
 };
+use crate::{errors::ProtocolError, frame::SimplifiedHeader};
 
 /// permessage-deflate window bit
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
@@ -63,39 +80,155 @@ impl TryFrom<u8> for WindowBit {
     }
 }
 
-/// permessage-deflate req handler
+/// raw deflate compressor of permessage-deflate, implemented by zlib backend
+/// (`deflate`, `deflate_ng` or `deflate_static` feature) and pure rust backend
+/// (`deflate_rust` feature)
+pub trait Compressor: Send + Sized {
+    /// error of backend
+    type Error: std::fmt::Debug + std::fmt::Display;
+
+    /// construct with window bit
+    fn new(window: WindowBit) -> Self;
+
+    /// compress inputs as one chunk ended with sync flush, output is overwritten
+    fn compress(&mut self, inputs: &[&[u8]], output: &mut Vec<u8>) -> Result<(), Self::Error>;
+
+    /// reset stream state
+    fn reset(&mut self) -> Result<(), Self::Error>;
+}
+
+/// raw deflate decompressor of permessage-deflate, see [Compressor]
+pub trait DeCompressor: Send + Sized {
+    /// error of backend
+    type Error: std::fmt::Debug + std::fmt::Display;
+
+    /// construct with window bit
+    fn new(window: WindowBit) -> Self;
+
+    /// decompress inputs, output is overwritten
+    fn de_compress(&mut self, inputs: &[&[u8]], output: &mut Vec<u8>) -> Result<(), Self::Error>;
+
+    /// reset stream state
+    fn reset(&mut self) -> Result<(), Self::Error>;
+}
+
+/// compressor used by deflate codecs, zlib backend is used if both backends are enabled
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static"
+))]
+pub type CompressStream = ZLibCompressStream;
+/// compressor used by deflate codecs, zlib backend is used if both backends are enabled
+#[cfg(not(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static"
+)))]
+pub type CompressStream = RustCompressStream;
+
+/// decompressor used by deflate codecs, zlib backend is used if both backends are enabled
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static"
+))]
+pub type DeCompressStream = ZLibDeCompressStream;
+/// decompressor used by deflate codecs, zlib backend is used if both backends are enabled
+#[cfg(not(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static"
+)))]
+pub type DeCompressStream = RustDeCompressStream;
+
+/// permessage-deflate req handler, accept first acceptable offer with default
+/// [PMDConfig], see [deflate_handshake_handler_with]
 pub fn deflate_handshake_handler(
     req: http::Request<()>,
 ) -> Result<(http::Request<()>, http::Response<String>), (http::Response<String>, WsError)> {
-    let (req, mut resp) = default_handshake_handler(req)?;
-    let mut configs: Vec<PMDConfig> = vec![];
-    for (k, v) in req.headers() {
-        if k.as_str().to_lowercase() == "sec-websocket-extensions" {
-            if let Ok(s) = v.to_str() {
-                match PMDConfig::parse_str(s) {
-                    Ok(mut conf) => {
-                        configs.append(&mut conf);
-                    }
-                    Err(e) => {
-                        let resp = http::Response::builder()
-                            .version(http::Version::HTTP_11)
-                            .status(http::StatusCode::BAD_REQUEST)
-                            .header("Content-Type", "text/html")
-                            .body(e.clone())
-                            .unwrap();
-                        return Err((resp, WsError::HandShakeFailed(e)));
-                    }
-                }
+    deflate_handshake_handler_with(PMDConfig::default())(req)
+}
+
+/// permessage-deflate req handler, `config` is the most permissive params to accept,
+/// offers are tried in order of client preference and invalid or unacceptable offers
+/// are declined, negotiated params are passed to `factory` of deflate codecs by request
+#[allow(clippy::type_complexity, clippy::result_large_err)]
+pub fn deflate_handshake_handler_with(
+    config: PMDConfig,
+) -> impl FnMut(
+    http::Request<()>,
+)
+    -> Result<(http::Request<()>, http::Response<String>), (http::Response<String>, WsError)> {
+    move |req| {
+        let (mut req, mut resp) = default_handshake_handler(req)?;
+        let offers = match ExtensionParams::parse_headers(req.headers()) {
+            Ok(offers) => offers,
+            Err(e) => {
+                let resp = http::Response::builder()
+                    .version(resp.version())
+                    .status(http::StatusCode::BAD_REQUEST)
+                    .header("Content-Type", "text/html")
+                    .body(e.to_string())
+                    .unwrap();
+                return Err((resp, e));
             }
+        };
+        let negotiated = config.negotiate(&offers);
+        if let Some((_, params)) = negotiated.as_ref() {
+            resp.headers_mut().insert(
+                "sec-websocket-extensions",
+                http::HeaderValue::from_str(&params.to_string()).unwrap(),
+            );
         }
+        req.extensions_mut()
+            .insert(Negotiated(negotiated.map(|(conf, _)| conf)));
+        Ok((req, resp))
     }
-    if let Some(config) = configs.pop() {
-        resp.headers_mut().insert(
-            "sec-websocket-extensions",
-            http::HeaderValue::from_str(&config.ext_string()).unwrap(),
-        );
+}
+
+/// params negotiated by deflate handshake handler
+#[derive(Clone)]
+struct Negotiated(Option<PMDConfig>);
+
+/// server side config of deflate codecs, use params negotiated by deflate handshake
+/// handler, or negotiate with default config if request is handled by other handler
+pub(crate) fn server_config(req: &http::Request<()>) -> Result<Option<PMDConfig>, WsError> {
+    if let Some(Negotiated(conf)) = req.extensions().get::<Negotiated>() {
+        return Ok(conf.clone());
     }
-    Ok((req, resp))
+    let offers = ExtensionParams::parse_headers(req.headers())?;
+    Ok(PMDConfig::default()
+        .negotiate(&offers)
+        .map(|(conf, _)| conf))
+}
+
+/// client side config of deflate codecs, response is validated against `offer`, it's
+/// an error if server accepts deflate which is not offered
+pub(crate) fn client_config(
+    offer: Option<&PMDConfig>,
+    resp: &http::Response<()>,
+) -> Result<Option<PMDConfig>, WsError> {
+    let params = ExtensionParams::parse_headers(resp.headers())?
+        .into_iter()
+        .filter(|params| params.is(EXT_ID))
+        .collect::<Vec<_>>();
+    let conf = match (params.as_slice(), offer) {
+        ([], _) => None,
+        ([params], Some(offer)) => Some(offer.check_response(params)?),
+        ([params], None) => {
+            return Err(WsError::HandShakeFailed(format!(
+                "unexpected permessage-deflate response `{params}`"
+            )))
+        }
+        _ => {
+            return Err(WsError::HandShakeFailed(
+                "multiple permessage-deflate params in response".into(),
+            ))
+        }
+    };
+    tracing::debug!("use deflate config: {:?}", conf);
+    Ok(conf)
 }
 
 fn gen_low_level_config(conf: &FrameConfig) -> FrameConfig {
@@ -114,7 +247,7 @@ pub struct WriteStreamHandler {
     /// permessage deflate config
     pub config: PMDConfig,
     /// compressor
-    pub com: ZLibCompressStream,
+    pub com: CompressStream,
 }
 
 /// helper struct to handle de stream
@@ -122,7 +255,7 @@ pub struct ReadStreamHandler {
     /// permessage deflate config
     pub config: PMDConfig,
     /// decompressor
-    pub de: ZLibDeCompressStream,
+    pub de: DeCompressStream,
 }
 
 /// permessage-deflate
@@ -181,228 +314,110 @@ impl PMDConfig {
     }
 }
 
-///
-pub struct ZLibDeCompressStream {
-    stream: Box<libz_sys::z_stream>,
+#[derive(Default)]
+struct PMDParamCounter {
+    server_no_context_takeover: bool,
+    client_no_context_takeover: bool,
+    server_max_window_bits: bool,
+    client_max_window_bits: bool,
 }
 
-unsafe impl Send for ZLibDeCompressStream {}
-unsafe impl Sync for ZLibDeCompressStream {}
-
-impl Drop for ZLibDeCompressStream {
-    fn drop(&mut self) {
-        match unsafe { libz_sys::inflateEnd(self.stream.as_mut()) } {
-            libz_sys::Z_STREAM_ERROR => {
-                tracing::trace!("decompression stream encountered bad state.")
-            }
-            // Ignore discarded data error because we are raw
-            libz_sys::Z_OK | libz_sys::Z_DATA_ERROR => {
-                tracing::trace!("deallocated compression context.")
-            }
-            code => tracing::trace!("bad zlib status encountered: {}", code),
-        }
+impl PMDConfig {
+    /// server side, select first acceptable `permessage-deflate` offer in order of client
+    /// preference (RFC 7692 section 5), `self` is the most permissive params to accept,
+    /// return negotiated config and params of response
+    ///
+    /// offers with invalid params are declined, `client_max_window_bits` less than 15
+    /// is only required if client claims the param, with or without value
+    pub fn negotiate(&self, offers: &[ExtensionParams]) -> Option<(Self, ExtensionParams)> {
+        offers
+            .iter()
+            .filter(|offer| offer.is(EXT_ID))
+            .find_map(|offer| self.negotiate_one(offer))
     }
-}
 
-impl ZLibDeCompressStream {
-    /// construct new compress stream
-    pub fn new(window: WindowBit) -> Self {
-        let mut stream: Box<MaybeUninit<libz_sys::z_stream>> = Box::new(MaybeUninit::zeroed());
-        let result = unsafe {
-            libz_sys::inflateInit2_(
-                stream.as_mut_ptr(),
-                -(window as i8) as c_int,
-                ZLIB_VERSION.as_ptr() as *const c_char,
-                mem::size_of::<libz_sys::z_stream>() as c_int,
-            )
+    /// client side, params of offer, `client_max_window_bits` is always claimed, window
+    /// bits of 15 and disabled flags are omitted
+    pub fn offer(&self) -> ExtensionParams {
+        let mut offer = ExtensionParams::new(EXT_ID);
+        if self.client_no_context_takeover {
+            offer = offer.flag(CLIENT_NO_CONTEXT_TAKEOVER);
+        }
+        if self.server_no_context_takeover {
+            offer = offer.flag(SERVER_NO_CONTEXT_TAKEOVER);
+        }
+        offer = match self.client_max_window_bits {
+            WindowBit::Fifteen => offer.flag(CLIENT_MAX_WINDOW_BITS),
+            bits => offer.value(CLIENT_MAX_WINDOW_BITS, bits as u8),
         };
-        assert!(result == libz_sys::Z_OK, "Failed to initialize compresser.");
-        Self {
-            stream: unsafe { Box::from_raw(Box::into_raw(stream) as *mut libz_sys::z_stream) },
+        if self.server_max_window_bits < WindowBit::Fifteen {
+            offer = offer.value(SERVER_MAX_WINDOW_BITS, self.server_max_window_bits as u8);
         }
+        offer
     }
 
-    /// construct with custom stream
-    pub fn with(stream: Box<libz_sys::z_stream>) -> Self {
-        Self { stream }
-    }
-
-    /// decompress data
-    pub fn de_compress(&mut self, inputs: &[&[u8]], output: &mut Vec<u8>) -> Result<(), c_int> {
-        let total_input: usize = inputs.iter().map(|i| i.len()).sum();
-        if total_input > output.capacity() * 2 + 4 {
-            output.resize(total_input * 2 + 4, 0);
+    /// client side, validate server response against offer of `self` (RFC 7692
+    /// section 7), return params used by connection
+    ///
+    /// window bits larger than offered or dropped `server_no_context_takeover` are
+    /// rejected, client side restrictions of offer are kept if server omits them
+    pub fn check_response(&self, params: &ExtensionParams) -> Result<Self, WsError> {
+        let mut conf = Self::parse_str(&params.to_string())
+            .map_err(WsError::HandShakeFailed)?
+            .pop()
+            .unwrap_or_default();
+        if params.get(CLIENT_MAX_WINDOW_BITS).is_none() {
+            conf.client_max_window_bits = self.client_max_window_bits;
         }
-        let mut write_idx = 0;
-        let before = self.stream.total_out;
-        for i in inputs {
-            let mut iter_read_idx = 0;
-            loop {
-                unsafe {
-                    self.stream.next_in = i.as_ptr().add(iter_read_idx) as *mut _;
-                }
-                self.stream.avail_in = (i.len() - iter_read_idx) as c_uint;
-                if output.capacity() - output.len() <= 0 {
-                    output.resize(output.capacity() * 2, 0);
-                }
-                let out_slice = unsafe {
-                    slice::from_raw_parts_mut(
-                        output.as_mut_ptr().add(write_idx),
-                        output.capacity() - write_idx,
-                    )
-                };
-                self.stream.next_out = out_slice.as_mut_ptr();
-                self.stream.avail_out = out_slice.len() as c_uint;
-
-                match unsafe { libz_sys::inflate(*&mut self.stream.as_mut(), Z_NO_FLUSH) } {
-                    Z_OK | Z_BUF_ERROR => {}
-                    code => return Err(code),
-                };
-                iter_read_idx = i.len() - self.stream.avail_in as usize;
-                write_idx = (self.stream.total_out - before) as usize;
-                if self.stream.avail_in == 0 {
-                    break;
-                }
-            }
+        conf.client_no_context_takeover |= self.client_no_context_takeover;
+        if conf.server_max_window_bits > self.server_max_window_bits
+            || conf.client_max_window_bits > self.client_max_window_bits
+            || (self.server_no_context_takeover && !conf.server_no_context_takeover)
+            // zlib can not compress raw deflate with window of 8 bits
+            || conf.client_max_window_bits == WindowBit::Eight
+        {
+            return Err(WsError::HandShakeFailed(format!(
+                "unexpected permessage-deflate response `{params}`"
+            )));
         }
-        unsafe {
-            match libz_sys::inflate(*&mut self.stream.as_mut(), Z_SYNC_FLUSH) {
-                Z_OK | Z_BUF_ERROR => {}
-                code => return Err(code),
-            }
-            output.set_len((self.stream.total_out - before) as usize);
-        };
-        Ok(())
+        Ok(conf)
     }
 
-    /// reset stream state
-    pub fn reset(&mut self) -> Result<(), c_int> {
-        let code = unsafe { libz_sys::inflateReset(self.stream.as_mut()) };
-        match code {
-            Z_OK => Ok(()),
-            code => Err(code),
+    fn negotiate_one(&self, offer: &ExtensionParams) -> Option<(Self, ExtensionParams)> {
+        let mut conf = Self::parse_str(&offer.to_string()).ok()?.pop()?;
+        let mut resp = ExtensionParams::new(EXT_ID);
+        // client can not handle limited window if it does not claim the param
+        if offer.get(CLIENT_MAX_WINDOW_BITS).is_none()
+            && self.client_max_window_bits < WindowBit::Fifteen
+        {
+            return None;
         }
-    }
-}
-
-/// zlib compress stream
-pub struct ZLibCompressStream {
-    stream: Box<libz_sys::z_stream>,
-}
-
-unsafe impl Send for ZLibCompressStream {}
-unsafe impl Sync for ZLibCompressStream {}
-
-impl Drop for ZLibCompressStream {
-    fn drop(&mut self) {
-        match unsafe { libz_sys::deflateEnd(self.stream.as_mut()) } {
-            libz_sys::Z_STREAM_ERROR => {
-                tracing::trace!("compression stream encountered bad state.")
-            }
-            // Ignore discarded data error because we are raw
-            libz_sys::Z_OK | libz_sys::Z_DATA_ERROR => {
-                tracing::trace!("deallocated compression context.")
-            }
-            code => tracing::trace!("bad zlib status encountered: {}", code),
+        conf.server_no_context_takeover |= self.server_no_context_takeover;
+        conf.client_no_context_takeover |= self.client_no_context_takeover;
+        conf.server_max_window_bits = conf.server_max_window_bits.min(self.server_max_window_bits);
+        conf.client_max_window_bits = conf.client_max_window_bits.min(self.client_max_window_bits);
+        // zlib can not compress raw deflate with window of 8 bits
+        if conf.server_max_window_bits == WindowBit::Eight {
+            return None;
         }
-    }
-}
-
-impl ZLibCompressStream {
-    /// construct with window bit
-    pub fn new(window: WindowBit) -> Self {
-        let mut stream: Box<MaybeUninit<libz_sys::z_stream>> = Box::new(MaybeUninit::zeroed());
-        let result = unsafe {
-            libz_sys::deflateInit2_(
-                stream.as_mut_ptr(),
-                9,
-                libz_sys::Z_DEFLATED,
-                -(window as i8) as c_int,
-                9,
-                libz_sys::Z_DEFAULT_STRATEGY,
-                ZLIB_VERSION.as_ptr() as *const c_char,
-                mem::size_of::<libz_sys::z_stream>() as c_int,
-            )
-        };
-        assert!(result == libz_sys::Z_OK, "Failed to initialize compresser.");
-        Self {
-            stream: unsafe { Box::from_raw(Box::into_raw(stream) as *mut libz_sys::z_stream) },
+        if conf.server_no_context_takeover {
+            resp = resp.flag(SERVER_NO_CONTEXT_TAKEOVER);
         }
-    }
-
-    /// construct with custom stream
-    pub fn with(stream: Box<libz_sys::z_stream>) -> Self {
-        Self { stream }
-    }
-
-    /// compress data
-    pub fn compress(&mut self, inputs: &[&[u8]], output: &mut Vec<u8>) -> Result<(), c_int> {
-        let total_input: usize = inputs.iter().map(|i| i.len()).sum();
-        if total_input > output.capacity() * 2 + 4 {
-            output.resize(total_input * 2 + 4, 0);
+        if conf.client_no_context_takeover {
+            resp = resp.flag(CLIENT_NO_CONTEXT_TAKEOVER);
         }
-        let mut write_idx = 0;
-        let mut total_remain = total_input;
-        let before = self.stream.total_out;
-        for i in inputs {
-            let mut iter_read_idx = 0;
-            loop {
-                unsafe {
-                    self.stream.next_in = i.as_ptr().add(iter_read_idx) as *mut _;
-                }
-                self.stream.avail_in = (i.len() - iter_read_idx) as c_uint;
-                if output.capacity() - output.len() <= 0 {
-                    output.resize(output.len() + total_remain * 2, 0)
-                }
-                let out_slice = unsafe {
-                    slice::from_raw_parts_mut(
-                        output.as_mut_ptr().add(write_idx),
-                        output.capacity() - write_idx,
-                    )
-                };
-                self.stream.next_out = out_slice.as_mut_ptr();
-                self.stream.avail_out = out_slice.len() as c_uint;
-
-                match unsafe { libz_sys::deflate(*&mut self.stream.as_mut(), Z_NO_FLUSH) } {
-                    libz_sys::Z_OK => {}
-                    code => return Err(code),
-                };
-                iter_read_idx = i.len() - self.stream.avail_in as usize;
-                write_idx = (self.stream.total_out - before) as usize;
-                if self.stream.avail_in == 0 {
-                    break;
-                }
-            }
-            total_remain -= iter_read_idx;
+        if offer.get(SERVER_MAX_WINDOW_BITS).is_some()
+            || conf.server_max_window_bits < WindowBit::Fifteen
+        {
+            resp = resp.value(SERVER_MAX_WINDOW_BITS, conf.server_max_window_bits as u8);
         }
-        unsafe {
-            match libz_sys::deflate(*&mut self.stream.as_mut(), Z_SYNC_FLUSH) {
-                Z_OK => {}
-                code => return Err(code),
-            }
-            output.set_len((self.stream.total_out - before) as usize);
-        };
-        Ok(())
-    }
-
-    /// reset stream state
-    pub fn reset(&mut self) -> Result<(), c_int> {
-        let code = unsafe { libz_sys::deflateReset(self.stream.as_mut()) };
-        match code {
-            Z_OK => Ok(()),
-            code => Err(code),
+        if conf.client_max_window_bits < WindowBit::Fifteen {
+            resp = resp.value(CLIENT_MAX_WINDOW_BITS, conf.client_max_window_bits as u8);
         }
+        Some((conf, resp))
     }
 }
 
-#[derive(Default)]
-struct PMDParamCounter {
-    server_no_context_takeover: bool,
-    client_no_context_takeover: bool,
-    server_max_window_bits: bool,
-    client_max_window_bits: bool,
-}
-
 impl PMDConfig {
     /// case-insensitive parse one line header
     pub fn parse_str(source: &str) -> Result<Vec<Self>, String> {
@@ -501,6 +516,80 @@ impl PMDConfig {
     }
 }
 
+/// decide whether a data message is compressed, message which is not compressed is
+/// sent with rsv1 cleared
+pub trait CompressPolicy: Send {
+    /// `size` is payload size of text/binary message, it's none if message is written
+    /// by message writer
+    fn should_compress(&self, code: OpCode, size: Option<usize>) -> bool;
+}
+
+impl<F: Fn(OpCode, Option<usize>) -> bool + Send> CompressPolicy for F {
+    fn should_compress(&self, code: OpCode, size: Option<usize>) -> bool {
+        self(code, size)
+    }
+}
+
+/// compression rule by opcode and minimum payload size, default rule compresses
+/// every message
+#[derive(Debug, Clone)]
+pub struct CompressRule {
+    /// message smaller than this size is not compressed, message with unknown size
+    /// is compressed
+    pub min_size: usize,
+    /// compress text message
+    pub text: bool,
+    /// compress binary message
+    pub binary: bool,
+}
+
+impl Default for CompressRule {
+    fn default() -> Self {
+        Self {
+            min_size: 0,
+            text: true,
+            binary: true,
+        }
+    }
+}
+
+impl CompressPolicy for CompressRule {
+    fn should_compress(&self, code: OpCode, size: Option<usize>) -> bool {
+        let enabled = match code {
+            OpCode::Text => self.text,
+            OpCode::Binary => self.binary,
+            _ => false,
+        };
+        enabled && !matches!(size, Some(size) if size < self.min_size)
+    }
+}
+
+/// compression counters of sent data messages
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompressStats {
+    /// count of compressed messages
+    pub compressed_messages: u64,
+    /// payload bytes of compressed messages before compression
+    pub input_bytes: u64,
+    /// payload bytes of compressed messages after compression
+    pub output_bytes: u64,
+    /// count of messages skipped by policy
+    pub skipped_messages: u64,
+    /// payload bytes of messages skipped by policy
+    pub skipped_bytes: u64,
+}
+
+impl CompressStats {
+    /// compressed size divided by original size of compressed messages, 1.0 if
+    /// nothing is compressed
+    pub fn ratio(&self) -> f64 {
+        if self.input_bytes == 0 {
+            return 1.0;
+        }
+        self.output_bytes as f64 / self.input_bytes as f64
+    }
+}
+
 /// deflate frame write state
 pub struct DeflateWriteState {
     write_state: FrameWriteState,
@@ -508,6 +597,11 @@ pub struct DeflateWriteState {
     config: FrameConfig,
     header_buf: [u8; 14],
     is_server: bool,
+    policy: Box<dyn CompressPolicy>,
+    stats: CompressStats,
+    /// whether current message is compressed, none if extension is disabled or
+    /// message is not data message
+    compressing: Option<bool>,
 }
 
 impl DeflateWriteState {
@@ -521,11 +615,11 @@ impl DeflateWriteState {
         let write_state = FrameWriteState::with_config(low_level_config);
         let com = if let Some(config) = pmd_config {
             let com_size = if is_server {
-                config.client_max_window_bits
-            } else {
                 config.server_max_window_bits
+            } else {
+                config.client_max_window_bits
             };
-            let com = ZLibCompressStream::new(com_size);
+            let com = CompressStream::new(com_size);
             Some(WriteStreamHandler { config, com })
         } else {
             None
@@ -536,8 +630,173 @@ impl DeflateWriteState {
             config: frame_config,
             header_buf: [0; 14],
             is_server,
+            policy: Box::new(CompressRule::default()),
+            stats: CompressStats::default(),
+            compressing: None,
         }
     }
+
+    /// set policy to decide which data message is compressed
+    pub fn set_compress_policy(&mut self, policy: impl CompressPolicy + 'static) {
+        self.policy = Box::new(policy);
+    }
+
+    /// compression counters of sent messages
+    pub fn compress_stats(&self) -> CompressStats {
+        self.stats
+    }
+
+    /// check if close frame has been sent
+    pub fn is_close_sent(&self) -> bool {
+        self.write_state.is_close_sent()
+    }
+
+    #[inline]
+    pub(crate) fn check_send(&self, code: OpCode) -> Result<(), WsError> {
+        self.write_state.check_send(code)
+    }
+
+    /// encoded bytes of prepared frame which can be written directly, return None if
+    /// frame has to be encoded by this connection
+    pub(crate) fn prepared<'a>(
+        &mut self,
+        frame: &'a PreparedFrame,
+    ) -> Result<Option<&'a [u8]>, WsError> {
+        if !self.write_state.can_send_prepared(frame) {
+            return Ok(None);
+        }
+        let size = frame.payload().len();
+        self.begin(frame.opcode(), Some(size), true);
+        if self.compressing == Some(false) {
+            self.stats.skipped_messages += 1;
+            self.stats.skipped_bytes += size as u64;
+        }
+        match self.com.as_ref() {
+            Some(handler) if self.compressing == Some(true) => {
+                let config = &handler.config;
+                let (no_context_takeover, window) = if self.is_server {
+                    (
+                        config.server_no_context_takeover,
+                        config.server_max_window_bits,
+                    )
+                } else {
+                    (
+                        config.client_no_context_takeover,
+                        config.client_max_window_bits,
+                    )
+                };
+                if no_context_takeover {
+                    let encoded = frame.compressed(window)?;
+                    // prepared frame is never masked
+                    let header_len = match encoded[1] & 0x7f {
+                        126 => 4,
+                        127 => 10,
+                        _ => 2,
+                    };
+                    self.stats.compressed_messages += 1;
+                    self.stats.input_bytes += size as u64;
+                    self.stats.output_bytes += (encoded.len() - header_len) as u64;
+                    Ok(Some(encoded))
+                } else {
+                    Ok(None)
+                }
+            }
+            _ => Ok(Some(frame.encoded())),
+        }
+    }
+
+    /// start a message, decide whether it's compressed by policy, `allow` is false
+    /// if message is sent uncompressed explicitly
+    pub(crate) fn begin(&mut self, code: OpCode, size: Option<usize>, allow: bool) {
+        self.compressing = (self.com.is_some() && code.is_data())
+            .then(|| allow && self.policy.should_compress(code, size));
+    }
+
+    /// payload of next fragment of message started by [Self::begin], it's compressed
+    /// if message is compressed, return rsv1 of frame and frame payload
+    ///
+    /// each fragment is sync flushed, trailing empty block is only removed from the
+    /// final fragment
+    pub(crate) fn fragment<'a>(
+        &mut self,
+        first: bool,
+        fin: bool,
+        payload: &'a [u8],
+    ) -> Result<(bool, Cow<'a, [u8]>), WsError> {
+        let handler = match (self.compressing, self.com.as_mut()) {
+            (Some(true), Some(handler)) => handler,
+            (Some(false), _) => {
+                self.stats.skipped_bytes += payload.len() as u64;
+                self.stats.skipped_messages += fin as u64;
+                return Ok((false, Cow::Borrowed(payload)));
+            }
+            _ => return Ok((false, Cow::Borrowed(payload))),
+        };
+        let mut compressed = Vec::with_capacity(payload.len());
+        handler
+            .com
+            .compress(&[payload], &mut compressed)
+            .map_err(|code| WsError::CompressFailed(code.to_string()))?;
+        if fin {
+            // nothing is flushed if no data is written since last fragment, send
+            // an empty stored block header, which is completed by receiver
+            if compressed.is_empty() {
+                compressed.push(0);
+            } else {
+                compressed.truncate(compressed.len() - 4);
+            }
+            if (self.is_server && handler.config.server_no_context_takeover)
+                || (!self.is_server && handler.config.client_no_context_takeover)
+            {
+                handler
+                    .com
+                    .reset()
+                    .map_err(|code| WsError::CompressFailed(code.to_string()))?;
+                tracing::trace!("reset compressor");
+            }
+            self.stats.compressed_messages += 1;
+        }
+        self.stats.input_bytes += payload.len() as u64;
+        self.stats.output_bytes += compressed.len() as u64;
+        Ok((first, Cow::Owned(compressed)))
+    }
+
+    /// compress payload of owned data frame, first frame of message decides whether
+    /// the message is compressed, continuation frames follow that decision and only
+    /// first frame has rsv1 set
+    pub(crate) fn compress_owned(&mut self, mut frame: OwnedFrame) -> Result<OwnedFrame, WsError> {
+        let header = frame.header();
+        let (code, fin) = (header.opcode(), header.fin());
+        let first = code != OpCode::Continue;
+        if first {
+            // size of whole message is unknown if it's fragmented
+            self.begin(code, fin.then_some(frame.payload().len()), true);
+        }
+        let compressing = self.compressing == Some(true);
+        let mask = frame.unmask();
+        let (rsv1, payload) = self.fragment(first, fin, frame.payload())?;
+        if !compressing {
+            if let Some(mask) = mask {
+                frame.mask(mask);
+            }
+            return Ok(frame);
+        }
+        let mut new = OwnedFrame::new(code, mask, &payload);
+        let header = new.header_mut();
+        header.set_rsv1(rsv1);
+        header.set_fin(fin);
+        Ok(new)
+    }
+
+    /// compress data frame payload if extension is enabled and policy allows, then
+    /// encode it into pending buffer
+    #[cfg(feature = "async")]
+    pub(crate) fn encode(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        self.begin(code, Some(payload.len()), true);
+        let (rsv1, payload) = self.fragment(true, true, payload)?;
+        self.write_state.encode(code, rsv1, &payload);
+        Ok(())
+    }
 }
 
 /// deflate frame read state
@@ -550,6 +809,20 @@ pub struct DeflateReadState {
     control_buf: Vec<u8>,
     fragmented_type: OpCode,
     is_server: bool,
+    keepalive: KeepaliveState,
+    streaming: Option<InflateState>,
+    stream_buf: Vec<u8>,
+    /// whether fragmented message being received is compressed
+    inflating: bool,
+}
+
+/// message being streamed, compressed payload is inflated chunk by chunk
+struct InflateState {
+    header: SimplifiedHeader,
+    out: Vec<u8>,
+    pos: usize,
+    utf8: Option<Utf8Check>,
+    ended: bool,
 }
 
 impl DeflateReadState {
@@ -567,7 +840,7 @@ impl DeflateReadState {
             } else {
                 config.server_max_window_bits
             };
-            let de = ZLibDeCompressStream::new(de_size);
+            let de = DeCompressStream::new(de_size);
             Some(ReadStreamHandler { config, de })
         } else {
             None
@@ -575,12 +848,443 @@ impl DeflateReadState {
         Self {
             read_state,
             de,
+            keepalive: KeepaliveState::new(frame_config.keepalive.clone()),
             config: frame_config,
             fragmented: false,
             fragmented_data: vec![],
             control_buf: vec![],
             fragmented_type: OpCode::Binary,
             is_server,
+            streaming: None,
+            stream_buf: vec![],
+            inflating: false,
+        }
+    }
+
+    /// inflate payload of a received frame, continuation frames of compressed message
+    /// are inflated with the same stream
+    pub(crate) fn inflate_frame(
+        &mut self,
+        header: &mut SimplifiedHeader,
+        data: Vec<u8>,
+    ) -> Result<Vec<u8>, WsError> {
+        let is_data_frame = header.code.is_data();
+        if header.rsv1 && !is_data_frame {
+            return Err(WsError::ProtocolError {
+                close_code: CloseCode::ProtocolError,
+                error: ProtocolError::CompressedControlFrame,
+            });
+        }
+        if !is_data_frame {
+            return Ok(data);
+        }
+        if header.code != OpCode::Continue {
+            self.inflating = header.rsv1;
+        }
+        if !self.inflating {
+            return Ok(data);
+        }
+        let Some(handler) = self.de.as_mut() else {
+            return Err(WsError::DeCompressFailed(
+                "extension not enabled but got compressed frame".into(),
+            ));
+        };
+        let mut de_data = vec![];
+        let inputs: &[&[u8]] = if header.fin {
+            &[&data, &[0, 0, 255, 255]]
+        } else {
+            &[&data]
+        };
+        handler
+            .de
+            .de_compress(inputs, &mut de_data)
+            .map_err(|code| WsError::DeCompressFailed(code.to_string()))?;
+        if header.fin {
+            self.inflating = false;
+            if (self.is_server && handler.config.client_no_context_takeover)
+                || (!self.is_server && handler.config.server_no_context_takeover)
+            {
+                handler
+                    .de
+                    .reset()
+                    .map_err(|code| WsError::DeCompressFailed(code.to_string()))?;
+                tracing::trace!("reset decompressor state");
+            }
+        }
+        header.rsv1 = false;
+        Ok(de_data)
+    }
+
+    /// update keepalive config and reset keepalive timer
+    pub fn set_keepalive(&mut self, config: KeepaliveConfig) {
+        self.keepalive = KeepaliveState::new(config.clone());
+        self.config.keepalive = config;
+    }
+
+    /// check if close frame from peer has been received
+    pub fn is_close_received(&self) -> bool {
+        self.read_state.is_close_received()
+    }
+
+    /// payload of message returned by `next_message`
+    #[inline]
+    fn payload(&self, is_control: bool) -> &[u8] {
+        if is_control {
+            &self.control_buf
+        } else {
+            &self.fragmented_data
+        }
+    }
+
+    #[inline]
+    #[cfg(feature = "sync")]
+    fn payload_mut(&mut self, is_control: bool) -> &mut [u8] {
+        if is_control {
+            &mut self.control_buf
+        } else {
+            &mut self.fragmented_data
+        }
+    }
+
+    /// header of message being streamed
+    pub(crate) fn streaming_header(&self) -> Option<SimplifiedHeader> {
+        self.streaming.as_ref().map(|state| state.header)
+    }
+
+    /// return inflated payload in buffer or end of message, `None` means more data
+    /// should be read
+    fn stream_output(&mut self, buf: &mut [u8]) -> Option<StreamRead> {
+        let state = self.streaming.as_mut()?;
+        if state.pos < state.out.len() || buf.is_empty() {
+            let data = &state.out[state.pos..];
+            let count = data.len().min(buf.len());
+            buf[..count].copy_from_slice(&data[..count]);
+            state.pos += count;
+            return Some(StreamRead::Data(count));
+        }
+        if state.ended {
+            let header = state.header;
+            self.streaming = None;
+            return Some(StreamRead::End(header));
+        }
+        None
+    }
+
+    /// size of raw chunk to read and whether it can be read into user buffer directly
+    fn stream_input_size(&self, buf: &[u8]) -> (usize, bool) {
+        match self.streaming.as_ref() {
+            Some(state) if state.header.rsv1 => (buf.len(), false),
+            Some(_) => (buf.len(), true),
+            None => (0, false),
+        }
+    }
+
+    /// handle result of low level stream read, `data` is the buffer passed to it,
+    /// return control frame or uncompressed payload which is read into user buffer
+    fn stream_input(
+        &mut self,
+        ret: StreamRead,
+        data: &[u8],
+    ) -> Result<Option<StreamRead>, WsError> {
+        let (count, end) = match ret {
+            StreamRead::Data(count) => (count, None),
+            StreamRead::End(header) => (0, Some(header)),
+            StreamRead::Control(header, _) if header.rsv1 => {
+                return Err(WsError::ProtocolError {
+                    close_code: CloseCode::ProtocolError,
+                    error: ProtocolError::CompressedControlFrame,
+                })
+            }
+            ret => return Ok(Some(ret)),
+        };
+        if self.streaming.is_none() {
+            let Some(header) = end.or_else(|| self.read_state.streaming_header()) else {
+                return Ok(None);
+            };
+            if header.rsv1 && self.de.is_none() {
+                return Err(WsError::DeCompressFailed(
+                    "extension not enabled but got compressed frame".into(),
+                ));
+            }
+            let check = header.code == OpCode::Text && self.config.validate_utf8.should_check();
+            self.streaming = Some(InflateState {
+                header,
+                out: vec![],
+                pos: 0,
+                utf8: check.then(Utf8Check::default),
+                ended: false,
+            });
+        }
+        let Some(state) = self.streaming.as_mut() else {
+            return Ok(None);
+        };
+        let mut ret = None;
+        match self.de.as_mut() {
+            Some(handler) if state.header.rsv1 => {
+                if count > 0 || end.is_some() {
+                    let tail: &[u8] = if end.is_some() {
+                        &[0, 0, 255, 255]
+                    } else {
+                        &[]
+                    };
+                    handler
+                        .de
+                        .de_compress(&[&data[..count], tail], &mut state.out)
+                        .map_err(|code| WsError::DeCompressFailed(code.to_string()))?;
+                    state.pos = 0;
+                    if let Some(utf8) = state.utf8.as_mut() {
+                        utf8.feed(&state.out)?;
+                    }
+                }
+                if end.is_some()
+                    && ((self.is_server && handler.config.client_no_context_takeover)
+                        || (!self.is_server && handler.config.server_no_context_takeover))
+                {
+                    handler
+                        .de
+                        .reset()
+                        .map_err(|code| WsError::DeCompressFailed(code.to_string()))?;
+                    tracing::trace!("reset decompressor state");
+                }
+            }
+            _ if count > 0 => {
+                if let Some(utf8) = state.utf8.as_mut() {
+                    utf8.feed(&data[..count])?;
+                }
+                ret = Some(StreamRead::Data(count));
+            }
+            _ => {}
+        }
+        if end.is_some() {
+            if let Some(utf8) = state.utf8.as_mut() {
+                utf8.finish()?;
+            }
+            state.ended = true;
+        }
+        Ok(ret)
+    }
+}
+
+/// permessage-deflate as [Extension], run by [ExtCodec](crate::codec::ExtCodec) together
+/// with other extensions
+///
+/// compressor uses window bits and context takeover of own side, decompressor uses
+/// those of peer side
+pub struct PermessageDeflate {
+    config: PMDConfig,
+    is_server: bool,
+    com: Option<CompressStream>,
+    de: Option<DeCompressStream>,
+    buf: Vec<u8>,
+}
+
+impl PermessageDeflate {
+    /// client side, offer params of `config`
+    pub fn client(config: PMDConfig) -> Self {
+        Self {
+            config,
+            is_server: false,
+            com: None,
+            de: None,
+            buf: vec![],
+        }
+    }
+
+    /// server side, `config` is the most permissive params to accept
+    pub fn server(config: PMDConfig) -> Self {
+        Self {
+            is_server: true,
+            ..Self::client(config)
+        }
+    }
+
+    fn init(&mut self, config: PMDConfig) {
+        let (com, de) = if self.is_server {
+            (config.server_max_window_bits, config.client_max_window_bits)
+        } else {
+            (config.client_max_window_bits, config.server_max_window_bits)
+        };
+        self.com = Some(CompressStream::new(com));
+        self.de = Some(DeCompressStream::new(de));
+        self.config = config;
+    }
+
+    /// (reset compressor, reset decompressor) after each message
+    fn no_context_takeover(&self) -> (bool, bool) {
+        let PMDConfig {
+            server_no_context_takeover: server,
+            client_no_context_takeover: client,
+            ..
+        } = self.config;
+        if self.is_server {
+            (server, client)
+        } else {
+            (client, server)
+        }
+    }
+}
+
+impl Extension for PermessageDeflate {
+    fn name(&self) -> &str {
+        EXT_ID
+    }
+
+    fn rsv_bits(&self) -> u8 {
+        RSV1
+    }
+
+    fn offer(&self) -> Vec<ExtensionParams> {
+        vec![self.config.offer()]
+    }
+
+    fn accept(&mut self, offers: &[ExtensionParams]) -> Result<Option<ExtensionParams>, WsError> {
+        match self.config.negotiate(offers) {
+            Some((conf, resp)) => {
+                self.init(conf);
+                Ok(Some(resp))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn configure(&mut self, params: &ExtensionParams) -> Result<(), WsError> {
+        let conf = self.config.check_response(params)?;
+        self.init(conf);
+        Ok(())
+    }
+
+    fn encode(&mut self, _code: OpCode, payload: &mut Vec<u8>) -> Result<u8, WsError> {
+        let reset = self.no_context_takeover().0;
+        let Some(com) = self.com.as_mut() else {
+            return Ok(0);
+        };
+        com.compress(&[payload], &mut self.buf)
+            .map_err(|code| WsError::CompressFailed(code.to_string()))?;
+        if self.buf.ends_with(&[0, 0, 255, 255]) {
+            self.buf.truncate(self.buf.len() - 4);
+        }
+        if self.buf.is_empty() {
+            self.buf.push(0);
         }
+        std::mem::swap(payload, &mut self.buf);
+        if reset {
+            com.reset()
+                .map_err(|code| WsError::CompressFailed(code.to_string()))?;
+        }
+        Ok(RSV1)
     }
+
+    fn decode(&mut self, _code: OpCode, rsv: u8, payload: &mut Vec<u8>) -> Result<(), WsError> {
+        let reset = self.no_context_takeover().1;
+        let Some(de) = self.de.as_mut().filter(|_| rsv & RSV1 != 0) else {
+            return Ok(());
+        };
+        de.de_compress(&[payload, &[0, 0, 255, 255]], &mut self.buf)
+            .map_err(|code| WsError::DeCompressFailed(code.to_string()))?;
+        std::mem::swap(payload, &mut self.buf);
+        if reset {
+            de.reset()
+                .map_err(|code| WsError::DeCompressFailed(code.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_pmd_negotiate() {
+    let negotiate = |local: PMDConfig, offers: &str| {
+        local
+            .negotiate(&ExtensionParams::parse(offers).unwrap())
+            .map(|(conf, resp)| (conf, resp.to_string()))
+    };
+    let limited = PMDConfig {
+        client_max_window_bits: WindowBit::Ten,
+        server_max_window_bits: WindowBit::Twelve,
+        ..Default::default()
+    };
+
+    // invalid offer is declined, next one is accepted
+    let (conf, resp) = negotiate(
+        Default::default(),
+        "permessage-deflate; server_max_window_bits=7, permessage-deflate; client_max_window_bits",
+    )
+    .unwrap();
+    assert_eq!(conf.client_max_window_bits, WindowBit::Fifteen);
+    assert_eq!(resp, "permessage-deflate");
+
+    // offer without client_max_window_bits can not accept limited client window
+    assert!(negotiate(limited.clone(), "permessage-deflate").is_none());
+    let (conf, resp) = negotiate(
+        limited.clone(),
+        "permessage-deflate; client_max_window_bits=9; server_max_window_bits=14",
+    )
+    .unwrap();
+    assert_eq!(conf.client_max_window_bits, WindowBit::Nine);
+    assert_eq!(conf.server_max_window_bits, WindowBit::Twelve);
+    assert_eq!(
+        resp,
+        "permessage-deflate; server_max_window_bits=12; client_max_window_bits=9"
+    );
+
+    // context takeover flags are independent
+    let (conf, resp) = negotiate(
+        Default::default(),
+        "permessage-deflate; client_no_context_takeover; client_max_window_bits",
+    )
+    .unwrap();
+    assert!(conf.client_no_context_takeover);
+    assert!(!conf.server_no_context_takeover);
+    assert_eq!(resp, "permessage-deflate; client_no_context_takeover");
+
+    // server window of 8 bits is not supported by zlib
+    assert!(negotiate(
+        Default::default(),
+        "permessage-deflate; server_max_window_bits=8"
+    )
+    .is_none());
+}
+
+#[test]
+fn test_pmd_check_response() {
+    let offer = PMDConfig {
+        server_no_context_takeover: true,
+        client_max_window_bits: WindowBit::Ten,
+        server_max_window_bits: WindowBit::Twelve,
+        ..Default::default()
+    };
+    let check = |resp: &str| offer.check_response(&ExtensionParams::parse(resp).unwrap()[0]);
+
+    let conf =
+        check("permessage-deflate; server_no_context_takeover; server_max_window_bits=11").unwrap();
+    assert_eq!(conf.server_max_window_bits, WindowBit::Eleven);
+    // client window is limited by offer if server omits it
+    assert_eq!(conf.client_max_window_bits, WindowBit::Ten);
+    assert!(
+        check("permessage-deflate; server_no_context_takeover; server_max_window_bits=13").is_err()
+    );
+    assert!(check(
+        "permessage-deflate; server_no_context_takeover; server_max_window_bits=12; client_max_window_bits=11"
+    )
+    .is_err());
+    assert!(check("permessage-deflate; server_max_window_bits=12").is_err());
+
+    let resp = |ext: &[&str]| {
+        let mut builder = http::Response::builder();
+        for ext in ext {
+            builder = builder.header(http::header::SEC_WEBSOCKET_EXTENSIONS, *ext);
+        }
+        builder.body(()).unwrap()
+    };
+    let accepted = resp(&["permessage-deflate"]);
+    assert!(client_config(Some(&Default::default()), &accepted)
+        .unwrap()
+        .is_some());
+    // deflate is not offered
+    assert!(client_config(None, &accepted).is_err());
+    assert!(client_config(None, &resp(&[])).unwrap().is_none());
+    assert!(client_config(
+        Some(&Default::default()),
+        &resp(&["permessage-deflate", "permessage-deflate"])
+    )
+    .is_err());
 }
@@ -1,73 +1,47 @@
+#[cfg(feature = "stream")]
+use crate::Message;
 use http;
 use crate::{
-    codec::{apply_mask, FrameConfig, Split},
-    errors::{ProtocolError, WsError},
-    frame::{ctor_header, OpCode, OwnedFrame, SimplifiedHeader},
-    protocol::standard_handshake_resp_check,
+    codec::{
+        echo_close_payload, with_deadline, with_timeout, ConnectionState, FrameConfig,
+        KeepaliveConfig, PreparedFrame, Split, StreamRead,
+    },
+    errors::{ProtocolError, TimeoutPhase, WsError},
+    frame::{OpCode, OwnedFrame, SimplifiedHeader},
+    protocol::{standard_handshake_resp_check, CloseCode},
+    WsMessage,
 };
-use bytes::BytesMut;
-use rand::random;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use std::{
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
 
-use super::{DeflateReadState, DeflateWriteState, PMDConfig};
+use super::{
+    client_config, server_config, CompressPolicy, CompressStats, DeflateReadState,
+    DeflateWriteState, PMDConfig,
+};
 
 impl DeflateWriteState {
     /// send a read frame, **this method will not check validation of frame and do not fragment**
     pub async fn async_send_owned_frame<S: AsyncWrite + Unpin>(
         &mut self,
         stream: &mut S,
-        mut frame: OwnedFrame,
+        frame: OwnedFrame,
     ) -> Result<(), WsError> {
-        if !frame.header().opcode().is_data() {
-            return self
-                .write_state
-                .async_send_owned_frame(stream, frame)
-                .await
-                .map_err(WsError::IOError);
-        }
-        let prev_mask = frame.unmask();
-        let header = frame.header();
-        let frame: Result<OwnedFrame, WsError> = header
-            .opcode()
-            .is_data()
-            .then(|| self.com.as_mut())
-            .flatten()
-            .map(|handler| {
-                let mut compressed = Vec::with_capacity(frame.payload().len());
-                handler
-                    .com
-                    .compress(&[frame.payload()], &mut compressed)
-                    .map_err(|code| WsError::CompressFailed(code.to_string()))?;
-                compressed.truncate(compressed.len() - 4);
-                let mut new = OwnedFrame::new(header.opcode(), prev_mask, &compressed);
-                let header = new.header_mut();
-                header.set_rsv1(true);
-                header.set_fin(header.fin());
-
-                if (self.is_server && handler.config.server_no_context_takeover)
-                    || (!self.is_server && handler.config.client_no_context_takeover)
-                {
-                    handler
-                        .com
-                        .reset()
-                        .map_err(|code| WsError::CompressFailed(code.to_string()))?;
-                    tracing::trace!("reset compressor");
-                }
-                Ok(new)
-            })
-            .unwrap_or_else(|| {
-                if let Some(mask) = prev_mask {
-                    frame.mask(mask);
-                }
-                Ok(frame)
-            });
+        let frame = if frame.header().opcode().is_data() {
+            self.compress_owned(frame)?
+        } else {
+            frame
+        };
         self.write_state
-            .async_send_owned_frame(stream, frame?)
+            .async_send_owned_frame(stream, frame)
             .await
             .map_err(WsError::IOError)
     }
 
-    /// send payload
+    /// send payload, data message is compressed if compress policy allows
     ///
     /// will auto fragment **before compression** if auto_fragment_size > 0
     pub async fn async_send<S: AsyncWrite + Unpin>(
@@ -76,156 +50,109 @@ impl DeflateWriteState {
         code: OpCode,
         payload: &[u8],
     ) -> Result<(), WsError> {
-        let mask_send = self.config.mask_send_frame;
-        let mask_fn = || {
-            if mask_send {
-                Some(random())
-            } else {
-                None
-            }
-        };
-        if payload.is_empty() {
-            let mask = mask_fn();
-            let frame = OwnedFrame::new(code, mask, &[]);
-            return self.async_send_owned_frame(stream, frame).await;
-        }
+        self.async_send_with(stream, code, payload, true).await
+    }
+
+    /// async version of [DeflateWriteState::send_uncompressed]
+    pub async fn async_send_uncompressed<S: AsyncWrite + Unpin>(
+        &mut self,
+        stream: &mut S,
+        code: OpCode,
+        payload: &[u8],
+    ) -> Result<(), WsError> {
+        self.async_send_with(stream, code, payload, false).await
+    }
 
-        let chunk_size = if self.config.auto_fragment_size > 0 {
+    async fn async_send_with<S: AsyncWrite + Unpin>(
+        &mut self,
+        stream: &mut S,
+        code: OpCode,
+        payload: &[u8],
+        compress: bool,
+    ) -> Result<(), WsError> {
+        self.write_state.write_pending(stream).await?;
+        let chunk_size = if self.config.auto_fragment_size > 0 && code.is_data() {
             self.config.auto_fragment_size
         } else {
-            payload.len()
+            payload.len().max(1)
         };
-        let parts: Vec<&[u8]> = payload.chunks(chunk_size).collect();
+        let mut parts: Vec<&[u8]> = payload.chunks(chunk_size).collect();
+        if parts.is_empty() {
+            parts.push(&[]);
+        }
+        // whole message is encoded into pending buffer before writing, so that a
+        // write interrupted by timeout is resumed by following write
+        self.begin(code, Some(payload.len()), compress);
         let total = parts.len();
         for (idx, chunk) in parts.into_iter().enumerate() {
-            let fin = idx + 1 == total;
-            let mask = mask_fn();
-            match (self.com.as_mut(), code.is_data()) {
-                (Some(handler), true) => {
-                    let mut output = vec![];
-                    handler
-                        .com
-                        .compress(&[chunk], &mut output)
-                        .map_err(|code| WsError::CompressFailed(code.to_string()))?;
-                    output.truncate(output.len() - 4);
-                    let header = ctor_header(
-                        &mut self.header_buf,
-                        fin,
-                        true,
-                        false,
-                        false,
-                        mask,
-                        code,
-                        output.len() as u64,
-                    );
-                    stream.write_all(header).await?;
-                    if let Some(mask) = mask {
-                        apply_mask(&mut output, mask)
-                    };
-                    stream.write_all(&output).await?;
-                    if (self.is_server && handler.config.server_no_context_takeover)
-                        || (!self.is_server && handler.config.client_no_context_takeover)
-                    {
-                        handler
-                            .com
-                            .reset()
-                            .map_err(|code| WsError::CompressFailed(code.to_string()))?;
-                        tracing::trace!("reset compressor");
-                    }
-                }
-                _ => {
-                    let header = ctor_header(
-                        &mut self.header_buf,
-                        fin,
-                        false,
-                        false,
-                        false,
-                        mask,
-                        code,
-                        chunk.len() as u64,
-                    );
-                    stream.write_all(header).await?;
-                    if let Some(mask) = mask {
-                        let mut data = BytesMut::from_iter(chunk);
-                        apply_mask(&mut data, mask);
-                        stream.write_all(&data).await?;
-                    } else {
-                        stream.write_all(chunk).await?;
-                    }
+            let (first, fin) = (idx == 0, idx + 1 == total);
+            let (rsv1, data) = match self.fragment(first, fin, chunk) {
+                Ok(ret) => ret,
+                Err(e) => {
+                    self.write_state.pending.clear();
+                    return Err(e);
                 }
-            }
+            };
+            let code = if first { code } else { OpCode::Continue };
+            self.write_state.encode_frame(fin, rsv1, code, &data);
         }
-        Ok(())
+        self.write_state.set_sent(code);
+        Ok(self.write_state.write_pending(stream).await?)
     }
-}
 
-impl DeflateReadState {
-    async fn async_receive_one<S: AsyncRead + Unpin>(
+    /// async version of [DeflateWriteState::send_prepared]
+    pub async fn async_send_prepared<S: AsyncWrite + Unpin>(
         &mut self,
         stream: &mut S,
-    ) -> Result<(SimplifiedHeader, Vec<u8>), WsError> {
-        let (mut header, data) = self.read_state.async_receive(stream).await?;
-        let data = data.to_vec();
-        let compressed = header.rsv1;
-        let is_data_frame = header.code.is_data();
-        if compressed && !is_data_frame {
-            return Err(WsError::ProtocolError {
-                close_code: 1002,
-                error: ProtocolError::CompressedControlFrame,
-            });
-        }
-        if !is_data_frame || !compressed {
-            return Ok((header, data.to_vec()));
-        }
-        let frame = match self.de.as_mut() {
-            Some(handler) => {
-                let mut de_data = vec![];
-                handler
-                    .de
-                    .de_compress(&[&data, &[0, 0, 255, 255]], &mut de_data)
-                    .map_err(|code| WsError::DeCompressFailed(code.to_string()))?;
-                if (self.is_server && handler.config.server_no_context_takeover)
-                    || (!self.is_server && handler.config.client_no_context_takeover)
-                {
-                    handler
-                        .de
-                        .reset()
-                        .map_err(|code| WsError::DeCompressFailed(code.to_string()))?;
-                    tracing::trace!("reset decompressor state");
-                }
-                de_data
+        frame: &PreparedFrame,
+    ) -> Result<(), WsError> {
+        self.write_state.write_pending(stream).await?;
+        match self.prepared(frame)? {
+            Some(encoded) => {
+                self.write_state.set_sent(frame.opcode());
+                Ok(self
+                    .write_state
+                    .async_write_encoded(stream, encoded)
+                    .await?)
             }
             None => {
-                if header.rsv1 {
-                    return Err(WsError::DeCompressFailed(
-                        "extension not enabled but got compressed frame".into(),
-                    ));
-                } else {
-                    data
-                }
+                self.async_send(stream, frame.opcode(), frame.payload())
+                    .await
             }
-        };
-        header.rsv1 = false;
-        Ok((header, frame))
+        }
     }
+}
 
-    /// receive a message
-    pub async fn async_receive<S: AsyncRead + Unpin>(
+impl DeflateReadState {
+    fn poll_receive_one<S: AsyncRead + Unpin>(
         &mut self,
+        cx: &mut Context<'_>,
         stream: &mut S,
-    ) -> Result<(SimplifiedHeader, &[u8]), WsError> {
-        loop {
-            let (mut header, mut data) = self.async_receive_one(stream).await?;
+    ) -> Poll<Result<(SimplifiedHeader, Vec<u8>), WsError>> {
+        let (mut header, range) = ready!(self.read_state.poll_next_frame(cx, stream))?;
+        let data = self.read_state.payload(range).to_vec();
+        let data = self.inflate_frame(&mut header, data)?;
+        Poll::Ready(Ok((header, data)))
+    }
+
+    /// poll a message, return true if payload is in control frame buffer
+    pub(crate) fn poll_next_message<S: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stream: &mut S,
+    ) -> Poll<Result<(SimplifiedHeader, bool), WsError>> {
+        let ret = loop {
+            let (mut header, mut data) = ready!(self.poll_receive_one(cx, stream))?;
             if !self.config.merge_frame {
                 self.fragmented_data.clear();
                 self.fragmented_data.append(&mut data);
-                break Ok((header, &self.fragmented_data));
+                break Ok((header, false));
             }
             match header.code {
                 OpCode::Continue => {
                     if !self.fragmented {
-                        return Err(WsError::ProtocolError {
-                            close_code: 1002,
+                        break Err(WsError::ProtocolError {
+                            close_code: CloseCode::ProtocolError,
                             error: ProtocolError::MissInitialFragmentedFrame,
                         });
                     }
@@ -234,15 +161,15 @@ impl DeflateReadState {
                     if fin {
                         self.fragmented = false;
                         header.code = self.fragmented_type;
-                        break Ok((header, &self.fragmented_data));
+                        break Ok((header, false));
                     } else {
                         continue;
                     }
                 }
                 OpCode::Text | OpCode::Binary => {
                     if self.fragmented {
-                        return Err(WsError::ProtocolError {
-                            close_code: 1002,
+                        break Err(WsError::ProtocolError {
+                            close_code: CloseCode::ProtocolError,
                             error: ProtocolError::NotContinueFrameAfterFragmented,
                         });
                     }
@@ -253,8 +180,8 @@ impl DeflateReadState {
                             && self.config.validate_utf8.is_fast_fail()
                             && simdutf8::basic::from_utf8(&data).is_err()
                         {
-                            return Err(WsError::ProtocolError {
-                                close_code: 1007,
+                            break Err(WsError::ProtocolError {
+                                close_code: CloseCode::InvalidPayload,
                                 error: ProtocolError::InvalidUtf8,
                             });
                         }
@@ -266,22 +193,78 @@ impl DeflateReadState {
                             && self.config.validate_utf8.should_check()
                             && simdutf8::basic::from_utf8(&data).is_err()
                         {
-                            return Err(WsError::ProtocolError {
-                                close_code: 1007,
+                            break Err(WsError::ProtocolError {
+                                close_code: CloseCode::InvalidPayload,
                                 error: ProtocolError::InvalidUtf8,
                             });
                         }
                         self.fragmented_data.clear();
                         self.fragmented_data.extend_from_slice(&data);
-                        break Ok((header, &self.fragmented_data));
+                        break Ok((header, false));
                     }
                 }
                 OpCode::Close | OpCode::Ping | OpCode::Pong => {
                     self.control_buf = data;
-                    break Ok((header, &self.control_buf));
+                    break Ok((header, true));
                 }
                 _ => break Err(WsError::UnsupportedFrame(header.code)),
             }
+        };
+        Poll::Ready(ret)
+    }
+
+    /// read a message, return true if payload is in control frame buffer
+    ///
+    /// this future is cancel safe
+    async fn async_next_message<S: AsyncRead + Unpin>(
+        &mut self,
+        stream: &mut S,
+    ) -> Result<(SimplifiedHeader, bool), WsError> {
+        std::future::poll_fn(|cx| self.poll_next_message(cx, stream)).await
+    }
+
+    /// receive a message
+    pub async fn async_receive<S: AsyncRead + Unpin>(
+        &mut self,
+        stream: &mut S,
+    ) -> Result<(SimplifiedHeader, &[u8]), WsError> {
+        let (header, is_control) = self.async_next_message(stream).await?;
+        Ok((header, self.payload(is_control)))
+    }
+
+    /// async version of [DeflateReadState::stream_read](super::DeflateReadState)
+    pub(crate) fn poll_stream_read<S: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stream: &mut S,
+        buf: &mut [u8],
+    ) -> Poll<Result<StreamRead, WsError>> {
+        loop {
+            if let Some(ret) = self.stream_output(buf) {
+                return Poll::Ready(Ok(ret));
+            }
+            let ret = match self.stream_input_size(buf) {
+                (_, true) => {
+                    let ret = ready!(self.read_state.poll_stream_read(cx, stream, buf))?;
+                    self.stream_input(ret, buf)?
+                }
+                (size, false) => {
+                    let mut raw = std::mem::take(&mut self.stream_buf);
+                    raw.resize(size, 0);
+                    let ret = match self.read_state.poll_stream_read(cx, stream, &mut raw) {
+                        Poll::Ready(ret) => ret.and_then(|ret| self.stream_input(ret, &raw)),
+                        Poll::Pending => {
+                            self.stream_buf = raw;
+                            return Poll::Pending;
+                        }
+                    };
+                    self.stream_buf = raw;
+                    ret?
+                }
+            };
+            if let Some(ret) = ret {
+                return Poll::Ready(Ok(ret));
+            }
         }
     }
 }
@@ -311,27 +294,10 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncDeflateCodec<S> {
         }
     }
 
-    /// used for server side to construct a new server
+    /// used for server side to construct a new server, params negotiated by
+    /// [deflate_handshake_handler_with](crate::codec::deflate_handshake_handler_with) are used
     pub fn factory(req: http::Request<()>, stream: S) -> Result<Self, WsError> {
-        let mut pmd_configs: Vec<PMDConfig> = vec![];
-        for (k, v) in req.headers() {
-            if k.as_str().to_lowercase() == "sec-websocket-extensions" {
-                if let Ok(s) = v.to_str() {
-                    match PMDConfig::parse_str(s) {
-                        Ok(mut conf) => {
-                            pmd_configs.append(&mut conf);
-                        }
-                        Err(e) => return Err(WsError::HandShakeFailed(e)),
-                    }
-                }
-            }
-        }
-        let mut pmd_config = pmd_configs.pop();
-        if let Some(conf) = pmd_config.as_mut() {
-            let min = conf.client_max_window_bits.min(conf.server_max_window_bits);
-            conf.client_max_window_bits = min;
-            conf.server_max_window_bits = min;
-        }
+        let pmd_config = server_config(&req)?;
         tracing::debug!("use deflate config {:?}", pmd_config);
         let frame_conf = FrameConfig {
             mask_send_frame: false,
@@ -341,31 +307,23 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncDeflateCodec<S> {
         Ok(codec)
     }
 
-    /// used for client side to construct a new client
+    /// used for client side to construct a new client, params accepted by server are used,
+    /// response is validated against default [PMDConfig] offer
     pub fn check_fn(key: String, resp: http::Response<()>, stream: S) -> Result<Self, WsError> {
-        standard_handshake_resp_check(key.as_bytes(), &resp)?;
-        let mut pmd_confs: Vec<PMDConfig> = vec![];
-        for (k, v) in resp.headers() {
-            if k.as_str().to_lowercase() == "sec-websocket-extensions" {
-                if let Ok(s) = v.to_str() {
-                    match PMDConfig::parse_str(s) {
-                        Ok(mut conf) => {
-                            pmd_confs.append(&mut conf);
-                        }
-                        Err(e) => return Err(WsError::HandShakeFailed(e)),
-                    }
-                }
-            }
-        }
-        let mut pmd_conf = pmd_confs.pop();
-        if let Some(conf) = pmd_conf.as_mut() {
-            let min = conf.client_max_window_bits.min(conf.server_max_window_bits);
-            conf.client_max_window_bits = min;
-            conf.server_max_window_bits = min;
+        Self::check_fn_with(Some(Default::default()))(key, resp, stream)
+    }
+
+    /// client side check fn which validates response against `offer`, `None` if
+    /// deflate is not offered
+    pub fn check_fn_with(
+        offer: Option<PMDConfig>,
+    ) -> impl FnMut(String, http::Response<()>, S) -> Result<Self, WsError> {
+        move |key, resp, stream| {
+            standard_handshake_resp_check(key.as_bytes(), &resp)?;
+            let pmd_config = client_config(offer.as_ref(), &resp)?;
+            let codec = AsyncDeflateCodec::new(stream, Default::default(), pmd_config, false);
+            Ok(codec)
         }
-        tracing::debug!("use deflate config: {:?}", pmd_conf);
-        let codec = AsyncDeflateCodec::new(stream, Default::default(), pmd_conf, false);
-        Ok(codec)
     }
 
     /// get mutable underlying stream
@@ -374,31 +332,274 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncDeflateCodec<S> {
     }
 
     /// receive a message
+    ///
+    /// close frame from peer is echoed automatically if close frame has not been sent,
+    /// after that, [WsError::ConnectionClosed] is returned
+    ///
+    /// if keepalive is enabled, ping frame is replied automatically and tokio timer
+    /// is used to send ping & detect dead peer
     pub async fn receive(&mut self) -> Result<(SimplifiedHeader, &[u8]), WsError> {
-        self.read_state.async_receive(&mut self.stream).await
+        self.discard_stream().await?;
+        let read_timeout = self.read_state.config.read_timeout;
+        // keepalive ticks do not extend read timeout, it's reset by received frame only
+        let read_deadline = |timeout: Option<Duration>| {
+            timeout.map(|timeout| tokio::time::Instant::now() + timeout)
+        };
+        let mut read_at = read_deadline(read_timeout);
+        loop {
+            let deadline = self.read_state.keepalive.deadline();
+            let next = with_deadline(
+                read_at,
+                TimeoutPhase::Read,
+                self.read_state.async_next_message(&mut self.stream),
+            );
+            let ret = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline.into(), next).await.ok(),
+                None => Some(next.await),
+            };
+            match ret {
+                Some(ret) => {
+                    let (header, is_control) = ret?;
+                    if header.code == OpCode::Close {
+                        self.echo_close().await;
+                    }
+                    if !self.read_state.keepalive.is_enabled() {
+                        return Ok((header, self.read_state.payload(is_control)));
+                    }
+                    let need_pong = self.read_state.keepalive.on_frame(header.code);
+                    if !self.write_state.is_close_sent() && self.read_state.keepalive.on_tick()? {
+                        self.ping(&[]).await?;
+                        self.flush().await?;
+                    }
+                    if need_pong && !self.write_state.is_close_sent() {
+                        let payload = self.read_state.payload(is_control).to_vec();
+                        self.pong(&payload).await?;
+                        self.flush().await?;
+                        read_at = read_deadline(read_timeout);
+                        continue;
+                    }
+                    return Ok((header, self.read_state.payload(is_control)));
+                }
+                None => {
+                    if !self.write_state.is_close_sent() && self.read_state.keepalive.on_tick()? {
+                        self.ping(&[]).await?;
+                        self.flush().await?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub async fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive().await?;
+        WsMessage::from_header(&header, data)
+    }
+
+    /// receive a text or binary message as [AsyncDeflateMessageReader], compressed
+    /// payload is inflated on demand instead of being buffered, see
+    /// [AsyncFrameCodec::receive_stream](crate::codec::AsyncFrameCodec::receive_stream)
+    pub async fn receive_stream(&mut self) -> Result<AsyncDeflateMessageReader<'_, S>, WsError> {
+        self.discard_stream().await?;
+        let read_timeout = self.read_state.config.read_timeout;
+        let start = std::future::poll_fn(|cx| self.poll_stream_read(cx, &mut []));
+        let (header, done) = match with_timeout(read_timeout, TimeoutPhase::Read, start).await? {
+            StreamRead::End(header) => (header, true),
+            _ => match self.read_state.streaming_header() {
+                Some(header) => (header, false),
+                None => unreachable!("message stream is not started"),
+            },
+        };
+        Ok(AsyncDeflateMessageReader {
+            codec: self,
+            header,
+            done,
+        })
+    }
+
+    /// poll streamed message, control frames are handled, return [StreamRead::Data]
+    /// or [StreamRead::End]
+    fn poll_stream_read(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<StreamRead, WsError>> {
+        loop {
+            let write_state = &mut self.write_state.write_state;
+            if !write_state.pending.is_empty() {
+                match ready!(write_state.poll_flush_pending(cx, &mut self.stream)) {
+                    Err(e) if self.read_state.is_close_received() => {
+                        tracing::debug!("failed to echo close frame {e}");
+                        write_state.pending.clear();
+                    }
+                    ret => ret?,
+                }
+            }
+            match ready!(self.read_state.poll_stream_read(cx, &mut self.stream, buf))? {
+                StreamRead::Control(header, range) => {
+                    self.read_state.keepalive.on_frame(header.code);
+                    if self.write_state.is_close_sent() {
+                        continue;
+                    }
+                    let payload = self.read_state.read_state.payload(Some(range));
+                    let (code, payload) = match header.code {
+                        OpCode::Ping => (OpCode::Pong, payload.to_vec()),
+                        OpCode::Close => (OpCode::Close, echo_close_payload(payload).to_vec()),
+                        _ => continue,
+                    };
+                    self.write_state.check_send(code)?;
+                    self.write_state.encode(code, &payload)?;
+                }
+                ret => return Poll::Ready(Ok(ret)),
+            }
+        }
+    }
+
+    /// discard unread payload of streamed message
+    async fn discard_stream(&mut self) -> Result<(), WsError> {
+        let mut buf = [0; 4096];
+        while self.read_state.streaming_header().is_some() {
+            std::future::poll_fn(|cx| self.poll_stream_read(cx, &mut buf)).await?;
+        }
+        Ok(())
+    }
+
+    async fn echo_close(&mut self) {
+        if self.write_state.is_close_sent() {
+            return;
+        }
+        let payload = echo_close_payload(self.read_state.payload(true)).to_vec();
+        let ret = match self.send(OpCode::Close, &payload).await {
+            Ok(_) => self.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = ret {
+            tracing::debug!("failed to echo close frame {e}");
+        }
+    }
+
+    /// current state of close handshake
+    pub fn state(&self) -> ConnectionState {
+        ConnectionState::new(
+            self.write_state.is_close_sent(),
+            self.read_state.is_close_received(),
+        )
+    }
+
+    /// send close frame if not sent, then wait for close frame from peer until timeout
+    /// and shutdown underlying stream, return true if close frame from peer is received
+    ///
+    /// close frame interrupted by write timeout is resumed, so it can be retried
+    ///
+    /// messages received before close frame are dropped
+    pub async fn graceful_close(
+        &mut self,
+        code: impl Into<CloseCode>,
+        reason: &[u8],
+        timeout: Duration,
+    ) -> Result<bool, WsError> {
+        if !self.write_state.is_close_sent() {
+            self.close(code, reason).await?;
+        }
+        self.flush().await?;
+        let wait = async {
+            while !self.read_state.is_close_received() {
+                if let Err(e) = self.read_state.async_next_message(&mut self.stream).await {
+                    tracing::debug!("failed to wait close frame {e}");
+                    return false;
+                }
+            }
+            true
+        };
+        let acked = tokio::time::timeout(timeout, wait).await.unwrap_or(false);
+        if let Err(e) = self.stream.shutdown().await {
+            tracing::debug!("failed to shutdown stream {e}");
+        }
+        Ok(acked)
+    }
+
+    /// last time pong frame is received
+    pub fn last_pong(&self) -> Option<std::time::Instant> {
+        self.read_state.keepalive.last_pong()
+    }
+
+    /// update keepalive config and reset keepalive timer
+    pub fn set_keepalive(&mut self, config: KeepaliveConfig) {
+        self.read_state.set_keepalive(config)
+    }
+
+    /// update read & write timeout, see [FrameConfig::read_timeout] and [FrameConfig::write_timeout]
+    pub fn set_timeout(&mut self, read: Option<Duration>, write: Option<Duration>) {
+        self.read_state.config.read_timeout = read;
+        self.write_state.config.write_timeout = write;
     }
 
     /// send a read frame, **this method will not check validation of frame and do not fragment**
     pub async fn send_owned_frame(&mut self, frame: OwnedFrame) -> Result<(), WsError> {
-        self.write_state
-            .async_send_owned_frame(&mut self.stream, frame)
-            .await
+        self.write_state.check_send(frame.header().opcode())?;
+        let timeout = self.write_state.config.write_timeout;
+        let send = self
+            .write_state
+            .async_send_owned_frame(&mut self.stream, frame);
+        with_timeout(timeout, TimeoutPhase::Write, send).await
     }
 
     /// send payload
     ///
     /// will auto fragment **before compression** if auto_fragment_size > 0
     pub async fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
-        self.write_state
-            .async_send(&mut self.stream, code, payload)
-            .await
+        self.write_state.check_send(code)?;
+        let timeout = self.write_state.config.write_timeout;
+        let send = self.write_state.async_send(&mut self.stream, code, payload);
+        with_timeout(timeout, TimeoutPhase::Write, send).await
+    }
+
+    /// send payload without compression, rsv1 of frame is cleared
+    pub async fn send_uncompressed(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        self.write_state.check_send(code)?;
+        let timeout = self.write_state.config.write_timeout;
+        let send = self
+            .write_state
+            .async_send_uncompressed(&mut self.stream, code, payload);
+        with_timeout(timeout, TimeoutPhase::Write, send).await
+    }
+
+    /// set policy to decide which data message is compressed
+    pub fn set_compress_policy(&mut self, policy: impl CompressPolicy + 'static) {
+        self.write_state.set_compress_policy(policy)
+    }
+
+    /// compression counters of sent messages
+    pub fn compress_stats(&self) -> CompressStats {
+        self.write_state.compress_stats()
+    }
+
+    /// send owned message
+    pub async fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload()).await
+    }
+
+    /// send prepared frame, see [DeflateWriteState::send_prepared]
+    pub async fn send_prepared(&mut self, frame: &PreparedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.opcode())?;
+        let timeout = self.write_state.config.write_timeout;
+        let send = self
+            .write_state
+            .async_send_prepared(&mut self.stream, frame);
+        with_timeout(timeout, TimeoutPhase::Write, send).await
+    }
+
+    /// start a text or binary message, see [AsyncDeflateMessageWriter]
+    pub fn begin_message(
+        &mut self,
+        code: OpCode,
+    ) -> Result<AsyncDeflateMessageWriter<'_, S>, WsError> {
+        AsyncDeflateMessageWriter::new(&mut self.stream, &mut self.write_state, code)
     }
 
     /// helper function to send text message
     pub async fn text(&mut self, text: &str) -> Result<(), WsError> {
-        self.write_state
-            .async_send(&mut self.stream, OpCode::Text, text.as_bytes())
-            .await
+        self.send(OpCode::Text, text.as_bytes()).await
     }
 
     /// helper function to send binary message
@@ -417,15 +618,201 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncDeflateCodec<S> {
     }
 
     /// helper method to send close message
-    pub async fn close(&mut self, code: u16, msg: &[u8]) -> Result<(), WsError> {
-        let mut data = code.to_be_bytes().to_vec();
+    pub async fn close(&mut self, code: impl Into<CloseCode>, msg: &[u8]) -> Result<(), WsError> {
+        let mut data = u16::from(code.into()).to_be_bytes().to_vec();
         data.extend_from_slice(msg);
         self.send(OpCode::Close, &data).await
     }
 
     /// flush stream to ensure all data are send
     pub async fn flush(&mut self) -> Result<(), WsError> {
-        self.stream.flush().await.map_err(WsError::IOError)
+        let timeout = self.write_state.config.write_timeout;
+        let flush = async {
+            self.write_state
+                .write_state
+                .write_pending(&mut self.stream)
+                .await
+                .map_err(WsError::IOError)?;
+            self.stream.flush().await.map_err(WsError::IOError)
+        };
+        with_timeout(timeout, TimeoutPhase::Write, flush).await
+    }
+}
+
+/// message received by [AsyncDeflateCodec::receive_stream], compressed payload is
+/// read and inflated on demand
+pub struct AsyncDeflateMessageReader<'a, S: AsyncRead + AsyncWrite> {
+    codec: &'a mut AsyncDeflateCodec<S>,
+    header: SimplifiedHeader,
+    done: bool,
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> AsyncDeflateMessageReader<'a, S> {
+    /// opcode of message, text or binary
+    pub fn opcode(&self) -> OpCode {
+        self.header.code
+    }
+
+    /// check if whole message has been read
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> AsyncRead for AsyncDeflateMessageReader<'a, S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if this.done || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        match ready!(this.codec.poll_stream_read(cx, buf.initialize_unfilled()))? {
+            StreamRead::Data(count) => buf.advance(count),
+            _ => this.done = true,
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// async version of [DeflateMessageWriter](super::DeflateMessageWriter), compressed
+/// frames are encoded into pending buffer and written on next write or flush
+///
+/// only [AsyncDeflateMessageWriter::finish], [AsyncDeflateMessageWriter::finish_with]
+/// or `shutdown` sends the final frame, if writer is dropped after some fragments are
+/// encoded, close frame 1011 is encoded to be written by next write or flush, and
+/// following data frames of codec fail with [WsError::UnfinishedMessage]
+pub struct AsyncDeflateMessageWriter<'a, S: AsyncWrite> {
+    stream: &'a mut S,
+    write_state: &'a mut DeflateWriteState,
+    code: OpCode,
+    started: bool,
+    finished: bool,
+}
+
+impl<'a, S: AsyncWrite + Unpin> AsyncDeflateMessageWriter<'a, S> {
+    pub(crate) fn new(
+        stream: &'a mut S,
+        write_state: &'a mut DeflateWriteState,
+        code: OpCode,
+    ) -> Result<Self, WsError> {
+        if !code.is_data() {
+            return Err(WsError::UnsupportedFrame(code));
+        }
+        write_state.check_send(code)?;
+        write_state.begin(code, None, true);
+        Ok(Self {
+            stream,
+            write_state,
+            code,
+            started: false,
+            finished: false,
+        })
+    }
+
+    /// opcode of message, text or binary
+    pub fn opcode(&self) -> OpCode {
+        self.code
+    }
+
+    /// send a control frame between fragments
+    pub async fn control(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        if code.is_data() {
+            return Err(WsError::UnsupportedFrame(code));
+        }
+        self.write_state.check_send(code)?;
+        let write_state = &mut self.write_state.write_state;
+        write_state.encode_frame(true, false, code, payload);
+        write_state.set_sent(code);
+        write_state
+            .write_pending(self.stream)
+            .await
+            .map_err(WsError::IOError)
+    }
+
+    fn encode_fragment(&mut self, fin: bool, payload: &[u8]) -> Result<(), WsError> {
+        let code = if self.started {
+            OpCode::Continue
+        } else {
+            self.code
+        };
+        self.write_state.check_send(code)?;
+        let (rsv1, data) = self.write_state.fragment(!self.started, fin, payload)?;
+        self.write_state
+            .write_state
+            .encode_frame(fin, rsv1, code, &data);
+        self.started = true;
+        Ok(())
+    }
+
+    fn poll_finish(&mut self, cx: &mut Context<'_>, payload: &[u8]) -> Poll<std::io::Result<()>> {
+        if !self.finished {
+            self.encode_fragment(true, payload)?;
+            self.finished = true;
+        }
+        self.write_state
+            .write_state
+            .poll_flush_pending(cx, self.stream)
+    }
+
+    /// send empty final frame and flush stream
+    pub async fn finish(self) -> Result<(), WsError> {
+        self.finish_with(&[]).await
+    }
+
+    /// send last chunk of message as final frame and flush stream
+    pub async fn finish_with(mut self, payload: &[u8]) -> Result<(), WsError> {
+        std::future::poll_fn(|cx| self.poll_finish(cx, payload))
+            .await
+            .map_err(WsError::IOError)
+    }
+}
+
+impl<'a, S: AsyncWrite + Unpin> AsyncWrite for AsyncDeflateMessageWriter<'a, S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this
+            .write_state
+            .write_state
+            .poll_write_pending(cx, this.stream))?;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        this.encode_fragment(false, buf)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        this.write_state
+            .write_state
+            .poll_flush_pending(cx, this.stream)
+    }
+
+    /// send final frame, underlying stream is not shutdown
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.get_mut().poll_finish(cx, &[])
+    }
+}
+
+impl<'a, S: AsyncWrite> Drop for AsyncDeflateMessageWriter<'a, S> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if !self.started {
+            return;
+        }
+        let write_state = &mut self.write_state.write_state;
+        if let Some(payload) = write_state.set_unfinished() {
+            write_state.encode(OpCode::Close, false, &payload);
+        }
     }
 }
 
@@ -450,6 +837,12 @@ impl<S: AsyncRead + Unpin> AsyncDeflateRecv<S> {
     pub async fn receive(&mut self) -> Result<(SimplifiedHeader, &[u8]), WsError> {
         self.read_state.async_receive(&mut self.stream).await
     }
+
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub async fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive().await?;
+        WsMessage::from_header(&header, data)
+    }
 }
 
 /// send part of deflate message
@@ -474,6 +867,7 @@ impl<S: AsyncWrite + Unpin> AsyncDeflateSend<S> {
 
     /// send a read frame, **this method will not check validation of frame and do not fragment**
     pub async fn send_owned_frame(&mut self, frame: OwnedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.header().opcode())?;
         self.write_state
             .async_send_owned_frame(&mut self.stream, frame)
             .await
@@ -483,18 +877,56 @@ impl<S: AsyncWrite + Unpin> AsyncDeflateSend<S> {
     ///
     /// will auto fragment **before compression** if auto_fragment_size > 0
     pub async fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        self.write_state.check_send(code)?;
         self.write_state
             .async_send(&mut self.stream, code, payload)
             .await
     }
 
-    /// helper function to send text message
-    pub async fn text(&mut self, text: &str) -> Result<(), WsError> {
+    /// send payload without compression, rsv1 of frame is cleared
+    pub async fn send_uncompressed(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        self.write_state.check_send(code)?;
         self.write_state
-            .async_send(&mut self.stream, OpCode::Text, text.as_bytes())
+            .async_send_uncompressed(&mut self.stream, code, payload)
             .await
     }
 
+    /// set policy to decide which data message is compressed
+    pub fn set_compress_policy(&mut self, policy: impl CompressPolicy + 'static) {
+        self.write_state.set_compress_policy(policy)
+    }
+
+    /// compression counters of sent messages
+    pub fn compress_stats(&self) -> CompressStats {
+        self.write_state.compress_stats()
+    }
+
+    /// send owned message
+    pub async fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload()).await
+    }
+
+    /// send prepared frame, see [DeflateWriteState::send_prepared]
+    pub async fn send_prepared(&mut self, frame: &PreparedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.opcode())?;
+        self.write_state
+            .async_send_prepared(&mut self.stream, frame)
+            .await
+    }
+
+    /// start a text or binary message, see [AsyncDeflateMessageWriter]
+    pub fn begin_message(
+        &mut self,
+        code: OpCode,
+    ) -> Result<AsyncDeflateMessageWriter<'_, S>, WsError> {
+        AsyncDeflateMessageWriter::new(&mut self.stream, &mut self.write_state, code)
+    }
+
+    /// helper function to send text message
+    pub async fn text(&mut self, text: &str) -> Result<(), WsError> {
+        self.send(OpCode::Text, text.as_bytes()).await
+    }
+
     /// helper function to send binary message
     pub async fn binary(&mut self, data: &[u8]) -> Result<(), WsError> {
         self.send(OpCode::Binary, data).await
@@ -511,14 +943,19 @@ impl<S: AsyncWrite + Unpin> AsyncDeflateSend<S> {
     }
 
     /// helper method to send close message
-    pub async fn close(&mut self, code: u16, msg: &[u8]) -> Result<(), WsError> {
-        let mut data = code.to_be_bytes().to_vec();
+    pub async fn close(&mut self, code: impl Into<CloseCode>, msg: &[u8]) -> Result<(), WsError> {
+        let mut data = u16::from(code.into()).to_be_bytes().to_vec();
         data.extend_from_slice(msg);
         self.send(OpCode::Close, &data).await
     }
 
     /// flush stream to ensure all data are send
     pub async fn flush(&mut self) -> Result<(), WsError> {
+        self.write_state
+            .write_state
+            .write_pending(&mut self.stream)
+            .await
+            .map_err(WsError::IOError)?;
         self.stream.flush().await.map_err(WsError::IOError)
     }
 }
@@ -543,3 +980,212 @@ where
         )
     }
 }
+
+/// close frame from peer is echoed before end of stream if close frame has not been sent,
+/// **NOTE** keepalive is not handled by stream api
+#[cfg(feature = "stream")]
+impl<S: AsyncRead + AsyncWrite + Unpin> futures_core::Stream for AsyncDeflateCodec<S> {
+    type Item = Result<Message<Vec<u8>>, WsError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let write_state = &mut this.write_state.write_state;
+        if this.read_state.is_close_received() {
+            if let Err(e) = ready!(write_state.poll_flush_pending(cx, &mut this.stream)) {
+                tracing::debug!("failed to echo close frame {e}");
+            }
+            return Poll::Ready(None);
+        }
+        match ready!(this.read_state.poll_next_message(cx, &mut this.stream)) {
+            Ok((header, is_control)) => {
+                let payload = this.read_state.payload(is_control);
+                let msg = Message::from_frame(header.code, payload);
+                if header.code == OpCode::Close && !write_state.is_close_sent() {
+                    let echo = echo_close_payload(payload).to_vec();
+                    write_state.encode(OpCode::Close, false, &echo);
+                    // try to send it now, remaining data is flushed before end of stream
+                    let _ = write_state.poll_flush_pending(cx, &mut this.stream);
+                }
+                Poll::Ready(Some(Ok(msg)))
+            }
+            Err(WsError::ConnectionClosed) => Poll::Ready(None),
+            Err(e) => Poll::Ready(Some(Err(e))),
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<S, T> futures_sink::Sink<Message<T>> for AsyncDeflateCodec<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: AsRef<[u8]>,
+{
+    type Error = WsError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        this.write_state
+            .write_state
+            .poll_write_pending(cx, &mut this.stream)
+            .map_err(WsError::IOError)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message<T>) -> Result<(), WsError> {
+        let this = self.get_mut();
+        this.write_state.check_send(item.code)?;
+        this.write_state.encode(item.code, &item.frame_payload())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        this.write_state
+            .write_state
+            .poll_flush_pending(cx, &mut this.stream)
+            .map_err(WsError::IOError)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        ready!(this
+            .write_state
+            .write_state
+            .poll_flush_pending(cx, &mut this.stream))?;
+        Pin::new(&mut this.stream)
+            .poll_shutdown(cx)
+            .map_err(WsError::IOError)
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<S: AsyncRead + Unpin> futures_core::Stream for AsyncDeflateRecv<S> {
+    type Item = Result<Message<Vec<u8>>, WsError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match ready!(this.read_state.poll_next_message(cx, &mut this.stream)) {
+            Ok((header, is_control)) => Poll::Ready(Some(Ok(Message::from_frame(
+                header.code,
+                this.read_state.payload(is_control),
+            )))),
+            Err(WsError::ConnectionClosed) => Poll::Ready(None),
+            Err(e) => Poll::Ready(Some(Err(e))),
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<S, T> futures_sink::Sink<Message<T>> for AsyncDeflateSend<S>
+where
+    S: AsyncWrite + Unpin,
+    T: AsRef<[u8]>,
+{
+    type Error = WsError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        this.write_state
+            .write_state
+            .poll_write_pending(cx, &mut this.stream)
+            .map_err(WsError::IOError)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message<T>) -> Result<(), WsError> {
+        let this = self.get_mut();
+        this.write_state.check_send(item.code)?;
+        this.write_state.encode(item.code, &item.frame_payload())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        this.write_state
+            .write_state
+            .poll_flush_pending(cx, &mut this.stream)
+            .map_err(WsError::IOError)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        ready!(this
+            .write_state
+            .write_state
+            .poll_flush_pending(cx, &mut this.stream))?;
+        Pin::new(&mut this.stream)
+            .poll_shutdown(cx)
+            .map_err(WsError::IOError)
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_async_deflate_receive_stream() {
+    use tokio::io::AsyncReadExt;
+
+    let text = "hello world ".repeat(1000);
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client =
+        AsyncDeflateCodec::new(client, Default::default(), Some(Default::default()), false);
+    let mut server = AsyncDeflateCodec::new(
+        server,
+        FrameConfig {
+            mask_send_frame: false,
+            ..Default::default()
+        },
+        Some(Default::default()),
+        true,
+    );
+    server.text(&text).await.unwrap();
+    server.ping(b"p").await.unwrap();
+    server.text("next").await.unwrap();
+    server.flush().await.unwrap();
+
+    let mut reader = client.receive_stream().await.unwrap();
+    assert_eq!(reader.opcode(), OpCode::Text);
+    let mut data = String::new();
+    reader.read_to_string(&mut data).await.unwrap();
+    assert_eq!(data, text);
+    let reader = client.receive_stream().await.unwrap();
+    assert_eq!(reader.opcode(), OpCode::Text);
+
+    // unread payload is discarded by next receive
+    server.text("last").await.unwrap();
+    server.flush().await.unwrap();
+    assert_eq!(
+        client.receive_message().await.unwrap(),
+        WsMessage::Text("last".into())
+    );
+    let (header, data) = server.receive().await.unwrap();
+    assert_eq!(header.code, OpCode::Pong);
+    assert_eq!(data, b"p");
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_async_deflate_message_writer() {
+    use tokio::io::AsyncReadExt;
+
+    let text = "hello world ".repeat(100);
+    let (client, server) = tokio::io::duplex(1 << 16);
+    let mut client =
+        AsyncDeflateCodec::new(client, Default::default(), Some(Default::default()), false);
+    let mut server =
+        AsyncDeflateCodec::new(server, Default::default(), Some(Default::default()), true);
+    for _ in 0..2 {
+        let mut writer = client.begin_message(OpCode::Text).unwrap();
+        for chunk in text.as_bytes().chunks(500) {
+            writer.write_all(chunk).await.unwrap();
+        }
+        writer.control(OpCode::Ping, b"p").await.unwrap();
+        writer.finish().await.unwrap();
+    }
+    for _ in 0..2 {
+        let mut data = String::new();
+        server
+            .receive_stream()
+            .await
+            .unwrap()
+            .read_to_string(&mut data)
+            .await
+            .unwrap();
+        assert_eq!(data, text);
+    }
+}
@@ -0,0 +1,208 @@
+use flate2::{
+    Compress, CompressError, Compression, Decompress, DecompressError, FlushCompress,
+    FlushDecompress,
+};
+
+use super::{Compressor, DeCompressor, WindowBit};
+
+/// grow spare capacity of output, flate2 only writes into spare capacity
+fn reserve(output: &mut Vec<u8>, min: usize) {
+    if output.len() == output.capacity() {
+        output.reserve(output.len().max(min));
+    }
+}
+
+/// pure rust raw deflate decompress stream
+pub struct RustDeCompressStream {
+    stream: Decompress,
+}
+
+impl RustDeCompressStream {
+    /// construct with window bit, window of 8 bits is inflated with 9 bits window,
+    /// which is compatible
+    pub fn new(window: WindowBit) -> Self {
+        let window = window.max(WindowBit::Nine);
+        Self {
+            stream: Decompress::new_with_window_bits(false, window as u8),
+        }
+    }
+
+    /// decompress data
+    ///
+    /// output is overwritten and grows until all inflated data fit in, so input can be
+    /// fed in small chunks
+    pub fn de_compress(
+        &mut self,
+        inputs: &[&[u8]],
+        output: &mut Vec<u8>,
+    ) -> Result<(), DecompressError> {
+        let total_input: usize = inputs.iter().map(|i| i.len()).sum();
+        output.clear();
+        for input in inputs {
+            let mut read_idx = 0;
+            while read_idx < input.len() {
+                reserve(output, total_input * 2 + 4);
+                let before = self.stream.total_in();
+                self.stream
+                    .decompress_vec(&input[read_idx..], output, FlushDecompress::None)?;
+                let read = (self.stream.total_in() - before) as usize;
+                // stream is finished by a final block, remaining input is discarded
+                if read == 0 && output.len() < output.capacity() {
+                    break;
+                }
+                read_idx += read;
+            }
+        }
+        loop {
+            reserve(output, total_input * 2 + 4);
+            self.stream
+                .decompress_vec(&[], output, FlushDecompress::Sync)?;
+            if output.len() < output.capacity() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// reset stream state
+    pub fn reset(&mut self) -> Result<(), DecompressError> {
+        self.stream.reset(false);
+        Ok(())
+    }
+}
+
+/// pure rust raw deflate compress stream
+pub struct RustCompressStream {
+    stream: Compress,
+}
+
+impl RustCompressStream {
+    /// construct with window bit, panic if window is 8 bits, same as zlib
+    pub fn new(window: WindowBit) -> Self {
+        Self {
+            stream: Compress::new_with_window_bits(Compression::best(), false, window as u8),
+        }
+    }
+
+    /// compress data
+    pub fn compress(
+        &mut self,
+        inputs: &[&[u8]],
+        output: &mut Vec<u8>,
+    ) -> Result<(), CompressError> {
+        let total_input: usize = inputs.iter().map(|i| i.len()).sum();
+        output.clear();
+        for input in inputs.iter().filter(|i| !i.is_empty()) {
+            let mut read_idx = 0;
+            while read_idx < input.len() {
+                reserve(output, total_input + 64);
+                let before = self.stream.total_in();
+                self.stream
+                    .compress_vec(&input[read_idx..], output, FlushCompress::None)?;
+                read_idx += (self.stream.total_in() - before) as usize;
+            }
+        }
+        // flush until there is room left, else some output is still pending
+        loop {
+            reserve(output, total_input + 64);
+            self.stream.compress_vec(&[], output, FlushCompress::Sync)?;
+            if output.len() < output.capacity() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// reset stream state
+    pub fn reset(&mut self) -> Result<(), CompressError> {
+        self.stream.reset();
+        Ok(())
+    }
+}
+
+impl Compressor for RustCompressStream {
+    type Error = CompressError;
+
+    fn new(window: WindowBit) -> Self {
+        Self::new(window)
+    }
+
+    fn compress(&mut self, inputs: &[&[u8]], output: &mut Vec<u8>) -> Result<(), CompressError> {
+        self.compress(inputs, output)
+    }
+
+    fn reset(&mut self) -> Result<(), CompressError> {
+        self.reset()
+    }
+}
+
+impl DeCompressor for RustDeCompressStream {
+    type Error = DecompressError;
+
+    fn new(window: WindowBit) -> Self {
+        Self::new(window)
+    }
+
+    fn de_compress(
+        &mut self,
+        inputs: &[&[u8]],
+        output: &mut Vec<u8>,
+    ) -> Result<(), DecompressError> {
+        self.de_compress(inputs, output)
+    }
+
+    fn reset(&mut self) -> Result<(), DecompressError> {
+        self.reset()
+    }
+}
+
+#[cfg(test)]
+fn round_trip<C: Compressor, D: DeCompressor>(window: WindowBit, context_takeover: bool) {
+    let mut rng = fastrand::Rng::with_seed(window as u64);
+    let mut com = C::new(window);
+    let mut de = D::new(window);
+    let (mut compressed, mut inflated) = (vec![], vec![]);
+    for size in [0, 16, 64, 256, 1024, 4096, 16384, 65536] {
+        let text = "hello permessage-deflate ".repeat(size / 25 + 1);
+        let mut binary = vec![0; size];
+        rng.fill(&mut binary);
+        for payload in [&text.as_bytes()[..size], &binary] {
+            com.compress(&[payload], &mut compressed).unwrap();
+            if compressed.ends_with(&[0, 0, 255, 255]) {
+                compressed.truncate(compressed.len() - 4);
+            }
+            if compressed.is_empty() {
+                compressed.push(0);
+            }
+            let (first, second) = compressed.split_at(compressed.len() / 2);
+            de.de_compress(&[first, second, &[0, 0, 255, 255]], &mut inflated)
+                .unwrap();
+            assert_eq!(inflated, payload, "window {window:?} size {size}");
+            if !context_takeover {
+                com.reset().unwrap();
+                de.reset().unwrap();
+            }
+        }
+    }
+}
+
+#[test]
+fn test_backend_round_trip() {
+    for bits in 9..=15 {
+        let window = WindowBit::try_from(bits).unwrap();
+        for context_takeover in [true, false] {
+            round_trip::<RustCompressStream, RustDeCompressStream>(window, context_takeover);
+            #[cfg(any(
+                feature = "deflate",
+                feature = "deflate_ng",
+                feature = "deflate_static"
+            ))]
+            {
+                use super::{ZLibCompressStream, ZLibDeCompressStream};
+
+                round_trip::<ZLibCompressStream, RustDeCompressStream>(window, context_takeover);
+                round_trip::<RustCompressStream, ZLibDeCompressStream>(window, context_takeover);
+            }
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    ffi::{c_char, c_int, c_uint},
+    mem::{self, MaybeUninit},
+};
+
+use libz_sys::{Z_BUF_ERROR, Z_NO_FLUSH, Z_OK, Z_SYNC_FLUSH};
+
+use super::{Compressor, DeCompressor, WindowBit};
+
+/// zlib version
+pub const ZLIB_VERSION: &str = "1.2.13\0";
+
+///
+pub struct ZLibDeCompressStream {
+    stream: Box<libz_sys::z_stream>,
+}
+
+unsafe impl Send for ZLibDeCompressStream {}
+unsafe impl Sync for ZLibDeCompressStream {}
+
+impl Drop for ZLibDeCompressStream {
+    fn drop(&mut self) {
+        match unsafe { libz_sys::inflateEnd(self.stream.as_mut()) } {
+            libz_sys::Z_STREAM_ERROR => {
+                tracing::trace!("decompression stream encountered bad state.")
+            }
+            // Ignore discarded data error because we are raw
+            libz_sys::Z_OK | libz_sys::Z_DATA_ERROR => {
+                tracing::trace!("deallocated compression context.")
+            }
+            code => tracing::trace!("bad zlib status encountered: {}", code),
+        }
+    }
+}
+
+impl ZLibDeCompressStream {
+    /// construct new compress stream
+    pub fn new(window: WindowBit) -> Self {
+        let mut stream: Box<MaybeUninit<libz_sys::z_stream>> = Box::new(MaybeUninit::zeroed());
+        let result = unsafe {
+            libz_sys::inflateInit2_(
+                stream.as_mut_ptr(),
+                -(window as i8) as c_int,
+                ZLIB_VERSION.as_ptr() as *const c_char,
+                mem::size_of::<libz_sys::z_stream>() as c_int,
+            )
+        };
+        assert!(result == libz_sys::Z_OK, "Failed to initialize compresser.");
+        Self {
+            stream: unsafe { Box::from_raw(Box::into_raw(stream) as *mut libz_sys::z_stream) },
+        }
+    }
+
+    /// construct with custom stream
+    pub fn with(stream: Box<libz_sys::z_stream>) -> Self {
+        Self { stream }
+    }
+
+    /// decompress data
+    ///
+    /// output is overwritten and grows until all inflated data fit in, so input can be
+    /// fed in small chunks
+    pub fn de_compress(&mut self, inputs: &[&[u8]], output: &mut Vec<u8>) -> Result<(), c_int> {
+        let total_input: usize = inputs.iter().map(|i| i.len()).sum();
+        let grow = |output: &mut Vec<u8>| {
+            let size = (output.len() * 2).max(total_input * 2 + 4);
+            output.resize(size, 0);
+        };
+        let mut write_idx = 0;
+        let before = self.stream.total_out;
+        for i in inputs {
+            let mut iter_read_idx = 0;
+            loop {
+                unsafe {
+                    self.stream.next_in = i.as_ptr().add(iter_read_idx) as *mut _;
+                }
+                self.stream.avail_in = (i.len() - iter_read_idx) as c_uint;
+                if write_idx >= output.len() {
+                    grow(output);
+                }
+                let out_slice = &mut output[write_idx..];
+                self.stream.next_out = out_slice.as_mut_ptr();
+                self.stream.avail_out = out_slice.len() as c_uint;
+
+                match unsafe { libz_sys::inflate(*&mut self.stream.as_mut(), Z_NO_FLUSH) } {
+                    Z_OK | Z_BUF_ERROR => {}
+                    code => return Err(code),
+                };
+                iter_read_idx = i.len() - self.stream.avail_in as usize;
+                write_idx = (self.stream.total_out - before) as usize;
+                if self.stream.avail_in == 0 {
+                    break;
+                }
+            }
+        }
+        loop {
+            if write_idx >= output.len() {
+                grow(output);
+            }
+            let out_slice = &mut output[write_idx..];
+            self.stream.next_out = out_slice.as_mut_ptr();
+            self.stream.avail_out = out_slice.len() as c_uint;
+            match unsafe { libz_sys::inflate(*&mut self.stream.as_mut(), Z_SYNC_FLUSH) } {
+                Z_OK | Z_BUF_ERROR => {}
+                code => return Err(code),
+            }
+            write_idx = (self.stream.total_out - before) as usize;
+            if self.stream.avail_out != 0 {
+                break;
+            }
+        }
+        output.truncate(write_idx);
+        Ok(())
+    }
+
+    /// reset stream state
+    pub fn reset(&mut self) -> Result<(), c_int> {
+        let code = unsafe { libz_sys::inflateReset(self.stream.as_mut()) };
+        match code {
+            Z_OK => Ok(()),
+            code => Err(code),
+        }
+    }
+}
+
+/// zlib compress stream
+pub struct ZLibCompressStream {
+    stream: Box<libz_sys::z_stream>,
+}
+
+unsafe impl Send for ZLibCompressStream {}
+unsafe impl Sync for ZLibCompressStream {}
+
+impl Drop for ZLibCompressStream {
+    fn drop(&mut self) {
+        match unsafe { libz_sys::deflateEnd(self.stream.as_mut()) } {
+            libz_sys::Z_STREAM_ERROR => {
+                tracing::trace!("compression stream encountered bad state.")
+            }
+            // Ignore discarded data error because we are raw
+            libz_sys::Z_OK | libz_sys::Z_DATA_ERROR => {
+                tracing::trace!("deallocated compression context.")
+            }
+            code => tracing::trace!("bad zlib status encountered: {}", code),
+        }
+    }
+}
+
+impl ZLibCompressStream {
+    /// construct with window bit
+    pub fn new(window: WindowBit) -> Self {
+        let mut stream: Box<MaybeUninit<libz_sys::z_stream>> = Box::new(MaybeUninit::zeroed());
+        let result = unsafe {
+            libz_sys::deflateInit2_(
+                stream.as_mut_ptr(),
+                9,
+                libz_sys::Z_DEFLATED,
+                -(window as i8) as c_int,
+                9,
+                libz_sys::Z_DEFAULT_STRATEGY,
+                ZLIB_VERSION.as_ptr() as *const c_char,
+                mem::size_of::<libz_sys::z_stream>() as c_int,
+            )
+        };
+        assert!(result == libz_sys::Z_OK, "Failed to initialize compresser.");
+        Self {
+            stream: unsafe { Box::from_raw(Box::into_raw(stream) as *mut libz_sys::z_stream) },
+        }
+    }
+
+    /// construct with custom stream
+    pub fn with(stream: Box<libz_sys::z_stream>) -> Self {
+        Self { stream }
+    }
+
+    /// compress data
+    pub fn compress(&mut self, inputs: &[&[u8]], output: &mut Vec<u8>) -> Result<(), c_int> {
+        let total_input: usize = inputs.iter().map(|i| i.len()).sum();
+        let grow = |output: &mut Vec<u8>| {
+            let size = (output.len() * 2).max(total_input + 64);
+            output.resize(size, 0);
+        };
+        let mut write_idx = 0;
+        let before = self.stream.total_out;
+        for i in inputs.iter().filter(|i| !i.is_empty()) {
+            let mut iter_read_idx = 0;
+            loop {
+                unsafe {
+                    self.stream.next_in = i.as_ptr().add(iter_read_idx) as *mut _;
+                }
+                self.stream.avail_in = (i.len() - iter_read_idx) as c_uint;
+                if write_idx >= output.len() {
+                    grow(output);
+                }
+                let out_slice = &mut output[write_idx..];
+                self.stream.next_out = out_slice.as_mut_ptr();
+                self.stream.avail_out = out_slice.len() as c_uint;
+
+                match unsafe { libz_sys::deflate(*&mut self.stream.as_mut(), Z_NO_FLUSH) } {
+                    libz_sys::Z_OK => {}
+                    code => return Err(code),
+                };
+                iter_read_idx = i.len() - self.stream.avail_in as usize;
+                write_idx = (self.stream.total_out - before) as usize;
+                if self.stream.avail_in == 0 {
+                    break;
+                }
+            }
+        }
+        // flush until there is room left, else some output is still pending
+        loop {
+            if write_idx >= output.len() {
+                grow(output);
+            }
+            let out_slice = &mut output[write_idx..];
+            self.stream.next_out = out_slice.as_mut_ptr();
+            self.stream.avail_out = out_slice.len() as c_uint;
+            match unsafe { libz_sys::deflate(*&mut self.stream.as_mut(), Z_SYNC_FLUSH) } {
+                Z_OK | Z_BUF_ERROR => {}
+                code => return Err(code),
+            }
+            write_idx = (self.stream.total_out - before) as usize;
+            if self.stream.avail_out != 0 {
+                break;
+            }
+        }
+        output.truncate(write_idx);
+        Ok(())
+    }
+
+    /// reset stream state
+    pub fn reset(&mut self) -> Result<(), c_int> {
+        let code = unsafe { libz_sys::deflateReset(self.stream.as_mut()) };
+        match code {
+            Z_OK => Ok(()),
+            code => Err(code),
+        }
+    }
+}
+
+impl Compressor for ZLibCompressStream {
+    type Error = c_int;
+
+    fn new(window: WindowBit) -> Self {
+        Self::new(window)
+    }
+
+    fn compress(&mut self, inputs: &[&[u8]], output: &mut Vec<u8>) -> Result<(), c_int> {
+        self.compress(inputs, output)
+    }
+
+    fn reset(&mut self) -> Result<(), c_int> {
+        self.reset()
+    }
+}
+
+impl DeCompressor for ZLibDeCompressStream {
+    type Error = c_int;
+
+    fn new(window: WindowBit) -> Self {
+        Self::new(window)
+    }
+
+    fn de_compress(&mut self, inputs: &[&[u8]], output: &mut Vec<u8>) -> Result<(), c_int> {
+        self.de_compress(inputs, output)
+    }
+
+    fn reset(&mut self) -> Result<(), c_int> {
+        self.reset()
+    }
+}
@@ -0,0 +1,395 @@
+use std::{
+    io::{Read, Write},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{
+    codec::{ConnectionState, FrameCodec, FrameConfig, FrameRecv, FrameSend, Shutdown, Split},
+    errors::WsError,
+    frame::{OpCode, SimplifiedHeader},
+    protocol::{standard_handshake_resp_check, CloseCode},
+    WsMessage,
+};
+use http;
+
+use super::{
+    lock, low_level_config, negotiated, ExtReadState, ExtWriteState, ExtensionChain, SharedChain,
+};
+
+/// recv/send websocket message, payload of data message is transformed by negotiated
+/// [ExtensionChain]
+///
+/// fragmented frames are always merged, `auto_fragment_size` of config is applied
+/// after encoding, `max_frame_payload_size` of config also limits size of merged
+/// message, before and after decoding
+pub struct ExtCodec<S: Read + Write> {
+    frame: FrameCodec<S>,
+    chain: SharedChain,
+    read_state: ExtReadState,
+    write_state: ExtWriteState,
+}
+
+impl<S: Read + Write> ExtCodec<S> {
+    /// construct with negotiated extensions
+    pub fn new(stream: S, config: FrameConfig, chain: ExtensionChain) -> Self {
+        let chain = Arc::new(Mutex::new(chain));
+        Self {
+            frame: FrameCodec::new_with(stream, low_level_config(&config)),
+            read_state: ExtReadState::new(chain.clone(), &config),
+            write_state: ExtWriteState::new(chain.clone(), &config),
+            chain,
+        }
+    }
+
+    /// used for server side to construct a new server, use with
+    /// [ext_handshake_handler](crate::codec::ext_handshake_handler)
+    pub fn factory(req: http::Request<()>, stream: S) -> Result<Self, WsError> {
+        let config = FrameConfig {
+            mask_send_frame: false,
+            ..Default::default()
+        };
+        Ok(Self::new(stream, config, negotiated(&req)))
+    }
+
+    /// used for client side to construct a new client, `chain` should be the one
+    /// offered by [ClientBuilder::offer](crate::ClientBuilder::offer)
+    pub fn check_fn_with(
+        chain: ExtensionChain,
+    ) -> impl FnMut(String, http::Response<()>, S) -> Result<Self, WsError> {
+        let mut chain = Some(chain);
+        move |key, resp, stream| {
+            standard_handshake_resp_check(key.as_bytes(), &resp)?;
+            let mut chain = chain.take().ok_or_else(|| {
+                WsError::HandShakeFailed("extension chain has been consumed".into())
+            })?;
+            chain.configure(&resp)?;
+            Ok(Self::new(stream, Default::default(), chain))
+        }
+    }
+
+    /// get mutable underlying stream
+    pub fn stream_mut(&mut self) -> &mut S {
+        self.frame.stream_mut()
+    }
+
+    /// names of negotiated extensions
+    pub fn extensions(&self) -> Vec<String> {
+        lock(&self.chain).names()
+    }
+
+    /// receive a message, see [FrameCodec::receive]
+    pub fn receive(&mut self) -> Result<(SimplifiedHeader, &[u8]), WsError> {
+        loop {
+            let (header, data) = self.frame.receive()?;
+            if let Some((header, is_control)) = self.read_state.feed(header, data)? {
+                return Ok((header, self.read_state.payload(is_control)));
+            }
+        }
+    }
+
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive()?;
+        WsMessage::from_header(&header, data)
+    }
+
+    /// current state of close handshake
+    pub fn state(&self) -> ConnectionState {
+        self.frame.state()
+    }
+
+    /// send payload, data message is encoded by extensions
+    pub fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        for frame in self.write_state.frames(code, payload)? {
+            self.frame.send_owned_frame(frame)?;
+        }
+        Ok(())
+    }
+
+    /// send owned message
+    pub fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload())
+    }
+
+    /// helper function to send text message
+    pub fn text(&mut self, text: &str) -> Result<(), WsError> {
+        self.send(OpCode::Text, text.as_bytes())
+    }
+
+    /// helper function to send binary message
+    pub fn binary(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Binary, data)
+    }
+
+    /// helper function to send ping message
+    pub fn ping(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Ping, data)
+    }
+
+    /// helper function to send pong message
+    pub fn pong(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Pong, data)
+    }
+
+    /// helper method to send close message
+    pub fn close(&mut self, code: impl Into<CloseCode>, msg: &[u8]) -> Result<(), WsError> {
+        let mut data = u16::from(code.into()).to_be_bytes().to_vec();
+        data.extend_from_slice(msg);
+        self.send(OpCode::Close, &data)
+    }
+
+    /// flush stream to ensure all data are send
+    pub fn flush(&mut self) -> Result<(), WsError> {
+        self.frame.flush()
+    }
+}
+
+impl<S: Read + Write + Shutdown> ExtCodec<S> {
+    /// send close frame if not sent, then wait for close frame from peer until timeout
+    /// and shutdown underlying stream, return true if close frame from peer is received,
+    /// see [FrameCodec::graceful_close]
+    pub fn graceful_close(
+        &mut self,
+        code: impl Into<CloseCode>,
+        reason: &[u8],
+        timeout: Duration,
+    ) -> Result<bool, WsError> {
+        self.frame.graceful_close(code, reason, timeout)
+    }
+}
+
+impl<R, W, S> ExtCodec<S>
+where
+    R: Read,
+    W: Write,
+    S: Read + Write + Split<R = R, W = W>,
+{
+    /// split codec to recv and send parts, negotiated extensions are shared by both
+    /// parts
+    pub fn split(self) -> (ExtRecv<R>, ExtSend<W>) {
+        let (read, write) = self.frame.split();
+        (
+            ExtRecv {
+                frame: read,
+                read_state: self.read_state,
+            },
+            ExtSend {
+                frame: write,
+                write_state: self.write_state,
+            },
+        )
+    }
+}
+
+/// recv part of [ExtCodec]
+pub struct ExtRecv<S: Read> {
+    frame: FrameRecv<S>,
+    read_state: ExtReadState,
+}
+
+impl<S: Read> ExtRecv<S> {
+    /// receive a message
+    pub fn receive(&mut self) -> Result<(SimplifiedHeader, &[u8]), WsError> {
+        loop {
+            let (header, data) = self.frame.receive()?;
+            if let Some((header, is_control)) = self.read_state.feed(header, data)? {
+                return Ok((header, self.read_state.payload(is_control)));
+            }
+        }
+    }
+
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive()?;
+        WsMessage::from_header(&header, data)
+    }
+}
+
+/// send part of [ExtCodec]
+pub struct ExtSend<S: Write> {
+    frame: FrameSend<S>,
+    write_state: ExtWriteState,
+}
+
+impl<S: Write> ExtSend<S> {
+    /// send payload, data message is encoded by extensions
+    pub fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        for frame in self.write_state.frames(code, payload)? {
+            self.frame.send_owned_frame(frame)?;
+        }
+        Ok(())
+    }
+
+    /// send owned message
+    pub fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload())
+    }
+
+    /// helper function to send text message
+    pub fn text(&mut self, text: &str) -> Result<(), WsError> {
+        self.send(OpCode::Text, text.as_bytes())
+    }
+
+    /// helper function to send binary message
+    pub fn binary(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Binary, data)
+    }
+
+    /// helper function to send ping message
+    pub fn ping(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Ping, data)
+    }
+
+    /// helper function to send pong message
+    pub fn pong(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Pong, data)
+    }
+
+    /// helper method to send close message
+    pub fn close(&mut self, code: impl Into<CloseCode>, msg: &[u8]) -> Result<(), WsError> {
+        let mut data = u16::from(code.into()).to_be_bytes().to_vec();
+        data.extend_from_slice(msg);
+        self.send(OpCode::Close, &data)
+    }
+
+    /// flush stream to ensure all data are send
+    pub fn flush(&mut self) -> Result<(), WsError> {
+        self.frame.flush()
+    }
+}
+
+#[cfg(test)]
+struct Checksum;
+
+#[cfg(test)]
+impl super::Extension for Checksum {
+    fn name(&self) -> &str {
+        "x-checksum"
+    }
+
+    fn rsv_bits(&self) -> u8 {
+        super::RSV2
+    }
+
+    fn offer(&self) -> Vec<super::ExtensionParams> {
+        vec![super::ExtensionParams::new("x-checksum")]
+    }
+
+    fn accept(
+        &mut self,
+        offers: &[super::ExtensionParams],
+    ) -> Result<Option<super::ExtensionParams>, WsError> {
+        Ok(offers.first().cloned())
+    }
+
+    fn configure(&mut self, _params: &super::ExtensionParams) -> Result<(), WsError> {
+        Ok(())
+    }
+
+    fn encode(&mut self, _code: OpCode, payload: &mut Vec<u8>) -> Result<u8, WsError> {
+        let sum = payload.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        payload.push(sum);
+        Ok(super::RSV2)
+    }
+
+    fn decode(&mut self, _code: OpCode, rsv: u8, payload: &mut Vec<u8>) -> Result<(), WsError> {
+        let sum = payload.pop();
+        let expect = payload.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        if rsv == 0 || sum != Some(expect) {
+            return Err(WsError::HandShakeFailed("checksum mismatch".into()));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_ext_codec() {
+    use crate::codec::Duplex;
+
+    let chain = || {
+        let chain = ExtensionChain::new();
+        #[cfg(any(
+            feature = "deflate",
+            feature = "deflate_ng",
+            feature = "deflate_static",
+            feature = "deflate_rust"
+        ))]
+        let chain = chain.with(crate::codec::PermessageDeflate::client(Default::default()));
+        chain.with(Checksum)
+    };
+    let mut client_chain = chain();
+    let mut req = http::Request::builder();
+    for offer in client_chain.offers() {
+        req = req.header("sec-websocket-extensions", offer);
+    }
+    let req = req
+        .header("sec-websocket-extensions", "x-unknown")
+        .body(())
+        .unwrap();
+
+    let mut server_chain = chain();
+    let accepted = server_chain.accept(&req).unwrap().unwrap();
+    assert_eq!(server_chain.names(), client_chain.names());
+    let resp = http::Response::builder()
+        .header("sec-websocket-extensions", accepted)
+        .body(())
+        .unwrap();
+    client_chain.configure(&resp).unwrap();
+
+    let stream = Duplex::new(vec![]);
+    let config = FrameConfig {
+        auto_fragment_size: 4,
+        ..Default::default()
+    };
+    let mut client = ExtCodec::new(stream, config, client_chain);
+    let text = "hello hello hello hello";
+    client.send(OpCode::Text, text.as_bytes()).unwrap();
+    client.send(OpCode::Ping, b"ping").unwrap();
+    client.send(OpCode::Binary, &[]).unwrap();
+
+    let stream = Duplex::new(std::mem::take(&mut client.stream_mut().output));
+    let mut server = ExtCodec::new(stream, Default::default(), server_chain);
+    let (header, data) = server.receive().unwrap();
+    assert_eq!(header.code, OpCode::Text);
+    assert!(!header.rsv1 && !header.rsv2);
+    assert_eq!(data, text.as_bytes());
+    let (header, data) = server.receive().unwrap();
+    assert_eq!(header.code, OpCode::Ping);
+    assert_eq!(data, b"ping");
+    let (header, data) = server.receive().unwrap();
+    assert_eq!(header.code, OpCode::Binary);
+    assert!(data.is_empty());
+}
+
+#[test]
+fn test_ext_codec_max_payload() {
+    use crate::{codec::Duplex, errors::ProtocolError, protocol::CloseCode};
+
+    let stream = Duplex::new(vec![]);
+    let config = FrameConfig {
+        auto_fragment_size: 4,
+        ..Default::default()
+    };
+    let mut client = ExtCodec::new(stream, config, ExtensionChain::new());
+    client.text("hello").unwrap();
+    client.text("hello hello").unwrap();
+
+    let stream = Duplex::new(std::mem::take(&mut client.stream_mut().output));
+    let config = FrameConfig {
+        max_frame_payload_size: 8,
+        ..Default::default()
+    };
+    let mut server = ExtCodec::new(stream, config, ExtensionChain::new());
+    assert_eq!(
+        server.receive_message().unwrap(),
+        WsMessage::Text("hello".into())
+    );
+    assert!(matches!(
+        server.receive_message(),
+        Err(WsError::ProtocolError {
+            close_code: CloseCode::MessageTooBig,
+            error: ProtocolError::PayloadTooLarge(8),
+        })
+    ));
+}
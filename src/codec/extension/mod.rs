@@ -0,0 +1,581 @@
+use http;
+use std::{
+    fmt,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use rand::random;
+
+use crate::{
+    errors::{ProtocolError, WsError},
+    frame::{OpCode, OwnedFrame, SimplifiedHeader},
+    protocol::CloseCode,
+};
+
+use super::{default_handshake_handler, FrameConfig, ValidateUtf8Policy};
+
+#[cfg(feature = "sync")]
+mod blocking;
+
+#[cfg(feature = "sync")]
+pub use blocking::*;
+
+#[cfg(feature = "async")]
+mod non_blocking;
+
+#[cfg(feature = "async")]
+pub use non_blocking::*;
+
+/// rsv1 bit, in the same position as in first byte of frame header
+pub const RSV1: u8 = 0b0100_0000;
+/// rsv2 bit, in the same position as in first byte of frame header
+pub const RSV2: u8 = 0b0010_0000;
+/// rsv3 bit, in the same position as in first byte of frame header
+pub const RSV3: u8 = 0b0001_0000;
+
+fn rsv_bits(header: &SimplifiedHeader) -> u8 {
+    let mut bits = 0;
+    if header.rsv1 {
+        bits |= RSV1;
+    }
+    if header.rsv2 {
+        bits |= RSV2;
+    }
+    if header.rsv3 {
+        bits |= RSV3;
+    }
+    bits
+}
+
+fn invalid_rsv(bits: u8) -> WsError {
+    WsError::ProtocolError {
+        close_code: CloseCode::ProtocolError,
+        error: ProtocolError::InvalidLeadingBits(bits >> 4),
+    }
+}
+
+/// one extension of `Sec-WebSocket-Extensions` header, e.g.
+/// `permessage-deflate; client_max_window_bits`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtensionParams {
+    /// extension token
+    pub name: String,
+    /// params in order, value of quoted param is unquoted
+    pub params: Vec<(String, Option<String>)>,
+}
+
+impl ExtensionParams {
+    /// construct without params
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            params: vec![],
+        }
+    }
+
+    /// add a param without value
+    pub fn flag(mut self, key: impl Into<String>) -> Self {
+        self.params.push((key.into(), None));
+        self
+    }
+
+    /// add a param with value
+    pub fn value(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.params.push((key.into(), Some(value.to_string())));
+        self
+    }
+
+    /// check if extension token matches `name`, case-insensitive
+    pub fn is(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+    }
+
+    /// get first param of `key`, case-insensitive, inner option is value of param
+    pub fn get(&self, key: &str) -> Option<Option<&str>> {
+        self.params
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_deref())
+    }
+
+    /// parse value of one `Sec-WebSocket-Extensions` header
+    pub fn parse(header: &str) -> Result<Vec<Self>, WsError> {
+        let invalid = || WsError::HandShakeFailed(format!("invalid extension header `{header}`"));
+        let mut exts = vec![];
+        for item in header.split(',') {
+            let mut parts = item.split(';').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            if name.is_empty() {
+                if item.trim().is_empty() {
+                    continue;
+                }
+                return Err(invalid());
+            }
+            let mut ext = Self::new(name);
+            for param in parts {
+                let param = match param.split_once('=') {
+                    Some((k, v)) => (k.trim(), Some(v.trim().trim_matches('"'))),
+                    None => (param, None),
+                };
+                if param.0.is_empty() || param.1 == Some("") {
+                    return Err(invalid());
+                }
+                ext.params
+                    .push((param.0.to_string(), param.1.map(str::to_string)));
+            }
+            exts.push(ext);
+        }
+        Ok(exts)
+    }
+
+    /// parse all `Sec-WebSocket-Extensions` headers
+    pub fn parse_headers(headers: &http::HeaderMap) -> Result<Vec<Self>, WsError> {
+        let mut exts = vec![];
+        for value in headers.get_all("sec-websocket-extensions") {
+            let value = value
+                .to_str()
+                .map_err(|e| WsError::HandShakeFailed(e.to_string()))?;
+            exts.extend(Self::parse(value)?);
+        }
+        Ok(exts)
+    }
+}
+
+impl fmt::Display for ExtensionParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.name)?;
+        for (k, v) in self.params.iter() {
+            match v {
+                Some(v) => write!(f, "; {k}={v}")?,
+                None => write!(f, "; {k}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// websocket extension (RFC 6455 section 9), negotiated in handshake, then run on
+/// payload of data messages by [ExtCodec]
+///
+/// one instance is used by only one connection, control frames are not passed to
+/// extension
+pub trait Extension: Send {
+    /// extension token in `Sec-WebSocket-Extensions` header
+    fn name(&self) -> &str;
+
+    /// rsv bits claimed by this extension, see [RSV1], [RSV2] and [RSV3], extensions
+    /// claiming same bit can not be negotiated together
+    fn rsv_bits(&self) -> u8 {
+        0
+    }
+
+    /// client side, params offered in handshake request, in order of preference
+    fn offer(&self) -> Vec<ExtensionParams>;
+
+    /// server side, choose one of offers of this extension, return params of
+    /// response, `None` to decline this extension
+    fn accept(&mut self, offers: &[ExtensionParams]) -> Result<Option<ExtensionParams>, WsError>;
+
+    /// client side, apply params accepted by server, return error to fail handshake
+    fn configure(&mut self, params: &ExtensionParams) -> Result<(), WsError>;
+
+    /// transform payload of outgoing data message, return rsv bits set on first frame
+    fn encode(&mut self, code: OpCode, payload: &mut Vec<u8>) -> Result<u8, WsError>;
+
+    /// reverse transform of incoming data message, `rsv` is rsv bits of first frame
+    /// claimed by this extension
+    fn decode(&mut self, code: OpCode, rsv: u8, payload: &mut Vec<u8>) -> Result<(), WsError>;
+}
+
+/// ordered extensions of a connection
+///
+/// before handshake, it contains extensions to offer or accept, after handshake only
+/// negotiated extensions are kept, outgoing message is encoded in chain order and
+/// incoming message is decoded in reverse order
+#[derive(Default)]
+pub struct ExtensionChain {
+    exts: Vec<Box<dyn Extension>>,
+}
+
+impl ExtensionChain {
+    /// construct empty chain
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// append extension
+    pub fn with(mut self, ext: impl Extension + 'static) -> Self {
+        self.exts.push(Box::new(ext));
+        self
+    }
+
+    /// append boxed extension
+    pub fn push(&mut self, ext: Box<dyn Extension>) {
+        self.exts.push(ext);
+    }
+
+    /// names of extensions in chain
+    pub fn names(&self) -> Vec<String> {
+        self.exts.iter().map(|ext| ext.name().to_string()).collect()
+    }
+
+    /// check if no extension is in chain
+    pub fn is_empty(&self) -> bool {
+        self.exts.is_empty()
+    }
+
+    /// client side, `Sec-WebSocket-Extensions` header values of offers, one for each
+    /// extension, see [ClientBuilder::offer](crate::ClientBuilder::offer)
+    pub fn offers(&self) -> Vec<String> {
+        self.exts
+            .iter()
+            .map(|ext| {
+                ext.offer()
+                    .iter()
+                    .map(|params| params.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .filter(|offer| !offer.is_empty())
+            .collect()
+    }
+
+    /// server side, negotiate with offers in request, declined extensions are removed,
+    /// return value of `Sec-WebSocket-Extensions` response header
+    pub fn accept(&mut self, req: &http::Request<()>) -> Result<Option<String>, WsError> {
+        let offers = ExtensionParams::parse_headers(req.headers())?;
+        let mut used = 0;
+        let mut accepted = vec![];
+        let mut responses = vec![];
+        for mut ext in std::mem::take(&mut self.exts) {
+            let matched: Vec<ExtensionParams> = offers
+                .iter()
+                .filter(|offer| offer.is(ext.name()))
+                .cloned()
+                .collect();
+            if matched.is_empty() || used & ext.rsv_bits() != 0 {
+                continue;
+            }
+            if let Some(params) = ext.accept(&matched)? {
+                used |= ext.rsv_bits();
+                responses.push(params.to_string());
+                accepted.push(ext);
+            }
+        }
+        self.exts = accepted;
+        Ok((!responses.is_empty()).then(|| responses.join(", ")))
+    }
+
+    /// client side, configure extensions accepted in server response, extensions not
+    /// accepted are removed
+    pub fn configure(&mut self, resp: &http::Response<()>) -> Result<(), WsError> {
+        let responses = ExtensionParams::parse_headers(resp.headers())?;
+        let mut used = 0;
+        let mut offered = std::mem::take(&mut self.exts);
+        for params in responses {
+            let idx = offered
+                .iter()
+                .position(|ext| params.is(ext.name()))
+                .ok_or_else(|| {
+                    WsError::HandShakeFailed(format!("extension `{}` is not offered", params.name))
+                })?;
+            let mut ext = offered.remove(idx);
+            if used & ext.rsv_bits() != 0 {
+                return Err(WsError::HandShakeFailed(format!(
+                    "rsv bits of extension `{}` conflict",
+                    params.name
+                )));
+            }
+            used |= ext.rsv_bits();
+            ext.configure(&params)?;
+            self.exts.push(ext);
+        }
+        Ok(())
+    }
+
+    fn encode(&mut self, code: OpCode, payload: &mut Vec<u8>) -> Result<u8, WsError> {
+        let mut rsv = 0;
+        for ext in self.exts.iter_mut() {
+            rsv |= ext.encode(code, payload)? & ext.rsv_bits();
+        }
+        Ok(rsv)
+    }
+
+    fn decode(&mut self, code: OpCode, rsv: u8, payload: &mut Vec<u8>) -> Result<(), WsError> {
+        let claimed = self.exts.iter().fold(0, |bits, ext| bits | ext.rsv_bits());
+        if rsv & !claimed != 0 {
+            return Err(invalid_rsv(rsv));
+        }
+        for ext in self.exts.iter_mut().rev() {
+            ext.decode(code, rsv & ext.rsv_bits(), payload)?;
+        }
+        Ok(())
+    }
+}
+
+/// negotiated chain passed from handshake handler to codec factory
+#[derive(Clone)]
+struct Negotiated(Arc<Mutex<Option<ExtensionChain>>>);
+
+/// server side handshake handler, negotiate extensions of chain created by `make`
+/// for each connection, negotiated chain is passed to [ExtCodec::factory] by request
+#[allow(clippy::type_complexity, clippy::result_large_err)]
+pub fn ext_handshake_handler<F>(
+    mut make: F,
+) -> impl FnMut(
+    http::Request<()>,
+)
+    -> Result<(http::Request<()>, http::Response<String>), (http::Response<String>, WsError)>
+where
+    F: FnMut() -> ExtensionChain,
+{
+    move |req| {
+        let (mut req, mut resp) = default_handshake_handler(req)?;
+        let mut chain = make();
+        let header = chain.accept(&req).and_then(|value| {
+            value
+                .map(|value| http::HeaderValue::from_str(&value))
+                .transpose()
+                .map_err(|e| WsError::HandShakeFailed(e.to_string()))
+        });
+        match header {
+            Ok(Some(value)) => {
+                resp.headers_mut().insert("sec-websocket-extensions", value);
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let resp = http::Response::builder()
+                    .version(resp.version())
+                    .status(http::StatusCode::BAD_REQUEST)
+                    .header("Content-Type", "text/html")
+                    .body(e.to_string())
+                    .unwrap();
+                return Err((resp, e));
+            }
+        }
+        req.extensions_mut()
+            .insert(Negotiated(Arc::new(Mutex::new(Some(chain)))));
+        Ok((req, resp))
+    }
+}
+
+/// take chain negotiated by [ext_handshake_handler], empty chain if not found
+fn negotiated(req: &http::Request<()>) -> ExtensionChain {
+    req.extensions()
+        .get::<Negotiated>()
+        .and_then(|n| n.0.lock().ok()?.take())
+        .unwrap_or_default()
+}
+
+/// chain shared by codec and its split parts
+type SharedChain = Arc<Mutex<ExtensionChain>>;
+
+fn lock(chain: &SharedChain) -> MutexGuard<'_, ExtensionChain> {
+    chain.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// frames are merged and checked by extension codec itself
+fn low_level_config(config: &FrameConfig) -> FrameConfig {
+    FrameConfig {
+        check_rsv: false,
+        auto_fragment_size: 0,
+        merge_frame: false,
+        validate_utf8: ValidateUtf8Policy::Off,
+        ..config.clone()
+    }
+}
+
+/// merge frames into message and decode it by extension chain
+pub(crate) struct ExtReadState {
+    chain: SharedChain,
+    validate_utf8: ValidateUtf8Policy,
+    /// limit of merged message size, before and after decoding, 0 means no limit
+    max_payload_size: usize,
+    fragmented: Option<(OpCode, u8)>,
+    data: Vec<u8>,
+    control: Vec<u8>,
+}
+
+impl ExtReadState {
+    pub(crate) fn new(chain: SharedChain, config: &FrameConfig) -> Self {
+        Self {
+            chain,
+            validate_utf8: config.validate_utf8.clone(),
+            max_payload_size: config.max_frame_payload_size,
+            fragmented: None,
+            data: vec![],
+            control: vec![],
+        }
+    }
+
+    /// feed a frame, return header of complete message and whether its payload is
+    /// in control frame buffer
+    pub(crate) fn feed(
+        &mut self,
+        header: SimplifiedHeader,
+        payload: &[u8],
+    ) -> Result<Option<(SimplifiedHeader, bool)>, WsError> {
+        let rsv = rsv_bits(&header);
+        match header.code {
+            OpCode::Text | OpCode::Binary => {
+                if self.fragmented.is_some() {
+                    return Err(WsError::ProtocolError {
+                        close_code: CloseCode::ProtocolError,
+                        error: ProtocolError::NotContinueFrameAfterFragmented,
+                    });
+                }
+                self.data.clear();
+                self.data.extend_from_slice(payload);
+                self.check_size()?;
+                if !header.fin {
+                    self.fragmented = Some((header.code, rsv));
+                    return Ok(None);
+                }
+                self.finish(header.code, rsv)
+            }
+            OpCode::Continue => {
+                let Some((code, first_rsv)) = self.fragmented else {
+                    return Err(WsError::ProtocolError {
+                        close_code: CloseCode::ProtocolError,
+                        error: ProtocolError::MissInitialFragmentedFrame,
+                    });
+                };
+                if rsv != 0 {
+                    return Err(invalid_rsv(rsv));
+                }
+                self.data.extend_from_slice(payload);
+                self.check_size()?;
+                if !header.fin {
+                    return Ok(None);
+                }
+                self.fragmented = None;
+                self.finish(code, first_rsv)
+            }
+            _ => {
+                if rsv != 0 {
+                    return Err(invalid_rsv(rsv));
+                }
+                self.control.clear();
+                self.control.extend_from_slice(payload);
+                Ok(Some((header, true)))
+            }
+        }
+    }
+
+    fn finish(
+        &mut self,
+        code: OpCode,
+        rsv: u8,
+    ) -> Result<Option<(SimplifiedHeader, bool)>, WsError> {
+        lock(&self.chain).decode(code, rsv, &mut self.data)?;
+        self.check_size()?;
+        if code == OpCode::Text
+            && self.validate_utf8.should_check()
+            && simdutf8::basic::from_utf8(&self.data).is_err()
+        {
+            return Err(WsError::ProtocolError {
+                close_code: CloseCode::InvalidPayload,
+                error: ProtocolError::InvalidUtf8,
+            });
+        }
+        let header = SimplifiedHeader {
+            fin: true,
+            rsv1: false,
+            rsv2: false,
+            rsv3: false,
+            code,
+        };
+        Ok(Some((header, false)))
+    }
+
+    fn check_size(&mut self) -> Result<(), WsError> {
+        if self.max_payload_size > 0 && self.data.len() > self.max_payload_size {
+            self.fragmented = None;
+            self.data.clear();
+            return Err(WsError::ProtocolError {
+                close_code: CloseCode::MessageTooBig,
+                error: ProtocolError::PayloadTooLarge(self.max_payload_size),
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn payload(&self, is_control: bool) -> &[u8] {
+        if is_control {
+            &self.control
+        } else {
+            &self.data
+        }
+    }
+}
+
+/// encode message by extension chain and fragment it into frames
+pub(crate) struct ExtWriteState {
+    chain: SharedChain,
+    mask_send_frame: bool,
+    fragment_size: usize,
+    buf: Vec<u8>,
+}
+
+impl ExtWriteState {
+    pub(crate) fn new(chain: SharedChain, config: &FrameConfig) -> Self {
+        Self {
+            chain,
+            mask_send_frame: config.mask_send_frame,
+            fragment_size: config.auto_fragment_size,
+            buf: vec![],
+        }
+    }
+
+    pub(crate) fn frames(
+        &mut self,
+        code: OpCode,
+        payload: &[u8],
+    ) -> Result<Vec<OwnedFrame>, WsError> {
+        let mask_send = self.mask_send_frame;
+        let mask = || mask_send.then(random::<[u8; 4]>);
+        if !code.is_data() {
+            return Ok(vec![OwnedFrame::new(code, mask(), payload)]);
+        }
+        self.buf.clear();
+        self.buf.extend_from_slice(payload);
+        let rsv = lock(&self.chain).encode(code, &mut self.buf)?;
+        let chunks: Vec<&[u8]> = if self.fragment_size == 0 || self.buf.is_empty() {
+            vec![&self.buf]
+        } else {
+            self.buf.chunks(self.fragment_size).collect()
+        };
+        let total = chunks.len();
+        let frames = chunks
+            .into_iter()
+            .enumerate()
+            .map(|(idx, chunk)| {
+                let first = idx == 0;
+                let code = if first { code } else { OpCode::Continue };
+                let mut frame = OwnedFrame::new(code, mask(), chunk);
+                let header = frame.header_mut();
+                header.set_fin(idx + 1 == total);
+                if first {
+                    header.set_rsv1(rsv & RSV1 != 0);
+                    header.set_rsv2(rsv & RSV2 != 0);
+                    header.set_rsv3(rsv & RSV3 != 0);
+                }
+                frame
+            })
+            .collect();
+        Ok(frames)
+    }
+}
+
+#[test]
+fn test_extension_params() {
+    let header = r#"permessage-deflate; client_max_window_bits, x-checksum; algo="crc32""#;
+    let exts = ExtensionParams::parse(header).unwrap();
+    assert_eq!(exts.len(), 2);
+    assert!(exts[0].is("Permessage-Deflate"));
+    assert_eq!(exts[0].get("client_max_window_bits"), Some(None));
+    assert_eq!(exts[1].get("ALGO"), Some(Some("crc32")));
+    assert_eq!(exts[1].to_string(), "x-checksum; algo=crc32");
+    assert!(ExtensionParams::parse("; foo").is_err());
+    assert!(ExtensionParams::parse("foo; bar=").is_err());
+}
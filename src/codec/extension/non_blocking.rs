@@ -0,0 +1,261 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use http;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use crate::{
+    codec::{AsyncFrameCodec, AsyncFrameRecv, AsyncFrameSend, ConnectionState, FrameConfig, Split},
+    errors::WsError,
+    frame::{OpCode, SimplifiedHeader},
+    protocol::{standard_handshake_resp_check, CloseCode},
+    WsMessage,
+};
+
+use super::{
+    lock, low_level_config, negotiated, ExtReadState, ExtWriteState, ExtensionChain, SharedChain,
+};
+
+/// recv/send websocket message, payload of data message is transformed by negotiated
+/// [ExtensionChain]
+///
+/// fragmented frames are always merged, `auto_fragment_size` of config is applied
+/// after encoding, `max_frame_payload_size` of config also limits size of merged
+/// message, before and after decoding
+pub struct AsyncExtCodec<S: AsyncRead + AsyncWrite> {
+    frame: AsyncFrameCodec<S>,
+    chain: SharedChain,
+    read_state: ExtReadState,
+    write_state: ExtWriteState,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncExtCodec<S> {
+    /// construct with negotiated extensions
+    pub fn new(stream: S, config: FrameConfig, chain: ExtensionChain) -> Self {
+        let chain = Arc::new(Mutex::new(chain));
+        Self {
+            frame: AsyncFrameCodec::new_with(stream, low_level_config(&config)),
+            read_state: ExtReadState::new(chain.clone(), &config),
+            write_state: ExtWriteState::new(chain.clone(), &config),
+            chain,
+        }
+    }
+
+    /// used for server side to construct a new server, use with
+    /// [ext_handshake_handler](crate::codec::ext_handshake_handler)
+    pub fn factory(req: http::Request<()>, stream: S) -> Result<Self, WsError> {
+        let config = FrameConfig {
+            mask_send_frame: false,
+            ..Default::default()
+        };
+        Ok(Self::new(stream, config, negotiated(&req)))
+    }
+
+    /// used for client side to construct a new client, `chain` should be the one
+    /// offered by [ClientBuilder::offer](crate::ClientBuilder::offer)
+    pub fn check_fn_with(
+        chain: ExtensionChain,
+    ) -> impl FnMut(String, http::Response<()>, S) -> Result<Self, WsError> {
+        let mut chain = Some(chain);
+        move |key, resp, stream| {
+            standard_handshake_resp_check(key.as_bytes(), &resp)?;
+            let mut chain = chain.take().ok_or_else(|| {
+                WsError::HandShakeFailed("extension chain has been consumed".into())
+            })?;
+            chain.configure(&resp)?;
+            Ok(Self::new(stream, Default::default(), chain))
+        }
+    }
+
+    /// get mutable underlying stream
+    pub fn stream_mut(&mut self) -> &mut S {
+        self.frame.stream_mut()
+    }
+
+    /// names of negotiated extensions
+    pub fn extensions(&self) -> Vec<String> {
+        lock(&self.chain).names()
+    }
+
+    /// receive a message, see [AsyncFrameCodec::receive]
+    pub async fn receive(&mut self) -> Result<(SimplifiedHeader, &[u8]), WsError> {
+        loop {
+            let (header, data) = self.frame.receive().await?;
+            if let Some((header, is_control)) = self.read_state.feed(header, data)? {
+                return Ok((header, self.read_state.payload(is_control)));
+            }
+        }
+    }
+
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub async fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive().await?;
+        WsMessage::from_header(&header, data)
+    }
+
+    /// current state of close handshake
+    pub fn state(&self) -> ConnectionState {
+        self.frame.state()
+    }
+
+    /// send payload, data message is encoded by extensions
+    pub async fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        for frame in self.write_state.frames(code, payload)? {
+            self.frame.send_owned_frame(frame).await?;
+        }
+        Ok(())
+    }
+
+    /// send owned message
+    pub async fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload()).await
+    }
+
+    /// helper function to send text message
+    pub async fn text(&mut self, text: &str) -> Result<(), WsError> {
+        self.send(OpCode::Text, text.as_bytes()).await
+    }
+
+    /// helper function to send binary message
+    pub async fn binary(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Binary, data).await
+    }
+
+    /// helper function to send ping message
+    pub async fn ping(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Ping, data).await
+    }
+
+    /// helper function to send pong message
+    pub async fn pong(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Pong, data).await
+    }
+
+    /// helper method to send close message
+    pub async fn close(&mut self, code: impl Into<CloseCode>, msg: &[u8]) -> Result<(), WsError> {
+        let mut data = u16::from(code.into()).to_be_bytes().to_vec();
+        data.extend_from_slice(msg);
+        self.send(OpCode::Close, &data).await
+    }
+
+    /// flush stream to ensure all data are send
+    pub async fn flush(&mut self) -> Result<(), WsError> {
+        self.frame.flush().await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncExtCodec<S> {
+    /// send close frame if not sent, then wait for close frame from peer until timeout
+    /// and shutdown underlying stream, return true if close frame from peer is received,
+    /// see [AsyncFrameCodec::graceful_close]
+    pub async fn graceful_close(
+        &mut self,
+        code: impl Into<CloseCode>,
+        reason: &[u8],
+        timeout: Duration,
+    ) -> Result<bool, WsError> {
+        self.frame.graceful_close(code, reason, timeout).await
+    }
+}
+
+impl<R, W, S> AsyncExtCodec<S>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    S: AsyncRead + AsyncWrite + Unpin + Split<R = R, W = W>,
+{
+    /// split codec to recv and send parts, negotiated extensions are shared by both
+    /// parts
+    pub fn split(self) -> (AsyncExtRecv<R>, AsyncExtSend<W>) {
+        let (read, write) = self.frame.split();
+        (
+            AsyncExtRecv {
+                frame: read,
+                read_state: self.read_state,
+            },
+            AsyncExtSend {
+                frame: write,
+                write_state: self.write_state,
+            },
+        )
+    }
+}
+
+/// recv part of [AsyncExtCodec]
+pub struct AsyncExtRecv<S: AsyncRead> {
+    frame: AsyncFrameRecv<S>,
+    read_state: ExtReadState,
+}
+
+impl<S: AsyncRead + Unpin> AsyncExtRecv<S> {
+    /// receive a message
+    pub async fn receive(&mut self) -> Result<(SimplifiedHeader, &[u8]), WsError> {
+        loop {
+            let (header, data) = self.frame.receive().await?;
+            if let Some((header, is_control)) = self.read_state.feed(header, data)? {
+                return Ok((header, self.read_state.payload(is_control)));
+            }
+        }
+    }
+
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub async fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive().await?;
+        WsMessage::from_header(&header, data)
+    }
+}
+
+/// send part of [AsyncExtCodec]
+pub struct AsyncExtSend<S: AsyncWrite> {
+    frame: AsyncFrameSend<S>,
+    write_state: ExtWriteState,
+}
+
+impl<S: AsyncWrite + Unpin> AsyncExtSend<S> {
+    /// send payload, data message is encoded by extensions
+    pub async fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        for frame in self.write_state.frames(code, payload)? {
+            self.frame.send_owned_frame(frame).await?;
+        }
+        Ok(())
+    }
+
+    /// send owned message
+    pub async fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload()).await
+    }
+
+    /// helper function to send text message
+    pub async fn text(&mut self, text: &str) -> Result<(), WsError> {
+        self.send(OpCode::Text, text.as_bytes()).await
+    }
+
+    /// helper function to send binary message
+    pub async fn binary(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Binary, data).await
+    }
+
+    /// helper function to send ping message
+    pub async fn ping(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Ping, data).await
+    }
+
+    /// helper function to send pong message
+    pub async fn pong(&mut self, data: &[u8]) -> Result<(), WsError> {
+        self.send(OpCode::Pong, data).await
+    }
+
+    /// helper method to send close message
+    pub async fn close(&mut self, code: impl Into<CloseCode>, msg: &[u8]) -> Result<(), WsError> {
+        let mut data = u16::from(code.into()).to_be_bytes().to_vec();
+        data.extend_from_slice(msg);
+        self.send(OpCode::Close, &data).await
+    }
+
+    /// flush stream to ensure all data are send
+    pub async fn flush(&mut self) -> Result<(), WsError> {
+        self.frame.flush().await
+    }
+}
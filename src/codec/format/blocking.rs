@@ -0,0 +1,381 @@
+use std::{
+    io::{Read, Write},
+    marker::PhantomData,
+};
+
+use super::{Decode, Encode, Format};
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static",
+    feature = "deflate_rust"
+))]
+use crate::codec::{DeflateCodec, DeflateRecv, DeflateSend};
+use crate::{
+    codec::{FrameCodec, FrameRecv, FrameSend, Split},
+    errors::{SerdeError, WsError},
+    frame::OpCode,
+    protocol::CloseCode,
+};
+
+macro_rules! impl_recv {
+    () => {
+        /// receive a text or binary message and deserialize it from codec buffer
+        /// without copy, return None if a control frame is received
+        ///
+        /// close frame is returned as [WsError::ConnectionClosed]
+        pub fn receive_borrowed<'a, B>(&'a mut self) -> Result<Option<B>, SerdeError>
+        where
+            F: Decode<'a, B>,
+        {
+            self.send_pong()?;
+            let (header, data) = self.codec.receive()?;
+            match header.code {
+                OpCode::Text | OpCode::Binary => F::decode(data).map(Some),
+                OpCode::Close => Err(WsError::ConnectionClosed.into()),
+                OpCode::Ping => {
+                    self.pong = Some(data.to_vec());
+                    Ok(None)
+                }
+                _ => Ok(None),
+            }
+        }
+
+        /// receive and deserialize next text or binary message, control frames
+        /// are skipped
+        pub fn receive(&mut self) -> Result<T, SerdeError>
+        where
+            F: for<'a> Decode<'a, T>,
+        {
+            loop {
+                if let Some(msg) = self.receive_borrowed::<T>()? {
+                    return Ok(msg);
+                }
+            }
+        }
+    };
+}
+
+macro_rules! impl_send {
+    () => {
+        /// serialize and send message as a data frame of format opcode
+        pub fn send<M: ?Sized>(&mut self, msg: &M) -> Result<(), SerdeError>
+        where
+            F: Encode<M>,
+        {
+            self.send_pong()?;
+            self.buf.clear();
+            F::encode(msg, &mut self.buf)?;
+            self.codec.send(F::OPCODE, &self.buf)?;
+            Ok(())
+        }
+
+        /// send close frame
+        pub fn close(
+            &mut self,
+            code: impl Into<CloseCode>,
+            reason: &str,
+        ) -> Result<(), SerdeError> {
+            let mut payload = u16::from(code.into()).to_be_bytes().to_vec();
+            payload.extend_from_slice(reason.as_bytes());
+            self.codec.send(OpCode::Close, &payload)?;
+            Ok(())
+        }
+
+        /// flush underlying stream
+        pub fn flush(&mut self) -> Result<(), SerdeError> {
+            self.send_pong()?;
+            self.codec.flush()?;
+            Ok(())
+        }
+    };
+}
+
+macro_rules! impl_pong {
+    () => {
+        /// answer ping received by last receive
+        fn send_pong(&mut self) -> Result<(), WsError> {
+            if let Some(payload) = self.pong.take() {
+                self.codec.send(OpCode::Pong, &payload)?;
+                self.codec.flush()?;
+            }
+            Ok(())
+        }
+    };
+}
+
+/// recv part of serialized message
+pub struct SerdeRecv<F, T, C> {
+    codec: C,
+    pong: Option<Vec<u8>>,
+    _msg: PhantomData<fn() -> (F, T)>,
+}
+
+impl<F, T, C> SerdeRecv<F, T, C> {
+    /// construct method
+    pub fn new(codec: C) -> Self {
+        Self {
+            codec,
+            pong: None,
+            _msg: PhantomData,
+        }
+    }
+
+    /// get mutable inner codec
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    /// payload of last ping received, recv part can not answer it, pass it to
+    /// [SerdeSend::pong] to answer it by send part
+    pub fn take_ping(&mut self) -> Option<Vec<u8>> {
+        self.pong.take()
+    }
+
+    /// ping is kept until taken by [SerdeRecv::take_ping]
+    fn send_pong(&mut self) -> Result<(), WsError> {
+        Ok(())
+    }
+}
+
+/// send part of serialized message
+pub struct SerdeSend<F, C> {
+    codec: C,
+    buf: Vec<u8>,
+    pong: Option<Vec<u8>>,
+    _format: PhantomData<fn() -> F>,
+}
+
+impl<F, C> SerdeSend<F, C> {
+    /// construct method
+    pub fn new(codec: C) -> Self {
+        Self {
+            codec,
+            buf: vec![],
+            pong: None,
+            _format: PhantomData,
+        }
+    }
+
+    /// get mutable inner codec
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    /// queue pong with payload of ping taken by [SerdeRecv::take_ping], it is sent
+    /// before next send or flush, a pending pong not sent yet is replaced
+    pub fn pong(&mut self, payload: Vec<u8>) {
+        self.pong = Some(payload);
+    }
+}
+
+/// recv/send message serialized by format `F` over data frames of [FrameCodec] or
+/// [DeflateCodec](crate::codec::DeflateCodec), `T` is type of received message
+///
+/// text and binary frames are both accepted on receive, send uses opcode of format
+///
+/// ping received is answered before next receive or send
+pub struct SerdeCodec<F, T, C> {
+    codec: C,
+    buf: Vec<u8>,
+    pong: Option<Vec<u8>>,
+    _msg: PhantomData<fn() -> (F, T)>,
+}
+
+impl<F, T, C> SerdeCodec<F, T, C> {
+    /// construct method
+    pub fn new(codec: C) -> Self {
+        Self {
+            codec,
+            buf: vec![],
+            pong: None,
+            _msg: PhantomData,
+        }
+    }
+
+    /// get mutable inner codec
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    /// return inner codec
+    pub fn into_inner(self) -> C {
+        self.codec
+    }
+}
+
+macro_rules! impl_codec {
+    ($codec:ident, $recv:ident, $send:ident) => {
+        impl<F: Format, T, S: Read> SerdeRecv<F, T, $recv<S>> {
+            impl_recv! {}
+        }
+
+        impl<F: Format, S: Write> SerdeSend<F, $send<S>> {
+            impl_pong! {}
+
+            impl_send! {}
+        }
+
+        impl<F: Format, T, S: Read + Write> SerdeCodec<F, T, $codec<S>> {
+            /// used for server side to construct a new server
+            pub fn factory(req: http::Request<()>, stream: S) -> Result<Self, WsError> {
+                $codec::factory(req, stream).map(Self::new)
+            }
+
+            /// used to client side to construct a new client
+            pub fn check_fn(
+                key: String,
+                resp: http::Response<()>,
+                stream: S,
+            ) -> Result<Self, WsError> {
+                $codec::check_fn(key, resp, stream).map(Self::new)
+            }
+
+            impl_pong! {}
+
+            impl_recv! {}
+
+            impl_send! {}
+        }
+
+        impl<F, T, R, W, S> SerdeCodec<F, T, $codec<S>>
+        where
+            R: Read,
+            W: Write,
+            S: Read + Write + Split<R = R, W = W>,
+        {
+            /// split codec to recv and send parts
+            pub fn split(self) -> (SerdeRecv<F, T, $recv<R>>, SerdeSend<F, $send<W>>) {
+                let (recv, send) = self.codec.split();
+                let send = SerdeSend {
+                    codec: send,
+                    buf: self.buf,
+                    pong: self.pong,
+                    _format: PhantomData,
+                };
+                (SerdeRecv::new(recv), send)
+            }
+        }
+    };
+}
+
+impl_codec!(FrameCodec, FrameRecv, FrameSend);
+
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static",
+    feature = "deflate_rust"
+))]
+impl_codec!(DeflateCodec, DeflateRecv, DeflateSend);
+
+#[test]
+#[cfg(feature = "json")]
+fn test_json_codec() {
+    use crate::codec::{Duplex, FrameConfig, FrameWriteState, JsonCodec};
+    use serde::{Deserialize, Serialize};
+    use std::io::Cursor;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Trade<'a> {
+        symbol: &'a str,
+        price: f64,
+    }
+
+    let stream = Duplex::new(vec![]);
+    let mut client = JsonCodec::<(), _>::new(FrameCodec::new(stream));
+    let trade = Trade {
+        symbol: "BTC",
+        price: 1.5,
+    };
+    client.send(&trade).unwrap();
+    client.codec_mut().send(OpCode::Ping, b"p").unwrap();
+    client.codec_mut().send(OpCode::Text, b"{").unwrap();
+    client.send(&trade).unwrap();
+    let input = std::mem::take(&mut client.codec_mut().stream_mut().output);
+
+    let config = FrameConfig {
+        mask_send_frame: false,
+        ..Default::default()
+    };
+    let stream = Duplex::new(input);
+    let mut server = JsonCodec::<serde_json::Value, _>::new(FrameCodec::new_with(stream, config));
+    let borrowed: Trade = server.receive_borrowed().unwrap().unwrap();
+    assert_eq!(borrowed, trade);
+    assert!(matches!(server.receive(), Err(SerdeError::Deserialize(_))));
+    assert_eq!(server.receive().unwrap()["symbol"], "BTC");
+    let mut client = FrameCodec::new(Cursor::new(server.into_inner().stream_mut().output.clone()));
+    let (header, data) = client.receive().unwrap();
+    assert_eq!((header.code, data), (OpCode::Pong, &b"p"[..]));
+
+    let mut output = vec![];
+    let mut send =
+        SerdeSend::<super::Json, _>::new(FrameSend::new(&mut output, FrameWriteState::default()));
+    send.pong(b"q".to_vec());
+    send.send(&trade).unwrap();
+    let mut client = FrameCodec::new(Cursor::new(output));
+    let (header, data) = client.receive().unwrap();
+    assert_eq!((header.code, data), (OpCode::Pong, &b"q"[..]));
+    assert_eq!(client.receive().unwrap().0.code, OpCode::Text);
+}
+
+#[cfg(test)]
+fn round_trip<F, M>(msg: &M) -> M
+where
+    F: Format + Encode<M> + for<'a> Decode<'a, M>,
+{
+    use crate::codec::{Duplex, FrameConfig};
+    use std::io::Cursor;
+
+    let stream = Duplex::new(vec![]);
+    let mut client = SerdeCodec::<F, (), _>::new(FrameCodec::new(stream));
+    client.send(msg).unwrap();
+    let input = std::mem::take(&mut client.codec_mut().stream_mut().output);
+    let config = FrameConfig {
+        mask_send_frame: false,
+        ..Default::default()
+    };
+    let mut server = SerdeCodec::<F, M, _>::new(FrameCodec::new_with(Cursor::new(input), config));
+    server.receive().unwrap()
+}
+
+#[test]
+#[cfg(any(feature = "msgpack", feature = "cbor"))]
+fn test_serde_formats() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Order {
+        id: u64,
+        side: String,
+        qty: Vec<f32>,
+    }
+
+    let order = Order {
+        id: 42,
+        side: "buy".into(),
+        qty: vec![0.5, 1.0],
+    };
+    #[cfg(feature = "msgpack")]
+    assert_eq!(round_trip::<super::MsgPack, _>(&order), order);
+    #[cfg(feature = "cbor")]
+    assert_eq!(round_trip::<super::Cbor, _>(&order), order);
+}
+
+#[test]
+#[cfg(feature = "protobuf")]
+fn test_protobuf_format() {
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct Quote {
+        #[prost(string, tag = "1")]
+        symbol: String,
+        #[prost(int64, tag = "2")]
+        price: i64,
+    }
+
+    let quote = Quote {
+        symbol: "ETH".into(),
+        price: 3000,
+    };
+    assert_eq!(round_trip::<super::Protobuf, _>(&quote), quote);
+}
@@ -0,0 +1,204 @@
+use crate::{errors::SerdeError, frame::OpCode};
+
+#[cfg(feature = "sync")]
+mod blocking;
+
+#[cfg(feature = "sync")]
+pub use blocking::*;
+
+#[cfg(feature = "async")]
+mod non_blocking;
+
+#[cfg(feature = "async")]
+pub use non_blocking::*;
+
+/// encoding of messages sent/received by [SerdeCodec], swap encoding by changing
+/// format type parameter
+pub trait Format {
+    /// opcode of data frame sent
+    const OPCODE: OpCode;
+}
+
+/// format which can encode message of type `M`
+pub trait Encode<M: ?Sized>: Format {
+    /// append encoded message to buf
+    fn encode(msg: &M, buf: &mut Vec<u8>) -> Result<(), SerdeError>;
+}
+
+/// format which can decode message of type `M` from payload of a data frame,
+/// message may borrow from payload
+pub trait Decode<'a, M>: Format {
+    /// decode message from payload
+    fn decode(data: &'a [u8]) -> Result<M, SerdeError>;
+}
+
+fn ser_error(e: impl std::error::Error + Send + Sync + 'static) -> SerdeError {
+    SerdeError::Serialize(Box::new(e))
+}
+
+fn de_error(e: impl std::error::Error + Send + Sync + 'static) -> SerdeError {
+    SerdeError::Deserialize(Box::new(e))
+}
+
+macro_rules! def_alias {
+    ($format:ident, $codec:ident, $recv:ident, $send:ident, $async_codec:ident, $async_recv:ident, $async_send:ident) => {
+        #[doc = concat!("[SerdeCodec] of [", stringify!($format), "] format")]
+        #[cfg(feature = "sync")]
+        pub type $codec<T, C> = SerdeCodec<$format, T, C>;
+        #[doc = concat!("[SerdeRecv] of [", stringify!($format), "] format")]
+        #[cfg(feature = "sync")]
+        pub type $recv<T, C> = SerdeRecv<$format, T, C>;
+        #[doc = concat!("[SerdeSend] of [", stringify!($format), "] format")]
+        #[cfg(feature = "sync")]
+        pub type $send<C> = SerdeSend<$format, C>;
+        #[doc = concat!("[AsyncSerdeCodec] of [", stringify!($format), "] format")]
+        #[cfg(feature = "async")]
+        pub type $async_codec<T, C> = AsyncSerdeCodec<$format, T, C>;
+        #[doc = concat!("[AsyncSerdeRecv] of [", stringify!($format), "] format")]
+        #[cfg(feature = "async")]
+        pub type $async_recv<T, C> = AsyncSerdeRecv<$format, T, C>;
+        #[doc = concat!("[AsyncSerdeSend] of [", stringify!($format), "] format")]
+        #[cfg(feature = "async")]
+        pub type $async_send<C> = AsyncSerdeSend<$format, C>;
+    };
+}
+
+/// json over text frames
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Copy)]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl Format for Json {
+    const OPCODE: OpCode = OpCode::Text;
+}
+
+#[cfg(feature = "json")]
+impl<M: serde::Serialize + ?Sized> Encode<M> for Json {
+    fn encode(msg: &M, buf: &mut Vec<u8>) -> Result<(), SerdeError> {
+        serde_json::to_writer(buf, msg).map_err(ser_error)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'a, M: serde::Deserialize<'a>> Decode<'a, M> for Json {
+    fn decode(data: &'a [u8]) -> Result<M, SerdeError> {
+        serde_json::from_slice(data).map_err(de_error)
+    }
+}
+
+#[cfg(feature = "json")]
+def_alias!(
+    Json,
+    JsonCodec,
+    JsonRecv,
+    JsonSend,
+    AsyncJsonCodec,
+    AsyncJsonRecv,
+    AsyncJsonSend
+);
+
+/// MessagePack over binary frames, struct is encoded as map
+#[cfg(feature = "msgpack")]
+#[derive(Debug, Clone, Copy)]
+pub struct MsgPack;
+
+#[cfg(feature = "msgpack")]
+impl Format for MsgPack {
+    const OPCODE: OpCode = OpCode::Binary;
+}
+
+#[cfg(feature = "msgpack")]
+impl<M: serde::Serialize + ?Sized> Encode<M> for MsgPack {
+    fn encode(msg: &M, buf: &mut Vec<u8>) -> Result<(), SerdeError> {
+        rmp_serde::encode::write_named(buf, msg).map_err(ser_error)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+impl<'a, M: serde::Deserialize<'a>> Decode<'a, M> for MsgPack {
+    fn decode(data: &'a [u8]) -> Result<M, SerdeError> {
+        rmp_serde::from_slice(data).map_err(de_error)
+    }
+}
+
+#[cfg(feature = "msgpack")]
+def_alias!(
+    MsgPack,
+    MsgPackCodec,
+    MsgPackRecv,
+    MsgPackSend,
+    AsyncMsgPackCodec,
+    AsyncMsgPackRecv,
+    AsyncMsgPackSend
+);
+
+/// CBOR over binary frames, decoded message can not borrow from payload
+#[cfg(feature = "cbor")]
+#[derive(Debug, Clone, Copy)]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl Format for Cbor {
+    const OPCODE: OpCode = OpCode::Binary;
+}
+
+#[cfg(feature = "cbor")]
+impl<M: serde::Serialize + ?Sized> Encode<M> for Cbor {
+    fn encode(msg: &M, buf: &mut Vec<u8>) -> Result<(), SerdeError> {
+        ciborium::ser::into_writer(msg, buf).map_err(ser_error)
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl<'a, M: serde::de::DeserializeOwned> Decode<'a, M> for Cbor {
+    fn decode(data: &'a [u8]) -> Result<M, SerdeError> {
+        ciborium::de::from_reader(data).map_err(de_error)
+    }
+}
+
+#[cfg(feature = "cbor")]
+def_alias!(
+    Cbor,
+    CborCodec,
+    CborRecv,
+    CborSend,
+    AsyncCborCodec,
+    AsyncCborRecv,
+    AsyncCborSend
+);
+
+/// protobuf over binary frames, message type is generated by `prost`
+#[cfg(feature = "protobuf")]
+#[derive(Debug, Clone, Copy)]
+pub struct Protobuf;
+
+#[cfg(feature = "protobuf")]
+impl Format for Protobuf {
+    const OPCODE: OpCode = OpCode::Binary;
+}
+
+#[cfg(feature = "protobuf")]
+impl<M: prost::Message> Encode<M> for Protobuf {
+    fn encode(msg: &M, buf: &mut Vec<u8>) -> Result<(), SerdeError> {
+        msg.encode(buf).map_err(ser_error)
+    }
+}
+
+#[cfg(feature = "protobuf")]
+impl<'a, M: prost::Message + Default> Decode<'a, M> for Protobuf {
+    fn decode(data: &'a [u8]) -> Result<M, SerdeError> {
+        M::decode(data).map_err(de_error)
+    }
+}
+
+#[cfg(feature = "protobuf")]
+def_alias!(
+    Protobuf,
+    ProtobufCodec,
+    ProtobufRecv,
+    ProtobufSend,
+    AsyncProtobufCodec,
+    AsyncProtobufRecv,
+    AsyncProtobufSend
+);
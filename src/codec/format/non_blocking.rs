@@ -0,0 +1,267 @@
+use std::marker::PhantomData;
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use super::{Decode, Encode, Format};
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static",
+    feature = "deflate_rust"
+))]
+use crate::codec::{AsyncDeflateCodec, AsyncDeflateRecv, AsyncDeflateSend};
+use crate::{
+    codec::{AsyncFrameCodec, AsyncFrameRecv, AsyncFrameSend, Split},
+    errors::{SerdeError, WsError},
+    frame::OpCode,
+    protocol::CloseCode,
+};
+
+macro_rules! impl_recv {
+    () => {
+        /// receive a text or binary message and deserialize it from codec buffer
+        /// without copy, return None if a control frame is received
+        ///
+        /// close frame is returned as [WsError::ConnectionClosed]
+        pub async fn receive_borrowed<'a, B>(&'a mut self) -> Result<Option<B>, SerdeError>
+        where
+            F: Decode<'a, B>,
+        {
+            self.send_pong().await?;
+            let (header, data) = self.codec.receive().await?;
+            match header.code {
+                OpCode::Text | OpCode::Binary => F::decode(data).map(Some),
+                OpCode::Close => Err(WsError::ConnectionClosed.into()),
+                OpCode::Ping => {
+                    self.pong = Some(data.to_vec());
+                    Ok(None)
+                }
+                _ => Ok(None),
+            }
+        }
+
+        /// receive and deserialize next text or binary message, control frames
+        /// are skipped
+        pub async fn receive(&mut self) -> Result<T, SerdeError>
+        where
+            F: for<'a> Decode<'a, T>,
+        {
+            loop {
+                if let Some(msg) = self.receive_borrowed::<T>().await? {
+                    return Ok(msg);
+                }
+            }
+        }
+    };
+}
+
+macro_rules! impl_send {
+    () => {
+        /// serialize and send message as a data frame of format opcode
+        pub async fn send<M: ?Sized>(&mut self, msg: &M) -> Result<(), SerdeError>
+        where
+            F: Encode<M>,
+        {
+            self.send_pong().await?;
+            self.buf.clear();
+            F::encode(msg, &mut self.buf)?;
+            self.codec.send(F::OPCODE, &self.buf).await?;
+            Ok(())
+        }
+
+        /// send close frame
+        pub async fn close(
+            &mut self,
+            code: impl Into<CloseCode>,
+            reason: &str,
+        ) -> Result<(), SerdeError> {
+            let mut payload = u16::from(code.into()).to_be_bytes().to_vec();
+            payload.extend_from_slice(reason.as_bytes());
+            self.codec.send(OpCode::Close, &payload).await?;
+            Ok(())
+        }
+
+        /// flush underlying stream
+        pub async fn flush(&mut self) -> Result<(), SerdeError> {
+            self.send_pong().await?;
+            self.codec.flush().await?;
+            Ok(())
+        }
+    };
+}
+
+macro_rules! impl_pong {
+    () => {
+        /// answer ping received by last receive
+        async fn send_pong(&mut self) -> Result<(), WsError> {
+            if let Some(payload) = self.pong.take() {
+                self.codec.send(OpCode::Pong, &payload).await?;
+                self.codec.flush().await?;
+            }
+            Ok(())
+        }
+    };
+}
+
+/// recv part of async serialized message
+pub struct AsyncSerdeRecv<F, T, C> {
+    codec: C,
+    pong: Option<Vec<u8>>,
+    _msg: PhantomData<fn() -> (F, T)>,
+}
+
+impl<F, T, C> AsyncSerdeRecv<F, T, C> {
+    /// construct method
+    pub fn new(codec: C) -> Self {
+        Self {
+            codec,
+            pong: None,
+            _msg: PhantomData,
+        }
+    }
+
+    /// get mutable inner codec
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    /// payload of last ping received, recv part can not answer it, pass it to
+    /// [AsyncSerdeSend::pong] to answer it by send part
+    pub fn take_ping(&mut self) -> Option<Vec<u8>> {
+        self.pong.take()
+    }
+
+    /// ping is kept until taken by [AsyncSerdeRecv::take_ping]
+    async fn send_pong(&mut self) -> Result<(), WsError> {
+        Ok(())
+    }
+}
+
+/// send part of async serialized message
+pub struct AsyncSerdeSend<F, C> {
+    codec: C,
+    buf: Vec<u8>,
+    pong: Option<Vec<u8>>,
+    _format: PhantomData<fn() -> F>,
+}
+
+impl<F, C> AsyncSerdeSend<F, C> {
+    /// construct method
+    pub fn new(codec: C) -> Self {
+        Self {
+            codec,
+            buf: vec![],
+            pong: None,
+            _format: PhantomData,
+        }
+    }
+
+    /// get mutable inner codec
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    /// queue pong with payload of ping taken by [AsyncSerdeRecv::take_ping], it is sent
+    /// before next send or flush, a pending pong not sent yet is replaced
+    pub fn pong(&mut self, payload: Vec<u8>) {
+        self.pong = Some(payload);
+    }
+}
+
+/// async version of [SerdeCodec](super::SerdeCodec), over data frames of
+/// [AsyncFrameCodec] or [AsyncDeflateCodec](crate::codec::AsyncDeflateCodec)
+///
+/// ping received is answered before next receive or send
+pub struct AsyncSerdeCodec<F, T, C> {
+    codec: C,
+    buf: Vec<u8>,
+    pong: Option<Vec<u8>>,
+    _msg: PhantomData<fn() -> (F, T)>,
+}
+
+impl<F, T, C> AsyncSerdeCodec<F, T, C> {
+    /// construct method
+    pub fn new(codec: C) -> Self {
+        Self {
+            codec,
+            buf: vec![],
+            pong: None,
+            _msg: PhantomData,
+        }
+    }
+
+    /// get mutable inner codec
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    /// return inner codec
+    pub fn into_inner(self) -> C {
+        self.codec
+    }
+}
+
+macro_rules! impl_codec {
+    ($codec:ident, $recv:ident, $send:ident) => {
+        impl<F: Format, T, S: AsyncRead + Unpin> AsyncSerdeRecv<F, T, $recv<S>> {
+            impl_recv! {}
+        }
+
+        impl<F: Format, S: AsyncWrite + Unpin> AsyncSerdeSend<F, $send<S>> {
+            impl_pong! {}
+
+            impl_send! {}
+        }
+
+        impl<F: Format, T, S: AsyncRead + AsyncWrite + Unpin> AsyncSerdeCodec<F, T, $codec<S>> {
+            /// used for server side to construct a new server
+            pub fn factory(req: http::Request<()>, stream: S) -> Result<Self, WsError> {
+                $codec::factory(req, stream).map(Self::new)
+            }
+
+            /// used to client side to construct a new client
+            pub fn check_fn(
+                key: String,
+                resp: http::Response<()>,
+                stream: S,
+            ) -> Result<Self, WsError> {
+                $codec::check_fn(key, resp, stream).map(Self::new)
+            }
+
+            impl_pong! {}
+
+            impl_recv! {}
+
+            impl_send! {}
+        }
+
+        impl<F, T, R, W, S> AsyncSerdeCodec<F, T, $codec<S>>
+        where
+            R: AsyncRead + Unpin,
+            W: AsyncWrite + Unpin,
+            S: AsyncRead + AsyncWrite + Unpin + Split<R = R, W = W>,
+        {
+            /// split codec to recv and send parts
+            pub fn split(self) -> (AsyncSerdeRecv<F, T, $recv<R>>, AsyncSerdeSend<F, $send<W>>) {
+                let (recv, send) = self.codec.split();
+                let send = AsyncSerdeSend {
+                    codec: send,
+                    buf: self.buf,
+                    pong: self.pong,
+                    _format: PhantomData,
+                };
+                (AsyncSerdeRecv::new(recv), send)
+            }
+        }
+    };
+}
+
+impl_codec!(AsyncFrameCodec, AsyncFrameRecv, AsyncFrameSend);
+
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static",
+    feature = "deflate_rust"
+))]
+impl_codec!(AsyncDeflateCodec, AsyncDeflateRecv, AsyncDeflateSend);
@@ -1,26 +1,34 @@
-use super::{FrameConfig, FrameReadState, FrameWriteState};
+use super::{
+    echo_close_payload, io_timeout, is_timeout, ConnectionState, FrameConfig, FrameReadState,
+    FrameWriteState, StreamRead, StreamStep,
+};
 use http;
 use crate::{
-    codec::{apply_mask, Split},
-    errors::WsError,
-    frame::{ctor_header, header_len, OpCode, OwnedFrame, SimplifiedHeader},
-    protocol::standard_handshake_resp_check,
+    codec::{PreparedFrame, Shutdown, Split},
+    errors::{TimeoutPhase, WsError},
+    frame::{OpCode, OwnedFrame, SimplifiedHeader},
+    protocol::{standard_handshake_resp_check, CloseCode},
+    WsMessage,
 };
-use bytes::BytesMut;
 use std::{
     io::{IoSlice, Read, Write},
     ops::Range,
+    time::{Duration, Instant},
 };
 
 type IOResult<T> = std::io::Result<T>;
 
 impl FrameReadState {
-    /// **NOTE** masked frame has already been unmasked
-    pub fn receive<S: Read>(
+    /// read a frame, return payload range in read buffer, `None` means payload is
+    /// merged fragmented data
+    pub(crate) fn next_frame<S: Read>(
         &mut self,
         stream: &mut S,
-    ) -> Result<(SimplifiedHeader, &[u8]), WsError> {
-        if self.config.merge_frame {
+    ) -> Result<(SimplifiedHeader, Option<Range<usize>>), WsError> {
+        if self.close_received {
+            return Err(WsError::ConnectionClosed);
+        }
+        let (header, range) = if self.config.merge_frame {
             loop {
                 let (mut header, range) = self.read_one_frame(stream)?;
                 if let Some(merged) = self
@@ -29,17 +37,28 @@ impl FrameReadState {
                 {
                     if merged {
                         header.code = self.fragmented_type;
-                        break Ok((header, &self.fragmented_data));
+                        break (header, None);
                     } else {
-                        break Ok((header, &self.buf.buf[range]));
+                        break (header, Some(range));
                     }
                 }
             }
         } else {
             let (header, range) = self.read_one_frame(stream)?;
             self.check_frame(header, range.clone())?;
-            Ok((header, &self.buf.buf[range]))
-        }
+            (header, Some(range))
+        };
+        self.close_received = header.code == OpCode::Close;
+        Ok((header, range))
+    }
+
+    /// **NOTE** masked frame has already been unmasked
+    pub fn receive<S: Read>(
+        &mut self,
+        stream: &mut S,
+    ) -> Result<(SimplifiedHeader, &[u8]), WsError> {
+        let (header, range) = self.next_frame(stream)?;
+        Ok((header, self.payload(range)))
     }
 
     /// **NOTE** masked frame has already been unmasked
@@ -47,25 +66,56 @@ impl FrameReadState {
         &mut self,
         stream: &mut S,
     ) -> Result<(SimplifiedHeader, &mut [u8]), WsError> {
-        if self.config.merge_frame {
-            loop {
-                let (mut header, range) = self.read_one_frame(stream)?;
-                if let Some(merged) = self
-                    .check_frame(header, range.clone())
-                    .and_then(|_| self.merge_frame(header, range.clone()))?
-                {
-                    if merged {
-                        header.code = self.fragmented_type;
-                        break Ok((header, &mut self.fragmented_data));
-                    } else {
-                        break Ok((header, &mut self.buf.buf[range]));
+        let (header, range) = self.next_frame(stream)?;
+        Ok((header, self.payload_mut(range)))
+    }
+
+    /// read next chunk of streamed message into `buf`, new message is started if
+    /// no message is being streamed
+    pub(crate) fn stream_read<S: Read>(
+        &mut self,
+        stream: &mut S,
+        buf: &mut [u8],
+    ) -> Result<StreamRead, WsError> {
+        if self.close_received {
+            return Err(WsError::ConnectionClosed);
+        }
+        loop {
+            match self.stream_step()? {
+                StreamStep::Payload(remaining) => {
+                    let len = remaining.min(buf.len());
+                    let buf = &mut buf[..len];
+                    if buf.is_empty() {
+                        return Ok(StreamRead::Data(0));
+                    }
+                    let mut count = self.stream_buffered(buf);
+                    if count == 0 {
+                        count = stream
+                            .read(buf)
+                            .map_err(|e| io_timeout(e, TimeoutPhase::Read))?;
+                        if count == 0 {
+                            return Err(WsError::IOError(std::io::Error::new(
+                                std::io::ErrorKind::UnexpectedEof,
+                                "read eof",
+                            )));
+                        }
+                    }
+                    self.stream_payload(&mut buf[..count])?;
+                    return Ok(StreamRead::Data(count));
+                }
+                StreamStep::End(header) => return Ok(StreamRead::End(header)),
+                StreamStep::Header => {
+                    while !self.is_header_ok() {
+                        self.poll(stream)
+                            .map_err(|e| io_timeout(e, TimeoutPhase::Read))?;
+                    }
+                    if let Some(size) = self.stream_header()? {
+                        self.poll_one_frame(stream, size.2)
+                            .map_err(|e| io_timeout(e, TimeoutPhase::Read))?;
+                        return self.stream_control(size);
                     }
                 }
             }
-        } else {
-            let (header, range) = self.read_one_frame(stream)?;
-            self.check_frame(header, range.clone())?;
-            Ok((header, &mut self.buf.buf[range]))
         }
     }
 
@@ -75,10 +125,12 @@ impl FrameReadState {
         stream: &mut S,
     ) -> Result<(SimplifiedHeader, Range<usize>), WsError> {
         while !self.is_header_ok() {
-            self.poll(stream)?;
+            self.poll(stream)
+                .map_err(|e| io_timeout(e, TimeoutPhase::Read))?;
         }
         let (header_len, payload_len, total_len) = self.parse_frame_header()?;
-        self.poll_one_frame(stream, total_len)?;
+        self.poll_one_frame(stream, total_len)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Read))?;
         Ok(self.consume_frame(header_len, payload_len, total_len))
     }
 
@@ -96,23 +148,33 @@ impl FrameReadState {
         Ok(count)
     }
 
+    /// read until buffer contains a whole frame, keep partial read data in buffer
+    /// so that it can be resumed after read timeout
     #[inline]
     fn poll_one_frame<S: Read>(&mut self, stream: &mut S, size: usize) -> std::io::Result<()> {
-        let read_len = self.buf.ava_data().len();
-        if read_len < size {
+        loop {
+            let read_len = self.buf.ava_data().len();
+            if read_len >= size {
+                break Ok(());
+            }
             let buf = self.buf.prepare(size - read_len);
-            stream.read_exact(buf)?;
-            self.buf.produce(size - read_len);
+            let count = stream.read(buf)?;
+            if count == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "read eof",
+                ));
+            }
+            self.buf.produce(count);
         }
-        Ok(())
     }
 }
 
 impl FrameWriteState {
-    // DOUBT return error if payload len data >= 126 ?
     /// send immutable payload
     ///
-    /// if need to mask, copy data to inner buffer and then apply mask
+    /// if need to mask, copy data to inner buffer and then apply mask, else header
+    /// and payload are written by `write_vectored` without copying
     ///
     /// will auto fragment if auto_fragment_size > 0
     pub fn send<S: Write>(
@@ -121,149 +183,74 @@ impl FrameWriteState {
         opcode: OpCode,
         payload: &[u8],
     ) -> IOResult<()> {
-        if payload.is_empty() {
-            let mask = if self.config.mask_send_frame {
-                Some(rand::random())
-            } else {
-                None
-            };
-            let header = ctor_header(
-                &mut self.header_buf,
-                true,
-                false,
-                false,
-                false,
-                mask,
-                opcode,
-                0,
-            );
-            stream.write_all(header)?;
+        if let Some(header) = self.single_header(opcode, payload) {
+            let mut slices = [IoSlice::new(header), IoSlice::new(payload)];
+            write_all_vectored(stream, &mut slices)?;
+            self.set_sent(opcode);
             return Ok(());
         }
-        if self.config.auto_fragment_size > 0 && self.config.auto_fragment_size < payload.len() {
-            let chunk_size = self.config.auto_fragment_size;
-            let parts: Vec<&[u8]> = payload.chunks(chunk_size).collect();
-            let total = parts.len();
-            let single_header_len = header_len(self.config.mask_send_frame, chunk_size as u64);
-            let mut header_buf_len = single_header_len * parts.len();
-            let left = payload.len() % chunk_size;
-            if left != 0 {
-                header_buf_len += header_len(self.config.mask_send_frame, left as u64);
-            }
-            let total_bytes = payload.len() + header_buf_len;
-            if self.config.mask_send_frame {
-                if self.buf.len() < total_bytes {
-                    self.buf.resize(total_bytes, 0);
-                }
-                parts.iter().enumerate().for_each(|(idx, chunk)| {
-                    let fin = idx + 1 == total;
-                    let s_idx = idx * single_header_len;
-                    let mask = rand::random();
-                    let header_len = ctor_header(
-                        &mut self.buf[s_idx..],
-                        fin,
-                        false,
-                        false,
-                        false,
-                        mask,
-                        opcode,
-                        chunk.len() as u64,
-                    )
-                    .len();
-                    let slice = &mut self.buf[(s_idx + header_len)..];
-                    slice.copy_from_slice(chunk);
-                    apply_mask(slice, mask);
-                });
-                stream.write_all(&self.buf[..total_bytes])?;
-            } else {
-                if self.buf.len() < header_buf_len {
-                    self.buf.resize(header_buf_len, 0);
-                }
-                let mut slices = Vec::with_capacity(total * 2);
-                parts.iter().enumerate().for_each(|(idx, chunk)| {
-                    let fin = idx + 1 == total;
-                    let s_idx = idx * chunk_size;
-                    ctor_header(
-                        &mut self.buf[s_idx..],
-                        fin,
-                        false,
-                        false,
-                        false,
-                        None,
-                        opcode,
-                        chunk.len() as u64,
-                    );
-                });
-                parts.iter().enumerate().for_each(|(idx, chunk)| {
-                    let fin = idx + 1 == total;
-                    if fin {
-                        slices.push(IoSlice::new(&self.buf[(idx * single_header_len)..]))
-                    } else {
-                        slices.push(IoSlice::new(
-                            &self.buf[(idx * single_header_len)..(idx + 1) * single_header_len],
-                        ))
-                    }
-                    slices.push(IoSlice::new(chunk));
-                });
-                let num = stream.write_vectored(&slices)?;
-                let remain = total_bytes - num;
-                if remain > 0 {
-                    if let Some(buf) = slices.last() {
-                        stream.write_all(&buf[(buf.len() - remain)..])?;
-                    }
-                }
-            }
-        } else if self.config.mask_send_frame {
-            let total_bytes = header_len(true, payload.len() as u64) + payload.len();
-            let mask: [u8; 4] = rand::random();
-            let header = ctor_header(
-                &mut self.header_buf,
-                true,
-                false,
-                false,
-                false,
-                mask,
-                opcode,
-                payload.len() as u64,
-            );
-            if self.buf.len() < payload.len() {
-                self.buf.resize(payload.len(), 0)
-            }
-            self.buf[..(payload.len())].copy_from_slice(payload);
-            apply_mask(&mut self.buf[..(payload.len())], mask);
-            let num = stream.write_vectored(&[
-                IoSlice::new(header),
-                IoSlice::new(&self.buf[..(payload.len())]),
-            ])?;
-            let remain = total_bytes - num;
-            if remain > 0 {
-                stream.write_all(&self.buf[(payload.len() - remain)..(payload.len())])?;
+        self.send_many(stream, &[(opcode, payload)])
+    }
+
+    /// send frames with as few writes as possible, small frames are coalesced into
+    /// one syscall
+    ///
+    /// data frames are fragmented if auto_fragment_size > 0
+    pub fn send_many<S: Write>(
+        &mut self,
+        stream: &mut S,
+        frames: &[(OpCode, &[u8])],
+    ) -> IOResult<()> {
+        let fragments = self.fragments(frames);
+        let ret = if self.config.mask_send_frame {
+            for (fin, code, payload) in fragments {
+                self.encode_frame(fin, false, code, payload);
             }
+            let ret = stream.write_all(&self.pending);
+            self.pending.clear();
+            ret
         } else {
-            let total_bytes = header_len(false, payload.len() as u64) + payload.len();
-            let header = ctor_header(
-                &mut self.header_buf,
-                true,
-                false,
-                false,
-                false,
-                None,
-                opcode,
-                payload.len() as u64,
-            );
-            // if self.buf.len() < payload.len() {
-            //     self.buf.resize(payload.len(), 0)
-            // }
-            let num = stream.write_vectored(&[IoSlice::new(header), IoSlice::new(payload)])?;
-            let remain = total_bytes - num;
-            if remain > 0 {
-                stream.write_all(&payload[(payload.len() - remain)..])?;
-            }
+            let mut slices = Self::encode_vectored(&mut self.buf, &fragments);
+            write_all_vectored(stream, &mut slices)
         };
+        self.renew_buf();
+        ret?;
+        for (code, _) in frames {
+            self.set_sent(*code);
+        }
+        Ok(())
+    }
 
-        if self.config.renew_buf_on_write {
-            self.buf = BytesMut::new()
+    /// send prepared frame, encoded frame is written directly if it needs neither
+    /// mask nor fragmentation, else payload is encoded by [FrameWriteState::send]
+    pub fn send_prepared<S: Write>(
+        &mut self,
+        stream: &mut S,
+        frame: &PreparedFrame,
+    ) -> IOResult<()> {
+        if self.can_send_prepared(frame) {
+            stream.write_all(frame.encoded())?;
+            self.set_sent(frame.opcode());
+            Ok(())
+        } else {
+            self.send(stream, frame.opcode(), frame.payload())
         }
+    }
+
+    /// send a single frame without fragmentation
+    pub(crate) fn send_frame<S: Write>(
+        &mut self,
+        stream: &mut S,
+        fin: bool,
+        rsv1: bool,
+        code: OpCode,
+        payload: &[u8],
+    ) -> IOResult<()> {
+        self.encode_frame(fin, rsv1, code, payload);
+        let ret = stream.write_all(&self.pending);
+        self.pending.clear();
+        ret?;
+        self.set_sent(code);
         Ok(())
     }
 
@@ -272,18 +259,29 @@ impl FrameWriteState {
         stream: &mut S,
         frame: OwnedFrame,
     ) -> IOResult<()> {
-        let header = IoSlice::new(&frame.header().0);
-        let body = IoSlice::new(frame.payload());
-        let total = header.len() + body.len();
-        let num = stream.write_vectored(&[header, body])?;
-        let remain = total - num;
-        if remain > 0 {
-            stream.write_all(&body[(body.len() - remain)..])?
-        }
+        let mut slices = [
+            IoSlice::new(&frame.header().0),
+            IoSlice::new(frame.payload()),
+        ];
+        write_all_vectored(stream, &mut slices)?;
+        self.set_sent(frame.header().opcode());
         Ok(())
     }
 }
 
+/// `write_all` of io slices, `Write::write_all_vectored` is unstable
+fn write_all_vectored<S: Write>(stream: &mut S, mut slices: &mut [IoSlice<'_>]) -> IOResult<()> {
+    while !slices.is_empty() {
+        match stream.write_vectored(slices) {
+            Ok(0) => return Err(std::io::ErrorKind::WriteZero.into()),
+            Ok(num) => IoSlice::advance_slices(&mut slices, num),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
 /// recv part of websocket stream
 pub struct FrameRecv<S: Read> {
     stream: S,
@@ -301,6 +299,12 @@ impl<S: Read> FrameRecv<S> {
         self.read_state.receive(&mut self.stream)
     }
 
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive()?;
+        WsMessage::from_header(&header, data)
+    }
+
     /// receive a mutable frame
     pub fn receive_mut(&mut self) -> Result<(SimplifiedHeader, &mut [u8]), WsError> {
         self.read_state.receive_mut(&mut self.stream)
@@ -326,21 +330,51 @@ impl<S: Write> FrameSend<S> {
     ///
     /// will auto fragment if auto_fragment_size > 0
     pub fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        self.write_state.check_send(code)?;
         self.write_state
             .send(&mut self.stream, code, payload)
-            .map_err(WsError::IOError)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    /// send frames in one write if possible, see [FrameWriteState::send_many]
+    pub fn send_many(&mut self, frames: &[(OpCode, &[u8])]) -> Result<(), WsError> {
+        self.write_state.check_send_many(frames)?;
+        self.write_state
+            .send_many(&mut self.stream, frames)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    /// send owned message
+    pub fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload())
+    }
+
+    /// send prepared frame, see [FrameWriteState::send_prepared]
+    pub fn send_prepared(&mut self, frame: &PreparedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.opcode())?;
+        self.write_state
+            .send_prepared(&mut self.stream, frame)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
     }
 
     /// send a read frame, **this method will not check validation of frame and do not fragment**
     pub fn send_owned_frame(&mut self, frame: OwnedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.header().opcode())?;
         self.write_state
             .send_owned_frame(&mut self.stream, frame)
-            .map_err(WsError::IOError)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    /// start a text or binary message, see [MessageWriter]
+    pub fn begin_message(&mut self, code: OpCode) -> Result<MessageWriter<'_, S>, WsError> {
+        MessageWriter::new(&mut self.stream, &mut self.write_state, code)
     }
 
     /// flush stream to ensure all data are send
     pub fn flush(&mut self) -> Result<(), WsError> {
-        self.stream.flush().map_err(WsError::IOError)
+        self.stream
+            .flush()
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
     }
 }
 
@@ -394,27 +428,376 @@ impl<S: Read + Write> FrameCodec<S> {
     }
 
     /// receive a frame
+    ///
+    /// close frame from peer is echoed automatically if close frame has not been sent,
+    /// after that, [WsError::ConnectionClosed] is returned
+    ///
+    /// if keepalive is enabled, ping frame is replied automatically and read timeout
+    /// of underlying stream is used to send ping & detect dead peer
     pub fn receive(&mut self) -> Result<(SimplifiedHeader, &[u8]), WsError> {
-        self.read_state.receive(&mut self.stream)
+        self.discard_stream()?;
+        loop {
+            match self.read_state.next_frame(&mut self.stream) {
+                Ok((header, range)) => {
+                    if header.code == OpCode::Close {
+                        self.echo_close(range.clone());
+                    }
+                    if !self.read_state.keepalive.is_enabled() {
+                        return Ok((header, self.read_state.payload(range)));
+                    }
+                    let need_pong = self.read_state.keepalive.on_frame(header.code);
+                    if !self.write_state.is_close_sent() && self.read_state.keepalive.on_tick()? {
+                        self.send(OpCode::Ping, &[])?;
+                        self.flush()?;
+                    }
+                    if need_pong && !self.write_state.is_close_sent() {
+                        let payload = self.read_state.payload(range).to_vec();
+                        self.send(OpCode::Pong, &payload)?;
+                        self.flush()?;
+                        continue;
+                    }
+                    return Ok((header, self.read_state.payload(range)));
+                }
+                Err(e) if is_timeout(&e) && self.read_state.keepalive.is_enabled() => {
+                    if !self.write_state.is_close_sent() && self.read_state.keepalive.on_tick()? {
+                        self.send(OpCode::Ping, &[])?;
+                        self.flush()?;
+                    }
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive()?;
+        WsMessage::from_header(&header, data)
+    }
+
+    /// receive a text or binary message as [MessageReader], payload is read from
+    /// underlying stream on demand instead of being buffered, text payload is utf-8
+    /// checked incrementally
+    ///
+    /// ping frame before or between fragments is replied, close frame is echoed and
+    /// [WsError::ConnectionClosed] is returned, unread payload of previous message is
+    /// discarded
+    pub fn receive_stream(&mut self) -> Result<MessageReader<'_, S>, WsError> {
+        self.discard_stream()?;
+        let (header, done) = match self.stream_read(&mut [])? {
+            StreamRead::End(header) => (header, true),
+            _ => match self.read_state.streaming_header() {
+                Some(header) => (header, false),
+                None => unreachable!("message stream is not started"),
+            },
+        };
+        Ok(MessageReader {
+            codec: self,
+            header,
+            done,
+        })
+    }
+
+    /// read streamed message, control frames are handled, return [StreamRead::Data]
+    /// or [StreamRead::End]
+    fn stream_read(&mut self, buf: &mut [u8]) -> Result<StreamRead, WsError> {
+        loop {
+            match self.read_state.stream_read(&mut self.stream, buf)? {
+                StreamRead::Control(header, range) => {
+                    self.read_state.keepalive.on_frame(header.code);
+                    match header.code {
+                        OpCode::Ping if !self.write_state.is_close_sent() => {
+                            let payload = self.read_state.payload(Some(range)).to_vec();
+                            self.send(OpCode::Pong, &payload)?;
+                            self.flush()?;
+                        }
+                        OpCode::Close => {
+                            self.echo_close(Some(range));
+                            return Err(WsError::ConnectionClosed);
+                        }
+                        _ => {}
+                    }
+                }
+                ret => return Ok(ret),
+            }
+        }
+    }
+
+    /// discard unread payload of streamed message
+    fn discard_stream(&mut self) -> Result<(), WsError> {
+        let mut buf = [0; 4096];
+        while self.read_state.streaming_header().is_some() {
+            self.stream_read(&mut buf)?;
+        }
+        Ok(())
+    }
+
+    fn echo_close(&mut self, range: Option<Range<usize>>) {
+        if self.write_state.is_close_sent() {
+            return;
+        }
+        let payload = echo_close_payload(self.read_state.payload(range)).to_vec();
+        if let Err(e) = self
+            .send(OpCode::Close, &payload)
+            .and_then(|_| self.flush())
+        {
+            tracing::debug!("failed to echo close frame {e}");
+        }
+    }
+
+    /// current state of close handshake
+    pub fn state(&self) -> ConnectionState {
+        ConnectionState::new(
+            self.write_state.is_close_sent(),
+            self.read_state.is_close_received(),
+        )
+    }
+
+    /// last time pong frame is received
+    pub fn last_pong(&self) -> Option<std::time::Instant> {
+        self.read_state.keepalive.last_pong()
     }
 
     /// send data, **will copy data if need mask**
     pub fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        self.write_state.check_send(code)?;
         self.write_state
             .send(&mut self.stream, code, payload)
-            .map_err(WsError::IOError)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    /// send frames in one write if possible, see [FrameWriteState::send_many]
+    pub fn send_many(&mut self, frames: &[(OpCode, &[u8])]) -> Result<(), WsError> {
+        self.write_state.check_send_many(frames)?;
+        self.write_state
+            .send_many(&mut self.stream, frames)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    /// send owned message
+    pub fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload())
+    }
+
+    /// send prepared frame, see [FrameWriteState::send_prepared]
+    pub fn send_prepared(&mut self, frame: &PreparedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.opcode())?;
+        self.write_state
+            .send_prepared(&mut self.stream, frame)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
     }
 
     /// send a read frame, **this method will not check validation of frame and do not fragment**
     pub fn send_owned_frame(&mut self, frame: OwnedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.header().opcode())?;
         self.write_state
             .send_owned_frame(&mut self.stream, frame)
-            .map_err(WsError::IOError)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    /// start a text or binary message, see [MessageWriter]
+    pub fn begin_message(&mut self, code: OpCode) -> Result<MessageWriter<'_, S>, WsError> {
+        MessageWriter::new(&mut self.stream, &mut self.write_state, code)
     }
 
     /// flush stream to ensure all data are send
     pub fn flush(&mut self) -> Result<(), WsError> {
-        self.stream.flush().map_err(WsError::IOError)
+        self.stream
+            .flush()
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+}
+
+impl<S: Read + Write + Shutdown> FrameCodec<S> {
+    /// send close frame if not sent, then wait for close frame from peer until timeout
+    /// and shutdown underlying stream, return true if close frame from peer is received
+    ///
+    /// frames received before close frame are dropped, read timeout of underlying
+    /// stream is set to remaining time before each read
+    pub fn graceful_close(
+        &mut self,
+        code: impl Into<CloseCode>,
+        reason: &[u8],
+        timeout: Duration,
+    ) -> Result<bool, WsError> {
+        if !self.write_state.is_close_sent() {
+            let mut data = u16::from(code.into()).to_be_bytes().to_vec();
+            data.extend_from_slice(reason);
+            self.send(OpCode::Close, &data)?;
+            self.flush()?;
+        }
+        let deadline = Instant::now() + timeout;
+        let acked = loop {
+            if self.read_state.is_close_received() {
+                break true;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                break false;
+            }
+            if let Err(e) = self.stream.set_read_timeout(Some(deadline - now)) {
+                tracing::debug!("failed to set read timeout {e}");
+            }
+            match self.read_state.next_frame(&mut self.stream) {
+                Ok(_) => {}
+                Err(e) if is_timeout(&e) => {}
+                Err(e) => {
+                    tracing::debug!("failed to wait close frame {e}");
+                    break false;
+                }
+            }
+        };
+        if let Err(e) = self.stream.shutdown() {
+            tracing::debug!("failed to shutdown stream {e}");
+        }
+        Ok(acked)
+    }
+}
+
+/// message received by [FrameCodec::receive_stream], payload is read from underlying
+/// stream on demand
+pub struct MessageReader<'a, S: Read + Write> {
+    codec: &'a mut FrameCodec<S>,
+    header: SimplifiedHeader,
+    done: bool,
+}
+
+impl<'a, S: Read + Write> MessageReader<'a, S> {
+    /// opcode of message, text or binary
+    pub fn opcode(&self) -> OpCode {
+        self.header.code
+    }
+
+    /// check if whole message has been read
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+impl<'a, S: Read + Write> Read for MessageReader<'a, S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+        match self.codec.stream_read(buf)? {
+            StreamRead::Data(count) => Ok(count),
+            _ => {
+                self.done = true;
+                Ok(0)
+            }
+        }
+    }
+}
+
+/// message written fragment by fragment, each non-empty write is sent as a frame
+/// immediately, wrap it in [std::io::BufWriter] to avoid tiny frames
+///
+/// only [MessageWriter::finish] or [MessageWriter::finish_with] sends the final frame,
+/// if writer is dropped after some fragments are sent, e.g. by early return on error,
+/// close frame 1011 is sent and following data frames of codec fail with
+/// [WsError::UnfinishedMessage]
+pub struct MessageWriter<'a, S: Write> {
+    stream: &'a mut S,
+    write_state: &'a mut FrameWriteState,
+    code: OpCode,
+    started: bool,
+    finished: bool,
+}
+
+impl<'a, S: Write> MessageWriter<'a, S> {
+    pub(crate) fn new(
+        stream: &'a mut S,
+        write_state: &'a mut FrameWriteState,
+        code: OpCode,
+    ) -> Result<Self, WsError> {
+        if !code.is_data() {
+            return Err(WsError::UnsupportedFrame(code));
+        }
+        write_state.check_send(code)?;
+        Ok(Self {
+            stream,
+            write_state,
+            code,
+            started: false,
+            finished: false,
+        })
+    }
+
+    /// opcode of message, text or binary
+    pub fn opcode(&self) -> OpCode {
+        self.code
+    }
+
+    /// send a control frame between fragments
+    pub fn control(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        if code.is_data() {
+            return Err(WsError::UnsupportedFrame(code));
+        }
+        self.write_state.check_send(code)?;
+        self.write_state
+            .send_frame(self.stream, true, false, code, payload)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+
+    fn send_fragment(&mut self, fin: bool, payload: &[u8]) -> Result<(), WsError> {
+        let code = if self.started {
+            OpCode::Continue
+        } else {
+            self.code
+        };
+        self.write_state.check_send(code)?;
+        self.write_state
+            .send_frame(self.stream, fin, false, code, payload)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))?;
+        self.started = true;
+        Ok(())
+    }
+
+    /// send empty final frame and flush stream
+    pub fn finish(self) -> Result<(), WsError> {
+        self.finish_with(&[])
+    }
+
+    /// send last chunk of message as final frame and flush stream
+    pub fn finish_with(mut self, payload: &[u8]) -> Result<(), WsError> {
+        self.send_fragment(true, payload)?;
+        self.finished = true;
+        self.stream
+            .flush()
+            .map_err(|e| io_timeout(e, TimeoutPhase::Write))
+    }
+}
+
+impl<'a, S: Write> Write for MessageWriter<'a, S> {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.send_fragment(false, buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        self.stream.flush()
+    }
+}
+
+impl<'a, S: Write> Drop for MessageWriter<'a, S> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if !self.started {
+            return;
+        }
+        if let Some(payload) = self.write_state.set_unfinished() {
+            let ret =
+                self.write_state
+                    .send_frame(self.stream, true, false, OpCode::Close, &payload);
+            if let Err(e) = ret {
+                tracing::debug!("failed to send close frame {e}");
+            }
+        }
     }
 }
 
@@ -438,3 +821,388 @@ where
         )
     }
 }
+
+#[test]
+fn test_keepalive() {
+    use super::KeepaliveConfig;
+    use std::{net::TcpListener, time::Duration};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let config = FrameConfig {
+            mask_send_frame: false,
+            ..Default::default()
+        };
+        let mut server = FrameCodec::new_with(stream, config);
+        server.send(OpCode::Ping, b"hello").unwrap();
+        server.send(OpCode::Text, b"world").unwrap();
+        let (header, data) = server.receive().unwrap();
+        assert_eq!(header.code, OpCode::Pong);
+        assert_eq!(data, b"hello");
+        // keep silent, client should send ping then give up
+        let (header, _) = server.receive().unwrap();
+        assert_eq!(header.code, OpCode::Ping);
+        server
+    });
+
+    let stream = std::net::TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_millis(50)))
+        .unwrap();
+    let config = FrameConfig {
+        keepalive: KeepaliveConfig {
+            auto_pong: true,
+            ping_interval: Some(Duration::from_millis(100)),
+            timeout: Some(Duration::from_millis(300)),
+        },
+        ..Default::default()
+    };
+    let mut client = FrameCodec::new_with(stream, config);
+    let (header, data) = client.receive().unwrap();
+    assert_eq!(header.code, OpCode::Text);
+    assert_eq!(data, b"world");
+    assert!(matches!(
+        client.receive(),
+        Err(WsError::KeepaliveTimeout(_))
+    ));
+    server.join().unwrap();
+}
+
+#[test]
+fn test_close_handshake() {
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let config = FrameConfig {
+            mask_send_frame: false,
+            ..Default::default()
+        };
+        let mut server = FrameCodec::new_with(stream, config);
+        let (header, data) = server.receive().unwrap();
+        assert_eq!(header.code, OpCode::Close);
+        assert_eq!(data, b"\x03\xe8bye");
+        assert_eq!(server.state(), ConnectionState::Closed);
+        assert!(matches!(server.receive(), Err(WsError::ConnectionClosed)));
+        assert!(matches!(
+            server.send(OpCode::Text, b"hello"),
+            Err(WsError::SendAfterClose(OpCode::Text))
+        ));
+    });
+
+    let stream = std::net::TcpStream::connect(addr).unwrap();
+    let mut client = FrameCodec::new_with(stream, Default::default());
+    assert_eq!(client.state(), ConnectionState::Open);
+    assert!(client
+        .graceful_close(1000, b"bye", Duration::from_secs(3))
+        .unwrap());
+    assert_eq!(client.state(), ConnectionState::Closed);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_graceful_close_timeout() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    // peer never replies close frame
+    let server = std::thread::spawn(move || listener.accept().unwrap().0);
+
+    let stream = std::net::TcpStream::connect(addr).unwrap();
+    let mut client = FrameCodec::new_with(stream, Default::default());
+    let start = Instant::now();
+    assert!(!client
+        .graceful_close(1000, b"bye", Duration::from_millis(100))
+        .unwrap());
+    assert!(start.elapsed() < Duration::from_secs(3));
+    drop(server.join().unwrap());
+}
+
+#[test]
+fn test_keepalive_close() {
+    use super::KeepaliveConfig;
+    use std::io::Cursor;
+
+    let peer_frames = |frames: &[(OpCode, &[u8])]| {
+        let config = FrameConfig {
+            mask_send_frame: false,
+            ..Default::default()
+        };
+        let mut input = vec![];
+        FrameWriteState::with_config(config)
+            .send_many(&mut input, frames)
+            .unwrap();
+        input
+    };
+    let config = FrameConfig {
+        keepalive: KeepaliveConfig {
+            auto_pong: true,
+            // ping is due on every tick
+            ping_interval: Some(Duration::ZERO),
+            timeout: None,
+        },
+        ..Default::default()
+    };
+
+    // close from peer is echoed, no ping or pong is sent after that
+    let input = peer_frames(&[(OpCode::Close, b"\x03\xe8")]);
+    let stream = Duplex::new(input);
+    let mut client = FrameCodec::new_with(stream, config.clone());
+    let (header, data) = client.receive().unwrap();
+    assert_eq!((header.code, data), (OpCode::Close, &b"\x03\xe8"[..]));
+    let mut peer = FrameCodec::new(Cursor::new(std::mem::take(&mut client.stream.output)));
+    assert_eq!(peer.receive().unwrap().0.code, OpCode::Close);
+    assert!(peer.receive().is_err());
+
+    // ping received after close is sent is returned instead of replied
+    let input = peer_frames(&[(OpCode::Ping, b"p"), (OpCode::Close, b"\x03\xe8")]);
+    let stream = Duplex::new(input);
+    let mut client = FrameCodec::new_with(stream, config);
+    client.send(OpCode::Close, b"\x03\xe8").unwrap();
+    let (header, data) = client.receive().unwrap();
+    assert_eq!((header.code, data), (OpCode::Ping, &b"p"[..]));
+    assert_eq!(client.receive().unwrap().0.code, OpCode::Close);
+    assert_eq!(client.state(), ConnectionState::Closed);
+}
+
+#[cfg(test)]
+pub(crate) struct Duplex {
+    pub(crate) input: std::io::Cursor<Vec<u8>>,
+    pub(crate) output: Vec<u8>,
+}
+
+#[cfg(test)]
+impl Duplex {
+    pub(crate) fn new(input: Vec<u8>) -> Self {
+        Self {
+            input: std::io::Cursor::new(input),
+            output: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+impl Read for Duplex {
+    fn read(&mut self, buf: &mut [u8]) -> IOResult<usize> {
+        self.input.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl Write for Duplex {
+    fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+        self.output.write(buf)
+    }
+
+    fn flush(&mut self) -> IOResult<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_receive_stream() {
+    let frame = |code, fin, payload: &[u8]| {
+        let mut frame = OwnedFrame::new(code, rand::random::<[u8; 4]>(), payload);
+        frame.header_mut().set_fin(fin);
+        [&frame.header().0[..], frame.payload()].concat()
+    };
+    let input = [
+        frame(OpCode::Text, false, "hello ".as_bytes()),
+        frame(OpCode::Ping, true, b"p"),
+        frame(OpCode::Continue, false, &"世界".as_bytes()[..4]),
+        frame(OpCode::Continue, true, &"世界".as_bytes()[4..]),
+        frame(OpCode::Binary, true, b"next"),
+    ]
+    .concat();
+    let stream = Duplex::new(input);
+    let mut codec = FrameCodec::new_with(
+        stream,
+        FrameConfig {
+            mask_send_frame: false,
+            ..Default::default()
+        },
+    );
+    let mut reader = codec.receive_stream().unwrap();
+    assert_eq!(reader.opcode(), OpCode::Text);
+    let mut text = vec![];
+    let mut buf = [0; 3];
+    loop {
+        let count = reader.read(&mut buf).unwrap();
+        if count == 0 {
+            break;
+        }
+        text.extend_from_slice(&buf[..count]);
+    }
+    assert!(reader.is_done());
+    assert_eq!(String::from_utf8(text).unwrap(), "hello 世界");
+    assert_eq!(codec.stream_mut().output, [0x8a, 1, b'p']);
+
+    let mut reader = codec.receive_stream().unwrap();
+    assert_eq!(reader.opcode(), OpCode::Binary);
+    let mut data = vec![];
+    reader.read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"next");
+}
+
+#[test]
+fn test_message_writer() {
+    let stream = Duplex::new(vec![]);
+    let config = FrameConfig {
+        mask_send_frame: false,
+        ..Default::default()
+    };
+    let mut codec = FrameCodec::new_with(stream, config);
+    let mut writer = codec.begin_message(OpCode::Text).unwrap();
+    writer.write_all(b"hello ").unwrap();
+    writer.control(OpCode::Ping, b"p").unwrap();
+    writer.finish_with(b"world").unwrap();
+    // dropped writer sends close frame 1011, following data frames are rejected
+    codec
+        .begin_message(OpCode::Binary)
+        .unwrap()
+        .write_all(b"next")
+        .unwrap();
+    assert!(matches!(
+        codec.send(OpCode::Text, b"last"),
+        Err(WsError::UnfinishedMessage(OpCode::Text))
+    ));
+    assert!(codec.begin_message(OpCode::Text).is_err());
+    assert!(matches!(
+        codec.send(OpCode::Ping, b""),
+        Err(WsError::SendAfterClose(OpCode::Ping))
+    ));
+
+    let output = std::mem::take(&mut codec.stream_mut().output);
+    let config = FrameConfig {
+        merge_frame: false,
+        ..Default::default()
+    };
+    let mut peer = FrameCodec::new_with(Duplex::new(output), config);
+    let expected: [(bool, OpCode, &[u8]); 5] = [
+        (false, OpCode::Text, b"hello "),
+        (true, OpCode::Ping, b"p"),
+        (true, OpCode::Continue, b"world"),
+        (false, OpCode::Binary, b"next"),
+        (true, OpCode::Close, b"\x03\xf3message writer dropped"),
+    ];
+    for (fin, code, payload) in expected {
+        let (header, data) = peer.receive().unwrap();
+        assert_eq!((header.fin, header.code, data), (fin, code, payload));
+    }
+}
+
+#[test]
+fn test_send_many() {
+    use std::io::Cursor;
+
+    /// stream which accepts at most 3 bytes of each write
+    struct Slow(Vec<u8>);
+
+    impl Write for Slow {
+        fn write(&mut self, buf: &[u8]) -> IOResult<usize> {
+            let len = buf.len().min(3);
+            self.0.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> IOResult<()> {
+            Ok(())
+        }
+    }
+
+    let frames: &[(OpCode, &[u8])] = &[
+        (OpCode::Text, b"hello world"),
+        (OpCode::Ping, b""),
+        (OpCode::Binary, &[1; 300]),
+    ];
+    for mask_send_frame in [false, true] {
+        let config = FrameConfig {
+            mask_send_frame,
+            auto_fragment_size: 4,
+            ..Default::default()
+        };
+        let mut stream = Slow(vec![]);
+        FrameWriteState::with_config(config)
+            .send_many(&mut stream, frames)
+            .unwrap();
+        let mut codec = FrameCodec::new(Cursor::new(stream.0));
+        for (code, payload) in frames {
+            let msg = codec.receive_message().unwrap();
+            assert_eq!((msg.opcode(), msg.payload().as_ref()), (*code, *payload));
+        }
+    }
+
+    // frame after close is rejected without side effects
+    let stream = Duplex::new(vec![]);
+    let mut codec = FrameCodec::new(stream);
+    assert!(matches!(
+        codec.send_many(&[(OpCode::Close, b""), (OpCode::Text, b"late")]),
+        Err(WsError::SendAfterClose(OpCode::Text))
+    ));
+    assert!(codec.stream.output.is_empty());
+    assert_eq!(codec.state(), ConnectionState::Open);
+    codec.send_many(&[(OpCode::Close, b"")]).unwrap();
+    assert!(codec.write_state.is_close_sent());
+
+    // close is not recorded if write fails
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _buf: &mut [u8]) -> IOResult<usize> {
+            Ok(0)
+        }
+    }
+
+    impl Write for Broken {
+        fn write(&mut self, _buf: &[u8]) -> IOResult<usize> {
+            Err(std::io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> IOResult<()> {
+            Ok(())
+        }
+    }
+
+    let mut codec = FrameCodec::new(Broken);
+    assert!(codec.send_many(&[(OpCode::Close, b"")]).is_err());
+    assert!(!codec.write_state.is_close_sent());
+    assert!(codec.send(OpCode::Close, b"").is_err());
+    assert!(!codec.write_state.is_close_sent());
+}
+
+#[test]
+fn test_receive_message_fragment() {
+    use std::io::Cursor;
+
+    let config = FrameConfig {
+        mask_send_frame: false,
+        ..Default::default()
+    };
+    let mut codec = FrameCodec::new_with(Duplex::new(vec![]), config);
+    let mut writer = codec.begin_message(OpCode::Text).unwrap();
+    writer.write_all(b"hel").unwrap();
+    writer.finish_with(b"lo").unwrap();
+    codec.send(OpCode::Text, b"hello").unwrap();
+
+    let output = std::mem::take(&mut codec.stream.output);
+    let config = FrameConfig {
+        merge_frame: false,
+        ..Default::default()
+    };
+    let mut peer = FrameCodec::new_with(Cursor::new(output), config);
+    assert!(matches!(
+        peer.receive_message(),
+        Err(WsError::UnsupportedFrame(OpCode::Text))
+    ));
+    assert!(matches!(
+        peer.receive_message(),
+        Err(WsError::UnsupportedFrame(OpCode::Continue))
+    ));
+    assert_eq!(
+        peer.receive_message().unwrap(),
+        WsMessage::Text("hello".into())
+    );
+}
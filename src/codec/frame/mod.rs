@@ -1,10 +1,12 @@
-use crate::errors::{ProtocolError, WsError};
+use crate::errors::{ProtocolError, TimeoutPhase, WsError};
 use crate::frame::{get_bit, HeaderView, OpCode, SimplifiedHeader};
 use http;
-use crate::protocol::{cal_accept_key, standard_handshake_req_check};
+use crate::protocol::{cal_accept_key, standard_handshake_req_check, CloseCode};
 use bytes::BytesMut;
 use std::fmt::Debug;
+use std::io::IoSlice;
 use std::ops::Range;
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "sync")]
 mod blocking;
@@ -18,6 +20,9 @@ mod non_blocking;
 #[cfg(feature = "async")]
 pub use non_blocking::*;
 
+mod streaming;
+pub(crate) use streaming::*;
+
 /// text frame utf-8 checking policy
 #[derive(Debug, Clone)]
 pub enum ValidateUtf8Policy {
@@ -40,6 +45,185 @@ impl ValidateUtf8Policy {
     }
 }
 
+/// keepalive config, all disabled by default
+///
+/// keepalive only works for codecs which are not split, for blocking codecs, timer
+/// is driven by read timeout of underlying socket, so read timeout should be set to
+/// a value less than `ping_interval` and `timeout`
+#[derive(Debug, Clone, Default)]
+pub struct KeepaliveConfig {
+    /// reply ping frame with pong frame of same payload, ping frame will not be
+    /// returned to caller
+    pub auto_pong: bool,
+    /// send ping frame periodically
+    pub ping_interval: Option<Duration>,
+    /// fail connection with [WsError::KeepaliveTimeout] if no frame received
+    /// from peer within this duration
+    pub timeout: Option<Duration>,
+}
+
+impl KeepaliveConfig {
+    /// check if any keepalive feature is enabled
+    pub fn is_enabled(&self) -> bool {
+        self.auto_pong || self.ping_interval.is_some() || self.timeout.is_some()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct KeepaliveState {
+    config: KeepaliveConfig,
+    last_recv: Instant,
+    last_ping: Instant,
+    last_pong: Option<Instant>,
+}
+
+impl KeepaliveState {
+    pub(crate) fn new(config: KeepaliveConfig) -> Self {
+        let now = Instant::now();
+        Self {
+            config,
+            last_recv: now,
+            last_ping: now,
+            last_pong: None,
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.config.is_enabled()
+    }
+
+    pub(crate) fn last_pong(&self) -> Option<Instant> {
+        self.last_pong
+    }
+
+    /// record received frame, return true if need to reply pong
+    pub(crate) fn on_frame(&mut self, code: OpCode) -> bool {
+        let now = Instant::now();
+        self.last_recv = now;
+        match code {
+            OpCode::Pong => {
+                self.last_pong = Some(now);
+                false
+            }
+            OpCode::Ping => self.config.auto_pong,
+            _ => false,
+        }
+    }
+
+    /// check timer, return true if need to send ping
+    pub(crate) fn on_tick(&mut self) -> Result<bool, WsError> {
+        let now = Instant::now();
+        if let Some(timeout) = self.config.timeout {
+            let elapsed = now.duration_since(self.last_recv);
+            if elapsed >= timeout {
+                return Err(WsError::KeepaliveTimeout(elapsed));
+            }
+        }
+        match self.config.ping_interval {
+            Some(interval) if now.duration_since(self.last_ping) >= interval => {
+                self.last_ping = now;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// next time to check timer
+    #[cfg(feature = "async")]
+    pub(crate) fn deadline(&self) -> Option<Instant> {
+        let ping = self.config.ping_interval.map(|i| self.last_ping + i);
+        let timeout = self.config.timeout.map(|t| self.last_recv + t);
+        match (ping, timeout) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// websocket connection state, see close handshake of RFC 6455
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// both side can send & receive message
+    Open,
+    /// close frame has been sent or received, waiting for the other side
+    Closing,
+    /// close frame has been sent and received
+    Closed,
+}
+
+impl ConnectionState {
+    pub(crate) fn new(close_sent: bool, close_received: bool) -> Self {
+        match (close_sent, close_received) {
+            (false, false) => Self::Open,
+            (true, true) => Self::Closed,
+            _ => Self::Closing,
+        }
+    }
+}
+
+/// close code to echo, RFC 6455 requires endpoint to echo status code it received
+#[inline]
+pub(crate) fn echo_close_payload(payload: &[u8]) -> &[u8] {
+    &payload[..payload.len().min(2)]
+}
+
+/// check if error is caused by read timeout
+#[cfg(feature = "sync")]
+pub(crate) fn is_timeout(e: &WsError) -> bool {
+    match e {
+        WsError::IOError(e) => is_io_timeout(e),
+        WsError::Timeout { phase } => *phase == TimeoutPhase::Read,
+        _ => false,
+    }
+}
+
+#[cfg(feature = "sync")]
+fn is_io_timeout(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+    )
+}
+
+/// convert read/write timeout of blocking socket to [WsError::Timeout] of given phase
+#[cfg(feature = "sync")]
+pub(crate) fn io_timeout(e: impl Into<WsError>, phase: TimeoutPhase) -> WsError {
+    match e.into() {
+        WsError::IOError(e) if is_io_timeout(&e) => WsError::Timeout { phase },
+        e => e,
+    }
+}
+
+/// run future with optional timeout
+#[cfg(feature = "async")]
+pub(crate) async fn with_timeout<T>(
+    timeout: Option<Duration>,
+    phase: TimeoutPhase,
+    fut: impl std::future::Future<Output = Result<T, WsError>>,
+) -> Result<T, WsError> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, fut)
+            .await
+            .map_err(|_| WsError::Timeout { phase })?,
+        None => fut.await,
+    }
+}
+
+/// run future until optional deadline
+#[cfg(feature = "async")]
+pub(crate) async fn with_deadline<T>(
+    deadline: Option<tokio::time::Instant>,
+    phase: TimeoutPhase,
+    fut: impl std::future::Future<Output = Result<T, WsError>>,
+) -> Result<T, WsError> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, fut)
+            .await
+            .map_err(|_| WsError::Timeout { phase })?,
+        None => fut.await,
+    }
+}
+
 /// frame send/recv config
 #[derive(Debug, Clone)]
 pub struct FrameConfig {
@@ -63,6 +247,19 @@ pub struct FrameConfig {
     pub resize_size: usize,
     /// if available len < resize, resize read buf, default 1K
     pub resize_thresh: usize,
+    /// auto pong, heartbeat and dead peer detection, disabled by default
+    pub keepalive: KeepaliveConfig,
+    /// fail with [WsError::Timeout] if no frame is received within this duration,
+    /// only works for async codecs which are not split, blocking codecs rely on
+    /// read timeout of underlying socket
+    pub read_timeout: Option<Duration>,
+    /// fail with [WsError::Timeout] if sending or flushing does not complete within
+    /// this duration, only works for async codecs which are not split, blocking codecs
+    /// rely on write timeout of underlying socket
+    ///
+    /// unwritten part of a timed out frame is kept and written by following send or
+    /// flush before next frame
+    pub write_timeout: Option<Duration>,
 }
 
 impl Default for FrameConfig {
@@ -78,6 +275,9 @@ impl Default for FrameConfig {
             validate_utf8: ValidateUtf8Policy::FastFail,
             resize_size: 4096,
             resize_thresh: 1024,
+            keepalive: KeepaliveConfig::default(),
+            read_timeout: None,
+            write_timeout: None,
         }
     }
 }
@@ -108,6 +308,9 @@ pub struct FrameReadState {
     fragmented_data: Vec<u8>,
     fragmented_type: OpCode,
     buf: FrameBuffer,
+    pub(crate) keepalive: KeepaliveState,
+    close_received: bool,
+    streaming: Option<StreamState>,
 }
 
 impl Default for FrameReadState {
@@ -118,6 +321,9 @@ impl Default for FrameReadState {
             fragmented_data: vec![],
             fragmented_type: OpCode::default(),
             buf: FrameBuffer::new(),
+            keepalive: KeepaliveState::new(Default::default()),
+            close_received: false,
+            streaming: None,
         }
     }
 }
@@ -126,11 +332,40 @@ impl FrameReadState {
     /// construct with config
     pub fn with_config(config: FrameConfig) -> Self {
         Self {
+            keepalive: KeepaliveState::new(config.keepalive.clone()),
             config,
             ..Self::default()
         }
     }
 
+    /// update keepalive config and reset keepalive timer
+    pub fn set_keepalive(&mut self, config: KeepaliveConfig) {
+        self.keepalive = KeepaliveState::new(config.clone());
+        self.config.keepalive = config;
+    }
+
+    /// check if close frame from peer has been received
+    pub fn is_close_received(&self) -> bool {
+        self.close_received
+    }
+
+    /// payload of frame returned by `next_frame`, `None` means merged fragmented frame
+    #[inline]
+    pub(crate) fn payload(&self, range: Option<Range<usize>>) -> &[u8] {
+        match range {
+            Some(range) => &self.buf.buf[range],
+            None => &self.fragmented_data,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn payload_mut(&mut self, range: Option<Range<usize>>) -> &mut [u8] {
+        match range {
+            Some(range) => &mut self.buf.buf[range],
+            None => &mut self.fragmented_data,
+        }
+    }
+
     /// check if data in buffer is enough to parse frame header
     pub fn is_header_ok(&self) -> bool {
         let ava_data = self.buf.ava_data();
@@ -192,19 +427,19 @@ impl FrameReadState {
 
         if check_rsv && !(leading_bits == 0b00001000 || leading_bits == 0b00000000) {
             return Err(WsError::ProtocolError {
-                close_code: 1008,
+                close_code: CloseCode::PolicyViolation,
                 error: ProtocolError::InvalidLeadingBits(leading_bits),
             });
         }
         let (len_occ_bytes, payload_len) =
             parse_payload_len(ava_data).map_err(|e| WsError::ProtocolError {
-                close_code: 1008,
+                close_code: CloseCode::PolicyViolation,
                 error: e,
             })?;
 
         if max_payload_size > 0 && payload_len > max_payload_size {
             return Err(WsError::ProtocolError {
-                close_code: 1008,
+                close_code: CloseCode::PolicyViolation,
                 error: ProtocolError::PayloadTooLarge(max_payload_size),
             });
         }
@@ -252,7 +487,7 @@ impl FrameReadState {
             OpCode::Continue => {
                 if !*fragmented {
                     return Err(WsError::ProtocolError {
-                        close_code: 1002,
+                        close_code: CloseCode::ProtocolError,
                         error: ProtocolError::MissInitialFragmentedFrame,
                     });
                 }
@@ -264,7 +499,7 @@ impl FrameReadState {
             OpCode::Binary => {
                 if *fragmented {
                     return Err(WsError::ProtocolError {
-                        close_code: 1002,
+                        close_code: CloseCode::ProtocolError,
                         error: ProtocolError::NotContinueFrameAfterFragmented,
                     });
                 }
@@ -274,7 +509,7 @@ impl FrameReadState {
             OpCode::Text => {
                 if *fragmented {
                     return Err(WsError::ProtocolError {
-                        close_code: 1002,
+                        close_code: CloseCode::ProtocolError,
                         error: ProtocolError::NotContinueFrameAfterFragmented,
                     });
                 }
@@ -285,7 +520,7 @@ impl FrameReadState {
                         && simdutf8::basic::from_utf8(payload).is_err()
                     {
                         return Err(WsError::ProtocolError {
-                            close_code: 1007,
+                            close_code: CloseCode::InvalidPayload,
                             error: ProtocolError::InvalidUtf8,
                         });
                     }
@@ -297,7 +532,7 @@ impl FrameReadState {
                         && simdutf8::basic::from_utf8(payload).is_err()
                     {
                         return Err(WsError::ProtocolError {
-                            close_code: 1007,
+                            close_code: CloseCode::InvalidPayload,
                             error: ProtocolError::InvalidUtf8,
                         });
                     }
@@ -307,7 +542,7 @@ impl FrameReadState {
             OpCode::Close | OpCode::Ping | OpCode::Pong => {
                 if !header.fin {
                     return Err(WsError::ProtocolError {
-                        close_code: 1002,
+                        close_code: CloseCode::ProtocolError,
                         error: ProtocolError::FragmentedControlFrame,
                     });
                 }
@@ -315,7 +550,7 @@ impl FrameReadState {
                 if payload.len() > 125 {
                     let error = ProtocolError::ControlFrameTooBig(payload_len);
                     return Err(WsError::ProtocolError {
-                        close_code: 1002,
+                        close_code: CloseCode::ProtocolError,
                         error,
                     });
                 }
@@ -323,7 +558,7 @@ impl FrameReadState {
                     if payload_len == 1 {
                         let error = ProtocolError::InvalidCloseFramePayload;
                         return Err(WsError::ProtocolError {
-                            close_code: 1002,
+                            close_code: CloseCode::ProtocolError,
                             error,
                         });
                     }
@@ -332,14 +567,10 @@ impl FrameReadState {
                         let mut code_byte = [0u8; 2];
                         code_byte.copy_from_slice(&payload[..2]);
                         let code = u16::from_be_bytes(code_byte);
-                        if code < 1000
-                            || (1004..=1006).contains(&code)
-                            || (1015..=2999).contains(&code)
-                            || code >= 5000
-                        {
+                        if !CloseCode::from(code).is_sendable() {
                             let error = ProtocolError::InvalidCloseCode(code);
                             return Err(WsError::ProtocolError {
-                                close_code: 1002,
+                                close_code: CloseCode::ProtocolError,
                                 error,
                             });
                         }
@@ -348,7 +579,7 @@ impl FrameReadState {
                         if String::from_utf8(payload[2..].to_vec()).is_err() {
                             let error = ProtocolError::InvalidUtf8;
                             return Err(WsError::ProtocolError {
-                                close_code: 1007,
+                                close_code: CloseCode::InvalidPayload,
                                 error,
                             });
                         }
@@ -468,6 +699,10 @@ pub struct FrameWriteState {
     config: FrameConfig,
     header_buf: [u8; 14],
     buf: BytesMut,
+    close_sent: bool,
+    /// message writer was dropped before final frame is sent
+    unfinished: bool,
+    pub(crate) pending: BytesMut,
 }
 
 impl FrameWriteState {
@@ -477,6 +712,202 @@ impl FrameWriteState {
             config,
             header_buf: [0; 14],
             buf: BytesMut::new(),
+            close_sent: false,
+            unfinished: false,
+            pending: BytesMut::new(),
+        }
+    }
+
+    /// check if close frame has been sent
+    pub fn is_close_sent(&self) -> bool {
+        self.close_sent
+    }
+
+    /// return [WsError::SendAfterClose] if close frame has been sent, close frame is
+    /// recorded by [FrameWriteState::set_sent] after it's written
+    #[inline]
+    pub(crate) fn check_send(&self, code: OpCode) -> Result<(), WsError> {
+        if self.unfinished && code.is_data() {
+            return Err(WsError::UnfinishedMessage(code));
+        }
+        if self.close_sent {
+            return Err(WsError::SendAfterClose(code));
+        }
+        Ok(())
+    }
+
+    /// check a batch of frames without changing state, frame after close frame is
+    /// rejected
+    pub(crate) fn check_send_many(&self, frames: &[(OpCode, &[u8])]) -> Result<(), WsError> {
+        let mut close = self.close_sent;
+        for (code, _) in frames {
+            if self.unfinished && code.is_data() {
+                return Err(WsError::UnfinishedMessage(*code));
+            }
+            if close {
+                return Err(WsError::SendAfterClose(*code));
+            }
+            close = *code == OpCode::Close;
+        }
+        Ok(())
+    }
+
+    /// reject following data frames after message writer is dropped in the middle of
+    /// a message, return payload of close frame 1011 to send if close frame has not
+    /// been sent
+    pub(crate) fn set_unfinished(&mut self) -> Option<Vec<u8>> {
+        self.unfinished = true;
+        if self.close_sent {
+            return None;
+        }
+        let mut payload = u16::from(CloseCode::InternalError).to_be_bytes().to_vec();
+        payload.extend_from_slice(b"message writer dropped");
+        Some(payload)
+    }
+
+    /// record close frame after it's written, async write records it once it's
+    /// written or encoded into pending buffer, which is written by following write
+    /// or flush even if current write is interrupted
+    pub(crate) fn set_sent(&mut self, code: OpCode) {
+        if code == OpCode::Close {
+            self.close_sent = true;
+        }
+    }
+
+    /// whether encoded prepared frame can be written without mask or fragmentation
+    pub(crate) fn can_send_prepared(&self, frame: &super::PreparedFrame) -> bool {
+        let size = self.config.auto_fragment_size;
+        !self.config.mask_send_frame && (size == 0 || frame.payload().len() <= size)
+    }
+
+    /// split frames into `(fin, opcode, payload)` of each frame to write, data frame
+    /// is fragmented if auto_fragment_size > 0
+    pub(crate) fn fragments<'a>(
+        &self,
+        frames: &[(OpCode, &'a [u8])],
+    ) -> Vec<(bool, OpCode, &'a [u8])> {
+        let size = self.config.auto_fragment_size;
+        let mut fragments = Vec::with_capacity(frames.len());
+        for &(code, payload) in frames {
+            if size == 0 || payload.len() <= size || !code.is_data() {
+                fragments.push((true, code, payload));
+                continue;
+            }
+            let total = payload.len().div_ceil(size);
+            for (idx, chunk) in payload.chunks(size).enumerate() {
+                let code = if idx == 0 { code } else { OpCode::Continue };
+                fragments.push((idx + 1 == total, code, chunk));
+            }
+        }
+        fragments
+    }
+
+    /// encode unmasked headers of frames into `buf` and return io slices of headers
+    /// and payloads, payloads are not copied
+    ///
+    /// masked frames should be encoded by [FrameWriteState::encode_frame]
+    pub(crate) fn encode_vectored<'a>(
+        buf: &'a mut BytesMut,
+        frames: &[(bool, OpCode, &'a [u8])],
+    ) -> Vec<IoSlice<'a>> {
+        let mut header_buf = [0; 14];
+        buf.clear();
+        for &(fin, code, payload) in frames {
+            let header = crate::frame::ctor_header(
+                &mut header_buf,
+                fin,
+                false,
+                false,
+                false,
+                None,
+                code,
+                payload.len() as u64,
+            );
+            buf.extend_from_slice(header);
+        }
+        let mut slices = Vec::with_capacity(frames.len() * 2);
+        let mut offset = 0;
+        for &(_, _, payload) in frames {
+            let len = crate::frame::header_len(false, payload.len() as u64);
+            slices.push(IoSlice::new(&buf[offset..(offset + len)]));
+            offset += len;
+            if !payload.is_empty() {
+                slices.push(IoSlice::new(payload));
+            }
+        }
+        slices
+    }
+
+    /// header of a single unmasked frame if payload need not fragment
+    pub(crate) fn single_header(&mut self, code: OpCode, payload: &[u8]) -> Option<&[u8]> {
+        let size = self.config.auto_fragment_size;
+        if self.config.mask_send_frame || (size > 0 && payload.len() > size && code.is_data()) {
+            return None;
+        }
+        Some(crate::frame::ctor_header(
+            &mut self.header_buf,
+            true,
+            false,
+            false,
+            false,
+            None,
+            code,
+            payload.len() as u64,
+        ))
+    }
+
+    /// release inner buffers after write if renew_buf_on_write is set
+    pub(crate) fn renew_buf(&mut self) {
+        if self.config.renew_buf_on_write {
+            self.buf = BytesMut::new();
+            if self.pending.is_empty() {
+                self.pending = BytesMut::new();
+            }
+        }
+    }
+
+    /// encode payload into pending buffer, data frame is fragmented if
+    /// auto_fragment_size > 0, rsv1 is set on first frame only
+    #[cfg(feature = "async")]
+    pub(crate) fn encode(&mut self, code: OpCode, rsv1: bool, payload: &[u8]) {
+        let size = self.config.auto_fragment_size;
+        if size == 0 || payload.len() <= size || !code.is_data() {
+            self.encode_frame(true, rsv1, code, payload);
+            self.set_sent(code);
+            return;
+        }
+        let total = payload.len().div_ceil(size);
+        for (idx, chunk) in payload.chunks(size).enumerate() {
+            if idx == 0 {
+                self.encode_frame(false, rsv1, code, chunk);
+            } else {
+                self.encode_frame(idx + 1 == total, false, OpCode::Continue, chunk);
+            }
+        }
+    }
+
+    /// encode a single frame into pending buffer
+    pub(crate) fn encode_frame(&mut self, fin: bool, rsv1: bool, code: OpCode, payload: &[u8]) {
+        let mask: Option<[u8; 4]> = if self.config.mask_send_frame {
+            Some(rand::random())
+        } else {
+            None
+        };
+        let header = crate::frame::ctor_header(
+            &mut self.header_buf,
+            fin,
+            rsv1,
+            false,
+            false,
+            mask,
+            code,
+            payload.len() as u64,
+        );
+        self.pending.extend_from_slice(header);
+        let start = self.pending.len();
+        self.pending.extend_from_slice(payload);
+        if let Some(mask) = mask {
+            apply_mask(&mut self.pending[start..], mask);
         }
     }
 }
@@ -486,6 +917,14 @@ pub fn default_handshake_handler(
     req: http::Request<()>,
 ) -> Result<(http::Request<()>, http::Response<String>), (http::Response<String>, WsError)> {
     match standard_handshake_req_check(&req) {
+        Ok(_) if req.version() == http::Version::HTTP_2 => {
+            let resp = http::Response::builder()
+                .version(http::Version::HTTP_2)
+                .status(http::StatusCode::OK)
+                .body(String::new())
+                .unwrap();
+            Ok((req, resp))
+        }
         Ok(_) => {
             let key = req.headers().get("sec-websocket-key").unwrap();
             let resp = http::Response::builder()
@@ -499,8 +938,12 @@ pub fn default_handshake_handler(
             Ok((req, resp))
         }
         Err(e) => {
+            let version = match req.version() {
+                http::Version::HTTP_2 => http::Version::HTTP_2,
+                _ => http::Version::HTTP_11,
+            };
             let resp = http::Response::builder()
-                .version(http::Version::HTTP_11)
+                .version(version)
                 .status(http::StatusCode::BAD_REQUEST)
                 .header("Content-Type", "text/html")
                 .body(e.to_string())
@@ -1,85 +1,194 @@
-use http;
 use bytes::BytesMut;
-use std::{io::IoSlice, ops::Range};
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use http;
+use std::{
+    io::IoSlice,
+    ops::Range,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
 
-use super::{apply_mask, FrameConfig, FrameReadState, FrameWriteState};
+use super::{
+    echo_close_payload, with_deadline, with_timeout, ConnectionState, FrameConfig, FrameReadState,
+    FrameWriteState, StreamRead, StreamStep,
+};
+#[cfg(feature = "stream")]
+use crate::Message;
 use crate::{
-    codec::Split,
-    errors::WsError,
-    frame::{ctor_header, header_len, OpCode, OwnedFrame, SimplifiedHeader},
-    protocol::standard_handshake_resp_check,
+    codec::{PreparedFrame, Split},
+    errors::{TimeoutPhase, WsError},
+    frame::{OpCode, OwnedFrame, SimplifiedHeader},
+    protocol::{standard_handshake_resp_check, CloseCode},
+    WsMessage,
 };
 
 type IOResult<T> = std::io::Result<T>;
+type NextFrame = Result<(SimplifiedHeader, Option<Range<usize>>), WsError>;
 
 impl FrameReadState {
     #[inline]
-    async fn async_poll<S: AsyncRead + Unpin>(&mut self, stream: &mut S) -> IOResult<usize> {
-        let buf = self.buf.prepare(self.config.resize_size);
-        let count = stream.read(buf).await?;
-        self.buf.produce(count);
-        if count == 0 {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::ConnectionAborted,
-                "read eof",
-            ));
-        }
-        Ok(count)
-    }
-
-    #[inline]
-    async fn async_poll_one_frame<S: AsyncRead + Unpin>(
+    fn poll_fill<S: AsyncRead + Unpin>(
         &mut self,
+        cx: &mut Context<'_>,
         stream: &mut S,
         size: usize,
-    ) -> IOResult<()> {
-        let read_len = self.buf.ava_data().len();
-        if read_len < size {
-            let buf = self.buf.prepare(size - read_len);
-            stream.read_exact(buf).await?;
-            self.buf.produce(size - read_len);
-        }
-        Ok(())
+    ) -> Poll<IOResult<usize>> {
+        let mut buf = ReadBuf::new(self.buf.prepare(size));
+        ready!(Pin::new(stream).poll_read(cx, &mut buf))?;
+        let count = buf.filled().len();
+        self.buf.produce(count);
+        Poll::Ready(Ok(count))
     }
 
+    /// read until buffer contains a whole frame, keep partial read data in buffer
+    /// so that it can be resumed on next poll
     #[inline]
-    async fn async_read_one_frame<S: AsyncRead + Unpin>(
+    fn poll_read_one_frame<S: AsyncRead + Unpin>(
         &mut self,
+        cx: &mut Context<'_>,
         stream: &mut S,
-    ) -> Result<(SimplifiedHeader, Range<usize>), WsError> {
+    ) -> Poll<Result<(SimplifiedHeader, Range<usize>), WsError>> {
         while !self.is_header_ok() {
-            self.async_poll(stream).await?;
+            let resize_size = self.config.resize_size;
+            if ready!(self.poll_fill(cx, stream, resize_size))? == 0 {
+                return Poll::Ready(Err(WsError::IOError(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionAborted,
+                    "read eof",
+                ))));
+            }
         }
         let (header_len, payload_len, total_len) = self.parse_frame_header()?;
-        self.async_poll_one_frame(stream, total_len).await?;
-        Ok(self.consume_frame(header_len, payload_len, total_len))
+        loop {
+            let read_len = self.buf.ava_data().len();
+            if read_len >= total_len {
+                break;
+            }
+            if ready!(self.poll_fill(cx, stream, total_len - read_len))? == 0 {
+                return Poll::Ready(Err(WsError::IOError(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "read eof",
+                ))));
+            }
+        }
+        Poll::Ready(Ok(self.consume_frame(header_len, payload_len, total_len)))
     }
 
-    /// **NOTE** masked frame has already been unmasked
-    pub async fn async_receive<S: AsyncRead + Unpin>(
+    /// poll a frame, return payload range in read buffer, `None` means payload is
+    /// merged fragmented data
+    pub(crate) fn poll_next_frame<S: AsyncRead + Unpin>(
         &mut self,
+        cx: &mut Context<'_>,
         stream: &mut S,
-    ) -> Result<(SimplifiedHeader, &[u8]), WsError> {
-        if self.config.merge_frame {
+    ) -> Poll<NextFrame> {
+        if self.close_received {
+            return Poll::Ready(Err(WsError::ConnectionClosed));
+        }
+        let (header, range) = if self.config.merge_frame {
             loop {
-                let (mut header, range) = self.async_read_one_frame(stream).await?;
+                let (mut header, range) = ready!(self.poll_read_one_frame(cx, stream))?;
                 if let Some(merged) = self
                     .check_frame(header, range.clone())
                     .and_then(|_| self.merge_frame(header, range.clone()))?
                 {
                     if merged {
                         header.code = self.fragmented_type;
-                        break Ok((header, &self.fragmented_data));
+                        break (header, None);
                     } else {
-                        break Ok((header, &self.buf.buf[range]));
+                        break (header, Some(range));
                     }
                 }
             }
         } else {
-            let (header, range) = self.async_read_one_frame(stream).await?;
+            let (header, range) = ready!(self.poll_read_one_frame(cx, stream))?;
             self.check_frame(header, range.clone())?;
-            Ok((header, &self.buf.buf[range]))
+            (header, Some(range))
+        };
+        self.close_received = header.code == OpCode::Close;
+        Poll::Ready(Ok((header, range)))
+    }
+
+    /// read a frame, return payload range in read buffer, `None` means payload is
+    /// merged fragmented data
+    ///
+    /// this future is cancel safe
+    pub(crate) async fn async_next_frame<S: AsyncRead + Unpin>(
+        &mut self,
+        stream: &mut S,
+    ) -> NextFrame {
+        std::future::poll_fn(|cx| self.poll_next_frame(cx, stream)).await
+    }
+
+    /// **NOTE** masked frame has already been unmasked
+    pub async fn async_receive<S: AsyncRead + Unpin>(
+        &mut self,
+        stream: &mut S,
+    ) -> Result<(SimplifiedHeader, &[u8]), WsError> {
+        let (header, range) = self.async_next_frame(stream).await?;
+        Ok((header, self.payload(range)))
+    }
+
+    /// async version of [FrameReadState::stream_read](super::FrameReadState)
+    pub(crate) fn poll_stream_read<S: AsyncRead + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stream: &mut S,
+        buf: &mut [u8],
+    ) -> Poll<Result<StreamRead, WsError>> {
+        if self.close_received {
+            return Poll::Ready(Err(WsError::ConnectionClosed));
+        }
+        loop {
+            match self.stream_step()? {
+                StreamStep::Payload(remaining) => {
+                    let len = remaining.min(buf.len());
+                    let buf = &mut buf[..len];
+                    if buf.is_empty() {
+                        return Poll::Ready(Ok(StreamRead::Data(0)));
+                    }
+                    let mut count = self.stream_buffered(buf);
+                    if count == 0 {
+                        let mut read_buf = ReadBuf::new(buf);
+                        ready!(Pin::new(&mut *stream).poll_read(cx, &mut read_buf))?;
+                        count = read_buf.filled().len();
+                        if count == 0 {
+                            return Poll::Ready(Err(WsError::IOError(std::io::Error::new(
+                                std::io::ErrorKind::UnexpectedEof,
+                                "read eof",
+                            ))));
+                        }
+                    }
+                    self.stream_payload(&mut buf[..count])?;
+                    return Poll::Ready(Ok(StreamRead::Data(count)));
+                }
+                StreamStep::End(header) => return Poll::Ready(Ok(StreamRead::End(header))),
+                StreamStep::Header => {
+                    while !self.is_header_ok() {
+                        let resize_size = self.config.resize_size;
+                        if ready!(self.poll_fill(cx, stream, resize_size))? == 0 {
+                            return Poll::Ready(Err(WsError::IOError(std::io::Error::new(
+                                std::io::ErrorKind::ConnectionAborted,
+                                "read eof",
+                            ))));
+                        }
+                    }
+                    if let Some(size) = self.stream_header()? {
+                        loop {
+                            let read_len = self.buf.ava_data().len();
+                            if read_len >= size.2 {
+                                break;
+                            }
+                            if ready!(self.poll_fill(cx, stream, size.2 - read_len))? == 0 {
+                                return Poll::Ready(Err(WsError::IOError(std::io::Error::new(
+                                    std::io::ErrorKind::UnexpectedEof,
+                                    "read eof",
+                                ))));
+                            }
+                        }
+                        return Poll::Ready(self.stream_control(size));
+                    }
+                }
+            }
         }
     }
 }
@@ -87,7 +196,8 @@ impl FrameReadState {
 impl FrameWriteState {
     /// send immutable payload
     ///
-    /// if need to mask, copy data to inner buffer and then apply mask
+    /// if need to mask or stream does not support vectored write, copy data to inner
+    /// buffer, else header and payload are written by `write_vectored` without copying
     ///
     /// will auto fragment if auto_fragment_size > 0
     pub async fn async_send<S: AsyncWrite + Unpin>(
@@ -96,167 +206,129 @@ impl FrameWriteState {
         opcode: OpCode,
         payload: &[u8],
     ) -> IOResult<()> {
-        if payload.is_empty() {
-            let mask = if self.config.mask_send_frame {
-                Some(rand::random())
-            } else {
-                None
-            };
-            let header = ctor_header(
-                &mut self.header_buf,
-                true,
-                false,
-                false,
-                false,
-                mask,
-                opcode,
-                0,
-            );
-            stream.write_all(header).await?;
-            return Ok(());
-        }
-        if self.config.auto_fragment_size > 0 && self.config.auto_fragment_size < payload.len() {
-            let chunk_size = self.config.auto_fragment_size;
-            let parts: Vec<&[u8]> = payload.chunks(chunk_size).collect();
-            let total = parts.len();
-            let single_header_len = header_len(self.config.mask_send_frame, chunk_size as u64);
-            let mut header_buf_len = single_header_len * parts.len();
-            let left = payload.len() % chunk_size;
-            if left != 0 {
-                header_buf_len += header_len(self.config.mask_send_frame, left as u64);
+        if stream.is_write_vectored() {
+            self.write_pending(stream).await?;
+            if let Some(header) = self.single_header(opcode, payload) {
+                let mut head = [0; 14];
+                let head = &mut head[..header.len()];
+                head.copy_from_slice(header);
+                self.set_sent(opcode);
+                let mut slices = [IoSlice::new(head), IoSlice::new(payload)];
+                return write_all_vectored(stream, &mut self.pending, &mut slices).await;
             }
-            let total_bytes = payload.len() + header_buf_len;
-            if self.config.mask_send_frame {
-                if self.buf.len() < total_bytes {
-                    self.buf.resize(total_bytes, 0);
-                }
-                parts.iter().enumerate().for_each(|(idx, chunk)| {
-                    let fin = idx + 1 == total;
-                    let s_idx = idx * single_header_len;
-                    let mask = rand::random();
-                    let header_len = ctor_header(
-                        &mut self.buf[s_idx..],
-                        fin,
-                        false,
-                        false,
-                        false,
-                        mask,
-                        opcode,
-                        chunk.len() as u64,
-                    )
-                    .len();
-                    let slice = &mut self.buf[(s_idx + header_len)..];
-                    slice.copy_from_slice(chunk);
-                    apply_mask(slice, mask);
-                });
-                stream.write_all(&self.buf[..total_bytes]).await?;
-            } else {
-                if self.buf.len() < header_buf_len {
-                    self.buf.resize(header_buf_len, 0);
-                }
-                let mut slices = Vec::with_capacity(total * 2);
-                parts.iter().enumerate().for_each(|(idx, chunk)| {
-                    let fin = idx + 1 == total;
-                    let s_idx = idx * chunk_size;
-                    ctor_header(
-                        &mut self.buf[s_idx..],
-                        fin,
-                        false,
-                        false,
-                        false,
-                        None,
-                        opcode,
-                        chunk.len() as u64,
-                    );
-                });
-                parts.iter().enumerate().for_each(|(idx, chunk)| {
-                    let fin = idx + 1 == total;
-                    if fin {
-                        slices.push(IoSlice::new(&self.buf[(idx * single_header_len)..]))
-                    } else {
-                        slices.push(IoSlice::new(
-                            &self.buf[(idx * single_header_len)..(idx + 1) * single_header_len],
-                        ))
-                    }
-                    slices.push(IoSlice::new(chunk));
-                });
-                let num = stream.write_vectored(&slices).await?;
-                let remain = total_bytes - num;
-                if remain > 0 {
-                    if let Some(buf) = slices.last() {
-                        stream.write_all(&buf[(buf.len() - remain)..]).await?;
-                    }
-                }
-            }
-        } else if self.config.mask_send_frame {
-            let total_bytes = header_len(true, payload.len() as u64) + payload.len();
-            let mask: [u8; 4] = rand::random();
-            let header = ctor_header(
-                &mut self.header_buf,
-                true,
-                false,
-                false,
-                false,
-                mask,
-                opcode,
-                payload.len() as u64,
-            );
-            if self.buf.len() < payload.len() {
-                self.buf.resize(payload.len(), 0)
-            }
-            self.buf[..(payload.len())].copy_from_slice(payload);
-            apply_mask(&mut self.buf[..(payload.len())], mask);
-            let num = stream
-                .write_vectored(&[
-                    IoSlice::new(header),
-                    IoSlice::new(&self.buf[..(payload.len())]),
-                ])
-                .await?;
-            let remain = total_bytes - num;
-            if remain > 0 {
-                stream
-                    .write_all(&self.buf[(payload.len() - remain)..(payload.len())])
-                    .await?;
+        }
+        self.async_send_many(stream, &[(opcode, payload)]).await
+    }
+
+    /// send frames with as few writes as possible, small frames are coalesced into
+    /// one syscall
+    ///
+    /// data frames are fragmented if auto_fragment_size > 0
+    pub async fn async_send_many<S: AsyncWrite + Unpin>(
+        &mut self,
+        stream: &mut S,
+        frames: &[(OpCode, &[u8])],
+    ) -> IOResult<()> {
+        self.write_pending(stream).await?;
+        for (code, _) in frames {
+            self.set_sent(*code);
+        }
+        let fragments = self.fragments(frames);
+        if self.config.mask_send_frame || !stream.is_write_vectored() {
+            for (fin, code, payload) in fragments {
+                self.encode_frame(fin, false, code, payload);
             }
+            self.write_pending(stream).await?;
         } else {
-            let total_bytes = header_len(false, payload.len() as u64) + payload.len();
-            let header = ctor_header(
-                &mut self.header_buf,
-                true,
-                false,
-                false,
-                false,
-                None,
-                opcode,
-                payload.len() as u64,
-            );
-            // if self.buf.len() < payload.len() {
-            //     self.buf.resize(payload.len(), 0)
-            // }
-            let num = stream
-                .write_vectored(&[IoSlice::new(header), IoSlice::new(payload)])
-                .await?;
-            let remain = total_bytes - num;
-            if remain > 0 {
-                stream
-                    .write_all(&payload[(payload.len() - remain)..])
-                    .await?;
-            }
-        };
-
-        if self.config.renew_buf_on_write {
-            self.buf = BytesMut::new()
+            let mut slices = Self::encode_vectored(&mut self.buf, &fragments);
+            write_all_vectored(stream, &mut self.pending, &mut slices).await?;
         }
+        self.renew_buf();
         Ok(())
     }
 
+    /// async version of [FrameWriteState::send_prepared]
+    pub async fn async_send_prepared<S: AsyncWrite + Unpin>(
+        &mut self,
+        stream: &mut S,
+        frame: &PreparedFrame,
+    ) -> IOResult<()> {
+        self.write_pending(stream).await?;
+        if self.can_send_prepared(frame) {
+            self.set_sent(frame.opcode());
+            self.async_write_encoded(stream, frame.encoded()).await
+        } else {
+            self.async_send(stream, frame.opcode(), frame.payload())
+                .await
+        }
+    }
+
     pub(crate) async fn async_send_owned_frame<S: AsyncWrite + Unpin>(
         &mut self,
         stream: &mut S,
         frame: OwnedFrame,
     ) -> IOResult<()> {
-        stream.write_all(&frame.header().0).await?;
-        stream.write_all(frame.payload()).await
+        self.write_pending(stream).await?;
+        self.set_sent(frame.header().opcode());
+        let mut slices = [
+            IoSlice::new(&frame.header().0),
+            IoSlice::new(frame.payload()),
+        ];
+        write_all_vectored(stream, &mut self.pending, &mut slices).await
+    }
+
+    /// write encoded frames, see [write_all_vectored]
+    pub(crate) async fn async_write_encoded<S: AsyncWrite + Unpin>(
+        &mut self,
+        stream: &mut S,
+        data: &[u8],
+    ) -> IOResult<()> {
+        write_all_vectored(stream, &mut self.pending, &mut [IoSlice::new(data)]).await
+    }
+
+    /// write frames left in pending buffer, e.g. final frame of dropped message writer,
+    /// so that they are not reordered with frames being sent
+    pub(crate) async fn write_pending<S: AsyncWrite + Unpin>(
+        &mut self,
+        stream: &mut S,
+    ) -> IOResult<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        std::future::poll_fn(|cx| self.poll_write_pending(cx, stream)).await
+    }
+}
+
+/// `write_all` of io slices, if write is interrupted, e.g. future is dropped by write
+/// timeout, unwritten bytes are moved to pending buffer, so that following write
+/// resumes the frame instead of starting a new one in the middle of it
+async fn write_all_vectored<S: AsyncWrite + Unpin>(
+    stream: &mut S,
+    pending: &mut BytesMut,
+    slices: &mut [IoSlice<'_>],
+) -> IOResult<()> {
+    let mut rest = Unwritten { pending, slices };
+    while !rest.slices.is_empty() {
+        let num = stream.write_vectored(rest.slices).await?;
+        if num == 0 {
+            return Err(std::io::ErrorKind::WriteZero.into());
+        }
+        IoSlice::advance_slices(&mut rest.slices, num);
+    }
+    Ok(())
+}
+
+/// io slices not written yet, copied to pending buffer on drop
+struct Unwritten<'a, 'b> {
+    pending: &'a mut BytesMut,
+    slices: &'a mut [IoSlice<'b>],
+}
+
+impl Drop for Unwritten<'_, '_> {
+    fn drop(&mut self) {
+        for slice in self.slices.iter() {
+            self.pending.extend_from_slice(slice);
+        }
     }
 }
 
@@ -276,6 +348,12 @@ impl<S: AsyncRead + Unpin> AsyncFrameRecv<S> {
     pub async fn receive(&mut self) -> Result<(SimplifiedHeader, &[u8]), WsError> {
         self.read_state.async_receive(&mut self.stream).await
     }
+
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub async fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive().await?;
+        WsMessage::from_header(&header, data)
+    }
 }
 
 /// send part of websocket frame
@@ -297,22 +375,56 @@ impl<S: AsyncWrite + Unpin> AsyncFrameSend<S> {
     ///
     /// will auto fragment if auto_fragment_size > 0
     pub async fn send(&mut self, opcode: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        self.write_state.check_send(opcode)?;
         self.write_state
             .async_send(&mut self.stream, opcode, payload)
             .await
             .map_err(WsError::IOError)
     }
 
+    /// send frames in one write if possible, see [FrameWriteState::async_send_many]
+    pub async fn send_many(&mut self, frames: &[(OpCode, &[u8])]) -> Result<(), WsError> {
+        self.write_state.check_send_many(frames)?;
+        self.write_state
+            .async_send_many(&mut self.stream, frames)
+            .await
+            .map_err(WsError::IOError)
+    }
+
+    /// send owned message
+    pub async fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload()).await
+    }
+
+    /// send prepared frame, see [FrameWriteState::send_prepared]
+    pub async fn send_prepared(&mut self, frame: &PreparedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.opcode())?;
+        self.write_state
+            .async_send_prepared(&mut self.stream, frame)
+            .await
+            .map_err(WsError::IOError)
+    }
+
     /// send a read frame, **this method will not check validation of frame and do not fragment**
     pub async fn send_owned_frame(&mut self, frame: OwnedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.header().opcode())?;
         self.write_state
             .async_send_owned_frame(&mut self.stream, frame)
             .await
             .map_err(WsError::IOError)
     }
 
+    /// start a text or binary message, see [AsyncMessageWriter]
+    pub fn begin_message(&mut self, code: OpCode) -> Result<AsyncMessageWriter<'_, S>, WsError> {
+        AsyncMessageWriter::new(&mut self.stream, &mut self.write_state, code)
+    }
+
     /// flush to ensure all data are send
     pub async fn flush(&mut self) -> Result<(), WsError> {
+        self.write_state
+            .write_pending(&mut self.stream)
+            .await
+            .map_err(WsError::IOError)?;
         self.stream.flush().await.map_err(WsError::IOError)
     }
 }
@@ -367,31 +479,284 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncFrameCodec<S> {
     }
 
     /// receive a frame
+    ///
+    /// close frame from peer is echoed automatically if close frame has not been sent,
+    /// after that, [WsError::ConnectionClosed] is returned
+    ///
+    /// if keepalive is enabled, ping frame is replied automatically and tokio timer
+    /// is used to send ping & detect dead peer
     pub async fn receive(&mut self) -> Result<(SimplifiedHeader, &[u8]), WsError> {
-        self.read_state.async_receive(&mut self.stream).await
+        self.discard_stream().await?;
+        let read_timeout = self.read_state.config.read_timeout;
+        // keepalive ticks do not extend read timeout, it's reset by received frame only
+        let read_deadline = |timeout: Option<Duration>| {
+            timeout.map(|timeout| tokio::time::Instant::now() + timeout)
+        };
+        let mut read_at = read_deadline(read_timeout);
+        loop {
+            let deadline = self.read_state.keepalive.deadline();
+            let next = with_deadline(
+                read_at,
+                TimeoutPhase::Read,
+                self.read_state.async_next_frame(&mut self.stream),
+            );
+            let ret = match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline.into(), next).await.ok(),
+                None => Some(next.await),
+            };
+            match ret {
+                Some(ret) => {
+                    let (header, range) = ret?;
+                    if header.code == OpCode::Close {
+                        self.echo_close(range.clone()).await;
+                    }
+                    if !self.read_state.keepalive.is_enabled() {
+                        return Ok((header, self.read_state.payload(range)));
+                    }
+                    let need_pong = self.read_state.keepalive.on_frame(header.code);
+                    if !self.write_state.is_close_sent() && self.read_state.keepalive.on_tick()? {
+                        self.send(OpCode::Ping, &[]).await?;
+                        self.flush().await?;
+                    }
+                    if need_pong && !self.write_state.is_close_sent() {
+                        let payload = self.read_state.payload(range).to_vec();
+                        self.send(OpCode::Pong, &payload).await?;
+                        self.flush().await?;
+                        read_at = read_deadline(read_timeout);
+                        continue;
+                    }
+                    return Ok((header, self.read_state.payload(range)));
+                }
+                None => {
+                    if !self.write_state.is_close_sent() && self.read_state.keepalive.on_tick()? {
+                        self.send(OpCode::Ping, &[]).await?;
+                        self.flush().await?;
+                    }
+                }
+            }
+        }
+    }
+
+    /// receive a complete message, fragmented frame is rejected, see [WsMessage::from_header]
+    pub async fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+        let (header, data) = self.receive().await?;
+        WsMessage::from_header(&header, data)
+    }
+
+    /// receive a text or binary message as [AsyncMessageReader], payload is read from
+    /// underlying stream on demand instead of being buffered, text payload is utf-8
+    /// checked incrementally
+    ///
+    /// ping frame before or between fragments is replied, close frame is echoed and
+    /// [WsError::ConnectionClosed] is returned, unread payload of previous message is
+    /// discarded
+    pub async fn receive_stream(&mut self) -> Result<AsyncMessageReader<'_, S>, WsError> {
+        self.discard_stream().await?;
+        let read_timeout = self.read_state.config.read_timeout;
+        let start = std::future::poll_fn(|cx| self.poll_stream_read(cx, &mut []));
+        let (header, done) = match with_timeout(read_timeout, TimeoutPhase::Read, start).await? {
+            StreamRead::End(header) => (header, true),
+            _ => match self.read_state.streaming_header() {
+                Some(header) => (header, false),
+                None => unreachable!("message stream is not started"),
+            },
+        };
+        Ok(AsyncMessageReader {
+            codec: self,
+            header,
+            done,
+        })
+    }
+
+    /// poll streamed message, control frames are handled, return [StreamRead::Data]
+    /// or [StreamRead::End]
+    fn poll_stream_read(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<Result<StreamRead, WsError>> {
+        loop {
+            if !self.write_state.pending.is_empty() {
+                let ret = ready!(self.write_state.poll_flush_pending(cx, &mut self.stream));
+                match ret {
+                    Err(e) if self.read_state.is_close_received() => {
+                        tracing::debug!("failed to echo close frame {e}");
+                        self.write_state.pending.clear();
+                    }
+                    ret => ret?,
+                }
+            }
+            match ready!(self.read_state.poll_stream_read(cx, &mut self.stream, buf))? {
+                StreamRead::Control(header, range) => {
+                    self.read_state.keepalive.on_frame(header.code);
+                    if self.write_state.is_close_sent() {
+                        continue;
+                    }
+                    let payload = self.read_state.payload(Some(range));
+                    let (code, payload) = match header.code {
+                        OpCode::Ping => (OpCode::Pong, payload.to_vec()),
+                        OpCode::Close => (OpCode::Close, echo_close_payload(payload).to_vec()),
+                        _ => continue,
+                    };
+                    self.write_state.check_send(code)?;
+                    self.write_state.encode(code, false, &payload);
+                }
+                ret => return Poll::Ready(Ok(ret)),
+            }
+        }
+    }
+
+    /// discard unread payload of streamed message
+    async fn discard_stream(&mut self) -> Result<(), WsError> {
+        let mut buf = [0; 4096];
+        while self.read_state.streaming_header().is_some() {
+            std::future::poll_fn(|cx| self.poll_stream_read(cx, &mut buf)).await?;
+        }
+        Ok(())
+    }
+
+    async fn echo_close(&mut self, range: Option<Range<usize>>) {
+        if self.write_state.is_close_sent() {
+            return;
+        }
+        let payload = echo_close_payload(self.read_state.payload(range)).to_vec();
+        let ret = match self.send(OpCode::Close, &payload).await {
+            Ok(_) => self.flush().await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = ret {
+            tracing::debug!("failed to echo close frame {e}");
+        }
+    }
+
+    /// current state of close handshake
+    pub fn state(&self) -> ConnectionState {
+        ConnectionState::new(
+            self.write_state.is_close_sent(),
+            self.read_state.is_close_received(),
+        )
+    }
+
+    /// send close frame if not sent, then wait for close frame from peer until timeout
+    /// and shutdown underlying stream, return true if close frame from peer is received
+    ///
+    /// close frame interrupted by write timeout is resumed, so it can be retried
+    ///
+    /// frames received before close frame are dropped
+    pub async fn graceful_close(
+        &mut self,
+        code: impl Into<CloseCode>,
+        reason: &[u8],
+        timeout: Duration,
+    ) -> Result<bool, WsError> {
+        if !self.write_state.is_close_sent() {
+            let mut data = u16::from(code.into()).to_be_bytes().to_vec();
+            data.extend_from_slice(reason);
+            self.send(OpCode::Close, &data).await?;
+        }
+        self.flush().await?;
+        let wait = async {
+            while !self.read_state.is_close_received() {
+                if let Err(e) = self.read_state.async_next_frame(&mut self.stream).await {
+                    tracing::debug!("failed to wait close frame {e}");
+                    return false;
+                }
+            }
+            true
+        };
+        let acked = tokio::time::timeout(timeout, wait).await.unwrap_or(false);
+        if let Err(e) = self.stream.shutdown().await {
+            tracing::debug!("failed to shutdown stream {e}");
+        }
+        Ok(acked)
+    }
+
+    /// last time pong frame is received
+    pub fn last_pong(&self) -> Option<std::time::Instant> {
+        self.read_state.keepalive.last_pong()
     }
 
     /// send payload
     ///
     /// will auto fragment if auto_fragment_size > 0
     pub async fn send(&mut self, opcode: OpCode, payload: &[u8]) -> Result<(), WsError> {
-        self.write_state
-            .async_send(&mut self.stream, opcode, payload)
-            .await
-            .map_err(WsError::IOError)
+        self.write_state.check_send(opcode)?;
+        let timeout = self.write_state.config.write_timeout;
+        let send = async {
+            self.write_state
+                .async_send(&mut self.stream, opcode, payload)
+                .await
+                .map_err(WsError::IOError)
+        };
+        with_timeout(timeout, TimeoutPhase::Write, send).await
+    }
+
+    /// send frames in one write if possible, see [FrameWriteState::async_send_many]
+    pub async fn send_many(&mut self, frames: &[(OpCode, &[u8])]) -> Result<(), WsError> {
+        self.write_state.check_send_many(frames)?;
+        let timeout = self.write_state.config.write_timeout;
+        let send = async {
+            self.write_state
+                .async_send_many(&mut self.stream, frames)
+                .await
+                .map_err(WsError::IOError)
+        };
+        with_timeout(timeout, TimeoutPhase::Write, send).await
+    }
+
+    /// send owned message
+    pub async fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+        self.send(msg.opcode(), &msg.payload()).await
+    }
+
+    /// send prepared frame, see [FrameWriteState::send_prepared]
+    pub async fn send_prepared(&mut self, frame: &PreparedFrame) -> Result<(), WsError> {
+        self.write_state.check_send(frame.opcode())?;
+        let timeout = self.write_state.config.write_timeout;
+        let send = async {
+            self.write_state
+                .async_send_prepared(&mut self.stream, frame)
+                .await
+                .map_err(WsError::IOError)
+        };
+        with_timeout(timeout, TimeoutPhase::Write, send).await
     }
 
     /// send a read frame, **this method will not check validation of frame and do not fragment**
     pub async fn send_owned_frame(&mut self, frame: OwnedFrame) -> Result<(), WsError> {
-        self.write_state
-            .async_send_owned_frame(&mut self.stream, frame)
-            .await
-            .map_err(WsError::IOError)
+        self.write_state.check_send(frame.header().opcode())?;
+        let timeout = self.write_state.config.write_timeout;
+        let send = async {
+            self.write_state
+                .async_send_owned_frame(&mut self.stream, frame)
+                .await
+                .map_err(WsError::IOError)
+        };
+        with_timeout(timeout, TimeoutPhase::Write, send).await
+    }
+
+    /// start a text or binary message, see [AsyncMessageWriter]
+    pub fn begin_message(&mut self, code: OpCode) -> Result<AsyncMessageWriter<'_, S>, WsError> {
+        AsyncMessageWriter::new(&mut self.stream, &mut self.write_state, code)
     }
 
     /// flush to ensure all data are send
     pub async fn flush(&mut self) -> Result<(), WsError> {
-        self.stream.flush().await.map_err(WsError::IOError)
+        let timeout = self.write_state.config.write_timeout;
+        let flush = async {
+            self.write_state
+                .write_pending(&mut self.stream)
+                .await
+                .map_err(WsError::IOError)?;
+            self.stream.flush().await.map_err(WsError::IOError)
+        };
+        with_timeout(timeout, TimeoutPhase::Write, flush).await
+    }
+
+    /// update read & write timeout, see [FrameConfig::read_timeout] and [FrameConfig::write_timeout]
+    pub fn set_timeout(&mut self, read: Option<Duration>, write: Option<Duration>) {
+        self.read_state.config.read_timeout = read;
+        self.write_state.config.write_timeout = write;
     }
 }
 
@@ -415,3 +780,512 @@ where
         )
     }
 }
+
+/// message received by [AsyncFrameCodec::receive_stream], payload is read from
+/// underlying stream on demand
+pub struct AsyncMessageReader<'a, S: AsyncRead + AsyncWrite> {
+    codec: &'a mut AsyncFrameCodec<S>,
+    header: SimplifiedHeader,
+    done: bool,
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> AsyncMessageReader<'a, S> {
+    /// opcode of message, text or binary
+    pub fn opcode(&self) -> OpCode {
+        self.header.code
+    }
+
+    /// check if whole message has been read
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+impl<'a, S: AsyncRead + AsyncWrite + Unpin> AsyncRead for AsyncMessageReader<'a, S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<IOResult<()>> {
+        let this = self.get_mut();
+        if this.done || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        match ready!(this.codec.poll_stream_read(cx, buf.initialize_unfilled()))? {
+            StreamRead::Data(count) => buf.advance(count),
+            _ => this.done = true,
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// async version of [MessageWriter](super::MessageWriter), frames are encoded into
+/// pending buffer of write state and written on next write or flush
+///
+/// only [AsyncMessageWriter::finish], [AsyncMessageWriter::finish_with] or `shutdown`
+/// sends the final frame, if writer is dropped after some fragments are encoded, e.g.
+/// by early return on error, close frame 1011 is encoded to be written by next write
+/// or flush, and following data frames of codec fail with [WsError::UnfinishedMessage]
+pub struct AsyncMessageWriter<'a, S: AsyncWrite> {
+    stream: &'a mut S,
+    write_state: &'a mut FrameWriteState,
+    code: OpCode,
+    started: bool,
+    finished: bool,
+}
+
+impl<'a, S: AsyncWrite + Unpin> AsyncMessageWriter<'a, S> {
+    pub(crate) fn new(
+        stream: &'a mut S,
+        write_state: &'a mut FrameWriteState,
+        code: OpCode,
+    ) -> Result<Self, WsError> {
+        if !code.is_data() {
+            return Err(WsError::UnsupportedFrame(code));
+        }
+        write_state.check_send(code)?;
+        Ok(Self {
+            stream,
+            write_state,
+            code,
+            started: false,
+            finished: false,
+        })
+    }
+
+    /// opcode of message, text or binary
+    pub fn opcode(&self) -> OpCode {
+        self.code
+    }
+
+    /// send a control frame between fragments
+    pub async fn control(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+        if code.is_data() {
+            return Err(WsError::UnsupportedFrame(code));
+        }
+        self.write_state.check_send(code)?;
+        self.write_state.encode_frame(true, false, code, payload);
+        self.write_state.set_sent(code);
+        self.write_state
+            .write_pending(self.stream)
+            .await
+            .map_err(WsError::IOError)
+    }
+
+    fn encode_fragment(&mut self, fin: bool, payload: &[u8]) -> Result<(), WsError> {
+        let code = if self.started {
+            OpCode::Continue
+        } else {
+            self.code
+        };
+        self.write_state.check_send(code)?;
+        self.write_state.encode_frame(fin, false, code, payload);
+        self.started = true;
+        Ok(())
+    }
+
+    fn poll_finish(&mut self, cx: &mut Context<'_>, payload: &[u8]) -> Poll<IOResult<()>> {
+        if !self.finished {
+            self.encode_fragment(true, payload)?;
+            self.finished = true;
+        }
+        self.write_state.poll_flush_pending(cx, self.stream)
+    }
+
+    /// send empty final frame and flush stream
+    pub async fn finish(self) -> Result<(), WsError> {
+        self.finish_with(&[]).await
+    }
+
+    /// send last chunk of message as final frame and flush stream
+    pub async fn finish_with(mut self, payload: &[u8]) -> Result<(), WsError> {
+        std::future::poll_fn(|cx| self.poll_finish(cx, payload))
+            .await
+            .map_err(WsError::IOError)
+    }
+}
+
+impl<'a, S: AsyncWrite + Unpin> AsyncWrite for AsyncMessageWriter<'a, S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IOResult<usize>> {
+        let this = self.get_mut();
+        ready!(this.write_state.poll_write_pending(cx, this.stream))?;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        this.encode_fragment(false, buf)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        let this = self.get_mut();
+        this.write_state.poll_flush_pending(cx, this.stream)
+    }
+
+    /// send final frame, underlying stream is not shutdown
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IOResult<()>> {
+        self.get_mut().poll_finish(cx, &[])
+    }
+}
+
+impl<'a, S: AsyncWrite> Drop for AsyncMessageWriter<'a, S> {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if !self.started {
+            return;
+        }
+        if let Some(payload) = self.write_state.set_unfinished() {
+            self.write_state.encode(OpCode::Close, false, &payload);
+        }
+    }
+}
+
+impl FrameWriteState {
+    /// write encoded frames in pending buffer to stream
+    pub(crate) fn poll_write_pending<S: AsyncWrite + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stream: &mut S,
+    ) -> Poll<IOResult<()>> {
+        use bytes::Buf;
+        while !self.pending.is_empty() {
+            let num = ready!(Pin::new(&mut *stream).poll_write(cx, &self.pending))?;
+            if num == 0 {
+                return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+            }
+            self.pending.advance(num);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// write pending frames and flush stream
+    pub(crate) fn poll_flush_pending<S: AsyncWrite + Unpin>(
+        &mut self,
+        cx: &mut Context<'_>,
+        stream: &mut S,
+    ) -> Poll<IOResult<()>> {
+        ready!(self.poll_write_pending(cx, stream))?;
+        Pin::new(stream).poll_flush(cx)
+    }
+}
+
+/// close frame from peer is echoed before end of stream if close frame has not been sent,
+/// **NOTE** keepalive is not handled by stream api
+#[cfg(feature = "stream")]
+impl<S: AsyncRead + AsyncWrite + Unpin> futures_core::Stream for AsyncFrameCodec<S> {
+    type Item = Result<Message<Vec<u8>>, WsError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.read_state.is_close_received() {
+            if let Err(e) = ready!(this.write_state.poll_flush_pending(cx, &mut this.stream)) {
+                tracing::debug!("failed to echo close frame {e}");
+            }
+            return Poll::Ready(None);
+        }
+        match ready!(this.read_state.poll_next_frame(cx, &mut this.stream)) {
+            Ok((header, range)) => {
+                let payload = this.read_state.payload(range);
+                let msg = Message::from_frame(header.code, payload);
+                if header.code == OpCode::Close && !this.write_state.is_close_sent() {
+                    let echo = echo_close_payload(payload).to_vec();
+                    this.write_state.encode(OpCode::Close, false, &echo);
+                    // try to send it now, remaining data is flushed before end of stream
+                    let _ = this.write_state.poll_flush_pending(cx, &mut this.stream);
+                }
+                Poll::Ready(Some(Ok(msg)))
+            }
+            Err(WsError::ConnectionClosed) => Poll::Ready(None),
+            Err(e) => Poll::Ready(Some(Err(e))),
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<S, T> futures_sink::Sink<Message<T>> for AsyncFrameCodec<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+    T: AsRef<[u8]>,
+{
+    type Error = WsError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        this.write_state
+            .poll_write_pending(cx, &mut this.stream)
+            .map_err(WsError::IOError)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message<T>) -> Result<(), WsError> {
+        let this = self.get_mut();
+        this.write_state.check_send(item.code)?;
+        this.write_state
+            .encode(item.code, false, &item.frame_payload());
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        this.write_state
+            .poll_flush_pending(cx, &mut this.stream)
+            .map_err(WsError::IOError)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        ready!(this.write_state.poll_flush_pending(cx, &mut this.stream))?;
+        Pin::new(&mut this.stream)
+            .poll_shutdown(cx)
+            .map_err(WsError::IOError)
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<S: AsyncRead + Unpin> futures_core::Stream for AsyncFrameRecv<S> {
+    type Item = Result<Message<Vec<u8>>, WsError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        match ready!(this.read_state.poll_next_frame(cx, &mut this.stream)) {
+            Ok((header, range)) => Poll::Ready(Some(Ok(Message::from_frame(
+                header.code,
+                this.read_state.payload(range),
+            )))),
+            Err(WsError::ConnectionClosed) => Poll::Ready(None),
+            Err(e) => Poll::Ready(Some(Err(e))),
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<S, T> futures_sink::Sink<Message<T>> for AsyncFrameSend<S>
+where
+    S: AsyncWrite + Unpin,
+    T: AsRef<[u8]>,
+{
+    type Error = WsError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        this.write_state
+            .poll_write_pending(cx, &mut this.stream)
+            .map_err(WsError::IOError)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Message<T>) -> Result<(), WsError> {
+        let this = self.get_mut();
+        this.write_state.check_send(item.code)?;
+        this.write_state
+            .encode(item.code, false, &item.frame_payload());
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        this.write_state
+            .poll_flush_pending(cx, &mut this.stream)
+            .map_err(WsError::IOError)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), WsError>> {
+        let this = self.get_mut();
+        ready!(this.write_state.poll_flush_pending(cx, &mut this.stream))?;
+        Pin::new(&mut this.stream)
+            .poll_shutdown(cx)
+            .map_err(WsError::IOError)
+    }
+}
+
+#[cfg(all(test, feature = "stream"))]
+#[tokio::test]
+async fn test_stream_sink() {
+    use futures_util::{SinkExt, StreamExt};
+
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = AsyncFrameCodec::new_with(client, Default::default());
+    let mut server = AsyncFrameCodec::new_with(
+        server,
+        FrameConfig {
+            mask_send_frame: false,
+            ..Default::default()
+        },
+    );
+    let close = Message {
+        code: OpCode::Close,
+        data: &b"bye"[..],
+        close_code: Some(CloseCode::Normal),
+    };
+    SinkExt::send(&mut client, Message::from(&b"hello"[..]))
+        .await
+        .unwrap();
+    SinkExt::send(&mut client, close).await.unwrap();
+
+    let msg = server.next().await.unwrap().unwrap();
+    assert_eq!(msg.code, OpCode::Binary);
+    assert_eq!(msg.data, b"hello");
+    let msg = server.next().await.unwrap().unwrap();
+    assert_eq!(msg.code, OpCode::Close);
+    assert_eq!(msg.close_code, Some(CloseCode::Normal));
+    assert_eq!(msg.data, b"bye");
+    assert!(server.next().await.is_none());
+
+    let msg = client.next().await.unwrap().unwrap();
+    assert_eq!(msg.code, OpCode::Close);
+    assert_eq!(msg.close_code, Some(CloseCode::Normal));
+    assert!(client.next().await.is_none());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_async_receive_stream() {
+    use tokio::io::AsyncReadExt;
+
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = AsyncFrameCodec::new_with(client, Default::default());
+    let mut server = AsyncFrameCodec::new_with(
+        server,
+        FrameConfig {
+            mask_send_frame: false,
+            ..Default::default()
+        },
+    );
+    server.send(OpCode::Binary, b"hello world").await.unwrap();
+    server.send(OpCode::Ping, b"p").await.unwrap();
+    server.send(OpCode::Text, b"next").await.unwrap();
+    server.flush().await.unwrap();
+
+    let mut reader = client.receive_stream().await.unwrap();
+    assert_eq!(reader.opcode(), OpCode::Binary);
+    let mut data = vec![];
+    reader.read_to_end(&mut data).await.unwrap();
+    assert_eq!(data, b"hello world");
+    let reader = client.receive_stream().await.unwrap();
+    assert_eq!(reader.opcode(), OpCode::Text);
+
+    // unread payload is discarded by next receive
+    server.send(OpCode::Text, b"last").await.unwrap();
+    server.flush().await.unwrap();
+    let (header, data) = client.receive().await.unwrap();
+    assert_eq!(header.code, OpCode::Text);
+    assert_eq!(data, b"last");
+    let (header, data) = server.receive().await.unwrap();
+    assert_eq!(header.code, OpCode::Pong);
+    assert_eq!(data, b"p");
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_async_message_writer() {
+    let (client, server) = tokio::io::duplex(1024);
+    let mut client = AsyncFrameCodec::new_with(client, Default::default());
+    let mut server = AsyncFrameCodec::new(server);
+    let mut writer = client.begin_message(OpCode::Binary).unwrap();
+    writer.write_all(b"hello ").await.unwrap();
+    writer.control(OpCode::Ping, b"p").await.unwrap();
+    writer.finish_with(b"world").await.unwrap();
+    // dropped writer encodes close frame 1011, following data frames are rejected
+    client
+        .begin_message(OpCode::Text)
+        .unwrap()
+        .write_all(b"next")
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.send(OpCode::Text, b"last").await,
+        Err(WsError::UnfinishedMessage(OpCode::Text))
+    ));
+    assert!(matches!(
+        client.send(OpCode::Ping, b"").await,
+        Err(WsError::SendAfterClose(OpCode::Ping))
+    ));
+    client.flush().await.unwrap();
+
+    assert_eq!(
+        server.receive_message().await.unwrap(),
+        WsMessage::Ping("p".into())
+    );
+    assert_eq!(
+        server.receive_message().await.unwrap(),
+        WsMessage::Binary("hello world".into())
+    );
+    let (header, data) = server.receive().await.unwrap();
+    assert_eq!(header.code, OpCode::Close);
+    assert_eq!(&data[..2], &1011u16.to_be_bytes());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_async_write_timeout_resume() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let (server, _) = listener.accept().await.unwrap();
+    let mut server = AsyncFrameCodec::new_with(
+        server,
+        FrameConfig {
+            mask_send_frame: false,
+            write_timeout: Some(Duration::from_millis(100)),
+            ..Default::default()
+        },
+    );
+    // peer does not read, frame is written partially
+    let payload = vec![7; 32 * 1024 * 1024];
+    let ret = server.send(OpCode::Binary, &payload).await;
+    assert!(matches!(
+        ret,
+        Err(WsError::Timeout {
+            phase: TimeoutPhase::Write
+        })
+    ));
+
+    let reader = tokio::spawn(async move {
+        let mut client = AsyncFrameCodec::new(client);
+        let (header, data) = client.receive().await.unwrap();
+        assert_eq!(
+            (header.code, data.len()),
+            (OpCode::Binary, 32 * 1024 * 1024)
+        );
+        let (header, data) = client.receive().await.unwrap();
+        assert_eq!((header.code, data), (OpCode::Text, &b"next"[..]));
+    });
+    server.set_timeout(None, None);
+    server.send(OpCode::Text, b"next").await.unwrap();
+    server.flush().await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), reader)
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_async_read_timeout_keepalive() {
+    use super::KeepaliveConfig;
+
+    let (_client, server) = tokio::io::duplex(1024 * 1024);
+    let mut server = AsyncFrameCodec::new_with(
+        server,
+        FrameConfig {
+            mask_send_frame: false,
+            read_timeout: Some(Duration::from_millis(200)),
+            keepalive: KeepaliveConfig {
+                ping_interval: Some(Duration::from_millis(50)),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+    // ping ticks do not extend read timeout
+    let start = std::time::Instant::now();
+    let ret = tokio::time::timeout(Duration::from_secs(2), server.receive())
+        .await
+        .unwrap();
+    assert!(matches!(
+        ret,
+        Err(WsError::Timeout {
+            phase: TimeoutPhase::Read
+        })
+    ));
+    assert!(start.elapsed() < Duration::from_secs(1));
+}
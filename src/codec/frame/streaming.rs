@@ -0,0 +1,225 @@
+use std::ops::Range;
+
+use crate::{
+    errors::{ProtocolError, WsError},
+    frame::{HeaderView, OpCode, SimplifiedHeader},
+    protocol::CloseCode,
+};
+
+use super::{apply_mask, FrameReadState};
+
+fn invalid_utf8() -> WsError {
+    WsError::ProtocolError {
+        close_code: CloseCode::InvalidPayload,
+        error: ProtocolError::InvalidUtf8,
+    }
+}
+
+/// incremental utf-8 check of streamed text message, incomplete char at the end
+/// of a chunk is kept until next chunk
+#[derive(Debug, Default)]
+pub(crate) struct Utf8Check {
+    pending: [u8; 4],
+    len: usize,
+}
+
+impl Utf8Check {
+    pub(crate) fn feed(&mut self, mut data: &[u8]) -> Result<(), WsError> {
+        if self.len > 0 {
+            let width = match self.pending[0] {
+                0xC2..=0xDF => 2,
+                0xE0..=0xEF => 3,
+                0xF0..=0xF4 => 4,
+                _ => return Err(invalid_utf8()),
+            };
+            let take = (width - self.len).min(data.len());
+            self.pending[self.len..(self.len + take)].copy_from_slice(&data[..take]);
+            self.len += take;
+            data = &data[take..];
+            if self.len < width {
+                return Ok(());
+            }
+            if std::str::from_utf8(&self.pending[..width]).is_err() {
+                return Err(invalid_utf8());
+            }
+            self.len = 0;
+        }
+        match simdutf8::compat::from_utf8(data) {
+            Ok(_) => Ok(()),
+            Err(e) if e.error_len().is_none() => {
+                let tail = &data[e.valid_up_to()..];
+                self.pending[..tail.len()].copy_from_slice(tail);
+                self.len = tail.len();
+                Ok(())
+            }
+            Err(_) => Err(invalid_utf8()),
+        }
+    }
+
+    /// check there is no incomplete char at the end of message
+    pub(crate) fn finish(&mut self) -> Result<(), WsError> {
+        if std::mem::take(&mut self.len) > 0 {
+            return Err(invalid_utf8());
+        }
+        Ok(())
+    }
+}
+
+/// data message being streamed
+#[derive(Debug)]
+pub(crate) struct StreamState {
+    /// header of first frame
+    pub(crate) header: SimplifiedHeader,
+    fin: bool,
+    remaining: usize,
+    mask: Option<[u8; 4]>,
+    offset: usize,
+    utf8: Option<Utf8Check>,
+}
+
+/// result of reading streamed message
+#[derive(Debug)]
+pub(crate) enum StreamRead {
+    /// number of payload bytes read, 0 only if read buf is empty
+    Data(usize),
+    /// control frame received before or between fragments, payload is in read buffer
+    Control(SimplifiedHeader, Range<usize>),
+    /// message is finished, return header of first frame
+    End(SimplifiedHeader),
+}
+
+/// what to do next for streamed message
+pub(crate) enum StreamStep {
+    /// read payload of current frame
+    Payload(usize),
+    /// message is finished
+    End(SimplifiedHeader),
+    /// read next frame header
+    Header,
+}
+
+impl FrameReadState {
+    /// header of first frame of message being streamed
+    pub(crate) fn streaming_header(&self) -> Option<SimplifiedHeader> {
+        self.streaming.as_ref().map(|state| state.header)
+    }
+
+    pub(crate) fn stream_step(&mut self) -> Result<StreamStep, WsError> {
+        match self.streaming.as_mut() {
+            Some(state) if state.remaining > 0 => Ok(StreamStep::Payload(state.remaining)),
+            Some(state) if state.fin => {
+                if let Some(utf8) = state.utf8.as_mut() {
+                    utf8.finish()?;
+                }
+                let header = state.header;
+                self.streaming = None;
+                Ok(StreamStep::End(header))
+            }
+            _ => Ok(StreamStep::Header),
+        }
+    }
+
+    /// parse frame header in buffer, header of data frame is consumed, return total
+    /// frame len if it's a control frame, which should be read entirely
+    pub(crate) fn stream_header(&mut self) -> Result<Option<(usize, usize, usize)>, WsError> {
+        let (header_len, payload_len, total_len) = self.parse_frame_header()?;
+        let view = HeaderView(&self.buf.ava_data()[..header_len]);
+        let mask = view.masking_key();
+        let header: SimplifiedHeader = view.into();
+        match (header.code, self.streaming.as_mut()) {
+            (OpCode::Continue, Some(state)) => {
+                state.fin = header.fin;
+                state.remaining = payload_len;
+                state.mask = mask;
+                state.offset = 0;
+            }
+            (OpCode::Continue, None) => {
+                return Err(WsError::ProtocolError {
+                    close_code: CloseCode::ProtocolError,
+                    error: ProtocolError::MissInitialFragmentedFrame,
+                })
+            }
+            (OpCode::Text | OpCode::Binary, Some(_)) => {
+                return Err(WsError::ProtocolError {
+                    close_code: CloseCode::ProtocolError,
+                    error: ProtocolError::NotContinueFrameAfterFragmented,
+                })
+            }
+            (OpCode::Text | OpCode::Binary, None) => {
+                let check = header.code == OpCode::Text && self.config.validate_utf8.should_check();
+                self.streaming = Some(StreamState {
+                    header,
+                    fin: header.fin,
+                    remaining: payload_len,
+                    mask,
+                    offset: 0,
+                    utf8: check.then(Utf8Check::default),
+                });
+            }
+            _ => return Ok(Some((header_len, payload_len, total_len))),
+        }
+        self.buf.consume(header_len);
+        Ok(None)
+    }
+
+    /// consume and check control frame which is entirely in buffer
+    pub(crate) fn stream_control(
+        &mut self,
+        (header_len, payload_len, total_len): (usize, usize, usize),
+    ) -> Result<StreamRead, WsError> {
+        let (header, range) = self.consume_frame(header_len, payload_len, total_len);
+        self.check_frame(header, range.clone())?;
+        self.close_received = header.code == OpCode::Close;
+        Ok(StreamRead::Control(header, range))
+    }
+
+    /// copy buffered payload into `buf`, return 0 if nothing is buffered
+    pub(crate) fn stream_buffered(&mut self, buf: &mut [u8]) -> usize {
+        let data = self.buf.ava_data();
+        let count = data.len().min(buf.len());
+        buf[..count].copy_from_slice(&data[..count]);
+        self.buf.consume(count);
+        count
+    }
+
+    /// unmask & check payload which is just read into `data`
+    pub(crate) fn stream_payload(&mut self, data: &mut [u8]) -> Result<(), WsError> {
+        let Some(state) = self.streaming.as_mut() else {
+            return Ok(());
+        };
+        if let Some(mask) = state.mask {
+            let o = state.offset;
+            let mask = [
+                mask[o & 3],
+                mask[(o + 1) & 3],
+                mask[(o + 2) & 3],
+                mask[(o + 3) & 3],
+            ];
+            apply_mask(data, mask);
+        }
+        state.offset += data.len();
+        state.remaining -= data.len();
+        if let Some(utf8) = state.utf8.as_mut() {
+            utf8.feed(data)?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_utf8_check() {
+    let text = "hello 世界 🌍".as_bytes();
+    for size in 1..5 {
+        let mut check = Utf8Check::default();
+        for chunk in text.chunks(size) {
+            check.feed(chunk).unwrap();
+        }
+        check.finish().unwrap();
+    }
+    let mut check = Utf8Check::default();
+    check.feed(&text[..8]).unwrap();
+    assert!(check.finish().is_err());
+    let mut check = Utf8Check::default();
+    check.feed(&[0xE4]).unwrap();
+    assert!(check.feed(&[0x41, 0x41]).is_err());
+}
@@ -2,20 +2,40 @@ mod binary;
 #[cfg(any(
     feature = "deflate",
     feature = "deflate_ng",
-    feature = "deflate_static"
+    feature = "deflate_static",
+    feature = "deflate_rust"
 ))]
 mod deflate;
+mod extension;
+#[cfg(any(
+    feature = "json",
+    feature = "msgpack",
+    feature = "cbor",
+    feature = "protobuf"
+))]
+mod format;
 mod frame;
+mod prepared;
 mod text;
 
 pub use binary::*;
 #[cfg(any(
     feature = "deflate",
     feature = "deflate_ng",
-    feature = "deflate_static"
+    feature = "deflate_static",
+    feature = "deflate_rust"
 ))]
 pub use deflate::*;
+pub use extension::*;
+#[cfg(any(
+    feature = "json",
+    feature = "msgpack",
+    feature = "cbor",
+    feature = "protobuf"
+))]
+pub use format::*;
 pub use frame::*;
+pub use prepared::*;
 pub use text::*;
 
 /// split something into two parts
@@ -28,12 +48,24 @@ pub trait Split {
     fn split(self) -> (Self::R, Self::W);
 }
 
+/// shutdown underlying transport, used by `graceful_close` of blocking codecs
+#[cfg(feature = "sync")]
+pub trait Shutdown {
+    /// shutdown both read & write half
+    fn shutdown(&mut self) -> std::io::Result<()>;
+
+    /// set read timeout of underlying transport, `graceful_close` uses it to stop
+    /// waiting close frame at deadline
+    fn set_read_timeout(&mut self, timeout: Option<std::time::Duration>) -> std::io::Result<()>;
+}
+
 #[cfg(feature = "sync")]
 mod blocking {
-    use super::Split;
+    use super::{Shutdown, Split};
     use std::{
         io::{Read, Write},
         net::TcpStream,
+        time::Duration,
     };
 
     // impl<R: Read> Read for ReadHalf<R> {
@@ -80,6 +112,37 @@ mod blocking {
             (self, cloned)
         }
     }
+
+    impl Shutdown for TcpStream {
+        fn shutdown(&mut self) -> std::io::Result<()> {
+            TcpStream::shutdown(self, std::net::Shutdown::Both)
+        }
+
+        fn set_read_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
+            TcpStream::set_read_timeout(self, timeout)
+        }
+    }
+
+    #[cfg(unix)]
+    impl Split for std::os::unix::net::UnixStream {
+        type R = std::os::unix::net::UnixStream;
+        type W = std::os::unix::net::UnixStream;
+        fn split(self) -> (Self::R, Self::W) {
+            let cloned = self.try_clone().expect("failed to split unix stream");
+            (self, cloned)
+        }
+    }
+
+    #[cfg(unix)]
+    impl Shutdown for std::os::unix::net::UnixStream {
+        fn shutdown(&mut self) -> std::io::Result<()> {
+            std::os::unix::net::UnixStream::shutdown(self, std::net::Shutdown::Both)
+        }
+
+        fn set_read_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
+            std::os::unix::net::UnixStream::set_read_timeout(self, timeout)
+        }
+    }
 }
 
 #[cfg(feature = "async")]
@@ -97,6 +160,15 @@ mod non_blocking {
         }
     }
 
+    #[cfg(unix)]
+    impl crate::codec::Split for tokio::net::UnixStream {
+        type R = tokio::io::ReadHalf<tokio::net::UnixStream>;
+        type W = tokio::io::WriteHalf<tokio::net::UnixStream>;
+        fn split(self) -> (Self::R, Self::W) {
+            tokio::io::split(self)
+        }
+    }
+
     impl<S: AsyncRead + AsyncWrite> crate::codec::Split for BufStream<S> {
         type R = tokio::io::ReadHalf<BufStream<S>>;
         type W = tokio::io::WriteHalf<BufStream<S>>;
@@ -0,0 +1,193 @@
+use std::sync::Arc;
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static",
+    feature = "deflate_rust"
+))]
+use std::sync::OnceLock;
+
+use bytes::{Bytes, BytesMut};
+
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static",
+    feature = "deflate_rust"
+))]
+use crate::{
+    codec::{CompressStream, WindowBit},
+    errors::WsError,
+};
+use crate::{
+    frame::{ctor_header, OpCode},
+    WsMessage,
+};
+
+/// frame encoded once and written to many server side connections without
+/// re-encoding, cloning is cheap
+///
+/// encoded frame is unmasked and not fragmented, connections which mask send
+/// frame or need fragmentation fallback to encode payload themselves
+#[derive(Debug, Clone)]
+pub struct PreparedFrame {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    code: OpCode,
+    payload: Bytes,
+    encoded: Bytes,
+    /// compressed frame of each window bits, created on first use
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate_ng",
+        feature = "deflate_static",
+        feature = "deflate_rust"
+    ))]
+    compressed: [OnceLock<Bytes>; 8],
+}
+
+fn encode(code: OpCode, rsv1: bool, payload: &[u8]) -> Bytes {
+    let mut header_buf = [0; 14];
+    let header = ctor_header(
+        &mut header_buf,
+        true,
+        rsv1,
+        false,
+        false,
+        None,
+        code,
+        payload.len() as u64,
+    );
+    let mut buf = BytesMut::with_capacity(header.len() + payload.len());
+    buf.extend_from_slice(header);
+    buf.extend_from_slice(payload);
+    buf.freeze()
+}
+
+impl PreparedFrame {
+    /// encode payload into a complete frame
+    pub fn new(code: OpCode, payload: impl Into<Bytes>) -> Self {
+        let payload = payload.into();
+        let encoded = encode(code, false, &payload);
+        Self {
+            inner: Arc::new(Inner {
+                code,
+                payload,
+                encoded,
+                #[cfg(any(
+                    feature = "deflate",
+                    feature = "deflate_ng",
+                    feature = "deflate_static",
+                    feature = "deflate_rust"
+                ))]
+                compressed: Default::default(),
+            }),
+        }
+    }
+
+    /// opcode of frame
+    pub fn opcode(&self) -> OpCode {
+        self.inner.code
+    }
+
+    /// raw payload before encoding
+    pub fn payload(&self) -> &[u8] {
+        &self.inner.payload
+    }
+
+    /// encoded frame, including header
+    pub fn encoded(&self) -> &[u8] {
+        &self.inner.encoded
+    }
+
+    /// encoded permessage-deflate frame compressed with a fresh compressor, it can
+    /// only be sent by connections which disable context takeover of sending side,
+    /// compressed frame of each window bits is shared by all connections
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate_ng",
+        feature = "deflate_static",
+        feature = "deflate_rust"
+    ))]
+    pub fn compressed(&self, window: WindowBit) -> Result<&[u8], WsError> {
+        let cell = &self.inner.compressed[window as usize - 8];
+        if let Some(encoded) = cell.get() {
+            return Ok(encoded);
+        }
+        let mut com = CompressStream::new(window);
+        let mut compressed = Vec::with_capacity(self.inner.payload.len());
+        com.compress(&[&self.inner.payload], &mut compressed)
+            .map_err(|code| WsError::CompressFailed(code.to_string()))?;
+        compressed.truncate(compressed.len() - 4);
+        let encoded = cell.get_or_init(|| encode(self.inner.code, true, &compressed));
+        Ok(encoded)
+    }
+}
+
+impl From<&WsMessage> for PreparedFrame {
+    fn from(msg: &WsMessage) -> Self {
+        Self::new(msg.opcode(), msg.payload().into_owned())
+    }
+}
+
+impl From<WsMessage> for PreparedFrame {
+    fn from(msg: WsMessage) -> Self {
+        Self::from(&msg)
+    }
+}
+
+#[test]
+#[cfg(feature = "sync")]
+fn test_prepared_frame() {
+    use std::io::Cursor;
+
+    use crate::codec::{FrameCodec, FrameConfig};
+
+    let config = FrameConfig {
+        mask_send_frame: false,
+        ..Default::default()
+    };
+    let mut codec = FrameCodec::new_with(Cursor::new(vec![]), config);
+    codec.send(OpCode::Text, b"hello").unwrap();
+    let frame = PreparedFrame::from(WsMessage::Text("hello".into()));
+    assert_eq!(frame.encoded(), codec.stream_mut().get_ref().as_slice());
+}
+
+#[test]
+#[cfg(all(
+    feature = "sync",
+    any(
+        feature = "deflate",
+        feature = "deflate_ng",
+        feature = "deflate_static",
+        feature = "deflate_rust"
+    )
+))]
+fn test_compressed_prepared_frame() {
+    use std::io::Cursor;
+
+    use crate::codec::{DeflateCodec, FrameConfig, PMDConfig};
+
+    let frame = PreparedFrame::new(OpCode::Text, "hello hello hello");
+    let encoded = frame.compressed(WindowBit::Fifteen).unwrap().to_vec();
+    assert!(encoded.len() < frame.encoded().len());
+    let pmd = PMDConfig {
+        server_no_context_takeover: true,
+        ..Default::default()
+    };
+    let mut client = DeflateCodec::new(
+        Cursor::new([encoded.clone(), encoded].concat()),
+        FrameConfig::default(),
+        Some(pmd),
+        false,
+    );
+    for _ in 0..2 {
+        assert_eq!(
+            client.receive_message().unwrap(),
+            WsMessage::Text("hello hello hello".into())
+        );
+    }
+}
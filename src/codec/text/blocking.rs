@@ -1,16 +1,18 @@
 use http;
 use crate::{
     codec::{
-        FrameCodec, FrameConfig, FrameReadState, FrameRecv, FrameSend, FrameWriteState, Split,
+        ConnectionState, FrameCodec, FrameConfig, FrameReadState, FrameRecv, FrameSend,
+        FrameWriteState, MessageWriter, Shutdown, Split,
     },
     errors::{ProtocolError, WsError},
     frame::OpCode,
-    protocol::standard_handshake_resp_check,
-    Message,
+    protocol::{standard_handshake_resp_check, CloseCode},
+    Message, WsMessage,
 };
 use bytes::Buf;
 use std::borrow::Cow;
 use std::io::{Read, Write};
+use std::time::Duration;
 
 macro_rules! impl_recv {
     () => {
@@ -25,7 +27,7 @@ macro_rules! impl_recv {
                 } else {
                     1000
                 };
-                Some(code)
+                Some(code.into())
             } else {
                 None
             };
@@ -45,13 +47,13 @@ macro_rules! impl_recv {
                 } else {
                     1000
                 };
-                Some(code)
+                Some(code.into())
             } else {
                 None
             };
             let data = if self.validate_utf8 && header.code == OpCode::Text {
                 std::str::from_utf8(data).map_err(|_| WsError::ProtocolError {
-                    close_code: 1001,
+                    close_code: CloseCode::GoingAway,
                     error: ProtocolError::InvalidUtf8,
                 })?
             } else {
@@ -63,6 +65,11 @@ macro_rules! impl_recv {
                 code: header.code,
             })
         }
+
+        /// receive a complete owned message
+        pub fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+            self.frame_codec.receive_message()
+        }
     };
 }
 
@@ -79,8 +86,12 @@ macro_rules! impl_send {
         }
 
         /// helper method to send close message
-        pub fn close<'a>(&mut self, code: u16, msg: &'a str) -> Result<(), WsError> {
-            self.send((code, msg))
+        pub fn close<'a>(
+            &mut self,
+            code: impl Into<CloseCode>,
+            msg: &'a str,
+        ) -> Result<(), WsError> {
+            self.send((u16::from(code.into()), msg))
         }
 
         /// send text message
@@ -88,7 +99,7 @@ macro_rules! impl_send {
             let msg: Message<Cow<'a, str>> = msg.into();
             if let Some(close_code) = msg.close_code {
                 if msg.code == OpCode::Close {
-                    let mut data = close_code.to_be_bytes().to_vec();
+                    let mut data = u16::from(close_code).to_be_bytes().to_vec();
                     data.extend_from_slice(msg.data.as_bytes());
                     self.frame_codec.send(msg.code, &data)
                 } else {
@@ -99,6 +110,16 @@ macro_rules! impl_send {
             }
         }
 
+        /// send owned message
+        pub fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+            self.frame_codec.send_message(msg)
+        }
+
+        /// start a text or binary message written fragment by fragment, see [MessageWriter]
+        pub fn begin_message(&mut self, code: OpCode) -> Result<MessageWriter<'_, S>, WsError> {
+            self.frame_codec.begin_message(code)
+        }
+
         /// flush underlying stream
         pub fn flush(&mut self) -> Result<(), WsError> {
             self.frame_codec.flush()
@@ -183,11 +204,32 @@ impl<S: Read + Write> StringCodec<S> {
         Ok(Self::new_with(stream, FrameConfig::default(), true))
     }
 
+    /// current state of close handshake
+    pub fn state(&self) -> ConnectionState {
+        self.frame_codec.state()
+    }
+
     impl_recv! {}
 
     impl_send! {}
 }
 
+impl<S: Read + Write + Shutdown> StringCodec<S> {
+    /// send close frame if not sent, then wait for close frame from peer until timeout
+    /// and shutdown underlying stream, return true if close frame from peer is received
+    ///
+    /// read timeout of underlying stream is set to remaining time before each read
+    pub fn graceful_close(
+        &mut self,
+        code: impl Into<CloseCode>,
+        reason: &str,
+        timeout: Duration,
+    ) -> Result<bool, WsError> {
+        self.frame_codec
+            .graceful_close(code, reason.as_bytes(), timeout)
+    }
+}
+
 impl<R, W, S> StringCodec<S>
 where
     R: Read,
@@ -1,16 +1,16 @@
 use http;
 use crate::{
     codec::{
-        AsyncFrameCodec, AsyncFrameRecv, AsyncFrameSend, FrameConfig, FrameReadState,
-        FrameWriteState, Split,
+        AsyncFrameCodec, AsyncFrameRecv, AsyncFrameSend, AsyncMessageWriter, ConnectionState,
+        FrameConfig, FrameReadState, FrameWriteState, Split,
     },
     errors::{ProtocolError, WsError},
     frame::OpCode,
-    protocol::standard_handshake_resp_check,
-    Message,
+    protocol::{standard_handshake_resp_check, CloseCode},
+    Message, WsMessage,
 };
 use bytes::Buf;
-use std::borrow::Cow;
+use std::{borrow::Cow, time::Duration};
 use tokio::io::{AsyncRead, AsyncWrite};
 
 macro_rules! impl_recv {
@@ -26,7 +26,7 @@ macro_rules! impl_recv {
                 } else {
                     1000
                 };
-                Some(code)
+                Some(code.into())
             } else {
                 None
             };
@@ -47,13 +47,13 @@ macro_rules! impl_recv {
                 } else {
                     1000
                 };
-                Some(code)
+                Some(code.into())
             } else {
                 None
             };
             let data = if self.validate_utf8 && header.code == OpCode::Text {
                 std::str::from_utf8(data).map_err(|_| WsError::ProtocolError {
-                    close_code: 1001,
+                    close_code: CloseCode::GoingAway,
                     error: ProtocolError::InvalidUtf8,
                 })?
             } else {
@@ -65,6 +65,11 @@ macro_rules! impl_recv {
                 code: header.code,
             })
         }
+
+        /// receive a complete owned message
+        pub async fn receive_message(&mut self) -> Result<WsMessage, WsError> {
+            self.frame_codec.receive_message().await
+        }
     };
 }
 
@@ -81,8 +86,12 @@ macro_rules! impl_send {
         }
 
         /// helper method to send close message
-        pub async fn close<'a>(&mut self, code: u16, msg: &'a str) -> Result<(), WsError> {
-            self.send((code, msg)).await
+        pub async fn close<'a>(
+            &mut self,
+            code: impl Into<CloseCode>,
+            msg: &'a str,
+        ) -> Result<(), WsError> {
+            self.send((u16::from(code.into()), msg)).await
         }
 
         /// send text message
@@ -93,7 +102,7 @@ macro_rules! impl_send {
             let msg: Message<Cow<'a, str>> = msg.into();
             if let Some(close_code) = msg.close_code {
                 if msg.code == OpCode::Close {
-                    let mut data = close_code.to_be_bytes().to_vec();
+                    let mut data = u16::from(close_code).to_be_bytes().to_vec();
                     data.extend_from_slice(msg.data.as_bytes());
                     self.frame_codec.send(msg.code, &data).await
                 } else {
@@ -104,6 +113,19 @@ macro_rules! impl_send {
             }
         }
 
+        /// send owned message
+        pub async fn send_message(&mut self, msg: &WsMessage) -> Result<(), WsError> {
+            self.frame_codec.send_message(msg).await
+        }
+
+        /// start a text or binary message written fragment by fragment, see [AsyncMessageWriter]
+        pub fn begin_message(
+            &mut self,
+            code: OpCode,
+        ) -> Result<AsyncMessageWriter<'_, S>, WsError> {
+            self.frame_codec.begin_message(code)
+        }
+
         /// flush underlying stream
         pub async fn flush(&mut self) -> Result<(), WsError> {
             self.frame_codec.flush().await
@@ -188,6 +210,24 @@ impl<S: AsyncRead + AsyncWrite + Unpin> AsyncStringCodec<S> {
         Ok(Self::new_with(stream, FrameConfig::default(), true))
     }
 
+    /// send close frame if not sent, then wait for close frame from peer until timeout
+    /// and shutdown underlying stream, return true if close frame from peer is received
+    pub async fn graceful_close(
+        &mut self,
+        code: impl Into<CloseCode>,
+        reason: &str,
+        timeout: Duration,
+    ) -> Result<bool, WsError> {
+        self.frame_codec
+            .graceful_close(code, reason.as_bytes(), timeout)
+            .await
+    }
+
+    /// current state of close handshake
+    pub fn state(&self) -> ConnectionState {
+        self.frame_codec.state()
+    }
+
     impl_recv! {}
     impl_send! {}
 }
@@ -212,3 +252,101 @@ where
         )
     }
 }
+
+#[cfg(feature = "stream")]
+fn into_string_message(
+    msg: Message<Vec<u8>>,
+    validate_utf8: bool,
+) -> Result<Message<String>, WsError> {
+    let data = match String::from_utf8(msg.data) {
+        Ok(data) => data,
+        Err(_) if validate_utf8 && msg.code == OpCode::Text => {
+            return Err(WsError::ProtocolError {
+                close_code: CloseCode::InvalidPayload,
+                error: ProtocolError::InvalidUtf8,
+            })
+        }
+        Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+    };
+    Ok(Message {
+        code: msg.code,
+        data,
+        close_code: msg.close_code,
+    })
+}
+
+#[cfg(feature = "stream")]
+macro_rules! impl_stream {
+    ($name:ident, $($bound:tt)+) => {
+        impl<S: $($bound)+ + Unpin> futures_core::Stream for $name<S> {
+            type Item = Result<Message<String>, WsError>;
+
+            fn poll_next(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<Option<Self::Item>> {
+                let this = self.get_mut();
+                std::pin::Pin::new(&mut this.frame_codec)
+                    .poll_next(cx)
+                    .map(|item| item.map(|ret| ret.and_then(|msg| into_string_message(msg, this.validate_utf8))))
+            }
+        }
+    };
+}
+
+#[cfg(feature = "stream")]
+macro_rules! impl_sink {
+    ($name:ident, $($bound:tt)+) => {
+        impl<S: $($bound)+ + Unpin, T: AsRef<str>> futures_sink::Sink<Message<T>> for $name<S> {
+            type Error = WsError;
+
+            fn poll_ready(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<Result<(), WsError>> {
+                futures_sink::Sink::<Message<&[u8]>>::poll_ready(
+                    std::pin::Pin::new(&mut self.get_mut().frame_codec),
+                    cx,
+                )
+            }
+
+            fn start_send(self: std::pin::Pin<&mut Self>, item: Message<T>) -> Result<(), WsError> {
+                let msg = Message {
+                    code: item.code,
+                    data: item.data.as_ref().as_bytes(),
+                    close_code: item.close_code,
+                };
+                std::pin::Pin::new(&mut self.get_mut().frame_codec).start_send(msg)
+            }
+
+            fn poll_flush(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<Result<(), WsError>> {
+                futures_sink::Sink::<Message<&[u8]>>::poll_flush(
+                    std::pin::Pin::new(&mut self.get_mut().frame_codec),
+                    cx,
+                )
+            }
+
+            fn poll_close(
+                self: std::pin::Pin<&mut Self>,
+                cx: &mut std::task::Context<'_>,
+            ) -> std::task::Poll<Result<(), WsError>> {
+                futures_sink::Sink::<Message<&[u8]>>::poll_close(
+                    std::pin::Pin::new(&mut self.get_mut().frame_codec),
+                    cx,
+                )
+            }
+        }
+    };
+}
+
+#[cfg(feature = "stream")]
+impl_stream!(AsyncStringCodec, AsyncRead + AsyncWrite);
+#[cfg(feature = "stream")]
+impl_stream!(AsyncStringRecv, AsyncRead);
+#[cfg(feature = "stream")]
+impl_sink!(AsyncStringCodec, AsyncRead + AsyncWrite);
+#[cfg(feature = "stream")]
+impl_sink!(AsyncStringSend, AsyncWrite);
@@ -0,0 +1,223 @@
+#[cfg(any(feature = "sync_tls_native", feature = "async_tls_native"))]
+use std::path::Path;
+
+#[cfg(any(feature = "sync_tls_native", feature = "async_tls_native"))]
+use super::read_file;
+#[cfg(any(feature = "sync_tls_native", feature = "async_tls_native"))]
+use crate::errors::WsError;
+
+#[cfg(any(feature = "sync_tls_rustls", feature = "async_tls_rustls"))]
+mod rustls_config {
+    use std::{path::Path, sync::Arc};
+
+    use rustls_connector::rustls::{
+        server::AllowAnyAuthenticatedClient, Certificate, PrivateKey, RootCertStore, ServerConfig,
+    };
+
+    use crate::{connector::read_file, errors::WsError};
+
+    /// load PEM encoded certificate chain
+    pub fn load_pem_certs(path: &Path) -> Result<Vec<Certificate>, WsError> {
+        let data = read_file(path)?;
+        let certs = rustls_pemfile::certs(&mut data.as_slice())
+            .map_err(|e| WsError::LoadCertFailed(e.to_string()))?;
+        if certs.is_empty() {
+            return Err(WsError::LoadCertFailed(format!(
+                "no certificate found in {}",
+                path.display()
+            )));
+        }
+        Ok(certs.into_iter().map(Certificate).collect())
+    }
+
+    /// load first PEM encoded private key, pkcs8, rsa and ec keys are supported
+    pub fn load_pem_key(path: &Path) -> Result<PrivateKey, WsError> {
+        let data = read_file(path)?;
+        let mut reader = data.as_slice();
+        loop {
+            match rustls_pemfile::read_one(&mut reader)
+                .map_err(|e| WsError::LoadCertFailed(e.to_string()))?
+            {
+                Some(rustls_pemfile::Item::PKCS8Key(key))
+                | Some(rustls_pemfile::Item::RSAKey(key))
+                | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+                Some(_) => {}
+                None => {
+                    return Err(WsError::LoadCertFailed(format!(
+                        "no private key found in {}",
+                        path.display()
+                    )))
+                }
+            }
+        }
+    }
+
+    /// build rustls server config from PEM cert chain & private key
+    ///
+    /// if `client_ca` is set, clients are required to present a certificate
+    /// signed by one of the CA certs in this file
+    pub fn rustls_server_config(
+        cert_chain: &Path,
+        key: &Path,
+        client_ca: Option<&Path>,
+    ) -> Result<Arc<ServerConfig>, WsError> {
+        let certs = load_pem_certs(cert_chain)?;
+        let key = load_pem_key(key)?;
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match client_ca {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_pem_certs(path)? {
+                    roots
+                        .add(&cert)
+                        .map_err(|e| WsError::LoadCertFailed(e.to_string()))?;
+                }
+                builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+            }
+            None => builder.with_no_client_auth(),
+        };
+        let config = builder
+            .with_single_cert(certs, key)
+            .map_err(|e| WsError::LoadCertFailed(e.to_string()))?;
+        Ok(Arc::new(config))
+    }
+}
+
+#[cfg(any(feature = "sync_tls_rustls", feature = "async_tls_rustls"))]
+pub use rustls_config::*;
+
+/// build native tls acceptor from PEM cert chain & PEM pkcs8 private key
+///
+/// **NOTE**: native tls does not support requiring client certificates
+#[cfg(any(feature = "sync_tls_native", feature = "async_tls_native"))]
+pub fn native_tls_acceptor(
+    cert_chain: &Path,
+    key: &Path,
+) -> Result<native_tls::TlsAcceptor, WsError> {
+    let cert = read_file(cert_chain)?;
+    let key = read_file(key)?;
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key)
+        .map_err(|e| WsError::LoadCertFailed(e.to_string()))?;
+    native_tls::TlsAcceptor::new(identity).map_err(|e| WsError::LoadCertFailed(e.to_string()))
+}
+
+#[cfg(any(feature = "sync_tls_rustls", feature = "sync_tls_native"))]
+mod blocking {
+    #[cfg(feature = "sync_tls_native")]
+    use crate::errors::TimeoutPhase;
+    use crate::errors::WsError;
+
+    #[cfg(feature = "sync_tls_rustls")]
+    /// accept tls session, handshake is completed before return
+    pub fn accept_rustls<S: std::io::Read + std::io::Write>(
+        mut stream: S,
+        config: std::sync::Arc<rustls_connector::rustls::ServerConfig>,
+    ) -> Result<
+        rustls_connector::rustls::StreamOwned<rustls_connector::rustls::ServerConnection, S>,
+        WsError,
+    > {
+        let mut conn = rustls_connector::rustls::ServerConnection::new(config)
+            .map_err(|e| WsError::ConnectionFailed(e.to_string()))?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)
+                .map_err(crate::connector::tls_error)?;
+        }
+        tracing::debug!("tls connection accepted");
+        Ok(rustls_connector::rustls::StreamOwned::new(conn, stream))
+    }
+
+    #[cfg(feature = "sync_tls_native")]
+    /// accept tls session
+    pub fn accept_native_tls<S: std::io::Read + std::io::Write>(
+        stream: S,
+        acceptor: &native_tls::TlsAcceptor,
+    ) -> Result<native_tls::TlsStream<S>, WsError> {
+        let tls_stream = acceptor.accept(stream).map_err(|e| match e {
+            native_tls::HandshakeError::Failure(e) => {
+                WsError::ConnectionFailed(format!("tls handshake failed {e}"))
+            }
+            native_tls::HandshakeError::WouldBlock(_) => WsError::Timeout {
+                phase: TimeoutPhase::Tls,
+            },
+        })?;
+        tracing::debug!("tls connection accepted");
+        Ok(tls_stream)
+    }
+}
+
+#[cfg(any(feature = "sync_tls_rustls", feature = "sync_tls_native"))]
+pub use blocking::*;
+
+#[cfg(any(feature = "async_tls_rustls", feature = "async_tls_native"))]
+mod non_blocking {
+    use crate::errors::WsError;
+
+    #[cfg(feature = "async_tls_rustls")]
+    /// async version of accepting tls session
+    pub async fn async_accept_rustls<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin>(
+        stream: S,
+        config: std::sync::Arc<tokio_rustls::rustls::ServerConfig>,
+    ) -> Result<tokio_rustls::server::TlsStream<S>, WsError> {
+        let acceptor = tokio_rustls::TlsAcceptor::from(config);
+        let tls_stream = acceptor
+            .accept(stream)
+            .await
+            .map_err(|e| WsError::ConnectionFailed(format!("tls handshake failed {e}")))?;
+        tracing::debug!("tls connection accepted");
+        Ok(tls_stream)
+    }
+
+    #[cfg(feature = "async_tls_native")]
+    /// async version of accepting tls session
+    pub async fn async_accept_native_tls<
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    >(
+        stream: S,
+        acceptor: &native_tls::TlsAcceptor,
+    ) -> Result<tokio_native_tls::TlsStream<S>, WsError> {
+        let acceptor = tokio_native_tls::TlsAcceptor::from(acceptor.clone());
+        let tls_stream = acceptor
+            .accept(stream)
+            .await
+            .map_err(|e| WsError::ConnectionFailed(format!("tls handshake failed {e}")))?;
+        tracing::debug!("tls connection accepted");
+        Ok(tls_stream)
+    }
+}
+
+#[cfg(any(feature = "async_tls_rustls", feature = "async_tls_native"))]
+pub use non_blocking::*;
+
+#[cfg(feature = "sync_tls_rustls")]
+#[test]
+fn test_accept_rustls() {
+    use crate::{codec::FrameCodec, connector::wrap_rustls, frame::OpCode, stream::SyncStream};
+    use std::net::{TcpListener, TcpStream};
+
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = std::env::temp_dir().join(format!("ws-tool-acceptor-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+    std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+    std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+    let config = rustls_server_config(&cert_path, &key_path, None).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let stream = SyncStream::RustlsServer(accept_rustls(stream, config).unwrap());
+        let mut server = FrameCodec::factory(Default::default(), stream).unwrap();
+        let (header, data) = server.receive().unwrap();
+        assert_eq!(header.code, OpCode::Text);
+        assert_eq!(data, b"hello");
+    });
+
+    let stream = TcpStream::connect(addr).unwrap();
+    let stream = wrap_rustls(stream, "localhost", vec![cert_path]).unwrap();
+    let mut client = FrameCodec::new(SyncStream::Rustls(stream));
+    client.send(OpCode::Text, b"hello").unwrap();
+    client.flush().unwrap();
+    server.join().unwrap();
+    std::fs::remove_dir_all(dir).ok();
+}
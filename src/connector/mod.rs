@@ -0,0 +1,435 @@
+use http;
+use http::Uri;
+use crate::{errors::WsError, protocol::Mode};
+
+mod acceptor;
+mod proxy;
+mod resolver;
+mod tls;
+#[cfg(unix)]
+mod unix;
+pub use acceptor::*;
+pub use proxy::*;
+pub use resolver::*;
+pub use tls::*;
+#[cfg(unix)]
+pub use unix::*;
+
+/// get websocket scheme
+pub fn get_scheme(uri: &http::Uri) -> Result<Mode, WsError> {
+    match uri.scheme_str().unwrap_or("ws").to_lowercase().as_str() {
+        "ws" => Ok(Mode::WS),
+        "wss" => Ok(Mode::WSS),
+        "ws+unix" if cfg!(unix) => Ok(Mode::WSUnix),
+        s => Err(WsError::InvalidUri(format!("unknown scheme {s}"))),
+    }
+}
+
+/// get host from uri
+pub fn get_host(uri: &Uri) -> Result<&str, WsError> {
+    uri.host()
+        .ok_or_else(|| WsError::InvalidUri(format!("can not find host {}", uri)))
+}
+
+#[cfg(feature = "sync")]
+mod blocking {
+    use crate::errors::{TimeoutPhase, WsError};
+    use http;
+    use std::{
+        io::ErrorKind,
+        net::TcpStream,
+        time::{Duration, Instant},
+    };
+
+    use super::{get_host, get_scheme, ConnectOptions};
+    use crate::protocol::Mode;
+
+    /// performance tcp connection
+    pub fn tcp_connect(uri: &http::Uri) -> Result<TcpStream, WsError> {
+        let mode = get_scheme(uri)?;
+        if mode == Mode::WSUnix {
+            return Err(WsError::InvalidUri(format!(
+                "{uri} is not a tcp address, connect unix socket instead"
+            )));
+        }
+        let host = get_host(uri)?;
+        let port = uri.port_u16().unwrap_or_else(|| mode.default_port());
+        tcp_connect_with(host, port, &Default::default(), None)
+    }
+
+    /// performance tcp connection, try resolved addresses in order until one of them
+    /// is connected, fail with [WsError::Timeout] if it takes longer than `timeout`
+    pub fn tcp_connect_timeout(
+        host: &str,
+        port: u16,
+        timeout: Duration,
+    ) -> Result<TcpStream, WsError> {
+        tcp_connect_with(host, port, &Default::default(), Some(timeout))
+    }
+
+    /// performance tcp connection with custom resolve & bind options, try resolved
+    /// addresses in order until one of them is connected
+    pub fn tcp_connect_with(
+        host: &str,
+        port: u16,
+        options: &ConnectOptions,
+        timeout: Option<Duration>,
+    ) -> Result<TcpStream, WsError> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let expired = || deadline.map(|d| Instant::now() >= d).unwrap_or_default();
+        let addrs = options.resolve(host, port)?;
+        let mut last_err = None;
+        for addr in addrs {
+            let socket = match options.socket(&addr) {
+                Ok(socket) => socket,
+                Err(e) => {
+                    last_err = Some(e);
+                    continue;
+                }
+            };
+            let ret = match deadline {
+                Some(deadline) => {
+                    let remain = deadline.saturating_duration_since(Instant::now());
+                    if remain.is_zero() {
+                        break;
+                    }
+                    socket.connect_timeout(&addr.into(), remain)
+                }
+                None => socket.connect(&addr.into()),
+            };
+            match ret {
+                Ok(_) => return Ok(socket.into()),
+                Err(e) => last_err = Some(e),
+            }
+        }
+        match last_err {
+            Some(e) if deadline.is_none() || !expired() && e.kind() != ErrorKind::TimedOut => Err(
+                WsError::ConnectionFailed(format!("failed to create tcp connection {e}")),
+            ),
+            _ => Err(WsError::Timeout {
+                phase: TimeoutPhase::Connect,
+            }),
+        }
+    }
+
+    /// convert io error during tls handshake, read/write timeout of socket is
+    /// converted to [WsError::Timeout]
+    #[cfg(feature = "sync_tls_rustls")]
+    pub(crate) fn tls_error(e: std::io::Error) -> WsError {
+        match crate::codec::io_timeout(e, TimeoutPhase::Tls) {
+            WsError::IOError(e) => WsError::ConnectionFailed(format!("tls handshake failed {e}")),
+            e => e,
+        }
+    }
+
+    // #[cfg(feature = "sync_tls_rustls")]
+    // impl<S: std::io::Read + std::io::Write> crate::codec::Split for rustls_connector::TlsStream<S> {
+    //     type R = tokio::io::ReadHalf<BufStream<S>>;
+    //     type W = tokio::io::WriteHalf<BufStream<S>>;
+    //     fn split(self) -> (Self::R, Self::W) {
+    //         tokio::io::split(self)
+    //     }
+    // }
+
+    #[cfg(feature = "sync_tls_rustls")]
+    /// start tls session
+    pub fn wrap_rustls<
+        S: std::io::Read + std::io::Write + Sync + Send + std::fmt::Debug + 'static,
+    >(
+        stream: S,
+        host: &str,
+        certs: Vec<std::path::PathBuf>,
+    ) -> Result<rustls_connector::TlsStream<S>, WsError> {
+        wrap_rustls_with(stream, host, &certs, &Default::default())
+    }
+
+    #[cfg(feature = "sync_tls_rustls")]
+    /// start tls session with custom tls options
+    pub fn wrap_rustls_with<
+        S: std::io::Read + std::io::Write + Sync + Send + std::fmt::Debug + 'static,
+    >(
+        stream: S,
+        host: &str,
+        certs: &[std::path::PathBuf],
+        options: &crate::connector::TlsOptions,
+    ) -> Result<rustls_connector::TlsStream<S>, WsError> {
+        let config = crate::connector::rustls_client_config(certs, options)?;
+        let connector = rustls_connector::RustlsConnector::from(config);
+        let tls_stream = connector
+            .connect(options.server_name(host), stream)
+            .map_err(|e| match e {
+                rustls_connector::HandshakeError::WouldBlock(_) => WsError::Timeout {
+                    phase: TimeoutPhase::Tls,
+                },
+                rustls_connector::HandshakeError::Failure(e) => tls_error(e),
+            })?;
+        tracing::debug!("tls connection established");
+        Ok(tls_stream)
+    }
+
+    #[cfg(feature = "sync_tls_native")]
+    /// start tls session
+    pub fn wrap_native_tls<S: std::io::Read + std::io::Write>(
+        stream: S,
+        host: &str,
+        certs: Vec<std::path::PathBuf>,
+    ) -> Result<native_tls::TlsStream<S>, WsError> {
+        wrap_native_tls_with(stream, host, &certs, &Default::default())
+    }
+
+    #[cfg(feature = "sync_tls_native")]
+    /// start tls session with custom tls options
+    pub fn wrap_native_tls_with<S: std::io::Read + std::io::Write>(
+        stream: S,
+        host: &str,
+        certs: &[std::path::PathBuf],
+        options: &crate::connector::TlsOptions,
+    ) -> Result<native_tls::TlsStream<S>, WsError> {
+        let connector = crate::connector::native_tls_connector(certs, options)?;
+        let tls_stream = connector
+            .connect(options.server_name(host), stream)
+            .map_err(|e| match e {
+                native_tls::HandshakeError::WouldBlock(_) => WsError::Timeout {
+                    phase: TimeoutPhase::Tls,
+                },
+                native_tls::HandshakeError::Failure(_) => {
+                    WsError::ConnectionFailed("tls connect failed".into())
+                }
+            })?;
+        crate::connector::check_native_pinned_spki(&tls_stream, options)?;
+        tracing::debug!("tls connection established");
+        Ok(tls_stream)
+    }
+}
+
+#[cfg(feature = "sync")]
+pub use blocking::*;
+
+#[cfg(feature = "async")]
+mod non_blocking {
+    use std::{
+        future::{poll_fn, Future},
+        pin::Pin,
+        task::Poll,
+    };
+
+    use http::Uri;
+    use tokio::net::{TcpSocket, TcpStream};
+
+    use crate::errors::WsError;
+
+    use super::{get_host, get_scheme, ConnectOptions};
+    use crate::protocol::Mode;
+
+    /// performance tcp connection
+    pub async fn async_tcp_connect(uri: &Uri) -> Result<TcpStream, WsError> {
+        let mode = get_scheme(uri)?;
+        if mode == Mode::WSUnix {
+            return Err(WsError::InvalidUri(format!(
+                "{uri} is not a tcp address, connect unix socket instead"
+            )));
+        }
+        let host = get_host(uri)?;
+        let port = uri.port_u16().unwrap_or_else(|| mode.default_port());
+        async_tcp_connect_with(host, port, &Default::default()).await
+    }
+
+    /// performance tcp connection with custom resolve & bind options
+    ///
+    /// resolved addresses are raced as described in RFC 8305 (happy eyeballs), next
+    /// attempt starts if previous one fails or does not complete within
+    /// `happy_eyeballs_delay`, the first established connection is returned
+    pub async fn async_tcp_connect_with(
+        host: &str,
+        port: u16,
+        options: &ConnectOptions,
+    ) -> Result<TcpStream, WsError> {
+        let mut addrs = options
+            .async_resolve(host, port)
+            .await?
+            .into_iter()
+            .peekable();
+        let mut attempts: Vec<Attempt> = vec![];
+        let mut last_err = None;
+        let mut delay = Box::pin(tokio::time::sleep(options.happy_eyeballs_delay));
+        let mut start_next = true;
+        loop {
+            if std::mem::take(&mut start_next) {
+                if let Some(addr) = addrs.next() {
+                    let socket = match options.socket(&addr).and_then(|socket| {
+                        socket.set_nonblocking(true)?;
+                        Ok(TcpSocket::from_std_stream(socket.into()))
+                    }) {
+                        Ok(socket) => socket,
+                        Err(e) => {
+                            last_err = Some(e);
+                            start_next = true;
+                            continue;
+                        }
+                    };
+                    attempts.push(Box::pin(socket.connect(addr)));
+                    delay
+                        .as_mut()
+                        .reset(tokio::time::Instant::now() + options.happy_eyeballs_delay);
+                }
+            }
+            if attempts.is_empty() {
+                let e = last_err.map(|e| e.to_string()).unwrap_or_default();
+                return Err(WsError::ConnectionFailed(format!(
+                    "failed to create tcp connection {e}"
+                )));
+            }
+            let has_next = addrs.peek().is_some();
+            let ret = poll_fn(|cx| {
+                for idx in 0..attempts.len() {
+                    if let Poll::Ready(ret) = attempts[idx].as_mut().poll(cx) {
+                        drop(attempts.swap_remove(idx));
+                        return Poll::Ready(Some(ret));
+                    }
+                }
+                if has_next && delay.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+                Poll::Pending
+            })
+            .await;
+            match ret {
+                Some(Ok(stream)) => return Ok(stream),
+                Some(Err(e)) => {
+                    last_err = Some(e);
+                    start_next = true;
+                }
+                None => start_next = true,
+            }
+        }
+    }
+
+    type Attempt = Pin<Box<dyn Future<Output = std::io::Result<TcpStream>> + Send>>;
+
+    #[cfg(feature = "async_tls_rustls")]
+    impl<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin> crate::codec::Split
+        for tokio_rustls::client::TlsStream<S>
+    {
+        type R = tokio::io::ReadHalf<tokio_rustls::client::TlsStream<S>>;
+        type W = tokio::io::WriteHalf<tokio_rustls::client::TlsStream<S>>;
+        fn split(self) -> (Self::R, Self::W) {
+            tokio::io::split(self)
+        }
+    }
+
+    #[cfg(feature = "async_tls_rustls")]
+    impl<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin> crate::codec::Split
+        for tokio_rustls::server::TlsStream<S>
+    {
+        type R = tokio::io::ReadHalf<tokio_rustls::server::TlsStream<S>>;
+        type W = tokio::io::WriteHalf<tokio_rustls::server::TlsStream<S>>;
+        fn split(self) -> (Self::R, Self::W) {
+            tokio::io::split(self)
+        }
+    }
+
+    #[cfg(feature = "async_tls_rustls")]
+    /// async version of starting tls session
+    pub async fn async_wrap_rustls<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin>(
+        stream: S,
+        host: &str,
+        certs: Vec<std::path::PathBuf>,
+    ) -> Result<tokio_rustls::client::TlsStream<S>, WsError> {
+        async_wrap_rustls_with(stream, host, &certs, &Default::default()).await
+    }
+
+    #[cfg(feature = "async_tls_rustls")]
+    /// async version of starting tls session with custom tls options
+    pub async fn async_wrap_rustls_with<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin>(
+        stream: S,
+        host: &str,
+        certs: &[std::path::PathBuf],
+        options: &crate::connector::TlsOptions,
+    ) -> Result<tokio_rustls::client::TlsStream<S>, WsError> {
+        let config = crate::connector::rustls_client_config(certs, options)?;
+        let domain = tokio_rustls::rustls::ServerName::try_from(options.server_name(host))
+            .map_err(|e| WsError::TlsDnsFailed(e.to_string()))?;
+        let connector = tokio_rustls::TlsConnector::from(config);
+        let tls_stream = connector
+            .connect(domain, stream)
+            .await
+            .map_err(|e| WsError::ConnectionFailed(e.to_string()))?;
+        tracing::debug!("tls connection established");
+        Ok(tls_stream)
+    }
+
+    #[cfg(feature = "async_tls_native")]
+    impl<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin> crate::codec::Split
+        for tokio_native_tls::TlsStream<S>
+    {
+        type R = tokio::io::ReadHalf<tokio_native_tls::TlsStream<S>>;
+        type W = tokio::io::WriteHalf<tokio_native_tls::TlsStream<S>>;
+        fn split(self) -> (Self::R, Self::W) {
+            tokio::io::split(self)
+        }
+    }
+
+    #[cfg(feature = "async_tls_native")]
+    /// start tls session
+    pub async fn async_wrap_native_tls<S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin>(
+        stream: S,
+        host: &str,
+        certs: Vec<std::path::PathBuf>,
+    ) -> Result<tokio_native_tls::TlsStream<S>, WsError> {
+        async_wrap_native_tls_with(stream, host, &certs, &Default::default()).await
+    }
+
+    #[cfg(feature = "async_tls_native")]
+    /// async version of starting tls session with custom tls options
+    pub async fn async_wrap_native_tls_with<
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+    >(
+        stream: S,
+        host: &str,
+        certs: &[std::path::PathBuf],
+        options: &crate::connector::TlsOptions,
+    ) -> Result<tokio_native_tls::TlsStream<S>, WsError> {
+        let connector = crate::connector::native_tls_connector(certs, options)?;
+        let connector = tokio_native_tls::TlsConnector::from(connector);
+        let tls_stream = connector
+            .connect(options.server_name(host), stream)
+            .await
+            .map_err(|e| WsError::ConnectionFailed(e.to_string()))?;
+        crate::connector::check_native_pinned_spki(tls_stream.get_ref(), options)?;
+        tracing::debug!("tls connection established");
+        Ok(tls_stream)
+    }
+}
+
+#[cfg(feature = "async")]
+pub use non_blocking::*;
+
+#[cfg(feature = "async")]
+#[test]
+fn test_happy_eyeballs() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut options = ConnectOptions {
+            happy_eyeballs_delay: std::time::Duration::from_millis(10),
+            ..Default::default()
+        };
+        // nothing listens on 127.0.0.2, connection is refused
+        options.overrides.insert(
+            "ws.local".into(),
+            vec!["127.0.0.2".parse().unwrap(), "127.0.0.1".parse().unwrap()],
+        );
+        let stream = async_tcp_connect_with("ws.local", port, &options)
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap().ip().to_string(), "127.0.0.1");
+        options.overrides.insert("ws.local".into(), vec![]);
+        assert!(async_tcp_connect_with("ws.local", port, &options)
+            .await
+            .is_err());
+    });
+}
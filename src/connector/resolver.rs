@@ -0,0 +1,260 @@
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    sync::Arc,
+    time::Duration,
+};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::errors::WsError;
+
+/// ip version preference of resolved addresses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IpPreference {
+    /// keep order of resolver, interleave address families
+    #[default]
+    Any,
+    /// try ipv4 addresses first, interleave address families
+    PreferV4,
+    /// try ipv6 addresses first, interleave address families
+    PreferV6,
+    /// only use ipv4 addresses
+    V4Only,
+    /// only use ipv6 addresses
+    V6Only,
+}
+
+/// custom dns resolver
+pub trait Resolve: Send + Sync {
+    /// resolve host to socket addresses, async connection calls it on tokio
+    /// blocking pool
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, WsError>;
+}
+
+/// system resolver via `getaddrinfo`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+impl Resolve for SystemResolver {
+    fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, WsError> {
+        (host, port)
+            .to_socket_addrs()
+            .map(|addrs| addrs.collect())
+            .map_err(|e| WsError::ConnectionFailed(format!("failed to resolve {host}:{port} {e}")))
+    }
+}
+
+/// dns resolution & tcp connect options
+#[derive(Clone)]
+pub struct ConnectOptions {
+    /// static host overrides, take precedence over resolver
+    pub overrides: HashMap<String, Vec<IpAddr>>,
+    /// custom resolver, system resolver is used if not set
+    pub resolver: Option<Arc<dyn Resolve>>,
+    /// ip version preference
+    pub ip_preference: IpPreference,
+    /// bind socket to local address before connecting, only addresses of
+    /// same ip version are tried
+    pub local_addr: Option<IpAddr>,
+    /// bind socket to network interface, only supported on linux, android & fuchsia
+    pub interface: Option<String>,
+    /// delay before starting next connection attempt when previous one is still
+    /// pending, only used by async connection, see RFC 8305, default 250ms
+    pub happy_eyeballs_delay: Duration,
+}
+
+impl Default for ConnectOptions {
+    fn default() -> Self {
+        Self {
+            overrides: Default::default(),
+            resolver: None,
+            ip_preference: Default::default(),
+            local_addr: None,
+            interface: None,
+            happy_eyeballs_delay: Duration::from_millis(250),
+        }
+    }
+}
+
+impl Debug for ConnectOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConnectOptions")
+            .field("overrides", &self.overrides)
+            .field("resolver", &self.resolver.as_ref().map(|_| "custom"))
+            .field("ip_preference", &self.ip_preference)
+            .field("local_addr", &self.local_addr)
+            .field("interface", &self.interface)
+            .field("happy_eyeballs_delay", &self.happy_eyeballs_delay)
+            .finish()
+    }
+}
+
+impl ConnectOptions {
+    /// resolve host & sort addresses by preference
+    pub fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, WsError> {
+        let addrs = match self.lookup_static(host, port) {
+            Some(addrs) => addrs,
+            None => match &self.resolver {
+                Some(resolver) => resolver.resolve(host, port)?,
+                None => SystemResolver.resolve(host, port)?,
+            },
+        };
+        self.sort(host, addrs)
+    }
+
+    /// async version of resolving host, system & custom resolver run on tokio
+    /// blocking pool
+    #[cfg(feature = "async")]
+    pub async fn async_resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>, WsError> {
+        let addrs = match self.lookup_static(host, port) {
+            Some(addrs) => addrs,
+            None => match &self.resolver {
+                Some(resolver) => {
+                    let (resolver, owned) = (resolver.clone(), host.to_string());
+                    tokio::task::spawn_blocking(move || resolver.resolve(&owned, port))
+                        .await
+                        .map_err(|e| {
+                            WsError::ConnectionFailed(format!(
+                                "failed to resolve {host}:{port} {e}"
+                            ))
+                        })??
+                }
+                None => tokio::net::lookup_host((host, port))
+                    .await
+                    .map(|addrs| addrs.collect())
+                    .map_err(|e| {
+                        WsError::ConnectionFailed(format!("failed to resolve {host}:{port} {e}"))
+                    })?,
+            },
+        };
+        self.sort(host, addrs)
+    }
+
+    fn lookup_static(&self, host: &str, port: u16) -> Option<Vec<SocketAddr>> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if let Ok(ip) = host.parse::<IpAddr>() {
+            return Some(vec![SocketAddr::new(ip, port)]);
+        }
+        self.overrides
+            .get(host)
+            .map(|ips| ips.iter().map(|ip| SocketAddr::new(*ip, port)).collect())
+    }
+
+    fn sort(&self, host: &str, addrs: Vec<SocketAddr>) -> Result<Vec<SocketAddr>, WsError> {
+        let addrs: Vec<_> = addrs
+            .into_iter()
+            .filter(|addr| match self.local_addr {
+                Some(local) => local.is_ipv4() == addr.is_ipv4(),
+                None => true,
+            })
+            .collect();
+        let v6_first = match self.ip_preference {
+            IpPreference::Any => addrs.first().map(|addr| addr.is_ipv6()).unwrap_or(false),
+            IpPreference::PreferV4 => false,
+            IpPreference::PreferV6 => true,
+            IpPreference::V4Only => {
+                return non_empty(host, addrs.into_iter().filter(|a| a.is_ipv4()).collect())
+            }
+            IpPreference::V6Only => {
+                return non_empty(host, addrs.into_iter().filter(|a| a.is_ipv6()).collect())
+            }
+        };
+        let (v6, v4): (Vec<_>, Vec<_>) = addrs.into_iter().partition(|addr| addr.is_ipv6());
+        let (first, second) = if v6_first { (v6, v4) } else { (v4, v6) };
+        non_empty(host, interleave(first, second))
+    }
+
+    /// create tcp socket of same ip version as `addr`, bind to local address &
+    /// interface if configured
+    pub(crate) fn socket(&self, addr: &SocketAddr) -> std::io::Result<Socket> {
+        let socket = Socket::new(
+            Domain::for_address(*addr),
+            Type::STREAM,
+            Some(Protocol::TCP),
+        )?;
+        if let Some(ip) = self.local_addr {
+            socket.bind(&SocketAddr::new(ip, 0).into())?;
+        }
+        if let Some(interface) = &self.interface {
+            #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+            socket.bind_device(Some(interface.as_bytes()))?;
+            #[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("can not bind to interface {interface} on this platform"),
+            ));
+        }
+        Ok(socket)
+    }
+}
+
+fn non_empty(host: &str, addrs: Vec<SocketAddr>) -> Result<Vec<SocketAddr>, WsError> {
+    if addrs.is_empty() {
+        Err(WsError::ConnectionFailed(format!(
+            "no available address for {host}"
+        )))
+    } else {
+        Ok(addrs)
+    }
+}
+
+/// interleave address families, RFC 8305 section 4
+fn interleave(first: Vec<SocketAddr>, second: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let mut ret = Vec::with_capacity(first.len() + second.len());
+    let (mut first, mut second) = (first.into_iter(), second.into_iter());
+    loop {
+        match (first.next(), second.next()) {
+            (None, None) => break ret,
+            (a, b) => ret.extend(a.into_iter().chain(b)),
+        }
+    }
+}
+
+#[test]
+fn test_ip_preference() {
+    let v4: SocketAddr = "127.0.0.1:80".parse().unwrap();
+    let v4_2: SocketAddr = "127.0.0.2:80".parse().unwrap();
+    let v6: SocketAddr = "[::1]:80".parse().unwrap();
+    let mut options = ConnectOptions::default();
+    let addrs = vec![v6, v4, v4_2];
+    assert_eq!(options.sort("", addrs.clone()).unwrap(), vec![v6, v4, v4_2]);
+    options.ip_preference = IpPreference::PreferV4;
+    assert_eq!(options.sort("", addrs.clone()).unwrap(), vec![v4, v6, v4_2]);
+    options.ip_preference = IpPreference::V6Only;
+    assert_eq!(options.sort("", addrs.clone()).unwrap(), vec![v6]);
+    options.ip_preference = IpPreference::Any;
+    options.local_addr = Some(v4.ip());
+    assert_eq!(options.sort("", addrs).unwrap(), vec![v4, v4_2]);
+    options
+        .overrides
+        .insert("example.com".into(), vec![v4_2.ip()]);
+    assert_eq!(options.resolve("example.com", 80).unwrap(), vec![v4_2]);
+    assert!(options.resolve("[::1]", 80).is_err());
+}
+
+#[cfg(all(test, feature = "async"))]
+#[tokio::test]
+async fn test_async_custom_resolver() {
+    use std::{sync::Mutex, thread::ThreadId};
+
+    struct Recorder(Mutex<Option<ThreadId>>);
+
+    impl Resolve for Recorder {
+        fn resolve(&self, _host: &str, port: u16) -> Result<Vec<SocketAddr>, WsError> {
+            *self.0.lock().unwrap() = Some(std::thread::current().id());
+            Ok(vec![SocketAddr::from(([127, 0, 0, 1], port))])
+        }
+    }
+
+    let recorder = Arc::new(Recorder(Mutex::new(None)));
+    let options = ConnectOptions {
+        resolver: Some(recorder.clone()),
+        ..Default::default()
+    };
+    let addrs = options.async_resolve("example.com", 80).await.unwrap();
+    assert_eq!(addrs, vec![SocketAddr::from(([127, 0, 0, 1], 80))]);
+    let resolved_on = recorder.0.lock().unwrap().unwrap();
+    assert_ne!(resolved_on, std::thread::current().id());
+}
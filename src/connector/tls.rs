@@ -0,0 +1,317 @@
+use std::path::PathBuf;
+
+#[cfg(any(
+    feature = "sync_tls_rustls",
+    feature = "async_tls_rustls",
+    feature = "sync_tls_native",
+    feature = "async_tls_native"
+))]
+use crate::errors::WsError;
+
+/// tls options of client connection
+#[derive(Debug, Clone, Default)]
+pub struct TlsOptions {
+    /// PEM encoded client certificate chain for mutual tls
+    pub client_cert: Option<PathBuf>,
+    /// PEM encoded private key of client certificate, native tls requires pkcs8 key
+    pub client_key: Option<PathBuf>,
+    /// base64 encoded sha256 hashes of server certificate SubjectPublicKeyInfo,
+    /// `sha256/` prefix is optional, handshake fails if leaf certificate matches none of them
+    pub pinned_spki: Vec<String>,
+    /// do not trust built-in root certificates, only custom certs are trusted
+    pub disable_webpki_roots: bool,
+    /// server name used for SNI & certificate verification instead of uri host
+    pub sni: Option<String>,
+    /// ALPN protocols, e.g. `http/1.1`
+    pub alpn: Vec<String>,
+    /// **DANGEROUS** skip certificate verification, use it for local testing only
+    pub accept_invalid_certs: bool,
+}
+
+impl TlsOptions {
+    /// server name used by tls session
+    pub fn server_name<'a>(&'a self, host: &'a str) -> &'a str {
+        self.sni.as_deref().unwrap_or(host)
+    }
+}
+
+/// (tag, whole TLV, content, rest)
+#[cfg(any(
+    feature = "sync_tls_rustls",
+    feature = "async_tls_rustls",
+    feature = "sync_tls_native",
+    feature = "async_tls_native"
+))]
+type Tlv<'a> = (u8, &'a [u8], &'a [u8], &'a [u8]);
+
+/// read next der TLV
+#[cfg(any(
+    feature = "sync_tls_rustls",
+    feature = "async_tls_rustls",
+    feature = "sync_tls_native",
+    feature = "async_tls_native"
+))]
+fn der_next(data: &[u8]) -> Option<Tlv<'_>> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (len, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let num = first & 0x7f;
+        if num == 0 || num > 4 {
+            return None;
+        }
+        let len = data
+            .get(2..2 + num)?
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (len, 2 + num)
+    };
+    let end = header.checked_add(len)?;
+    let tlv = data.get(..end)?;
+    Some((tag, tlv, &tlv[header..], &data[end..]))
+}
+
+/// extract der encoded SubjectPublicKeyInfo from der encoded x509 certificate
+#[cfg(any(
+    feature = "sync_tls_rustls",
+    feature = "async_tls_rustls",
+    feature = "sync_tls_native",
+    feature = "async_tls_native"
+))]
+fn cert_spki(cert: &[u8]) -> Option<&[u8]> {
+    let (_, _, cert, _) = der_next(cert)?;
+    let (_, _, mut tbs, _) = der_next(cert)?;
+    // skip explicit version
+    if tbs.first() == Some(&0xa0) {
+        tbs = der_next(tbs)?.3;
+    }
+    // skip serial number, signature, issuer, validity and subject
+    for _ in 0..5 {
+        tbs = der_next(tbs)?.3;
+    }
+    match der_next(tbs)? {
+        (0x30, spki, _, _) => Some(spki),
+        _ => None,
+    }
+}
+
+/// check der encoded leaf certificate against pinned spki hashes, always pass if no pin is set
+#[cfg(any(
+    feature = "sync_tls_rustls",
+    feature = "async_tls_rustls",
+    feature = "sync_tls_native",
+    feature = "async_tls_native"
+))]
+pub(crate) fn check_pinned_spki(cert: &[u8], pins: &[String]) -> Result<(), WsError> {
+    if pins.is_empty() {
+        return Ok(());
+    }
+    let spki = cert_spki(cert)
+        .ok_or_else(|| WsError::ConnectionFailed("invalid server certificate".to_string()))?;
+    let hash = base64::encode(ring::digest::digest(&ring::digest::SHA256, spki));
+    if pins
+        .iter()
+        .any(|pin| pin.strip_prefix("sha256/").unwrap_or(pin) == hash)
+    {
+        Ok(())
+    } else {
+        Err(WsError::ConnectionFailed(format!(
+            "server certificate spki sha256/{hash} is not pinned"
+        )))
+    }
+}
+
+#[cfg(any(
+    feature = "sync_tls_rustls",
+    feature = "async_tls_rustls",
+    feature = "sync_tls_native",
+    feature = "async_tls_native"
+))]
+pub(crate) fn read_file(path: &std::path::Path) -> Result<Vec<u8>, WsError> {
+    std::fs::read(path)
+        .map_err(|_| WsError::CertFileNotFound(path.to_str().unwrap_or_default().to_string()))
+}
+
+#[cfg(any(feature = "sync_tls_rustls", feature = "async_tls_rustls"))]
+mod rustls_client {
+    use std::{path::PathBuf, sync::Arc, time::SystemTime};
+
+    use rustls_connector::rustls::{
+        client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+        Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+    };
+
+    use super::{check_pinned_spki, TlsOptions};
+    use crate::{
+        connector::{load_pem_certs, load_pem_key},
+        errors::WsError,
+    };
+
+    struct Verifier {
+        inner: Option<WebPkiVerifier>,
+        pins: Vec<String>,
+    }
+
+    impl ServerCertVerifier for Verifier {
+        fn verify_server_cert(
+            &self,
+            end_entity: &Certificate,
+            intermediates: &[Certificate],
+            server_name: &ServerName,
+            scts: &mut dyn Iterator<Item = &[u8]>,
+            ocsp_response: &[u8],
+            now: SystemTime,
+        ) -> Result<ServerCertVerified, rustls_connector::rustls::Error> {
+            if let Some(inner) = &self.inner {
+                inner.verify_server_cert(
+                    end_entity,
+                    intermediates,
+                    server_name,
+                    scts,
+                    ocsp_response,
+                    now,
+                )?;
+            }
+            check_pinned_spki(&end_entity.0, &self.pins)
+                .map_err(|e| rustls_connector::rustls::Error::General(e.to_string()))?;
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+
+    /// build rustls client config from extra PEM root certs and tls options
+    pub fn rustls_client_config(
+        certs: &[PathBuf],
+        options: &TlsOptions,
+    ) -> Result<Arc<ClientConfig>, WsError> {
+        let mut root_store = RootCertStore::empty();
+        if !options.disable_webpki_roots {
+            root_store.add_server_trust_anchors(
+                rustls_connector::webpki_roots::TLS_SERVER_ROOTS
+                    .0
+                    .iter()
+                    .map(|ta| {
+                        OwnedTrustAnchor::from_subject_spki_name_constraints(
+                            ta.subject,
+                            ta.spki,
+                            ta.name_constraints,
+                        )
+                    }),
+            );
+        }
+        for cert_path in certs.iter() {
+            for cert in load_pem_certs(cert_path)? {
+                root_store
+                    .add(&cert)
+                    .map_err(|e| WsError::LoadCertFailed(e.to_string()))?;
+            }
+        }
+        let inner = if options.accept_invalid_certs {
+            None
+        } else {
+            Some(WebPkiVerifier::new(root_store, None))
+        };
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(Verifier {
+                inner,
+                pins: options.pinned_spki.clone(),
+            }));
+        let mut config = match (&options.client_cert, &options.client_key) {
+            (Some(cert), Some(key)) => builder
+                .with_single_cert(load_pem_certs(cert)?, load_pem_key(key)?)
+                .map_err(|e| WsError::LoadCertFailed(e.to_string()))?,
+            (None, None) => builder.with_no_client_auth(),
+            _ => {
+                return Err(WsError::LoadCertFailed(
+                    "client cert and key should be set together".to_string(),
+                ))
+            }
+        };
+        config.alpn_protocols = options.alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+        Ok(Arc::new(config))
+    }
+}
+
+#[cfg(any(feature = "sync_tls_rustls", feature = "async_tls_rustls"))]
+pub use rustls_client::*;
+
+/// build native tls connector from extra DER root certs and tls options
+#[cfg(any(feature = "sync_tls_native", feature = "async_tls_native"))]
+pub fn native_tls_connector(
+    certs: &[PathBuf],
+    options: &TlsOptions,
+) -> Result<native_tls::TlsConnector, WsError> {
+    let mut builder = native_tls::TlsConnector::builder();
+    for cert_path in certs.iter() {
+        let data = read_file(cert_path)?;
+        match native_tls::Certificate::from_der(&data) {
+            Ok(cert) => {
+                builder.add_root_certificate(cert);
+            }
+            Err(e) => {
+                tracing::error!(
+                    "invalid cert file {} {}",
+                    cert_path.display(),
+                    e.to_string()
+                );
+                continue;
+            }
+        }
+    }
+    match (&options.client_cert, &options.client_key) {
+        (Some(cert), Some(key)) => {
+            let identity = native_tls::Identity::from_pkcs8(&read_file(cert)?, &read_file(key)?)
+                .map_err(|e| WsError::LoadCertFailed(e.to_string()))?;
+            builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => {
+            return Err(WsError::LoadCertFailed(
+                "client cert and key should be set together".to_string(),
+            ))
+        }
+    }
+    let alpn: Vec<&str> = options.alpn.iter().map(|p| p.as_str()).collect();
+    builder
+        .disable_built_in_roots(options.disable_webpki_roots)
+        .danger_accept_invalid_certs(options.accept_invalid_certs)
+        .request_alpns(&alpn);
+    builder
+        .build()
+        .map_err(|e| WsError::LoadCertFailed(e.to_string()))
+}
+
+/// check pinned spki of peer certificate after native tls handshake
+#[cfg(any(feature = "sync_tls_native", feature = "async_tls_native"))]
+pub(crate) fn check_native_pinned_spki<S: std::io::Read + std::io::Write>(
+    stream: &native_tls::TlsStream<S>,
+    options: &TlsOptions,
+) -> Result<(), WsError> {
+    if options.pinned_spki.is_empty() {
+        return Ok(());
+    }
+    let cert = stream
+        .peer_certificate()
+        .map_err(|e| WsError::ConnectionFailed(e.to_string()))?
+        .ok_or_else(|| WsError::ConnectionFailed("no server certificate".to_string()))?;
+    let der = cert
+        .to_der()
+        .map_err(|e| WsError::ConnectionFailed(e.to_string()))?;
+    check_pinned_spki(&der, &options.pinned_spki)
+}
+
+#[cfg(feature = "sync_tls_rustls")]
+#[test]
+fn test_pinned_spki() {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let der = cert.serialize_der().unwrap();
+    let hash = base64::encode(ring::digest::digest(
+        &ring::digest::SHA256,
+        &cert.get_key_pair().public_key_der(),
+    ));
+    assert!(check_pinned_spki(&der, &[]).is_ok());
+    assert!(check_pinned_spki(&der, &[hash.clone()]).is_ok());
+    assert!(check_pinned_spki(&der, &[format!("sha256/{hash}")]).is_ok());
+    assert!(check_pinned_spki(&der, &["sha256/AAAA".to_string()]).is_err());
+}
@@ -0,0 +1,83 @@
+use std::path::PathBuf;
+
+use http::Uri;
+
+use crate::{errors::WsError, protocol::Mode};
+
+use super::{get_scheme, percent_decode};
+
+/// get socket path of `ws+unix` uri
+///
+/// [http::Uri] does not accept percent encoded host, so percent encoded socket
+/// path is put in user info part, e.g. `ws+unix://%2Ftmp%2Fws.sock@localhost/chat`,
+/// host is sent as `Host` header. if there is no user info, host is used as relative
+/// socket path, e.g. `ws+unix://ws.sock/chat`
+pub fn get_unix_path(uri: &Uri) -> Result<PathBuf, WsError> {
+    if get_scheme(uri)? != Mode::WSUnix {
+        return Err(WsError::InvalidUri(format!("{uri} is not a ws+unix uri")));
+    }
+    let authority = uri
+        .authority()
+        .ok_or_else(|| WsError::InvalidUri(format!("can not find socket path {uri}")))?;
+    let path = match authority.as_str().rsplit_once('@') {
+        Some((user_info, _)) => percent_decode(user_info)?,
+        None => authority.host().to_string(),
+    };
+    if path.is_empty() {
+        return Err(WsError::InvalidUri(format!("empty socket path {uri}")));
+    }
+    Ok(PathBuf::from(path))
+}
+
+#[cfg(feature = "sync")]
+mod blocking {
+    use std::os::unix::net::UnixStream;
+
+    use http::Uri;
+
+    use crate::errors::WsError;
+
+    use super::get_unix_path;
+
+    /// connect to unix socket of `ws+unix` uri
+    pub fn unix_connect(uri: &Uri) -> Result<UnixStream, WsError> {
+        let path = get_unix_path(uri)?;
+        UnixStream::connect(&path).map_err(|e| {
+            WsError::ConnectionFailed(format!("failed to connect unix socket {path:?} {e}"))
+        })
+    }
+}
+
+#[cfg(feature = "sync")]
+pub use blocking::*;
+
+#[cfg(feature = "async")]
+mod non_blocking {
+    use http::Uri;
+    use tokio::net::UnixStream;
+
+    use crate::errors::WsError;
+
+    use super::get_unix_path;
+
+    /// async version of connecting to unix socket of `ws+unix` uri
+    pub async fn async_unix_connect(uri: &Uri) -> Result<UnixStream, WsError> {
+        let path = get_unix_path(uri)?;
+        UnixStream::connect(&path).await.map_err(|e| {
+            WsError::ConnectionFailed(format!("failed to connect unix socket {path:?} {e}"))
+        })
+    }
+}
+
+#[cfg(feature = "async")]
+pub use non_blocking::*;
+
+#[test]
+fn test_unix_path() {
+    let uri: Uri = "ws+unix://%2Ftmp%2Fws.sock@localhost/chat".parse().unwrap();
+    assert_eq!(get_unix_path(&uri).unwrap(), PathBuf::from("/tmp/ws.sock"));
+    let uri: Uri = "ws+unix://ws.sock/chat".parse().unwrap();
+    assert_eq!(get_unix_path(&uri).unwrap(), PathBuf::from("ws.sock"));
+    let uri: Uri = "ws://localhost/chat".parse().unwrap();
+    assert!(get_unix_path(&uri).is_err());
+}
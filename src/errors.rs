@@ -1,6 +1,6 @@
 use thiserror::Error;
 
-use crate::frame::OpCode;
+use crate::{frame::OpCode, protocol::CloseCode};
 
 // TODO add custom error kind
 /// errors during handshake, read/write frame
@@ -9,9 +9,15 @@ pub enum WsError {
     /// invalid websocket connection url
     #[error("invalid uri `{0}`")]
     InvalidUri(String),
-    #[error("unsupported proxy, expect socks5 or http, got {0}")]
+    #[error("cert file {0} not found")]
     /// invalid cert file path
     CertFileNotFound(String),
+    #[error("unsupported proxy, expect http, https, socks5 or socks5h, got {0}")]
+    /// unsupported proxy scheme or proxy usage
+    UnsupportedProxy(String),
+    #[error("proxy error `{0}`")]
+    /// failed to create tunnel via proxy server
+    ProxyError(String),
     #[error("load cert {0} failed")]
     /// broken certs
     LoadCertFailed(String),
@@ -31,18 +37,41 @@ pub enum WsError {
     #[error("{error:?}")]
     ProtocolError {
         /// peer close code
-        close_code: u16,
+        close_code: CloseCode,
         /// detail error
         error: ProtocolError,
     },
     /// peer send a frame with unknown opcode
     #[error("unsupported frame {0:?}")]
     UnsupportedFrame(OpCode),
+    /// no frame received from peer within keepalive timeout
+    #[error("keepalive timeout, peer is silent for {0:?}")]
+    KeepaliveTimeout(std::time::Duration),
+    /// close handshake is completed, no more frame will be received
+    #[error("connection closed")]
+    ConnectionClosed,
+    /// try to send frame after close frame has been sent
+    #[error("send {0:?} frame after close frame")]
+    SendAfterClose(OpCode),
+    /// message writer was dropped before final frame, close frame 1011 is sent and
+    /// data frame can not be sent any more
+    #[error("send {0:?} frame after message writer is dropped unfinished")]
+    UnfinishedMessage(OpCode),
+    /// broadcast subscriber is disconnected because its queue is full
+    #[error("slow consumer, queue of {0} frames is full")]
+    SlowConsumer(usize),
+    /// connection phase does not complete in time
+    #[error("{phase:?} timeout")]
+    Timeout {
+        /// phase of connection when timeout happens
+        phase: TimeoutPhase,
+    },
 
     #[cfg(any(
         feature = "deflate",
         feature = "deflate_ng",
-        feature = "deflate_static"
+        feature = "deflate_static",
+        feature = "deflate_rust"
     ))]
     /// compress failed
     #[error("compress failed {0}")]
@@ -50,7 +79,8 @@ pub enum WsError {
     #[cfg(any(
         feature = "deflate",
         feature = "deflate_ng",
-        feature = "deflate_static"
+        feature = "deflate_static",
+        feature = "deflate_rust"
     ))]
     /// decompress failed
     #[error("decompress failed {0}")]
@@ -63,9 +93,51 @@ impl From<std::io::Error> for WsError {
     }
 }
 
+/// errors of [SerdeCodec](crate::codec::SerdeCodec)
+#[cfg(any(
+    feature = "json",
+    feature = "msgpack",
+    feature = "cbor",
+    feature = "protobuf"
+))]
+#[derive(Debug, Error)]
+pub enum SerdeError {
+    /// websocket protocol or io error, connection should be closed
+    #[error(transparent)]
+    Ws(#[from] WsError),
+    /// message can not be decoded into expected type, connection is still usable
+    #[error("failed to deserialize message {0}")]
+    Deserialize(Box<dyn std::error::Error + Send + Sync>),
+    /// failed to serialize message, nothing is sent
+    #[error("failed to serialize message {0}")]
+    Serialize(Box<dyn std::error::Error + Send + Sync>),
+}
+
+/// errors of [JsonCodec](crate::codec::JsonCodec)
+#[cfg(feature = "json")]
+pub type JsonError = SerdeError;
+
+/// phase of connection, used by [WsError::Timeout]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutPhase {
+    /// tcp connect, including connecting to proxy server
+    Connect,
+    /// tls handshake
+    Tls,
+    /// http upgrade handshake, including proxy tunnel handshake
+    Handshake,
+    /// wait for data from peer
+    Read,
+    /// write data to peer
+    Write,
+}
+
 impl From<WsError> for std::io::Error {
     fn from(e: WsError) -> Self {
-        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+        match e {
+            WsError::IOError(e) => e,
+            e => std::io::Error::new(std::io::ErrorKind::InvalidData, e),
+        }
     }
 }
 
@@ -115,7 +187,8 @@ pub enum ProtocolError {
     #[cfg(any(
         feature = "deflate",
         feature = "deflate_ng",
-        feature = "deflate_static"
+        feature = "deflate_static",
+        feature = "deflate_rust"
     ))]
     /// compressed control frame
     #[error("compressed control frame")]
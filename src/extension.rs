@@ -82,7 +82,20 @@ pub mod axum_ext {
 
     use crate::errors::WsError;
 
-    /// accept axum raw request
+    /// check `:protocol` of http/2 CONNECT request, hyper server should enable
+    /// extended CONNECT protocol
+    #[cfg(feature = "h2")]
+    fn is_extended_connect(parts: &axum::http::request::Parts) -> bool {
+        parts.method == axum::http::Method::CONNECT
+            && parts
+                .extensions
+                .get::<hyper::ext::Protocol>()
+                .map(|p| p.as_str().eq_ignore_ascii_case("websocket"))
+                .unwrap_or_default()
+    }
+
+    /// accept axum raw request, both http/1.1 upgrade and http/2 extended CONNECT
+    /// (rfc 8441) are supported
     pub async fn adapt<T, F1, F2, Fut>(
         req: axum::extract::Request,
         mut handshake_handler: F1,
@@ -112,6 +125,15 @@ pub mod axum_ext {
                     .unwrap();
             }
         };
+        #[cfg(feature = "h2")]
+        if parts.version == axum::http::Version::HTTP_2 && !is_extended_connect(&parts) {
+            tracing::error!("expect extended CONNECT with `websocket` protocol");
+            return Response::builder()
+                .status(axum::http::StatusCode::BAD_REQUEST)
+                .body("".into())
+                .unwrap();
+        }
+        let version = parts.version;
         let req = axum::http::Request::from_parts(parts, ());
         let (req, resp) = match handshake_handler(req) {
             Ok(i) => i,
@@ -121,6 +143,18 @@ pub mod axum_ext {
                 return Response::from_parts(parts, body.into());
             }
         };
+        let (mut parts, body) = resp.into_parts();
+        if version == axum::http::Version::HTTP_2 {
+            // rfc 8441, extended CONNECT is accepted by 2xx response
+            if parts.status == axum::http::StatusCode::SWITCHING_PROTOCOLS {
+                parts.status = axum::http::StatusCode::OK;
+            }
+            parts.version = version;
+            for name in ["connection", "upgrade", "sec-websocket-accept"] {
+                parts.headers.remove(name);
+            }
+        }
+        let resp = http::Response::from_parts(parts, body);
         tokio::spawn(async move {
             match on_upgrade.await {
                 Err(e) => {
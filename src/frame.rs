@@ -1,4 +1,5 @@
 use crate::codec::apply_mask;
+use crate::protocol::CloseCode;
 use bytes::{BufMut, BytesMut};
 use std::fmt::Debug;
 
@@ -551,7 +552,7 @@ impl OwnedFrame {
     #[inline]
     pub fn close_frame(
         mask: impl Into<Option<[u8; 4]>>,
-        code: impl Into<Option<u16>>,
+        code: impl Into<Option<CloseCode>>,
         data: &[u8],
     ) -> Self {
         assert!(data.len() <= 123);
@@ -559,7 +560,7 @@ impl OwnedFrame {
         assert!(code.is_some() || data.is_empty());
         let mut payload = BytesMut::with_capacity(2 + data.len());
         if let Some(code) = code {
-            payload.put_u16(code);
+            payload.put_u16(code.into());
             payload.extend_from_slice(data);
         }
         Self::new(OpCode::Close, mask, &payload)
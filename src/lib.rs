@@ -24,11 +24,26 @@ pub use message::*;
 /// simple api to create websocket connection
 pub mod simple;
 #[cfg(feature = "simple")]
-pub use simple::ClientConfig;
+pub use simple::{ClientConfig, ServerConfig};
+#[cfg(all(
+    feature = "simple",
+    any(
+        feature = "deflate",
+        feature = "deflate_ng",
+        feature = "deflate_static",
+        feature = "deflate_rust"
+    )
+))]
+/// client with auto reconnecting
+pub mod reconnect;
 
 /// helper stream definition
 pub mod stream;
 
+/// websocket server runtime with connection registry
+#[cfg(any(feature = "sync", feature = "async"))]
+pub mod server;
+
 /// some helper extension
 pub mod extension;
 
@@ -85,6 +100,13 @@ impl ClientBuilder {
         Self { extensions, ..self }
     }
 
+    /// offer extensions of chain in handshake http header, pass the same chain to
+    /// [ExtCodec::check_fn_with](crate::codec::ExtCodec::check_fn_with) to configure it
+    pub fn offer(mut self, chain: &codec::ExtensionChain) -> Self {
+        self.extensions.extend(chain.offers());
+        self
+    }
+
     /// set websocket version, default 13
     pub fn version(self, version: u8) -> Self {
         Self { version, ..self }
@@ -244,6 +266,7 @@ mod blocking {
         });
         resp_lines.push("\r\n".to_string());
         stream.write_all(resp_lines.join("\r\n").as_bytes())?;
+        stream.flush()?;
         tracing::debug!("{:?}", &resp);
         Ok(if resp.status() != http::StatusCode::SWITCHING_PROTOCOLS {
             return Err(WsError::HandShakeFailed(resp.body().to_string()));
@@ -400,6 +423,7 @@ mod non_blocking {
         });
         resp_lines.push("\r\n".to_string());
         stream.write_all(resp_lines.join("\r\n").as_bytes()).await?;
+        stream.flush().await?;
         tracing::debug!("{:?}", &resp);
         Ok(if resp.status() != http::StatusCode::SWITCHING_PROTOCOLS {
             return Err(WsError::HandShakeFailed(resp.body().to_string()));
@@ -407,5 +431,92 @@ mod non_blocking {
     }
 }
 
+#[cfg(feature = "h2")]
+mod extended_connect {
+    use bytes::Bytes;
+    use h2::{client::SendRequest, server::SendResponse, RecvStream};
+
+    use crate::{
+        errors::WsError,
+        protocol::{h2_handle_handshake, h2_req_handshake, h2_write_resp},
+        stream::H2Stream,
+        ClientBuilder, ServerBuilder,
+    };
+
+    impl ClientBuilder {
+        /// open websocket stream on http/2 connection by extended CONNECT (rfc 8441)
+        /// & check server response, many streams can be opened on one connection
+        ///
+        /// connection task of `send_request` should be spawned by caller
+        pub async fn async_h2_connect<C, F>(
+            &self,
+            send_request: &mut SendRequest<Bytes>,
+            uri: http::Uri,
+            mut check_fn: F,
+        ) -> Result<C, WsError>
+        where
+            F: FnMut(String, http::Response<()>, H2Stream) -> Result<C, WsError>,
+        {
+            let (key, resp, stream) = h2_req_handshake(
+                send_request,
+                &uri,
+                &self.protocols,
+                &self.extensions,
+                self.version,
+                self.headers.clone(),
+            )
+            .await?;
+            check_fn(key, resp, stream)
+        }
+    }
+
+    impl ServerBuilder {
+        /// accept websocket stream opened by extended CONNECT request (rfc 8441),
+        /// request is accepted from http/2 server connection by caller
+        ///
+        /// server connection should enable extended CONNECT protocol
+        pub fn async_h2_accept<F1, F2, T, C>(
+            req: http::Request<RecvStream>,
+            mut respond: SendResponse<Bytes>,
+            mut handshake_handler: F1,
+            mut codec_factory: F2,
+        ) -> Result<C, WsError>
+        where
+            F1: FnMut(
+                http::Request<()>,
+            ) -> Result<
+                (http::Request<()>, http::Response<T>),
+                (http::Response<T>, WsError),
+            >,
+            F2: FnMut(http::Request<()>, H2Stream) -> Result<C, WsError>,
+            T: ToString,
+        {
+            let (req, recv) = match h2_handle_handshake(req) {
+                Ok(i) => i,
+                Err(e) => {
+                    let resp = http::Response::builder()
+                        .status(http::StatusCode::BAD_REQUEST)
+                        .body(e.to_string())
+                        .unwrap();
+                    h2_write_resp(resp, &mut respond)?;
+                    return Err(e);
+                }
+            };
+            match handshake_handler(req) {
+                Ok((req, resp)) => match h2_write_resp(resp, &mut respond)? {
+                    Some(send) => codec_factory(req, H2Stream::new(send, recv)),
+                    None => Err(WsError::HandShakeFailed(
+                        "handshake response is not successful".to_string(),
+                    )),
+                },
+                Err((resp, e)) => {
+                    h2_write_resp(resp, &mut respond)?;
+                    Err(e)
+                }
+            }
+        }
+    }
+}
+
 /// helper struct to config & construct websocket server
 pub struct ServerBuilder {}
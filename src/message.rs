@@ -1,6 +1,12 @@
 use std::borrow::Cow;
 
-use crate::frame::OpCode;
+use bytes::Bytes;
+
+use crate::{
+    errors::{ProtocolError, WsError},
+    frame::{OpCode, OwnedFrame, SimplifiedHeader},
+    protocol::CloseCode,
+};
 
 /// generic message receive/send from websocket stream
 #[derive(Debug)]
@@ -15,7 +21,7 @@ pub struct Message<T> {
     /// available in close frame only
     ///
     /// see [status code](https://datatracker.ietf.org/doc/html/rfc6455#section-7.4)
-    pub close_code: Option<u16>,
+    pub close_code: Option<CloseCode>,
 }
 
 impl<T> Message<T> {
@@ -25,6 +31,41 @@ impl<T> Message<T> {
     }
 }
 
+#[cfg(feature = "stream")]
+impl Message<Vec<u8>> {
+    /// construct message from received frame, first two bytes of close frame are close code
+    pub(crate) fn from_frame(code: OpCode, data: &[u8]) -> Self {
+        let (close_code, data) = match code {
+            OpCode::Close if data.len() >= 2 => (
+                Some(u16::from_be_bytes([data[0], data[1]]).into()),
+                &data[2..],
+            ),
+            OpCode::Close => (Some(CloseCode::Normal), data),
+            _ => (None, data),
+        };
+        Message {
+            code,
+            data: data.to_vec(),
+            close_code,
+        }
+    }
+}
+
+#[cfg(feature = "stream")]
+impl<T: AsRef<[u8]>> Message<T> {
+    /// frame payload of message, close code is prepended for close frame
+    pub(crate) fn frame_payload(&self) -> Cow<'_, [u8]> {
+        match self.close_code {
+            Some(close_code) if self.code == OpCode::Close => {
+                let mut data = u16::from(close_code).to_be_bytes().to_vec();
+                data.extend_from_slice(self.data.as_ref());
+                Cow::Owned(data)
+            }
+            _ => Cow::Borrowed(self.data.as_ref()),
+        }
+    }
+}
+
 impl<'a> From<&'a str> for Message<Cow<'a, str>> {
     fn from(data: &'a str) -> Self {
         Message {
@@ -80,7 +121,7 @@ impl<'a, T: Into<Cow<'a, str>>> From<(u16, T)> for Message<Cow<'a, str>> {
         Message {
             code: OpCode::Close,
             data: value.into(),
-            close_code: Some(close_code),
+            close_code: Some(close_code.into()),
         }
     }
 }
@@ -88,9 +129,9 @@ impl<'a, T: Into<Cow<'a, str>>> From<(u16, T)> for Message<Cow<'a, str>> {
 impl<'a, T: Into<Cow<'a, [u8]>>> From<(u16, T)> for Message<Cow<'a, [u8]>> {
     fn from((close_code, value): (u16, T)) -> Self {
         Message {
-            code: OpCode::Binary,
+            code: OpCode::Close,
             data: value.into(),
-            close_code: Some(close_code),
+            close_code: Some(close_code.into()),
         }
     }
 }
@@ -114,3 +155,232 @@ impl<'a, T: Into<Cow<'a, [u8]>>> From<(OpCode, T)> for Message<Cow<'a, [u8]>> {
         }
     }
 }
+
+/// status code and reason of close frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    /// close code
+    pub code: CloseCode,
+    /// utf-8 close reason
+    pub reason: String,
+}
+
+/// owned websocket message, can be stored or sent across threads
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WsMessage {
+    /// text message
+    Text(String),
+    /// binary message
+    Binary(Bytes),
+    /// ping message
+    Ping(Bytes),
+    /// pong message
+    Pong(Bytes),
+    /// close message, `None` if close frame has no body
+    Close(Option<CloseFrame>),
+}
+
+impl WsMessage {
+    /// helper function to construct a close message
+    pub fn close(code: impl Into<CloseCode>, reason: impl Into<String>) -> Self {
+        WsMessage::Close(Some(CloseFrame {
+            code: code.into(),
+            reason: reason.into(),
+        }))
+    }
+
+    /// opcode of message
+    pub fn opcode(&self) -> OpCode {
+        match self {
+            WsMessage::Text(_) => OpCode::Text,
+            WsMessage::Binary(_) => OpCode::Binary,
+            WsMessage::Ping(_) => OpCode::Ping,
+            WsMessage::Pong(_) => OpCode::Pong,
+            WsMessage::Close(_) => OpCode::Close,
+        }
+    }
+
+    /// frame payload of message, close code is prepended for close message
+    pub fn payload(&self) -> Cow<'_, [u8]> {
+        match self {
+            WsMessage::Text(data) => Cow::Borrowed(data.as_bytes()),
+            WsMessage::Binary(data) | WsMessage::Ping(data) | WsMessage::Pong(data) => {
+                Cow::Borrowed(data)
+            }
+            WsMessage::Close(None) => Cow::Borrowed(&[]),
+            WsMessage::Close(Some(frame)) => {
+                let mut data = u16::from(frame.code).to_be_bytes().to_vec();
+                data.extend_from_slice(frame.reason.as_bytes());
+                Cow::Owned(data)
+            }
+        }
+    }
+
+    /// parse message from header and unmasked payload of a received frame
+    ///
+    /// fragment of a message, i.e. non final or continue frame, is rejected with
+    /// [WsError::UnsupportedFrame], see [WsMessage::from_payload]
+    pub fn from_header(header: &SimplifiedHeader, data: &[u8]) -> Result<Self, WsError> {
+        if !header.fin {
+            return Err(WsError::UnsupportedFrame(header.code));
+        }
+        Self::from_payload(header.code, data)
+    }
+
+    /// parse message from unmasked payload of a complete frame
+    ///
+    /// continue and reserved frames are rejected with [WsError::UnsupportedFrame]
+    pub fn from_payload(code: OpCode, data: &[u8]) -> Result<Self, WsError> {
+        let msg = match code {
+            OpCode::Text => WsMessage::Text(utf8(data)?.to_string()),
+            OpCode::Binary => WsMessage::Binary(Bytes::copy_from_slice(data)),
+            OpCode::Ping => WsMessage::Ping(Bytes::copy_from_slice(data)),
+            OpCode::Pong => WsMessage::Pong(Bytes::copy_from_slice(data)),
+            OpCode::Close => match data.len() {
+                0 => WsMessage::Close(None),
+                1 => {
+                    return Err(WsError::ProtocolError {
+                        close_code: CloseCode::ProtocolError,
+                        error: ProtocolError::InvalidCloseFramePayload,
+                    })
+                }
+                _ => WsMessage::close(u16::from_be_bytes([data[0], data[1]]), utf8(&data[2..])?),
+            },
+            code => return Err(WsError::UnsupportedFrame(code)),
+        };
+        Ok(msg)
+    }
+
+    /// construct frame of message
+    pub fn into_frame(self, mask: impl Into<Option<[u8; 4]>>) -> OwnedFrame {
+        OwnedFrame::new(self.opcode(), mask, &self.payload())
+    }
+
+    fn from_parts(
+        code: OpCode,
+        close_code: Option<CloseCode>,
+        data: &[u8],
+    ) -> Result<Self, WsError> {
+        match (code, close_code) {
+            (OpCode::Close, Some(code)) => Ok(WsMessage::close(code, utf8(data)?)),
+            _ => Self::from_payload(code, data),
+        }
+    }
+}
+
+fn utf8(data: &[u8]) -> Result<&str, WsError> {
+    std::str::from_utf8(data).map_err(|_| WsError::ProtocolError {
+        close_code: CloseCode::InvalidPayload,
+        error: ProtocolError::InvalidUtf8,
+    })
+}
+
+impl From<String> for WsMessage {
+    fn from(data: String) -> Self {
+        WsMessage::Text(data)
+    }
+}
+
+impl From<&str> for WsMessage {
+    fn from(data: &str) -> Self {
+        WsMessage::Text(data.to_string())
+    }
+}
+
+impl From<Bytes> for WsMessage {
+    fn from(data: Bytes) -> Self {
+        WsMessage::Binary(data)
+    }
+}
+
+impl From<Vec<u8>> for WsMessage {
+    fn from(data: Vec<u8>) -> Self {
+        WsMessage::Binary(data.into())
+    }
+}
+
+impl From<&[u8]> for WsMessage {
+    fn from(data: &[u8]) -> Self {
+        WsMessage::Binary(Bytes::copy_from_slice(data))
+    }
+}
+
+impl<'a> TryFrom<Message<Cow<'a, [u8]>>> for WsMessage {
+    type Error = WsError;
+
+    fn try_from(msg: Message<Cow<'a, [u8]>>) -> Result<Self, Self::Error> {
+        Self::from_parts(msg.code, msg.close_code, &msg.data)
+    }
+}
+
+impl<'a> TryFrom<Message<Cow<'a, str>>> for WsMessage {
+    type Error = WsError;
+
+    fn try_from(msg: Message<Cow<'a, str>>) -> Result<Self, Self::Error> {
+        Self::from_parts(msg.code, msg.close_code, msg.data.as_bytes())
+    }
+}
+
+impl From<WsMessage> for Message<Cow<'static, [u8]>> {
+    fn from(msg: WsMessage) -> Self {
+        let code = msg.opcode();
+        let (data, close_code) = match msg {
+            WsMessage::Text(data) => (data.into_bytes(), None),
+            WsMessage::Binary(data) | WsMessage::Ping(data) | WsMessage::Pong(data) => {
+                (data.to_vec(), None)
+            }
+            WsMessage::Close(None) => (vec![], None),
+            WsMessage::Close(Some(frame)) => (frame.reason.into_bytes(), Some(frame.code)),
+        };
+        Message {
+            code,
+            data: Cow::Owned(data),
+            close_code,
+        }
+    }
+}
+
+/// fragmented frame is rejected with [WsError::UnsupportedFrame]
+impl TryFrom<OwnedFrame> for WsMessage {
+    type Error = WsError;
+
+    fn try_from(mut frame: OwnedFrame) -> Result<Self, Self::Error> {
+        let code = frame.header().opcode();
+        if !frame.header().fin() {
+            return Err(WsError::UnsupportedFrame(code));
+        }
+        frame.unmask();
+        Self::from_payload(code, frame.payload())
+    }
+}
+
+impl From<WsMessage> for OwnedFrame {
+    fn from(msg: WsMessage) -> Self {
+        msg.into_frame(None)
+    }
+}
+
+#[test]
+fn test_ws_message_conversion() {
+    let msgs = [
+        WsMessage::from("hello"),
+        WsMessage::from(vec![1, 2, 3]),
+        WsMessage::Ping(Bytes::from_static(b"ping")),
+        WsMessage::Close(None),
+        WsMessage::close(1000, "bye"),
+    ];
+    for msg in msgs {
+        let frame = msg.clone().into_frame(rand::random::<[u8; 4]>());
+        assert_eq!(WsMessage::try_from(frame).unwrap(), msg);
+        let raw: Message<Cow<[u8]>> = msg.clone().into();
+        assert_eq!(WsMessage::try_from(raw).unwrap(), msg);
+    }
+    let msg = Message::from((1000, &b"bye"[..]));
+    assert_eq!(msg.code, OpCode::Close);
+    assert_eq!(
+        WsMessage::try_from(msg).unwrap(),
+        WsMessage::close(1000, "bye")
+    );
+    assert!(WsMessage::from_payload(OpCode::Text, &[0xff]).is_err());
+    assert!(WsMessage::from_payload(OpCode::Continue, b"").is_err());
+}
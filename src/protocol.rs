@@ -8,106 +8,185 @@ use crate::errors::WsError;
 
 const GUID: &[u8] = b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
 
-/// helper struct for using close code
-pub struct StatusCode;
-
-impl StatusCode {
+/// close code of close frame
+///
+/// see [status code](https://datatracker.ietf.org/doc/html/rfc6455#section-7.4) and
+/// [IANA registry](https://www.iana.org/assignments/websocket/websocket.xml#close-code-number)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CloseCode {
     /// 1000 indicates a normal closure, meaning that the purpose for
     /// which the connection was established has been fulfilled.
-    pub fn normal() -> u16 {
-        1000
-    }
-
+    Normal,
     /// 1001 indicates that an endpoint is "going away", such as a server
     /// going down or a browser having navigated away from a page.
-    pub fn going_away() -> u16 {
-        1001
-    }
-
+    GoingAway,
     /// 1002 indicates that an endpoint is terminating the connection due
     /// to a protocol error.
-    pub fn protocol_error() -> u16 {
-        1002
-    }
-
+    ProtocolError,
     /// 1003 indicates that an endpoint is terminating the connection
     /// because it has received a type of data it cannot accept (e.g., an
     /// endpoint that understands only text data MAY send this if it
     /// receives a binary message).
-    pub fn terminate() -> u16 {
-        1003
-    }
-    /// Reserved.  The specific meaning might be defined in the future.
-    pub fn reserved() -> u16 {
-        1004
-    }
-
+    Unsupported,
+    /// 1004 Reserved.  The specific meaning might be defined in the future.
+    Reserved,
     /// 1005 is a reserved value and MUST NOT be set as a status code in a
     /// Close control frame by an endpoint.  It is designated for use in
     /// applications expecting a status code to indicate that no status
     /// code was actually present.
-    pub fn app_reserved() -> u16 {
-        1005
-    }
-
+    NoStatus,
     /// 1006 is a reserved value and MUST NOT be set as a status code in a
     /// Close control frame by an endpoint.  It is designated for use in
     /// applications expecting a status code to indicate that the
     /// connection was closed abnormally, e.g., without sending or
     /// receiving a Close control frame.
-    pub fn abnormal_reserved() -> u16 {
-        1006
-    }
-
+    Abnormal,
     /// 1007 indicates that an endpoint is terminating the connection
     /// because it has received data within a message that was not
     /// consistent with the type of the message (e.g., non-UTF-8 \[RFC3629\]
     /// data within a text message).
-    pub fn non_consistent() -> u16 {
-        1007
-    }
-
+    InvalidPayload,
     /// 1008 indicates that an endpoint is terminating the connection
     /// because it has received a message that violates its policy.  This
     /// is a generic status code that can be returned when there is no
     /// other more suitable status code (e.g., 1003 or 1009) or if there
     /// is a need to hide specific details about the policy.
-    pub fn violate_policy() -> u16 {
-        1008
-    }
-
+    PolicyViolation,
     /// 1009 indicates that an endpoint is terminating the connection
     /// because it has received a message that is too big for it to
     /// process.
-    pub fn too_big() -> u16 {
-        1009
-    }
-
+    MessageTooBig,
     /// 1010 indicates that an endpoint (client) is terminating the
     /// connection because it has expected the server to negotiate one or
     /// more extension, but the server didn't return them in the response
-    /// message of the WebSocket handshake.  The list of extensions that
-    /// are needed SHOULD appear in the /reason/ part of the Close frame.
-    /// Note that this status code is not used by the server, because it
-    /// can fail the WebSocket handshake instead.
-    pub fn require_ext() -> u16 {
-        1010
-    }
-
+    /// message of the WebSocket handshake.
+    MandatoryExtension,
     /// 1011 indicates that a server is terminating the connection because
     /// it encountered an unexpected condition that prevented it from
     /// fulfilling the request.
-    pub fn unexpected_condition() -> u16 {
-        1011
-    }
-
+    InternalError,
+    /// 1012 indicates that the service is restarted, client may reconnect.
+    ServiceRestart,
+    /// 1013 indicates that the service is experiencing overload, client
+    /// should reconnect later.
+    TryAgainLater,
+    /// 1014 indicates that the server was acting as a gateway or proxy and
+    /// received an invalid response from the upstream server.
+    BadGateway,
     /// 1015 is a reserved value and MUST NOT be set as a status code in a
     /// Close control frame by an endpoint.  It is designated for use in
     /// applications expecting a status code to indicate that the
     /// connection was closed due to a failure to perform a TLS handshake
     /// (e.g., the server certificate can't be verified).
-    pub fn platform_fail() -> u16 {
-        1015
+    TlsHandshake,
+    /// 3000 indicates that the endpoint is not authenticated, registered by IANA
+    Unauthorized,
+    /// 3003 indicates that the endpoint is authenticated but not allowed to access
+    /// the resource, registered by IANA
+    Forbidden,
+    /// 3008 indicates that the endpoint timed out, registered by IANA
+    Timeout,
+    /// other codes of 3000-3999, registered by libraries, frameworks and applications
+    Library(u16),
+    /// 4000-4999, private use of application
+    Private(u16),
+    /// unassigned or invalid code
+    Other(u16),
+}
+
+impl CloseCode {
+    /// return true if code is reserved and MUST NOT be sent by endpoint
+    pub fn is_reserved(&self) -> bool {
+        match self {
+            CloseCode::Reserved
+            | CloseCode::NoStatus
+            | CloseCode::Abnormal
+            | CloseCode::TlsHandshake => true,
+            CloseCode::Other(code) => (1016..=2999).contains(code),
+            _ => false,
+        }
+    }
+
+    /// return true if code can be sent in close frame
+    pub fn is_sendable(&self) -> bool {
+        !self.is_reserved() && !matches!(self, CloseCode::Other(_))
+    }
+
+    /// return false if peer rejects the connection for a reason which will
+    /// not change by reconnecting, e.g. protocol error or policy violation
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            CloseCode::ProtocolError
+                | CloseCode::Unsupported
+                | CloseCode::InvalidPayload
+                | CloseCode::PolicyViolation
+                | CloseCode::MessageTooBig
+                | CloseCode::MandatoryExtension
+                | CloseCode::TlsHandshake
+                | CloseCode::Reserved
+                | CloseCode::Unauthorized
+                | CloseCode::Forbidden
+                | CloseCode::Other(_)
+        )
+    }
+}
+
+impl From<u16> for CloseCode {
+    fn from(code: u16) -> Self {
+        match code {
+            1000 => CloseCode::Normal,
+            1001 => CloseCode::GoingAway,
+            1002 => CloseCode::ProtocolError,
+            1003 => CloseCode::Unsupported,
+            1004 => CloseCode::Reserved,
+            1005 => CloseCode::NoStatus,
+            1006 => CloseCode::Abnormal,
+            1007 => CloseCode::InvalidPayload,
+            1008 => CloseCode::PolicyViolation,
+            1009 => CloseCode::MessageTooBig,
+            1010 => CloseCode::MandatoryExtension,
+            1011 => CloseCode::InternalError,
+            1012 => CloseCode::ServiceRestart,
+            1013 => CloseCode::TryAgainLater,
+            1014 => CloseCode::BadGateway,
+            1015 => CloseCode::TlsHandshake,
+            3000..=3999 => match code {
+                3000 => CloseCode::Unauthorized,
+                3003 => CloseCode::Forbidden,
+                3008 => CloseCode::Timeout,
+                _ => CloseCode::Library(code),
+            },
+            4000..=4999 => CloseCode::Private(code),
+            _ => CloseCode::Other(code),
+        }
+    }
+}
+
+impl From<CloseCode> for u16 {
+    fn from(code: CloseCode) -> Self {
+        match code {
+            CloseCode::Normal => 1000,
+            CloseCode::GoingAway => 1001,
+            CloseCode::ProtocolError => 1002,
+            CloseCode::Unsupported => 1003,
+            CloseCode::Reserved => 1004,
+            CloseCode::NoStatus => 1005,
+            CloseCode::Abnormal => 1006,
+            CloseCode::InvalidPayload => 1007,
+            CloseCode::PolicyViolation => 1008,
+            CloseCode::MessageTooBig => 1009,
+            CloseCode::MandatoryExtension => 1010,
+            CloseCode::InternalError => 1011,
+            CloseCode::ServiceRestart => 1012,
+            CloseCode::TryAgainLater => 1013,
+            CloseCode::BadGateway => 1014,
+            CloseCode::TlsHandshake => 1015,
+            CloseCode::Unauthorized => 3000,
+            CloseCode::Forbidden => 3003,
+            CloseCode::Timeout => 3008,
+            CloseCode::Library(code) | CloseCode::Private(code) | CloseCode::Other(code) => code,
+        }
     }
 }
 
@@ -118,13 +197,16 @@ pub enum Mode {
     WS,
     /// tls mode `wss://secret.wow`
     WSS,
+    /// plain mode over unix domain socket `ws+unix://%2Ftmp%2Fws.sock@localhost/chat`,
+    /// see [crate::connector::get_unix_path]
+    WSUnix,
 }
 
 impl Mode {
     /// return corresponding port of websocket mode
     pub fn default_port(&self) -> u16 {
         match self {
-            Mode::WS => 80,
+            Mode::WS | Mode::WSUnix => 80,
             Mode::WSS => 443,
         }
     }
@@ -247,6 +329,135 @@ mod non_blocking {
 #[cfg(feature = "async")]
 pub use non_blocking::*;
 
+#[cfg(feature = "h2")]
+mod extended_connect {
+    use http;
+    use std::collections::HashMap;
+
+    use bytes::Bytes;
+    use h2::{client::SendRequest, ext::Protocol, server::SendResponse, RecvStream};
+
+    use crate::{connector::get_scheme, errors::WsError, stream::H2Stream};
+
+    use super::Mode;
+
+    fn h2_failed(e: h2::Error) -> WsError {
+        WsError::HandShakeFailed(format!("http/2 error {e}"))
+    }
+
+    /// open websocket stream by http/2 extended CONNECT (rfc 8441) on a http/2
+    /// connection, server should enable `SETTINGS_ENABLE_CONNECT_PROTOCOL`
+    ///
+    /// there is no key in http/2 handshake, empty key is returned
+    ///
+    /// **NOTE**: low level api
+    pub async fn h2_req_handshake(
+        send_request: &mut SendRequest<Bytes>,
+        uri: &http::Uri,
+        protocols: &[String],
+        extensions: &[String],
+        version: u8,
+        extra_headers: HashMap<String, String>,
+    ) -> Result<(String, http::Response<()>, H2Stream), WsError> {
+        let scheme = match get_scheme(uri)? {
+            Mode::WSS => "https",
+            Mode::WS | Mode::WSUnix => "http",
+        };
+        let target = http::Uri::builder()
+            .scheme(scheme)
+            .authority(
+                uri.authority()
+                    .ok_or_else(|| WsError::InvalidUri(format!("{uri} missing host")))?
+                    .as_str(),
+            )
+            .path_and_query(uri.path_and_query().map(|p| p.as_str()).unwrap_or("/"))
+            .build()
+            .map_err(|e| WsError::InvalidUri(e.to_string()))?;
+        let mut builder = http::Request::builder()
+            .method(http::Method::CONNECT)
+            .version(http::Version::HTTP_2)
+            .uri(target)
+            .extension(Protocol::from_static("websocket"))
+            .header("sec-websocket-version", version.to_string());
+        for pro in protocols {
+            builder = builder.header("sec-websocket-protocol", pro);
+        }
+        for ext in extensions {
+            builder = builder.header("sec-websocket-extensions", ext);
+        }
+        for (k, v) in extra_headers.iter() {
+            builder = builder.header(k, v);
+        }
+        let req = builder
+            .body(())
+            .map_err(|e| WsError::HandShakeFailed(e.to_string()))?;
+        tracing::debug!("handshake request {:?}", req);
+        std::future::poll_fn(|cx| send_request.poll_ready(cx))
+            .await
+            .map_err(h2_failed)?;
+        let (resp, send) = send_request.send_request(req, false).map_err(h2_failed)?;
+        let (parts, recv) = resp.await.map_err(h2_failed)?.into_parts();
+        let mut resp = http::Response::from_parts(parts, ());
+        *resp.version_mut() = http::Version::HTTP_2;
+        tracing::debug!("protocol handshake complete");
+        Ok((String::new(), resp, H2Stream::new(send, recv)))
+    }
+
+    /// check http/2 extended CONNECT request of websocket, return request
+    /// without body and the receiving side of stream
+    pub fn h2_handle_handshake(
+        req: http::Request<RecvStream>,
+    ) -> Result<(http::Request<()>, RecvStream), WsError> {
+        let is_websocket = req
+            .extensions()
+            .get::<Protocol>()
+            .map(|p| p.as_str().eq_ignore_ascii_case("websocket"))
+            .unwrap_or_default();
+        if req.method() != http::Method::CONNECT || !is_websocket {
+            return Err(WsError::HandShakeFailed(
+                "expect extended CONNECT with `websocket` protocol".to_string(),
+            ));
+        }
+        let (mut parts, recv) = req.into_parts();
+        parts.version = http::Version::HTTP_2;
+        Ok((http::Request::from_parts(parts, ()), recv))
+    }
+
+    /// send handshake response of http/2 extended CONNECT, `101` status is sent as
+    /// `200`, connection specific headers are removed
+    ///
+    /// return sending side of stream if handshake succeeded
+    pub fn h2_write_resp<T: ToString>(
+        resp: http::Response<T>,
+        respond: &mut SendResponse<Bytes>,
+    ) -> Result<Option<h2::SendStream<Bytes>>, WsError> {
+        let (mut parts, body) = resp.into_parts();
+        if parts.status == http::StatusCode::SWITCHING_PROTOCOLS {
+            parts.status = http::StatusCode::OK;
+        }
+        parts.version = http::Version::HTTP_2;
+        for name in ["connection", "upgrade", "sec-websocket-accept"] {
+            parts.headers.remove(name);
+        }
+        let success = parts.status.is_success();
+        let body = body.to_string();
+        let end = !success && body.is_empty();
+        let mut send = respond
+            .send_response(http::Response::from_parts(parts, ()), end)
+            .map_err(h2_failed)?;
+        if success {
+            return Ok(Some(send));
+        }
+        if !end {
+            send.send_data(Bytes::from(body), true).map_err(h2_failed)?;
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(feature = "h2")]
+pub use extended_connect::*;
+
 /// generate random key
 pub fn gen_key() -> String {
     let r: [u8; 16] = rand::random();
@@ -267,6 +478,16 @@ pub fn cal_accept_key(source: &[u8]) -> String {
 /// 2. check `sec-websocket-accept` header & value
 pub fn standard_handshake_resp_check(key: &[u8], resp: &http::Response<()>) -> Result<(), WsError> {
     tracing::debug!("handshake response {:?}", resp);
+    if resp.version() == http::Version::HTTP_2 {
+        // rfc 8441, there is no accept key in extended CONNECT
+        if !resp.status().is_success() {
+            return Err(WsError::HandShakeFailed(format!(
+                "expect 2xx response, got {}",
+                resp.status()
+            )));
+        }
+        return Ok(());
+    }
     if resp.status() != http::StatusCode::SWITCHING_PROTOCOLS {
         return Err(WsError::HandShakeFailed(format!(
             "expect 101 response, got {}",
@@ -288,6 +509,16 @@ pub fn standard_handshake_resp_check(key: &[u8], resp: &http::Response<()>) -> R
 
 /// perform rfc standard check
 pub fn standard_handshake_req_check(req: &http::Request<()>) -> Result<(), WsError> {
+    if req.version() == http::Version::HTTP_2 {
+        // rfc 8441, `:protocol` is checked when accepting http/2 stream
+        if req.method() != http::Method::CONNECT {
+            return Err(WsError::HandShakeFailed(format!(
+                "expect CONNECT, got {}",
+                req.method()
+            )));
+        }
+        return Ok(());
+    }
     if let Some(val) = req.headers().get("upgrade") {
         if val != "websocket" {
             return Err(WsError::HandShakeFailed(format!(
@@ -411,3 +642,75 @@ pub fn handle_parse_handshake(req_bytes: BytesMut) -> Result<http::Request<()>,
         .body(())
         .map_err(|e| WsError::HandShakeFailed(e.to_string()))
 }
+
+#[test]
+fn test_close_code() {
+    for code in 0..=6000u16 {
+        assert_eq!(u16::from(CloseCode::from(code)), code);
+    }
+    assert!(CloseCode::from(1000).is_sendable());
+    assert!(CloseCode::from(4001).is_sendable());
+    assert!(!CloseCode::NoStatus.is_sendable());
+    assert!(!CloseCode::from(999).is_sendable());
+    assert!(CloseCode::from(2000).is_reserved());
+    assert!(CloseCode::TryAgainLater.is_retryable());
+    assert!(!CloseCode::PolicyViolation.is_retryable());
+    assert_eq!(CloseCode::from(3008), CloseCode::Timeout);
+    assert!(CloseCode::Timeout.is_retryable());
+    assert!(!CloseCode::Forbidden.is_retryable());
+}
+
+#[cfg(all(test, feature = "h2"))]
+#[tokio::test]
+async fn test_h2_extended_connect() {
+    use crate::{
+        codec::{default_handshake_handler, AsyncFrameCodec},
+        frame::OpCode,
+        ClientBuilder, ServerBuilder,
+    };
+
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    tokio::spawn(async move {
+        let mut conn = h2::server::Builder::new()
+            .enable_connect_protocol()
+            .handshake::<_, bytes::Bytes>(server_io)
+            .await
+            .unwrap();
+        while let Some(Ok((req, respond))) = conn.accept().await {
+            let mut server = ServerBuilder::async_h2_accept(
+                req,
+                respond,
+                default_handshake_handler,
+                AsyncFrameCodec::factory,
+            )
+            .unwrap();
+            tokio::spawn(async move {
+                while let Ok((header, data)) = server.receive().await {
+                    if header.code == OpCode::Text {
+                        let data = data.to_vec();
+                        server.send(OpCode::Text, &data).await.unwrap();
+                    }
+                }
+            });
+        }
+    });
+
+    let (mut send_request, conn) = h2::client::handshake(client_io).await.unwrap();
+    tokio::spawn(conn);
+    let builder = ClientBuilder::new();
+    let mut sessions = vec![];
+    for idx in 0..2 {
+        let uri: http::Uri = format!("ws://localhost/echo/{idx}").parse().unwrap();
+        let client = builder
+            .async_h2_connect(&mut send_request, uri, AsyncFrameCodec::check_fn)
+            .await
+            .unwrap();
+        sessions.push(client);
+    }
+    for (idx, client) in sessions.iter_mut().enumerate().rev() {
+        let msg = format!("hello {idx}");
+        client.send(OpCode::Text, msg.as_bytes()).await.unwrap();
+        let (header, data) = client.receive().await.unwrap();
+        assert_eq!((header.code, data), (OpCode::Text, msg.as_bytes()));
+    }
+}
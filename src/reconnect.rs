@@ -0,0 +1,790 @@
+use std::time::Duration;
+
+use crate::{errors::WsError, protocol::CloseCode};
+
+/// exponential backoff policy used by reconnecting client
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    /// delay before first retry
+    pub initial: Duration,
+    /// upper bound of delay
+    pub max: Duration,
+    /// delay multiplier of each retry
+    pub multiplier: f64,
+    /// random jitter factor in `0.0..=1.0`, delay will be randomized in
+    /// `delay * (1 - jitter) .. delay * (1 + jitter)`
+    pub jitter: f64,
+    /// max retry times before give up, retry forever if none
+    pub max_retries: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_retries: None,
+        }
+    }
+}
+
+impl Backoff {
+    /// delay before `retry`th(start from 0) retry
+    pub fn delay(&self, retry: u32) -> Duration {
+        let base = self.initial.as_secs_f64() * self.multiplier.max(1.0).powi(retry as i32);
+        let base = base.min(self.max.as_secs_f64());
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = 1.0 + jitter * (rand::random::<f64>() * 2.0 - 1.0);
+        Duration::from_secs_f64((base * factor).min(self.max.as_secs_f64()))
+    }
+
+    fn give_up(&self, retry: u32) -> bool {
+        self.max_retries.map(|max| retry >= max).unwrap_or(false)
+    }
+}
+
+/// reason of losing connection
+#[derive(Debug)]
+pub enum DisconnectReason {
+    /// peer send close frame
+    Closed {
+        /// close code
+        code: Option<CloseCode>,
+        /// close reason
+        reason: String,
+    },
+    /// io or protocol error
+    Error(WsError),
+}
+
+impl DisconnectReason {
+    /// return false if peer closed connection with a non-retryable close code
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            DisconnectReason::Closed {
+                code: Some(code), ..
+            } => code.is_retryable(),
+            _ => true,
+        }
+    }
+}
+
+/// connection lifecycle event
+#[derive(Debug)]
+pub enum ConnectionEvent {
+    /// connection established and `on_connected` callback succeeded
+    Connected {
+        /// number of tries used to establish connection
+        attempt: u32,
+    },
+    /// connection is lost, client will reconnect on next call
+    Disconnected(DisconnectReason),
+    /// retry times exceed `max_retries` or peer closed connection with a
+    /// non-retryable close code, contains last error
+    GaveUp(WsError),
+}
+
+fn close_reason(data: &[u8]) -> DisconnectReason {
+    if data.len() >= 2 {
+        DisconnectReason::Closed {
+            code: Some(u16::from_be_bytes([data[0], data[1]]).into()),
+            reason: String::from_utf8_lossy(&data[2..]).to_string(),
+        }
+    } else {
+        DisconnectReason::Closed {
+            code: None,
+            reason: String::new(),
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+mod blocking {
+    use http::Uri;
+
+    use super::{close_reason, Backoff, ConnectionEvent, DisconnectReason};
+    use crate::{
+        codec::DeflateCodec,
+        errors::WsError,
+        frame::{OpCode, SimplifiedHeader},
+        protocol::CloseCode,
+        stream::{BufStream, SyncStream},
+        ClientConfig,
+    };
+
+    type Codec = DeflateCodec<BufStream<SyncStream>>;
+
+    type ConnectedFn = Box<dyn FnMut(&mut Codec) -> Result<(), WsError> + Send>;
+
+    /// websocket client which reconnects automatically with exponential backoff
+    ///
+    /// close frame from peer is handled internally, connection will be
+    /// reestablished on next `receive` unless close code is not retryable,
+    /// see [CloseCode::is_retryable]
+    ///
+    /// once client gave up, all following calls fail with
+    /// [WsError::ConnectionClosed] without reconnecting
+    pub struct ReconnectingClient {
+        uri: Uri,
+        config: ClientConfig,
+        backoff: Backoff,
+        on_connected: ConnectedFn,
+        on_event: Box<dyn FnMut(&ConnectionEvent) + Send>,
+        codec: Option<Codec>,
+        retry: u32,
+        gave_up: bool,
+    }
+
+    impl ReconnectingClient {
+        /// create client, connection is not established until first
+        /// `connect`/`receive`/`send` call
+        pub fn new(
+            uri: impl TryInto<Uri, Error = http::uri::InvalidUri>,
+            config: ClientConfig,
+        ) -> Result<Self, WsError> {
+            let uri = uri
+                .try_into()
+                .map_err(|e| WsError::InvalidUri(e.to_string()))?;
+            Ok(Self {
+                uri,
+                config,
+                backoff: Backoff::default(),
+                on_connected: Box::new(|_| Ok(())),
+                on_event: Box::new(|_| {}),
+                codec: None,
+                retry: 0,
+                gave_up: false,
+            })
+        }
+
+        /// set backoff policy
+        pub fn backoff(mut self, backoff: Backoff) -> Self {
+            self.backoff = backoff;
+            self
+        }
+
+        /// callback after every successful handshake, use it to replay
+        /// subscription messages
+        pub fn on_connected<F>(mut self, f: F) -> Self
+        where
+            F: FnMut(&mut Codec) -> Result<(), WsError> + Send + 'static,
+        {
+            self.on_connected = Box::new(f);
+            self
+        }
+
+        /// callback of connection lifecycle event
+        pub fn on_event<F>(mut self, f: F) -> Self
+        where
+            F: FnMut(&ConnectionEvent) + Send + 'static,
+        {
+            self.on_event = Box::new(f);
+            self
+        }
+
+        /// return true if underlying connection is alive
+        pub fn is_connected(&self) -> bool {
+            self.codec.is_some()
+        }
+
+        /// get current connection
+        pub fn codec_mut(&mut self) -> Option<&mut Codec> {
+            self.codec.as_mut()
+        }
+
+        /// establish connection if not connected, return error if gave up
+        pub fn connect(&mut self) -> Result<&mut Codec, WsError> {
+            if self.gave_up {
+                return Err(WsError::ConnectionClosed);
+            }
+            if self.codec.is_none() {
+                self.reconnect()?;
+            }
+            Ok(self.codec.as_mut().unwrap())
+        }
+
+        fn reconnect(&mut self) -> Result<(), WsError> {
+            let mut attempt = 0;
+            loop {
+                if self.retry > 0 {
+                    let delay = self.backoff.delay(self.retry - 1);
+                    tracing::debug!("reconnect to {} after {:?}", self.uri, delay);
+                    std::thread::sleep(delay);
+                }
+                attempt += 1;
+                let ret = self
+                    .config
+                    .connect(self.uri.to_string())
+                    .and_then(|mut codec| (self.on_connected)(&mut codec).map(|_| codec));
+                match ret {
+                    Ok(codec) => {
+                        self.codec = Some(codec);
+                        (self.on_event)(&ConnectionEvent::Connected { attempt });
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        tracing::warn!("failed to connect {} {e}", self.uri);
+                        // retry is only reset after a message is exchanged, so
+                        // max_retries also counts attempts of previous calls
+                        if self.backoff.give_up(self.retry) {
+                            return Err(self.give_up(e));
+                        }
+                        self.retry += 1;
+                    }
+                }
+            }
+        }
+
+        fn give_up(&mut self, e: WsError) -> WsError {
+            self.gave_up = true;
+            let event = ConnectionEvent::GaveUp(e);
+            (self.on_event)(&event);
+            let ConnectionEvent::GaveUp(e) = event else {
+                unreachable!()
+            };
+            e
+        }
+
+        fn disconnect(&mut self, reason: DisconnectReason) -> DisconnectReason {
+            self.codec = None;
+            // always wait before reconnecting to avoid busy loop on flapping server
+            self.retry = self.retry.max(1);
+            let event = ConnectionEvent::Disconnected(reason);
+            (self.on_event)(&event);
+            let ConnectionEvent::Disconnected(reason) = event else {
+                unreachable!()
+            };
+            reason
+        }
+
+        /// receive a message, reconnect if connection is lost
+        pub fn receive(&mut self) -> Result<(SimplifiedHeader, Vec<u8>), WsError> {
+            loop {
+                let codec = self.connect()?;
+                let reason = match codec.receive() {
+                    // close frame is echoed by codec
+                    Ok((header, data)) if header.code == OpCode::Close => close_reason(data),
+                    Ok((header, data)) => {
+                        let data = data.to_vec();
+                        self.retry = 0;
+                        return Ok((header, data));
+                    }
+                    Err(e) => DisconnectReason::Error(e),
+                };
+                let give_up = !reason.is_retryable();
+                self.disconnect(reason);
+                if give_up {
+                    return Err(self.give_up(WsError::ConnectionClosed));
+                }
+            }
+        }
+
+        /// send payload with given opcode, connection is dropped on error and
+        /// will be reestablished on next call
+        pub fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+            let codec = self.connect()?;
+            match codec.send(code, payload).and_then(|_| codec.flush()) {
+                Ok(_) => {
+                    self.retry = 0;
+                    Ok(())
+                }
+                Err(e) => {
+                    let DisconnectReason::Error(e) = self.disconnect(DisconnectReason::Error(e))
+                    else {
+                        unreachable!()
+                    };
+                    Err(e)
+                }
+            }
+        }
+
+        /// send text message
+        pub fn text(&mut self, text: &str) -> Result<(), WsError> {
+            self.send(OpCode::Text, text.as_bytes())
+        }
+
+        /// send binary message
+        pub fn binary(&mut self, data: &[u8]) -> Result<(), WsError> {
+            self.send(OpCode::Binary, data)
+        }
+
+        /// send ping message
+        pub fn ping(&mut self, data: &[u8]) -> Result<(), WsError> {
+            self.send(OpCode::Ping, data)
+        }
+
+        /// send pong message
+        pub fn pong(&mut self, data: &[u8]) -> Result<(), WsError> {
+            self.send(OpCode::Pong, data)
+        }
+
+        /// close connection and stop reconnecting
+        pub fn close(mut self, code: impl Into<CloseCode>, msg: &[u8]) -> Result<(), WsError> {
+            match self.codec.as_mut() {
+                Some(codec) => {
+                    codec.close(code, msg)?;
+                    codec.flush()
+                }
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "sync")]
+pub use blocking::*;
+
+#[cfg(feature = "async")]
+mod non_blocking {
+    use std::{future::Future, pin::Pin};
+
+    use http::Uri;
+    use tokio::io::BufStream;
+
+    use super::{close_reason, Backoff, ConnectionEvent, DisconnectReason};
+    use crate::{
+        codec::AsyncDeflateCodec,
+        errors::WsError,
+        frame::{OpCode, SimplifiedHeader},
+        protocol::CloseCode,
+        stream::AsyncStream,
+        ClientConfig,
+    };
+
+    type Codec = AsyncDeflateCodec<BufStream<AsyncStream>>;
+
+    /// future returned by async `on_connected` callback
+    pub type ConnectedFuture<'a> = Pin<Box<dyn Future<Output = Result<(), WsError>> + Send + 'a>>;
+
+    type ConnectedFn = Box<dyn for<'a> FnMut(&'a mut Codec) -> ConnectedFuture<'a> + Send>;
+
+    /// async websocket client which reconnects automatically with exponential backoff
+    ///
+    /// close frame from peer is handled internally, connection will be
+    /// reestablished on next `receive` unless close code is not retryable,
+    /// see [CloseCode::is_retryable]
+    ///
+    /// once client gave up, all following calls fail with
+    /// [WsError::ConnectionClosed] without reconnecting
+    pub struct AsyncReconnectingClient {
+        uri: Uri,
+        config: ClientConfig,
+        backoff: Backoff,
+        on_connected: ConnectedFn,
+        on_event: Box<dyn FnMut(&ConnectionEvent) + Send>,
+        codec: Option<Codec>,
+        retry: u32,
+        gave_up: bool,
+    }
+
+    impl AsyncReconnectingClient {
+        /// create client, connection is not established until first
+        /// `connect`/`receive`/`send` call
+        pub fn new(
+            uri: impl TryInto<Uri, Error = http::uri::InvalidUri>,
+            config: ClientConfig,
+        ) -> Result<Self, WsError> {
+            let uri = uri
+                .try_into()
+                .map_err(|e| WsError::InvalidUri(e.to_string()))?;
+            Ok(Self {
+                uri,
+                config,
+                backoff: Backoff::default(),
+                on_connected: Box::new(|_| Box::pin(async { Ok(()) })),
+                on_event: Box::new(|_| {}),
+                codec: None,
+                retry: 0,
+                gave_up: false,
+            })
+        }
+
+        /// set backoff policy
+        pub fn backoff(mut self, backoff: Backoff) -> Self {
+            self.backoff = backoff;
+            self
+        }
+
+        /// callback after every successful handshake, use it to replay
+        /// subscription messages
+        ///
+        /// ```ignore
+        /// client.on_connected(|codec| Box::pin(async move { codec.text("sub").await }))
+        /// ```
+        pub fn on_connected<F>(mut self, f: F) -> Self
+        where
+            F: for<'a> FnMut(&'a mut Codec) -> ConnectedFuture<'a> + Send + 'static,
+        {
+            self.on_connected = Box::new(f);
+            self
+        }
+
+        /// callback of connection lifecycle event
+        pub fn on_event<F>(mut self, f: F) -> Self
+        where
+            F: FnMut(&ConnectionEvent) + Send + 'static,
+        {
+            self.on_event = Box::new(f);
+            self
+        }
+
+        /// return true if underlying connection is alive
+        pub fn is_connected(&self) -> bool {
+            self.codec.is_some()
+        }
+
+        /// get current connection
+        pub fn codec_mut(&mut self) -> Option<&mut Codec> {
+            self.codec.as_mut()
+        }
+
+        /// establish connection if not connected, return error if gave up
+        pub async fn connect(&mut self) -> Result<&mut Codec, WsError> {
+            if self.gave_up {
+                return Err(WsError::ConnectionClosed);
+            }
+            if self.codec.is_none() {
+                self.reconnect().await?;
+            }
+            Ok(self.codec.as_mut().unwrap())
+        }
+
+        async fn reconnect(&mut self) -> Result<(), WsError> {
+            let mut attempt = 0;
+            loop {
+                if self.retry > 0 {
+                    let delay = self.backoff.delay(self.retry - 1);
+                    tracing::debug!("reconnect to {} after {:?}", self.uri, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                attempt += 1;
+                let ret = match self.config.async_connect(self.uri.to_string()).await {
+                    Ok(mut codec) => (self.on_connected)(&mut codec).await.map(|_| codec),
+                    Err(e) => Err(e),
+                };
+                match ret {
+                    Ok(codec) => {
+                        self.codec = Some(codec);
+                        (self.on_event)(&ConnectionEvent::Connected { attempt });
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        tracing::warn!("failed to connect {} {e}", self.uri);
+                        // retry is only reset after a message is exchanged, so
+                        // max_retries also counts attempts of previous calls
+                        if self.backoff.give_up(self.retry) {
+                            return Err(self.give_up(e));
+                        }
+                        self.retry += 1;
+                    }
+                }
+            }
+        }
+
+        fn give_up(&mut self, e: WsError) -> WsError {
+            self.gave_up = true;
+            let event = ConnectionEvent::GaveUp(e);
+            (self.on_event)(&event);
+            let ConnectionEvent::GaveUp(e) = event else {
+                unreachable!()
+            };
+            e
+        }
+
+        fn disconnect(&mut self, reason: DisconnectReason) -> DisconnectReason {
+            self.codec = None;
+            // always wait before reconnecting to avoid busy loop on flapping server
+            self.retry = self.retry.max(1);
+            let event = ConnectionEvent::Disconnected(reason);
+            (self.on_event)(&event);
+            let ConnectionEvent::Disconnected(reason) = event else {
+                unreachable!()
+            };
+            reason
+        }
+
+        /// receive a message, reconnect if connection is lost
+        pub async fn receive(&mut self) -> Result<(SimplifiedHeader, Vec<u8>), WsError> {
+            loop {
+                let codec = self.connect().await?;
+                let reason = match codec.receive().await {
+                    // close frame is echoed by codec
+                    Ok((header, data)) if header.code == OpCode::Close => close_reason(data),
+                    Ok((header, data)) => {
+                        let data = data.to_vec();
+                        self.retry = 0;
+                        return Ok((header, data));
+                    }
+                    Err(e) => DisconnectReason::Error(e),
+                };
+                let give_up = !reason.is_retryable();
+                self.disconnect(reason);
+                if give_up {
+                    return Err(self.give_up(WsError::ConnectionClosed));
+                }
+            }
+        }
+
+        /// send payload with given opcode, connection is dropped on error and
+        /// will be reestablished on next call
+        pub async fn send(&mut self, code: OpCode, payload: &[u8]) -> Result<(), WsError> {
+            let codec = self.connect().await?;
+            let ret = match codec.send(code, payload).await {
+                Ok(_) => codec.flush().await,
+                Err(e) => Err(e),
+            };
+            match ret {
+                Ok(_) => {
+                    self.retry = 0;
+                    Ok(())
+                }
+                Err(e) => {
+                    let DisconnectReason::Error(e) = self.disconnect(DisconnectReason::Error(e))
+                    else {
+                        unreachable!()
+                    };
+                    Err(e)
+                }
+            }
+        }
+
+        /// send text message
+        pub async fn text(&mut self, text: &str) -> Result<(), WsError> {
+            self.send(OpCode::Text, text.as_bytes()).await
+        }
+
+        /// send binary message
+        pub async fn binary(&mut self, data: &[u8]) -> Result<(), WsError> {
+            self.send(OpCode::Binary, data).await
+        }
+
+        /// send ping message
+        pub async fn ping(&mut self, data: &[u8]) -> Result<(), WsError> {
+            self.send(OpCode::Ping, data).await
+        }
+
+        /// send pong message
+        pub async fn pong(&mut self, data: &[u8]) -> Result<(), WsError> {
+            self.send(OpCode::Pong, data).await
+        }
+
+        /// close connection and stop reconnecting
+        pub async fn close(
+            mut self,
+            code: impl Into<CloseCode>,
+            msg: &[u8],
+        ) -> Result<(), WsError> {
+            match self.codec.as_mut() {
+                Some(codec) => {
+                    codec.close(code, msg).await?;
+                    codec.flush().await
+                }
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+pub use non_blocking::*;
+
+#[test]
+fn test_backoff() {
+    let mut backoff = Backoff {
+        initial: Duration::from_millis(100),
+        max: Duration::from_secs(1),
+        multiplier: 2.0,
+        jitter: 0.0,
+        max_retries: Some(2),
+    };
+    assert_eq!(backoff.delay(0), Duration::from_millis(100));
+    assert_eq!(backoff.delay(1), Duration::from_millis(200));
+    assert_eq!(backoff.delay(2), Duration::from_millis(400));
+    assert_eq!(backoff.delay(10), Duration::from_secs(1));
+    assert!(!backoff.give_up(1));
+    assert!(backoff.give_up(2));
+
+    backoff.jitter = 0.5;
+    for _ in 0..100 {
+        let delay = backoff.delay(1);
+        assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(300));
+        assert!(backoff.delay(3) <= backoff.max);
+    }
+    backoff.max_retries = None;
+    assert!(!backoff.give_up(u32::MAX));
+}
+
+/// accept one connection for each close code, expect replayed "sub" message,
+/// reply with connection index and close with the code
+#[cfg(all(test, feature = "sync"))]
+fn serve(listener: std::net::TcpListener, codes: Vec<CloseCode>) -> std::thread::JoinHandle<()> {
+    use crate::{
+        codec::{default_handshake_handler, FrameCodec},
+        frame::OpCode,
+        ServerConfig,
+    };
+
+    std::thread::spawn(move || {
+        for (idx, code) in codes.into_iter().enumerate() {
+            let (stream, _) = listener.accept().unwrap();
+            let mut codec = ServerConfig::default()
+                .accept(stream, Ok, default_handshake_handler, FrameCodec::factory)
+                .unwrap();
+            let (header, data) = codec.receive().unwrap();
+            assert_eq!((header.code, data), (OpCode::Text, &b"sub"[..]));
+            codec
+                .send(OpCode::Text, idx.to_string().as_bytes())
+                .unwrap();
+            codec
+                .send(OpCode::Close, &u16::from(code).to_be_bytes())
+                .unwrap();
+            codec.flush().unwrap();
+            // wait for close echo
+            codec.receive().ok();
+        }
+    })
+}
+
+#[cfg(all(test, feature = "sync"))]
+fn record(
+    events: &std::sync::Arc<std::sync::Mutex<Vec<&'static str>>>,
+) -> impl FnMut(&ConnectionEvent) + Send + 'static {
+    let events = events.clone();
+    move |event| {
+        events.lock().unwrap().push(match event {
+            ConnectionEvent::Connected { .. } => "connected",
+            ConnectionEvent::Disconnected(_) => "disconnected",
+            ConnectionEvent::GaveUp(_) => "gave up",
+        })
+    }
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_reconnect() {
+    use crate::{frame::OpCode, ClientConfig};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let uri = format!("ws://{}", listener.local_addr().unwrap());
+    let server = serve(
+        listener,
+        vec![CloseCode::GoingAway, CloseCode::PolicyViolation],
+    );
+    let events = Default::default();
+    let mut client = ReconnectingClient::new(uri.as_str(), ClientConfig::default())
+        .unwrap()
+        .backoff(Backoff {
+            initial: Duration::from_millis(10),
+            jitter: 0.0,
+            ..Default::default()
+        })
+        .on_connected(|codec| {
+            codec.send(OpCode::Text, b"sub")?;
+            codec.flush()
+        })
+        .on_event(record(&events));
+
+    assert_eq!(client.receive().unwrap().1, b"0");
+    // going away is retryable, subscription is replayed on new connection
+    assert_eq!(client.receive().unwrap().1, b"1");
+    assert!(matches!(client.receive(), Err(WsError::ConnectionClosed)));
+    server.join().unwrap();
+    // gave up state is sticky
+    assert!(matches!(client.receive(), Err(WsError::ConnectionClosed)));
+    assert!(matches!(
+        client.text("hello"),
+        Err(WsError::ConnectionClosed)
+    ));
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "connected",
+            "disconnected",
+            "connected",
+            "disconnected",
+            "gave up"
+        ]
+    );
+}
+
+#[cfg(feature = "sync")]
+#[test]
+fn test_give_up() {
+    use crate::ClientConfig;
+
+    // bind then drop listener to get a port which refuses connection
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let events = Default::default();
+    let mut client =
+        ReconnectingClient::new(format!("ws://{addr}").as_str(), ClientConfig::default())
+            .unwrap()
+            .backoff(Backoff {
+                initial: Duration::from_millis(1),
+                max_retries: Some(1),
+                ..Default::default()
+            })
+            .on_event(record(&events));
+    assert!(matches!(
+        client.connect(),
+        Err(WsError::ConnectionFailed(_))
+    ));
+    assert!(matches!(client.connect(), Err(WsError::ConnectionClosed)));
+    assert_eq!(*events.lock().unwrap(), ["gave up"]);
+}
+
+#[cfg(all(test, feature = "sync", feature = "async"))]
+#[tokio::test]
+async fn test_async_reconnect() {
+    use crate::{frame::OpCode, ClientConfig};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let uri = format!("ws://{}", listener.local_addr().unwrap());
+    let server = serve(
+        listener,
+        vec![CloseCode::GoingAway, CloseCode::PolicyViolation],
+    );
+    let events = Default::default();
+    let mut client = AsyncReconnectingClient::new(uri.as_str(), ClientConfig::default())
+        .unwrap()
+        .backoff(Backoff {
+            initial: Duration::from_millis(10),
+            jitter: 0.0,
+            ..Default::default()
+        })
+        .on_connected(|codec| {
+            Box::pin(async move {
+                codec.send(OpCode::Text, b"sub").await?;
+                codec.flush().await
+            })
+        })
+        .on_event(record(&events));
+
+    assert_eq!(client.receive().await.unwrap().1, b"0");
+    assert_eq!(client.receive().await.unwrap().1, b"1");
+    assert!(matches!(
+        client.receive().await,
+        Err(WsError::ConnectionClosed)
+    ));
+    assert!(matches!(
+        client.text("hello").await,
+        Err(WsError::ConnectionClosed)
+    ));
+    tokio::task::spawn_blocking(|| server.join().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "connected",
+            "disconnected",
+            "connected",
+            "disconnected",
+            "gave up"
+        ]
+    );
+}
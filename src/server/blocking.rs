@@ -0,0 +1,322 @@
+use std::{
+    io::{BufReader, BufWriter, ErrorKind},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{mpsc, Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    codec::{FrameCodec, FrameRecv, FrameSend, PreparedFrame},
+    errors::{TimeoutPhase, WsError},
+    protocol::CloseCode,
+    stream::BufStream,
+    ServerBuilder, WsMessage,
+};
+
+use super::{ConnectionInfo, Permit, Registration, Registry, WsServer};
+
+type Reader = FrameRecv<BufReader<TcpStream>>;
+type Writer = FrameSend<BufWriter<TcpStream>>;
+
+/// interval to check shutdown flag when there is no incoming connection
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(20);
+
+/// send half of [WsConnection], can be cloned and used by other threads
+#[derive(Clone)]
+pub struct WsSender {
+    writer: Arc<Mutex<Writer>>,
+    socket: Arc<TcpStream>,
+}
+
+impl WsSender {
+    /// send message and flush
+    pub fn send(&self, msg: &WsMessage) -> Result<(), WsError> {
+        let mut writer = self.writer.lock().map_err(|_| WsError::ConnectionClosed)?;
+        writer.send_message(msg)?;
+        writer.flush()
+    }
+
+    /// send prepared frame and flush, see [crate::codec::FrameWriteState::send_prepared]
+    pub fn send_prepared(&self, frame: &PreparedFrame) -> Result<(), WsError> {
+        let mut writer = self.writer.lock().map_err(|_| WsError::ConnectionClosed)?;
+        writer.send_prepared(frame)?;
+        writer.flush()
+    }
+
+    /// send close frame, connection is closed after client replies close frame
+    pub fn close(&self, code: impl Into<CloseCode>, reason: &str) -> Result<(), WsError> {
+        self.send(&WsMessage::close(code, reason))
+    }
+}
+
+/// connection accepted by [WsServer]
+///
+/// ping frame is replied and close frame is echoed automatically
+pub struct WsConnection {
+    reader: Reader,
+    sender: WsSender,
+    registration: Registration<WsSender>,
+}
+
+impl WsConnection {
+    /// peer metadata of connection
+    pub fn info(&self) -> &Arc<ConnectionInfo> {
+        &self.registration.info
+    }
+
+    /// clone send half
+    pub fn sender(&self) -> WsSender {
+        self.sender.clone()
+    }
+
+    /// send message and flush
+    pub fn send(&self, msg: &WsMessage) -> Result<(), WsError> {
+        self.sender.send(msg)
+    }
+
+    /// receive a complete message, close message is returned after it's echoed,
+    /// then [WsError::ConnectionClosed] is returned
+    pub fn receive(&mut self) -> Result<WsMessage, WsError> {
+        loop {
+            let msg = self.reader.receive_message()?;
+            match &msg {
+                WsMessage::Ping(data) => {
+                    self.sender.send(&WsMessage::Pong(data.clone()))?;
+                }
+                WsMessage::Close(frame) => {
+                    // close frame is already sent if server initiates close handshake
+                    let echo = WsMessage::Close(frame.clone());
+                    if let Err(e) = self.sender.send(&echo) {
+                        tracing::debug!("failed to echo close frame {e}");
+                    }
+                    return Ok(msg);
+                }
+                _ => return Ok(msg),
+            }
+        }
+    }
+}
+
+impl WsServer {
+    /// bind all addresses and serve connections until [super::ServerHandle::shutdown]
+    /// is called, blocking current thread, connections are handled by worker threads
+    pub fn run<F>(&self, handler: F) -> Result<(), WsError>
+    where
+        F: Fn(WsConnection) + Send + Sync + 'static,
+    {
+        let listeners = self
+            .addrs
+            .iter()
+            .map(|addr| {
+                TcpListener::bind(addr.as_str())
+                    .map_err(|e| WsError::ConnectionFailed(format!("failed to bind {addr} {e}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        self.serve(listeners, handler)
+    }
+
+    /// serve connections of bound listeners, see [WsServer::run]
+    pub fn serve<F>(&self, listeners: Vec<TcpListener>, handler: F) -> Result<(), WsError>
+    where
+        F: Fn(WsConnection) + Send + Sync + 'static,
+    {
+        for listener in &listeners {
+            listener.set_nonblocking(true)?;
+        }
+        let handler = Arc::new(handler);
+        let registry = Registry::new(self.shared.clone());
+        let (tx, rx) = mpsc::channel::<(TcpStream, SocketAddr)>();
+        let rx = Arc::new(Mutex::new(rx));
+        let workers = (0..self.threads)
+            .map(|_| {
+                let (server, handler) = (self.clone(), handler.clone());
+                let (registry, rx) = (registry.clone(), rx.clone());
+                std::thread::spawn(move || loop {
+                    let job = match rx.lock() {
+                        Ok(rx) => rx.recv(),
+                        Err(_) => break,
+                    };
+                    let Ok((stream, peer_addr)) = job else {
+                        break;
+                    };
+                    // drop queued connections once shutdown starts
+                    if server.shared.is_shutdown() {
+                        continue;
+                    }
+                    let permit = server.shared.acquire(server.max_connections);
+                    match server.handshake(stream, peer_addr, permit, &registry) {
+                        Ok(conn) => handler(conn),
+                        Err(e) => tracing::debug!("handshake with {peer_addr} failed {e}"),
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        while !self.shared.is_shutdown() {
+            let mut idle = true;
+            for listener in &listeners {
+                match listener.accept() {
+                    Ok((stream, peer_addr)) => {
+                        idle = false;
+                        stream.set_nonblocking(false)?;
+                        if tx.send((stream, peer_addr)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                    Err(e) => tracing::warn!("failed to accept connection {e}"),
+                }
+            }
+            if idle {
+                std::thread::sleep(ACCEPT_INTERVAL);
+            }
+        }
+        drop(listeners);
+        drop(tx);
+        for sender in registry.all_senders() {
+            if let Err(e) = sender.close(CloseCode::GoingAway, "server shutdown") {
+                tracing::debug!("failed to send close frame {e}");
+            }
+        }
+        let deadline = Instant::now() + self.shutdown_timeout;
+        while !registry.is_empty() && Instant::now() < deadline {
+            std::thread::sleep(ACCEPT_INTERVAL);
+        }
+        if !registry.is_empty() {
+            tracing::debug!("shutdown timeout, drop remaining connections");
+        }
+        for sender in registry.all_senders() {
+            sender.socket.shutdown(std::net::Shutdown::Both).ok();
+        }
+        for worker in workers {
+            worker.join().ok();
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn handshake(
+        &self,
+        stream: TcpStream,
+        peer_addr: SocketAddr,
+        permit: Option<Permit>,
+        registry: &Arc<Registry<WsSender>>,
+    ) -> Result<WsConnection, WsError> {
+        let local_addr = stream.local_addr()?;
+        let socket = Arc::new(stream.try_clone()?);
+        socket.set_read_timeout(self.handshake_timeout)?;
+        socket.set_write_timeout(self.handshake_timeout)?;
+        let (req, codec) = ServerBuilder::accept(
+            BufStream::new(stream),
+            |req| self.check_handshake(req, &permit),
+            |req, stream| {
+                let codec = FrameCodec::factory(Default::default(), stream)?;
+                Ok((req, codec))
+            },
+        )
+        .map_err(|e| crate::codec::io_timeout(e, TimeoutPhase::Handshake))?;
+        socket.set_read_timeout(None)?;
+        socket.set_write_timeout(None)?;
+        let (reader, writer) = codec.split();
+        let sender = WsSender {
+            writer: Arc::new(Mutex::new(writer)),
+            socket,
+        };
+        let registration = registry.register(peer_addr, local_addr, &req, sender.clone(), permit);
+        if self.shared.is_shutdown() {
+            // registered after serve loop closed all connections, close it here
+            // so that worker does not block on it
+            if let Err(e) = sender.close(CloseCode::GoingAway, "server shutdown") {
+                tracing::debug!("failed to send close frame {e}");
+            }
+            sender.socket.shutdown(std::net::Shutdown::Both).ok();
+            return Err(WsError::ConnectionClosed);
+        }
+        Ok(WsConnection {
+            reader,
+            sender,
+            registration,
+        })
+    }
+}
+
+#[test]
+fn test_blocking_server() {
+    use crate::{ClientBuilder, CloseFrame};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let uri: http::Uri = format!("ws://{}", listener.local_addr().unwrap())
+        .parse()
+        .unwrap();
+    let server = WsServer::new().threads(2);
+    let handle = server.handle();
+    let server = std::thread::spawn(move || {
+        server.serve(vec![listener], |mut conn| {
+            while let Ok(msg) = conn.receive() {
+                if matches!(msg, WsMessage::Close(_)) || conn.send(&msg).is_err() {
+                    break;
+                }
+            }
+        })
+    });
+
+    let mut client = ClientBuilder::new()
+        .connect(uri, FrameCodec::check_fn)
+        .unwrap();
+    client.send_message(&"hello".into()).unwrap();
+    client.flush().unwrap();
+    assert_eq!(
+        client.receive_message().unwrap(),
+        WsMessage::Text("hello".into())
+    );
+    let info = handle.connections().pop().unwrap();
+    assert_eq!(info.peer_addr, client.stream.local_addr().unwrap());
+
+    handle.shutdown();
+    let msg = client.receive_message().unwrap();
+    assert!(matches!(
+        msg,
+        WsMessage::Close(Some(CloseFrame {
+            code: CloseCode::GoingAway,
+            ..
+        }))
+    ));
+    server.join().unwrap().unwrap();
+    assert_eq!(handle.connection_count(), 0);
+}
+
+#[test]
+fn test_blocking_server_drop_queued() {
+    use crate::ClientBuilder;
+    use std::io::Read;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = WsServer::new().threads(1);
+    let handle = server.handle();
+    let server = std::thread::spawn(move || {
+        server.serve(vec![listener], |mut conn| while conn.receive().is_ok() {})
+    });
+
+    let mut client = ClientBuilder::new()
+        .connect(
+            format!("ws://{addr}").parse().unwrap(),
+            FrameCodec::check_fn,
+        )
+        .unwrap();
+    // the only worker is occupied, this connection is queued without handshake
+    let mut queued = TcpStream::connect(addr).unwrap();
+    queued
+        .set_read_timeout(Some(Duration::from_secs(2)))
+        .unwrap();
+    while handle.connection_count() == 0 {
+        std::thread::sleep(ACCEPT_INTERVAL);
+    }
+    std::thread::sleep(ACCEPT_INTERVAL * 3);
+
+    handle.shutdown();
+    assert!(matches!(client.receive_message(), Ok(WsMessage::Close(_))));
+    drop(client);
+    assert_eq!(queued.read(&mut [0; 1]).unwrap(), 0);
+    server.join().unwrap().unwrap();
+}
@@ -0,0 +1,362 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+use crate::{codec::PreparedFrame, errors::WsError};
+
+/// what to do when queue of a subscriber is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SlowConsumerPolicy {
+    /// drop frame being published
+    DropNewest,
+    /// drop oldest queued frame to make room for new frame
+    #[default]
+    DropOldest,
+    /// disconnect subscriber, receiving from it returns [WsError::SlowConsumer]
+    Disconnect,
+}
+
+/// broadcast config
+#[derive(Debug, Clone)]
+pub struct BroadcastConfig {
+    /// max number of queued frames of each subscriber, default 1024
+    pub queue_size: usize,
+    /// policy when queue of subscriber is full
+    pub policy: SlowConsumerPolicy,
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        Self {
+            queue_size: 1024,
+            policy: SlowConsumerPolicy::default(),
+        }
+    }
+}
+
+/// topic based fan-out of [PreparedFrame], frame is encoded once and shared by
+/// all subscribers, can be cloned and used by other threads/tasks
+///
+/// each subscriber owns a bounded queue, connection handler drains it by
+/// [Subscriber::recv] or [Subscriber::async_recv] and writes frame with
+/// `send_prepared` of its sender
+#[derive(Debug, Clone, Default)]
+pub struct Broadcast {
+    hub: Arc<Hub>,
+}
+
+/// queues of subscribers of each topic
+type Topics = HashMap<String, HashMap<u64, Arc<Queue>>>;
+
+#[derive(Debug, Default)]
+struct Hub {
+    config: BroadcastConfig,
+    next_id: AtomicU64,
+    topics: RwLock<Topics>,
+}
+
+impl Broadcast {
+    /// create broadcast with config
+    pub fn new(config: BroadcastConfig) -> Self {
+        Self {
+            hub: Arc::new(Hub {
+                config,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// create a subscriber without any topic
+    pub fn subscriber(&self) -> Subscriber {
+        Subscriber {
+            id: self.hub.next_id.fetch_add(1, Ordering::SeqCst),
+            hub: self.hub.clone(),
+            queue: Default::default(),
+            topics: HashSet::new(),
+        }
+    }
+
+    /// queue frame to all subscribers of topic, return number of subscribers
+    /// which accept the frame
+    pub fn publish(&self, topic: &str, frame: &PreparedFrame) -> usize {
+        let config = &self.hub.config;
+        let mut accepted = 0;
+        let mut lagged = vec![];
+        if let Some(subscribers) = self.hub.topics().get(topic) {
+            for (id, queue) in subscribers {
+                match queue.push(frame, config) {
+                    Ok(true) => accepted += 1,
+                    Ok(false) => {}
+                    Err(_) => lagged.push(*id),
+                }
+            }
+        }
+        if !lagged.is_empty() {
+            tracing::debug!("disconnect slow subscribers {lagged:?} of {topic}");
+            for id in lagged {
+                self.hub.remove(topic, id);
+            }
+        }
+        accepted
+    }
+
+    /// number of subscribers of topic
+    pub fn subscriber_count(&self, topic: &str) -> usize {
+        self.hub.topics().get(topic).map(|s| s.len()).unwrap_or(0)
+    }
+
+    /// topics which have at least one subscriber
+    pub fn topics(&self) -> Vec<String> {
+        self.hub.topics().keys().cloned().collect()
+    }
+}
+
+impl Hub {
+    fn topics(&self) -> RwLockReadGuard<'_, Topics> {
+        self.topics.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn topics_mut(&self) -> RwLockWriteGuard<'_, Topics> {
+        self.topics.write().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// remove subscriber from topic, topic is removed if it has no subscriber
+    fn remove(&self, topic: &str, id: u64) {
+        let mut topics = self.topics_mut();
+        if let Some(subscribers) = topics.get_mut(topic) {
+            subscribers.remove(&id);
+            if subscribers.is_empty() {
+                topics.remove(topic);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    state: Mutex<QueueState>,
+    cond: Condvar,
+    #[cfg(feature = "async")]
+    notify: tokio::sync::Notify,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    frames: VecDeque<PreparedFrame>,
+    dropped: u64,
+    disconnected: bool,
+}
+
+impl QueueState {
+    fn pop(&mut self, queue_size: usize) -> Result<Option<PreparedFrame>, WsError> {
+        if self.disconnected {
+            return Err(WsError::SlowConsumer(queue_size));
+        }
+        Ok(self.frames.pop_front())
+    }
+}
+
+impl Queue {
+    fn state(&self) -> MutexGuard<'_, QueueState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// return whether frame is queued, or error if subscriber is disconnected
+    fn push(&self, frame: &PreparedFrame, config: &BroadcastConfig) -> Result<bool, WsError> {
+        let mut state = self.state();
+        if state.disconnected {
+            return Err(WsError::SlowConsumer(config.queue_size));
+        }
+        if state.frames.len() >= config.queue_size {
+            match config.policy {
+                SlowConsumerPolicy::DropNewest => {
+                    state.dropped += 1;
+                    return Ok(false);
+                }
+                SlowConsumerPolicy::DropOldest => {
+                    state.frames.pop_front();
+                    state.dropped += 1;
+                }
+                SlowConsumerPolicy::Disconnect => {
+                    state.frames.clear();
+                    state.disconnected = true;
+                    drop(state);
+                    self.wake();
+                    return Err(WsError::SlowConsumer(config.queue_size));
+                }
+            }
+        }
+        state.frames.push_back(frame.clone());
+        drop(state);
+        self.wake();
+        Ok(true)
+    }
+
+    fn wake(&self) {
+        self.cond.notify_one();
+        #[cfg(feature = "async")]
+        self.notify.notify_one();
+    }
+}
+
+/// receiving side of [Broadcast], unsubscribe all topics on drop
+#[derive(Debug)]
+pub struct Subscriber {
+    id: u64,
+    hub: Arc<Hub>,
+    queue: Arc<Queue>,
+    topics: HashSet<String>,
+}
+
+impl Subscriber {
+    /// unique id of subscriber
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// subscribe topic
+    pub fn subscribe(&mut self, topic: impl Into<String>) {
+        let topic = topic.into();
+        self.hub
+            .topics_mut()
+            .entry(topic.clone())
+            .or_default()
+            .insert(self.id, self.queue.clone());
+        self.topics.insert(topic);
+    }
+
+    /// unsubscribe topic
+    pub fn unsubscribe(&mut self, topic: &str) {
+        if self.topics.remove(topic) {
+            self.hub.remove(topic, self.id);
+        }
+    }
+
+    /// number of frames dropped because queue is full
+    pub fn dropped(&self) -> u64 {
+        self.queue.state().dropped
+    }
+
+    /// number of queued frames
+    pub fn len(&self) -> usize {
+        self.queue.state().frames.len()
+    }
+
+    /// check if queue is empty
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// return queued frame without blocking
+    pub fn try_recv(&self) -> Result<Option<PreparedFrame>, WsError> {
+        self.queue.state().pop(self.hub.config.queue_size)
+    }
+
+    /// block until a frame is published
+    #[cfg(feature = "sync")]
+    pub fn recv(&self) -> Result<PreparedFrame, WsError> {
+        let mut state = self.queue.state();
+        loop {
+            if let Some(frame) = state.pop(self.hub.config.queue_size)? {
+                return Ok(frame);
+            }
+            state = self
+                .queue
+                .cond
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// block until a frame is published or timeout, return None on timeout
+    #[cfg(feature = "sync")]
+    pub fn recv_timeout(
+        &self,
+        timeout: std::time::Duration,
+    ) -> Result<Option<PreparedFrame>, WsError> {
+        let deadline = std::time::Instant::now() + timeout;
+        let mut state = self.queue.state();
+        loop {
+            if let Some(frame) = state.pop(self.hub.config.queue_size)? {
+                return Ok(Some(frame));
+            }
+            let now = std::time::Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            state = self
+                .queue
+                .cond
+                .wait_timeout(state, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+
+    /// wait until a frame is published
+    #[cfg(feature = "async")]
+    pub async fn async_recv(&self) -> Result<PreparedFrame, WsError> {
+        loop {
+            if let Some(frame) = self.try_recv()? {
+                return Ok(frame);
+            }
+            self.queue.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        for topic in &self.topics {
+            self.hub.remove(topic, self.id);
+        }
+    }
+}
+
+#[test]
+fn test_broadcast_policy() {
+    use crate::frame::OpCode;
+
+    let frames: Vec<PreparedFrame> = (0..3)
+        .map(|i| PreparedFrame::new(OpCode::Text, i.to_string()))
+        .collect();
+    let payload = |frame: Option<PreparedFrame>| frame.unwrap().payload().to_vec();
+    for (policy, expect_accepted, expect) in [
+        (SlowConsumerPolicy::DropNewest, 2, b"0"),
+        (SlowConsumerPolicy::DropOldest, 3, b"1"),
+    ] {
+        let broadcast = Broadcast::new(BroadcastConfig {
+            queue_size: 2,
+            policy,
+        });
+        let mut sub = broadcast.subscriber();
+        sub.subscribe("ticker");
+        let accepted: usize = frames.iter().map(|f| broadcast.publish("ticker", f)).sum();
+        assert_eq!(accepted, expect_accepted);
+        assert_eq!(sub.dropped(), 1);
+        assert_eq!(payload(sub.try_recv().unwrap()), expect);
+        assert_eq!(sub.len(), 1);
+    }
+
+    let broadcast = Broadcast::new(BroadcastConfig {
+        queue_size: 2,
+        policy: SlowConsumerPolicy::Disconnect,
+    });
+    let mut slow = broadcast.subscriber();
+    slow.subscribe("ticker");
+    {
+        let mut fast = broadcast.subscriber();
+        fast.subscribe("ticker");
+        for frame in &frames {
+            broadcast.publish("ticker", frame);
+            assert!(fast.try_recv().unwrap().is_some());
+        }
+        assert_eq!(broadcast.subscriber_count("ticker"), 1);
+    }
+    assert!(matches!(slow.try_recv(), Err(WsError::SlowConsumer(2))));
+    assert!(broadcast.topics().is_empty());
+}
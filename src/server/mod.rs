@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, SystemTime},
+};
+
+use crate::{codec::default_handshake_handler, errors::WsError};
+
+mod broadcast;
+pub use broadcast::*;
+
+#[cfg(feature = "sync")]
+mod blocking;
+#[cfg(feature = "async")]
+mod non_blocking;
+#[cfg(feature = "sync")]
+pub use blocking::*;
+#[cfg(feature = "async")]
+pub use non_blocking::*;
+
+/// handshake handler of server, see [crate::codec::default_handshake_handler]
+pub type HandshakeHandler = dyn Fn(
+        http::Request<()>,
+    )
+        -> Result<(http::Request<()>, http::Response<String>), (http::Response<String>, WsError)>
+    + Send
+    + Sync;
+
+/// peer metadata of a live connection
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    /// unique id of connection in server
+    pub id: u64,
+    /// remote address of client
+    pub peer_addr: SocketAddr,
+    /// local address of listener
+    pub local_addr: SocketAddr,
+    /// time when handshake is done
+    pub connected_at: SystemTime,
+    /// request uri of handshake
+    pub uri: http::Uri,
+    /// request headers of handshake
+    pub headers: http::HeaderMap,
+}
+
+/// websocket server, bind listeners, perform handshake and run handler of each
+/// connection, see [WsServer::run] and [WsServer::async_run]
+#[derive(Clone)]
+pub struct WsServer {
+    addrs: Vec<String>,
+    max_connections: Option<usize>,
+    handshake_timeout: Option<Duration>,
+    shutdown_timeout: Duration,
+    threads: usize,
+    handshake_handler: Arc<HandshakeHandler>,
+    shared: Arc<Shared>,
+}
+
+impl std::fmt::Debug for WsServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WsServer")
+            .field("addrs", &self.addrs)
+            .field("max_connections", &self.max_connections)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .field("shutdown_timeout", &self.shutdown_timeout)
+            .field("threads", &self.threads)
+            .finish()
+    }
+}
+
+impl Default for WsServer {
+    fn default() -> Self {
+        Self {
+            addrs: vec![],
+            max_connections: None,
+            handshake_timeout: Some(Duration::from_secs(10)),
+            shutdown_timeout: Duration::from_secs(3),
+            threads: 4,
+            handshake_handler: Arc::new(default_handshake_handler),
+            shared: Default::default(),
+        }
+    }
+}
+
+impl WsServer {
+    /// create server with default config
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add listening address, can be called multiple times
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.addrs.push(addr.into());
+        self
+    }
+
+    /// max number of live connections, handshake of new connection is rejected with
+    /// `503 Service Unavailable` if limit is reached
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max);
+        self
+    }
+
+    /// timeout of websocket handshake, default 10 seconds
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = Some(timeout);
+        self
+    }
+
+    /// time to wait for clients to finish close handshake after shutdown, remaining
+    /// connections are dropped, it also limits sending close frame to each
+    /// connection, default 3 seconds
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// number of worker threads of blocking server, each connection occupies a worker
+    /// until it's closed, default 4
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// custom handshake handler, default [crate::codec::default_handshake_handler]
+    pub fn handshake_handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(
+                http::Request<()>,
+            ) -> Result<
+                (http::Request<()>, http::Response<String>),
+                (http::Response<String>, WsError),
+            > + Send
+            + Sync
+            + 'static,
+    {
+        self.handshake_handler = Arc::new(handler);
+        self
+    }
+
+    /// handle to inspect connections & shutdown server
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// run handshake handler, reject with 503 if `permit` is not acquired
+    #[allow(clippy::type_complexity, clippy::result_large_err)]
+    fn check_handshake(
+        &self,
+        req: http::Request<()>,
+        permit: &Option<Permit>,
+    ) -> Result<(http::Request<()>, http::Response<String>), (http::Response<String>, WsError)>
+    {
+        if permit.is_some() {
+            return (self.handshake_handler)(req);
+        }
+        let resp = http::Response::builder()
+            .version(http::Version::HTTP_11)
+            .status(http::StatusCode::SERVICE_UNAVAILABLE)
+            .body("too many connections".to_string())
+            .unwrap();
+        Err((
+            resp,
+            WsError::HandShakeFailed("too many connections".to_string()),
+        ))
+    }
+}
+
+/// handle of [WsServer] to inspect live connections & shutdown server
+#[derive(Debug, Clone)]
+pub struct ServerHandle {
+    shared: Arc<Shared>,
+}
+
+impl ServerHandle {
+    /// info of all live connections
+    pub fn connections(&self) -> Vec<Arc<ConnectionInfo>> {
+        self.shared.conns().values().cloned().collect()
+    }
+
+    /// info of connection by id
+    pub fn connection(&self, id: u64) -> Option<Arc<ConnectionInfo>> {
+        self.shared.conns().get(&id).cloned()
+    }
+
+    /// number of live connections
+    pub fn connection_count(&self) -> usize {
+        self.shared.conns().len()
+    }
+
+    /// stop accepting new connection, send close frame with code 1001 to every
+    /// client and wait for them to close
+    pub fn shutdown(&self) {
+        self.shared.shutdown.store(true, Ordering::SeqCst);
+        #[cfg(feature = "async")]
+        self.shared.notify.send_replace(true);
+    }
+
+    /// whether shutdown is requested
+    pub fn is_shutdown(&self) -> bool {
+        self.shared.shutdown.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    next_id: AtomicU64,
+    active: AtomicUsize,
+    conns: Mutex<HashMap<u64, Arc<ConnectionInfo>>>,
+    shutdown: AtomicBool,
+    #[cfg(feature = "async")]
+    notify: tokio::sync::watch::Sender<bool>,
+}
+
+impl Default for Shared {
+    fn default() -> Self {
+        Self {
+            next_id: AtomicU64::new(1),
+            active: Default::default(),
+            conns: Default::default(),
+            shutdown: Default::default(),
+            #[cfg(feature = "async")]
+            notify: tokio::sync::watch::channel(false).0,
+        }
+    }
+}
+
+impl Shared {
+    fn conns(&self) -> MutexGuard<'_, HashMap<u64, Arc<ConnectionInfo>>> {
+        self.conns.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    /// reserve a connection slot, return None if limit is reached
+    fn acquire(self: &Arc<Self>, max: Option<usize>) -> Option<Permit> {
+        self.active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| match max {
+                Some(max) if active >= max => None,
+                _ => Some(active + 1),
+            })
+            .ok()
+            .map(|_| Permit(self.clone()))
+    }
+}
+
+/// connection slot, released on drop
+struct Permit(Arc<Shared>);
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.0.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// senders of live connections of a running server
+struct Registry<W: Clone> {
+    shared: Arc<Shared>,
+    senders: Mutex<HashMap<u64, W>>,
+}
+
+impl<W: Clone> Registry<W> {
+    fn new(shared: Arc<Shared>) -> Arc<Self> {
+        Arc::new(Self {
+            shared,
+            senders: Default::default(),
+        })
+    }
+
+    fn register(
+        self: &Arc<Self>,
+        peer_addr: SocketAddr,
+        local_addr: SocketAddr,
+        req: &http::Request<()>,
+        sender: W,
+        permit: Option<Permit>,
+    ) -> Registration<W> {
+        let id = self.shared.next_id.fetch_add(1, Ordering::SeqCst);
+        let info = Arc::new(ConnectionInfo {
+            id,
+            peer_addr,
+            local_addr,
+            connected_at: SystemTime::now(),
+            uri: req.uri().clone(),
+            headers: req.headers().clone(),
+        });
+        self.shared.conns().insert(id, info.clone());
+        self.senders().insert(id, sender);
+        Registration {
+            registry: self.clone(),
+            info,
+            _permit: permit,
+        }
+    }
+
+    fn senders(&self) -> MutexGuard<'_, HashMap<u64, W>> {
+        self.senders.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn all_senders(&self) -> Vec<W> {
+        self.senders().values().cloned().collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.senders().is_empty()
+    }
+}
+
+/// registered connection, removed from registry on drop
+struct Registration<W: Clone> {
+    registry: Arc<Registry<W>>,
+    info: Arc<ConnectionInfo>,
+    _permit: Option<Permit>,
+}
+
+impl<W: Clone> Drop for Registration<W> {
+    fn drop(&mut self) {
+        self.registry.senders().remove(&self.info.id);
+        self.registry.shared.conns().remove(&self.info.id);
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    future::{poll_fn, Future},
+    net::SocketAddr,
+    sync::Arc,
+    task::Poll,
+};
+
+use tokio::{
+    io::{BufStream, ReadHalf, WriteHalf},
+    net::{TcpListener, TcpStream},
+    task::JoinSet,
+};
+
+use crate::{
+    codec::{with_timeout, AsyncFrameCodec, AsyncFrameRecv, AsyncFrameSend, PreparedFrame},
+    errors::{TimeoutPhase, WsError},
+    protocol::CloseCode,
+    ServerBuilder, WsMessage,
+};
+
+use super::{ConnectionInfo, Registration, Registry, WsServer};
+
+type Reader = AsyncFrameRecv<ReadHalf<BufStream<TcpStream>>>;
+type Writer = AsyncFrameSend<WriteHalf<BufStream<TcpStream>>>;
+
+/// send half of [AsyncWsConnection], can be cloned and used by other tasks
+#[derive(Clone)]
+pub struct AsyncWsSender {
+    writer: Arc<tokio::sync::Mutex<Writer>>,
+}
+
+impl AsyncWsSender {
+    /// send message and flush
+    pub async fn send(&self, msg: &WsMessage) -> Result<(), WsError> {
+        let mut writer = self.writer.lock().await;
+        writer.send_message(msg).await?;
+        writer.flush().await
+    }
+
+    /// send prepared frame and flush, see [crate::codec::FrameWriteState::send_prepared]
+    pub async fn send_prepared(&self, frame: &PreparedFrame) -> Result<(), WsError> {
+        let mut writer = self.writer.lock().await;
+        writer.send_prepared(frame).await?;
+        writer.flush().await
+    }
+
+    /// send close frame, connection is closed after client replies close frame
+    pub async fn close(&self, code: impl Into<CloseCode>, reason: &str) -> Result<(), WsError> {
+        self.send(&WsMessage::close(code, reason)).await
+    }
+}
+
+/// connection accepted by [WsServer]
+///
+/// ping frame is replied and close frame is echoed automatically
+pub struct AsyncWsConnection {
+    reader: Reader,
+    sender: AsyncWsSender,
+    registration: Registration<AsyncWsSender>,
+}
+
+impl AsyncWsConnection {
+    /// peer metadata of connection
+    pub fn info(&self) -> &Arc<ConnectionInfo> {
+        &self.registration.info
+    }
+
+    /// clone send half
+    pub fn sender(&self) -> AsyncWsSender {
+        self.sender.clone()
+    }
+
+    /// send message and flush
+    pub async fn send(&self, msg: &WsMessage) -> Result<(), WsError> {
+        self.sender.send(msg).await
+    }
+
+    /// receive a complete message, close message is returned after it's echoed,
+    /// then [WsError::ConnectionClosed] is returned
+    pub async fn receive(&mut self) -> Result<WsMessage, WsError> {
+        loop {
+            let msg = self.reader.receive_message().await?;
+            match &msg {
+                WsMessage::Ping(data) => {
+                    self.sender.send(&WsMessage::Pong(data.clone())).await?;
+                }
+                WsMessage::Close(frame) => {
+                    // close frame is already sent if server initiates close handshake
+                    let echo = WsMessage::Close(frame.clone());
+                    if let Err(e) = self.sender.send(&echo).await {
+                        tracing::debug!("failed to echo close frame {e}");
+                    }
+                    return Ok(msg);
+                }
+                _ => return Ok(msg),
+            }
+        }
+    }
+}
+
+impl WsServer {
+    /// bind all addresses and serve connections until [super::ServerHandle::shutdown]
+    /// is called, each connection is handled in its own tokio task
+    pub async fn async_run<F, Fut>(&self, handler: F) -> Result<(), WsError>
+    where
+        F: Fn(AsyncWsConnection) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let mut listeners = vec![];
+        for addr in &self.addrs {
+            let listener = TcpListener::bind(addr.as_str())
+                .await
+                .map_err(|e| WsError::ConnectionFailed(format!("failed to bind {addr} {e}")))?;
+            listeners.push(listener);
+        }
+        self.async_serve(listeners, handler).await
+    }
+
+    /// serve connections of bound listeners, see [WsServer::async_run]
+    pub async fn async_serve<F, Fut>(
+        &self,
+        listeners: Vec<TcpListener>,
+        handler: F,
+    ) -> Result<(), WsError>
+    where
+        F: Fn(AsyncWsConnection) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        let registry = Registry::new(self.shared.clone());
+        let mut tasks = JoinSet::new();
+        let mut notify = self.shared.notify.subscribe();
+        let mut shutdown = Box::pin(async move {
+            notify.wait_for(|shutdown| *shutdown).await.ok();
+        });
+        loop {
+            let accepted = poll_fn(|cx| {
+                if shutdown.as_mut().poll(cx).is_ready() {
+                    return Poll::Ready(None);
+                }
+                while let Poll::Ready(Some(_)) = tasks.poll_join_next(cx) {}
+                for listener in &listeners {
+                    if let Poll::Ready(ret) = listener.poll_accept(cx) {
+                        return Poll::Ready(Some(ret));
+                    }
+                }
+                Poll::Pending
+            })
+            .await;
+            match accepted {
+                None => break,
+                Some(Ok((stream, peer_addr))) => {
+                    let server = self.clone();
+                    let (handler, registry) = (handler.clone(), registry.clone());
+                    tasks.spawn(async move {
+                        let conn = match server.async_handshake(stream, peer_addr, &registry).await
+                        {
+                            Ok(conn) => conn,
+                            Err(e) => {
+                                tracing::debug!("handshake with {peer_addr} failed {e}");
+                                return;
+                            }
+                        };
+                        handler(conn).await
+                    });
+                }
+                Some(Err(e)) => tracing::warn!("failed to accept connection {e}"),
+            }
+        }
+        drop(listeners);
+        // send close frames concurrently, a slow peer does not delay others
+        let mut closes = tokio::task::JoinSet::new();
+        for sender in registry.all_senders() {
+            let timeout = self.shutdown_timeout;
+            closes.spawn(async move {
+                let close = sender.close(CloseCode::GoingAway, "server shutdown");
+                match tokio::time::timeout(timeout, close).await {
+                    Ok(Err(e)) => tracing::debug!("failed to send close frame {e}"),
+                    Err(_) => tracing::debug!("send close frame timeout"),
+                    Ok(Ok(_)) => {}
+                }
+            });
+        }
+        let close_all = async {
+            while closes.join_next().await.is_some() {}
+            while tasks.join_next().await.is_some() {}
+        };
+        if tokio::time::timeout(self.shutdown_timeout, close_all)
+            .await
+            .is_err()
+        {
+            tracing::debug!("shutdown timeout, drop remaining connections");
+        }
+        tasks.shutdown().await;
+        Ok(())
+    }
+
+    #[allow(clippy::result_large_err)]
+    async fn async_handshake(
+        &self,
+        stream: TcpStream,
+        peer_addr: SocketAddr,
+        registry: &Arc<Registry<AsyncWsSender>>,
+    ) -> Result<AsyncWsConnection, WsError> {
+        let local_addr = stream.local_addr()?;
+        let permit = self.shared.acquire(self.max_connections);
+        let accept = ServerBuilder::async_accept(
+            BufStream::new(stream),
+            |req| self.check_handshake(req, &permit),
+            |req, stream| {
+                let codec = AsyncFrameCodec::factory(Default::default(), stream)?;
+                Ok((req, codec))
+            },
+        );
+        let (req, codec) =
+            with_timeout(self.handshake_timeout, TimeoutPhase::Handshake, accept).await?;
+        let (reader, writer) = codec.split();
+        let sender = AsyncWsSender {
+            writer: Arc::new(tokio::sync::Mutex::new(writer)),
+        };
+        let registration = registry.register(peer_addr, local_addr, &req, sender.clone(), permit);
+        if self.shared.is_shutdown() {
+            sender
+                .close(CloseCode::GoingAway, "server shutdown")
+                .await?;
+        }
+        Ok(AsyncWsConnection {
+            reader,
+            sender,
+            registration,
+        })
+    }
+}
+
+#[test]
+fn test_async_server() {
+    use crate::{ClientBuilder, CloseFrame};
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri: http::Uri = format!("ws://{}", listener.local_addr().unwrap())
+            .parse()
+            .unwrap();
+        let server = WsServer::new().max_connections(1);
+        let handle = server.handle();
+        let server = tokio::spawn(async move {
+            server
+                .async_serve(vec![listener], |mut conn| async move {
+                    while let Ok(msg) = conn.receive().await {
+                        if matches!(msg, WsMessage::Close(_)) || conn.send(&msg).await.is_err() {
+                            break;
+                        }
+                    }
+                })
+                .await
+        });
+
+        let builder = ClientBuilder::new();
+        let mut client = builder
+            .async_connect(uri.clone(), AsyncFrameCodec::check_fn)
+            .await
+            .unwrap();
+        client.send_message(&"hello".into()).await.unwrap();
+        client.flush().await.unwrap();
+        assert_eq!(
+            client.receive_message().await.unwrap(),
+            WsMessage::Text("hello".into())
+        );
+        assert_eq!(handle.connection_count(), 1);
+        let rejected = builder.async_connect(uri, AsyncFrameCodec::check_fn).await;
+        assert!(rejected.is_err());
+
+        handle.shutdown();
+        let msg = client.receive_message().await.unwrap();
+        assert!(matches!(
+            msg,
+            WsMessage::Close(Some(CloseFrame {
+                code: CloseCode::GoingAway,
+                ..
+            }))
+        ));
+        server.await.unwrap().unwrap();
+        assert_eq!(handle.connection_count(), 0);
+    });
+}
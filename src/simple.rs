@@ -1,12 +1,19 @@
+#[cfg(any(
+    feature = "deflate",
+    feature = "deflate_ng",
+    feature = "deflate_static",
+    feature = "deflate_rust"
+))]
+use crate::codec::PMDConfig;
 use http::Uri;
 use crate::{
-    codec::{PMDConfig, WindowBit},
-    connector::{get_host, get_scheme},
-    errors::WsError,
+    codec::KeepaliveConfig,
+    connector::{get_host, get_scheme, ConnectOptions, ProxyConfig, ProxyScheme, TlsOptions},
+    errors::{TimeoutPhase, WsError},
     protocol::Mode,
     ClientBuilder,
 };
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
 
 /// client connection config
 pub struct ClientConfig {
@@ -16,15 +23,54 @@ pub struct ClientConfig {
     pub write_buf: usize,
     /// custom certification path
     pub certs: Vec<PathBuf>,
-    /// deflate window size, if none, deflate will be disabled
-    pub window: Option<WindowBit>,
-    /// enable/disable deflate context taker over parameter
-    pub context_take_over: bool,
+    /// permessage-deflate params offered in handshake, window bits and context takeover
+    /// of client and server are set independently, if none, deflate will be disabled
+    #[cfg(any(
+        feature = "deflate",
+        feature = "deflate_ng",
+        feature = "deflate_static",
+        feature = "deflate_rust"
+    ))]
+    pub deflate: Option<PMDConfig>,
     /// extra header when perform websocket protocol handshake
     pub extra_headers: HashMap<String, String>,
     /// modified socket option after create tcp socket, this function will be applied
-    /// before start tls session
+    /// before start tls session, if proxy is used, it's applied to the socket connected
+    /// to proxy server, it's not applied to `ws+unix` connection
     pub set_socket_fn: Box<dyn FnMut(&std::net::TcpStream) -> Result<(), WsError>>,
+    /// proxy server, `http`, `https`, `socks5` and `socks5h` are supported, `wss`
+    /// connection over `https` proxy is out of scope and returns
+    /// [WsError::UnsupportedProxy]
+    pub proxy: Option<ProxyConfig>,
+    /// read proxy from `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY`
+    /// environment variables if `proxy` is not set, default false
+    pub proxy_from_env: bool,
+    /// keepalive config, for blocking connection, read timeout of socket is set to
+    /// the smaller one of `ping_interval` and `timeout` after handshake
+    pub keepalive: KeepaliveConfig,
+    /// tls options of wss connection, https proxy connection only uses `certs`
+    pub tls: TlsOptions,
+    /// dns resolution, ip version preference & local bind options, also applied to
+    /// connection to proxy server
+    pub connect_options: ConnectOptions,
+    /// timeout of tcp connection, including connection to proxy server
+    pub connect_timeout: Option<Duration>,
+    /// timeout of tls handshake
+    ///
+    /// for blocking connection, tls, handshake, read & write timeouts are set as read/write
+    /// timeout of socket, so they limit every single read/write call instead of whole phase
+    pub tls_timeout: Option<Duration>,
+    /// timeout of websocket handshake, including proxy tunnel handshake
+    pub handshake_timeout: Option<Duration>,
+    /// fail with [WsError::Timeout] if no data received within this duration after
+    /// handshake, use `keepalive` instead if keepalive is enabled
+    ///
+    /// for async connection, read & write timeouts are only applied by `async_connect`,
+    /// set [crate::codec::FrameConfig] of custom codec in `async_connect_with`
+    pub read_timeout: Option<Duration>,
+    /// fail with [WsError::Timeout] if writing does not complete within this duration
+    /// after handshake
+    pub write_timeout: Option<Duration>,
 }
 
 impl Default for ClientConfig {
@@ -33,10 +79,25 @@ impl Default for ClientConfig {
             read_buf: Default::default(),
             write_buf: Default::default(),
             certs: Default::default(),
-            window: Default::default(),
-            context_take_over: Default::default(),
+            #[cfg(any(
+                feature = "deflate",
+                feature = "deflate_ng",
+                feature = "deflate_static",
+                feature = "deflate_rust"
+            ))]
+            deflate: None,
             extra_headers: Default::default(),
             set_socket_fn: Box::new(|_| Ok(())),
+            proxy: None,
+            proxy_from_env: false,
+            keepalive: Default::default(),
+            tls: Default::default(),
+            connect_options: Default::default(),
+            connect_timeout: None,
+            tls_timeout: None,
+            handshake_timeout: None,
+            read_timeout: None,
+            write_timeout: None,
         }
     }
 }
@@ -64,57 +125,138 @@ impl ClientConfig {
             crate::stream::BufStream<crate::stream::SyncStream>,
         ) -> Result<C, WsError>,
     {
+        use crate::codec::io_timeout;
+
         let (uri, mode, builder) = self.prepare(uri)?;
-        let stream = crate::connector::tcp_connect(&uri)?;
-        (self.set_socket_fn)(&stream)?;
+        let (stream, timeout) = match mode {
+            #[cfg(unix)]
+            Mode::WSUnix => {
+                let stream = crate::connector::unix_connect(&uri)?;
+                let timeout = PhaseTimeout::new(stream.try_clone()?)?;
+                (crate::stream::SyncStream::Unix(stream), timeout)
+            }
+            _ => self.tcp_transport(&uri, mode)?,
+        };
+        timeout.set(self.handshake_timeout, self.handshake_timeout)?;
         let check_fn = |key, resp, stream| {
+            timeout.set(self.read_timeout, self.write_timeout)?;
             let stream =
                 crate::stream::BufStream::with_capacity(self.read_buf, self.write_buf, stream);
             check_fn(key, resp, stream)
         };
-        match mode {
-            Mode::WS => builder.with_stream(uri, crate::stream::SyncStream::Raw(stream), check_fn),
-            Mode::WSS => {
-                let host = get_host(&uri)?;
-                if cfg!(feature = "sync_tls_rustls") {
-                    #[cfg(feature = "sync_tls_rustls")]
-                    {
-                        let stream =
-                            crate::connector::wrap_rustls(stream, host, self.certs.clone())?;
-                        builder.with_stream(
-                            uri,
-                            crate::stream::SyncStream::Rustls(stream),
-                            check_fn,
-                        )
-                    }
-                    #[cfg(not(feature = "sync_tls_rustls"))]
-                    {
-                        panic!("")
-                    }
-                } else if cfg!(feature = "sync_tls_native") {
-                    #[cfg(feature = "sync_tls_native")]
-                    {
-                        let stream =
-                            crate::connector::wrap_native_tls(stream, host, self.certs.clone())?;
-                        builder.with_stream(
-                            uri,
-                            crate::stream::SyncStream::NativeTls(stream),
-                            check_fn,
-                        )
-                    }
-                    #[cfg(not(feature = "sync_tls_native"))]
-                    {
-                        panic!("")
+        builder
+            .with_stream(uri, stream, check_fn)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Handshake))
+    }
+
+    /// create tcp connection, perform proxy handshake & start tls session
+    #[cfg(feature = "sync")]
+    fn tcp_transport(
+        &mut self,
+        uri: &Uri,
+        mode: Mode,
+    ) -> Result<(crate::stream::SyncStream, PhaseTimeout), WsError> {
+        use crate::codec::io_timeout;
+
+        let proxy = self.get_proxy(uri)?;
+        let (host, port) = match &proxy {
+            Some(proxy) => (proxy.host.as_str(), proxy.port),
+            None => (
+                get_host(uri)?,
+                uri.port_u16().unwrap_or_else(|| mode.default_port()),
+            ),
+        };
+        let stream = crate::connector::tcp_connect_with(
+            host,
+            port,
+            &self.connect_options,
+            self.connect_timeout,
+        )?;
+        (self.set_socket_fn)(&stream)?;
+        let timeout = PhaseTimeout::new(stream.try_clone()?)?;
+        let host = get_host(uri)?;
+        let stream = match proxy {
+            Some(proxy) if proxy.scheme == ProxyScheme::Https => {
+                if mode == Mode::WSS {
+                    return Err(WsError::UnsupportedProxy(
+                        "wss connection over https proxy".to_string(),
+                    ));
+                }
+                timeout.set(self.tls_timeout, self.tls_timeout)?;
+                let mut stream = self.wrap_tls(stream, &proxy.host, &TlsOptions::default())?;
+                timeout.set(self.handshake_timeout, self.handshake_timeout)?;
+                crate::connector::proxy_handshake(&mut stream, &proxy, uri)
+                    .map_err(|e| io_timeout(e, TimeoutPhase::Handshake))?;
+                stream
+            }
+            Some(proxy) => {
+                let mut stream = stream;
+                timeout.set(self.handshake_timeout, self.handshake_timeout)?;
+                crate::connector::proxy_handshake(&mut stream, &proxy, uri)
+                    .map_err(|e| io_timeout(e, TimeoutPhase::Handshake))?;
+                match mode {
+                    Mode::WS | Mode::WSUnix => crate::stream::SyncStream::Raw(stream),
+                    Mode::WSS => {
+                        timeout.set(self.tls_timeout, self.tls_timeout)?;
+                        self.wrap_tls(stream, host, &self.tls)?
                     }
-                } else {
-                    panic!("for ssl connection, sync_tls_native or sync_tls_rustls feature is required")
                 }
             }
-        }
+            None => match mode {
+                Mode::WS | Mode::WSUnix => crate::stream::SyncStream::Raw(stream),
+                Mode::WSS => {
+                    timeout.set(self.tls_timeout, self.tls_timeout)?;
+                    self.wrap_tls(stream, host, &self.tls)?
+                }
+            },
+        };
+        Ok((stream, timeout))
     }
 
-    /// perform websocket handshake
     #[cfg(feature = "sync")]
+    fn wrap_tls(
+        &self,
+        stream: std::net::TcpStream,
+        host: &str,
+        options: &TlsOptions,
+    ) -> Result<crate::stream::SyncStream, WsError> {
+        if cfg!(feature = "sync_tls_rustls") {
+            #[cfg(feature = "sync_tls_rustls")]
+            {
+                let stream =
+                    crate::connector::wrap_rustls_with(stream, host, &self.certs, options)?;
+                Ok(crate::stream::SyncStream::Rustls(stream))
+            }
+            #[cfg(not(feature = "sync_tls_rustls"))]
+            {
+                panic!("")
+            }
+        } else if cfg!(feature = "sync_tls_native") {
+            #[cfg(feature = "sync_tls_native")]
+            {
+                let stream =
+                    crate::connector::wrap_native_tls_with(stream, host, &self.certs, options)?;
+                Ok(crate::stream::SyncStream::NativeTls(stream))
+            }
+            #[cfg(not(feature = "sync_tls_native"))]
+            {
+                panic!("")
+            }
+        } else {
+            panic!("for ssl connection, sync_tls_native or sync_tls_rustls feature is required")
+        }
+    }
+
+    /// perform websocket handshake, requires one of deflate backend
+    #[cfg(all(
+        feature = "sync",
+        any(
+            feature = "deflate",
+            feature = "deflate_ng",
+            feature = "deflate_static",
+            feature = "deflate_rust"
+        )
+    ))]
     pub fn connect(
         &mut self,
         uri: impl TryInto<Uri, Error = http::uri::InvalidUri>,
@@ -122,7 +264,22 @@ impl ClientConfig {
         crate::codec::DeflateCodec<crate::stream::BufStream<crate::stream::SyncStream>>,
         WsError,
     > {
-        self.connect_with(uri, crate::codec::DeflateCodec::check_fn)
+        let keepalive = self.keepalive.clone();
+        let mut check_fn = crate::codec::DeflateCodec::check_fn_with(self.deflate.clone());
+        self.connect_with(uri, move |key, resp, stream| {
+            let mut codec = check_fn(key, resp, stream)?;
+            if keepalive.is_enabled() {
+                let tick = [keepalive.ping_interval, keepalive.timeout]
+                    .into_iter()
+                    .flatten()
+                    .min();
+                if tick.is_some() {
+                    codec.stream_mut().get_mut().set_read_timeout(tick)?;
+                }
+                codec.set_keepalive(keepalive.clone());
+            }
+            Ok(codec)
+        })
     }
 
     /// perform websocket handshake
@@ -140,73 +297,141 @@ impl ClientConfig {
             tokio::io::BufStream<crate::stream::AsyncStream>,
         ) -> Result<C, WsError>,
     {
+        use crate::codec::with_timeout;
+
         let (uri, mode, builder) = self.prepare(uri)?;
-        let stream = crate::connector::async_tcp_connect(&uri).await?;
-        let stream = stream.into_std()?;
-        (self.set_socket_fn)(&stream)?;
-        let stream = tokio::net::TcpStream::from_std(stream)?;
+        let stream = match mode {
+            #[cfg(unix)]
+            Mode::WSUnix => {
+                let connect = crate::connector::async_unix_connect(&uri);
+                let stream =
+                    with_timeout(self.connect_timeout, TimeoutPhase::Connect, connect).await?;
+                crate::stream::AsyncStream::Unix(stream)
+            }
+            _ => self.async_tcp_transport(&uri, mode).await?,
+        };
         let check_fn = |key, resp, stream: crate::stream::AsyncStream| {
             let stream = tokio::io::BufStream::with_capacity(self.read_buf, self.write_buf, stream);
             check_fn(key, resp, stream)
         };
-        match mode {
-            Mode::WS => {
-                builder
-                    .async_with_stream(uri, crate::stream::AsyncStream::Raw(stream), check_fn)
-                    .await
-            }
-            Mode::WSS => {
-                let host = get_host(&uri)?;
-                if cfg!(feature = "async_tls_rustls") {
-                    #[cfg(feature = "async_tls_rustls")]
-                    {
-                        let stream =
-                            crate::connector::async_wrap_rustls(stream, host, self.certs.clone())
-                                .await?;
-                        builder
-                            .async_with_stream(
-                                uri,
-                                crate::stream::AsyncStream::Rustls(
-                                    tokio_rustls::TlsStream::Client(stream),
-                                ),
-                                check_fn,
-                            )
-                            .await
-                    }
-                    #[cfg(not(feature = "async_tls_rustls"))]
-                    {
-                        panic!("")
-                    }
-                } else if cfg!(feature = "async_tls_native") {
-                    #[cfg(feature = "async_tls_native")]
-                    {
-                        let stream = crate::connector::async_wrap_native_tls(
-                            stream,
-                            host,
-                            self.certs.clone(),
-                        )
-                        .await?;
-                        builder
-                            .async_with_stream(
-                                uri,
-                                crate::stream::AsyncStream::NativeTls(stream),
-                                check_fn,
-                            )
-                            .await
-                    }
-                    #[cfg(not(feature = "async_tls_native"))]
-                    {
-                        panic!("")
+        let handshake = builder.async_with_stream(uri, stream, check_fn);
+        with_timeout(self.handshake_timeout, TimeoutPhase::Handshake, handshake).await
+    }
+
+    /// async version of creating tcp connection, performing proxy handshake &
+    /// starting tls session
+    #[cfg(feature = "async")]
+    async fn async_tcp_transport(
+        &mut self,
+        uri: &Uri,
+        mode: Mode,
+    ) -> Result<crate::stream::AsyncStream, WsError> {
+        use crate::codec::with_timeout;
+
+        let proxy = self.get_proxy(uri)?;
+        let (host, port) = match &proxy {
+            Some(proxy) => (proxy.host.as_str(), proxy.port),
+            None => (
+                get_host(uri)?,
+                uri.port_u16().unwrap_or_else(|| mode.default_port()),
+            ),
+        };
+        let connect = crate::connector::async_tcp_connect_with(host, port, &self.connect_options);
+        let stream = with_timeout(self.connect_timeout, TimeoutPhase::Connect, connect).await?;
+        let stream = stream.into_std()?;
+        (self.set_socket_fn)(&stream)?;
+        let stream = tokio::net::TcpStream::from_std(stream)?;
+        let host = get_host(uri)?;
+        let (tls_timeout, handshake_timeout) = (self.tls_timeout, self.handshake_timeout);
+        let stream = match proxy {
+            Some(proxy) if proxy.scheme == ProxyScheme::Https => {
+                if mode == Mode::WSS {
+                    return Err(WsError::UnsupportedProxy(
+                        "wss connection over https proxy".to_string(),
+                    ));
+                }
+                let options = TlsOptions::default();
+                let tls = self.async_wrap_tls(stream, &proxy.host, &options);
+                let mut stream = with_timeout(tls_timeout, TimeoutPhase::Tls, tls).await?;
+                let handshake = crate::connector::async_proxy_handshake(&mut stream, &proxy, uri);
+                with_timeout(handshake_timeout, TimeoutPhase::Handshake, handshake).await?;
+                stream
+            }
+            Some(proxy) => {
+                let mut stream = stream;
+                let handshake = crate::connector::async_proxy_handshake(&mut stream, &proxy, uri);
+                with_timeout(handshake_timeout, TimeoutPhase::Handshake, handshake).await?;
+                match mode {
+                    Mode::WS | Mode::WSUnix => crate::stream::AsyncStream::Raw(stream),
+                    Mode::WSS => {
+                        let tls = self.async_wrap_tls(stream, host, &self.tls);
+                        with_timeout(tls_timeout, TimeoutPhase::Tls, tls).await?
                     }
-                } else {
-                    panic!("for ssl connection, async_tls_native or async_tls_rustls feature is required")
                 }
             }
-        }
+            None => match mode {
+                Mode::WS | Mode::WSUnix => crate::stream::AsyncStream::Raw(stream),
+                Mode::WSS => {
+                    let tls = self.async_wrap_tls(stream, host, &self.tls);
+                    with_timeout(tls_timeout, TimeoutPhase::Tls, tls).await?
+                }
+            },
+        };
+        Ok(stream)
     }
 
-    /// perform websocket handshake
     #[cfg(feature = "async")]
+    async fn async_wrap_tls(
+        &self,
+        stream: tokio::net::TcpStream,
+        host: &str,
+        options: &TlsOptions,
+    ) -> Result<crate::stream::AsyncStream, WsError> {
+        if cfg!(feature = "async_tls_rustls") {
+            #[cfg(feature = "async_tls_rustls")]
+            {
+                let stream =
+                    crate::connector::async_wrap_rustls_with(stream, host, &self.certs, options)
+                        .await?;
+                Ok(crate::stream::AsyncStream::Rustls(
+                    tokio_rustls::TlsStream::Client(stream),
+                ))
+            }
+            #[cfg(not(feature = "async_tls_rustls"))]
+            {
+                panic!("")
+            }
+        } else if cfg!(feature = "async_tls_native") {
+            #[cfg(feature = "async_tls_native")]
+            {
+                let stream = crate::connector::async_wrap_native_tls_with(
+                    stream,
+                    host,
+                    &self.certs,
+                    options,
+                )
+                .await?;
+                Ok(crate::stream::AsyncStream::NativeTls(stream))
+            }
+            #[cfg(not(feature = "async_tls_native"))]
+            {
+                panic!("")
+            }
+        } else {
+            panic!("for ssl connection, async_tls_native or async_tls_rustls feature is required")
+        }
+    }
+
+    /// perform websocket handshake, requires one of deflate backend
+    #[cfg(all(
+        feature = "async",
+        any(
+            feature = "deflate",
+            feature = "deflate_ng",
+            feature = "deflate_static",
+            feature = "deflate_rust"
+        )
+    ))]
     pub async fn async_connect(
         &mut self,
         uri: impl TryInto<Uri, Error = http::uri::InvalidUri>,
@@ -214,8 +439,31 @@ impl ClientConfig {
         crate::codec::AsyncDeflateCodec<tokio::io::BufStream<crate::stream::AsyncStream>>,
         WsError,
     > {
-        self.async_connect_with(uri, crate::codec::AsyncDeflateCodec::check_fn)
-            .await
+        let keepalive = self.keepalive.clone();
+        let (read_timeout, write_timeout) = (self.read_timeout, self.write_timeout);
+        let mut check_fn = crate::codec::AsyncDeflateCodec::check_fn_with(self.deflate.clone());
+        self.async_connect_with(uri, move |key, resp, stream| {
+            let mut codec = check_fn(key, resp, stream)?;
+            codec.set_timeout(read_timeout, write_timeout);
+            if keepalive.is_enabled() {
+                codec.set_keepalive(keepalive.clone());
+            }
+            Ok(codec)
+        })
+        .await
+    }
+
+    /// proxy used to connect uri, explicit `proxy` takes precedence over
+    /// environment variables
+    pub fn get_proxy(&self, uri: &Uri) -> Result<Option<ProxyConfig>, WsError> {
+        if let Some(proxy) = &self.proxy {
+            return Ok(Some(proxy.clone()));
+        }
+        if self.proxy_from_env {
+            ProxyConfig::from_env(uri)
+        } else {
+            Ok(None)
+        }
     }
 
     fn prepare(
@@ -227,14 +475,14 @@ impl ClientConfig {
             .map_err(|e| WsError::InvalidUri(e.to_string()))?;
         let mode = get_scheme(&uri)?;
         let mut builder = ClientBuilder::new();
-        let pmd_conf = self.window.map(|w| PMDConfig {
-            server_no_context_takeover: self.context_take_over,
-            client_no_context_takeover: self.context_take_over,
-            server_max_window_bits: w,
-            client_max_window_bits: w,
-        });
-        if let Some(conf) = pmd_conf {
-            builder = builder.extension(conf.ext_string())
+        #[cfg(any(
+            feature = "deflate",
+            feature = "deflate_ng",
+            feature = "deflate_static",
+            feature = "deflate_rust"
+        ))]
+        if let Some(conf) = &self.deflate {
+            builder = builder.extension(conf.offer().to_string())
         }
         for (k, v) in &self.extra_headers {
             builder = builder.header(k, v);
@@ -242,3 +490,247 @@ impl ClientConfig {
         Ok((uri, mode, builder))
     }
 }
+
+/// server connection config, timeouts behave the same as [ClientConfig]
+#[derive(Debug, Clone, Default)]
+pub struct ServerConfig {
+    /// timeout of tls handshake
+    pub tls_timeout: Option<Duration>,
+    /// timeout of websocket handshake
+    pub handshake_timeout: Option<Duration>,
+    /// fail with [WsError::Timeout] if no data received within this duration after
+    /// handshake, for async connection, set [crate::codec::FrameConfig] of codec instead
+    pub read_timeout: Option<Duration>,
+    /// fail with [WsError::Timeout] if writing does not complete within this duration
+    /// after handshake, for async connection, set [crate::codec::FrameConfig] of codec instead
+    pub write_timeout: Option<Duration>,
+}
+
+impl ServerConfig {
+    /// start tls session by `tls_fn`, pass `Ok` for plain connection, then wait for
+    /// protocol handshake from client, see [crate::ServerBuilder::accept]
+    #[cfg(feature = "sync")]
+    pub fn accept<S, F0, F1, F2, T, C>(
+        &self,
+        stream: std::net::TcpStream,
+        tls_fn: F0,
+        handshake_handler: F1,
+        codec_factory: F2,
+    ) -> Result<C, WsError>
+    where
+        S: std::io::Read + std::io::Write,
+        F0: FnOnce(std::net::TcpStream) -> Result<S, WsError>,
+        F1: FnMut(
+            http::Request<()>,
+        )
+            -> Result<(http::Request<()>, http::Response<T>), (http::Response<T>, WsError)>,
+        F2: FnMut(http::Request<()>, S) -> Result<C, WsError>,
+        T: ToString + std::fmt::Debug,
+    {
+        use crate::codec::io_timeout;
+
+        let timeout = PhaseTimeout::new(stream.try_clone()?)?;
+        timeout.set(self.tls_timeout, self.tls_timeout)?;
+        let stream = tls_fn(stream).map_err(|e| io_timeout(e, TimeoutPhase::Tls))?;
+        self.handshake(stream, timeout, handshake_handler, codec_factory)
+    }
+
+    /// wait for protocol handshake from client connected by unix socket
+    #[cfg(all(feature = "sync", unix))]
+    pub fn accept_unix<F1, F2, T, C>(
+        &self,
+        stream: std::os::unix::net::UnixStream,
+        handshake_handler: F1,
+        codec_factory: F2,
+    ) -> Result<C, WsError>
+    where
+        F1: FnMut(
+            http::Request<()>,
+        )
+            -> Result<(http::Request<()>, http::Response<T>), (http::Response<T>, WsError)>,
+        F2: FnMut(http::Request<()>, std::os::unix::net::UnixStream) -> Result<C, WsError>,
+        T: ToString + std::fmt::Debug,
+    {
+        let timeout = PhaseTimeout::new(stream.try_clone()?)?;
+        self.handshake(stream, timeout, handshake_handler, codec_factory)
+    }
+
+    #[cfg(feature = "sync")]
+    fn handshake<S, F1, F2, T, C>(
+        &self,
+        stream: S,
+        timeout: PhaseTimeout,
+        handshake_handler: F1,
+        mut codec_factory: F2,
+    ) -> Result<C, WsError>
+    where
+        S: std::io::Read + std::io::Write,
+        F1: FnMut(
+            http::Request<()>,
+        )
+            -> Result<(http::Request<()>, http::Response<T>), (http::Response<T>, WsError)>,
+        F2: FnMut(http::Request<()>, S) -> Result<C, WsError>,
+        T: ToString + std::fmt::Debug,
+    {
+        use crate::codec::io_timeout;
+
+        timeout.set(self.handshake_timeout, self.handshake_timeout)?;
+        let codec_factory = |req, stream| {
+            timeout.set(self.read_timeout, self.write_timeout)?;
+            codec_factory(req, stream)
+        };
+        crate::ServerBuilder::accept(stream, handshake_handler, codec_factory)
+            .map_err(|e| io_timeout(e, TimeoutPhase::Handshake))
+    }
+
+    /// async version of [ServerConfig::accept]
+    #[cfg(feature = "async")]
+    pub async fn async_accept<S, F0, Fut, F1, F2, T, C>(
+        &self,
+        stream: tokio::net::TcpStream,
+        tls_fn: F0,
+        handshake_handler: F1,
+        codec_factory: F2,
+    ) -> Result<C, WsError>
+    where
+        S: tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin,
+        F0: FnOnce(tokio::net::TcpStream) -> Fut,
+        Fut: std::future::Future<Output = Result<S, WsError>>,
+        F1: FnMut(
+            http::Request<()>,
+        )
+            -> Result<(http::Request<()>, http::Response<T>), (http::Response<T>, WsError)>,
+        F2: FnMut(http::Request<()>, S) -> Result<C, WsError>,
+        T: ToString + std::fmt::Debug,
+    {
+        use crate::codec::with_timeout;
+
+        let stream = with_timeout(self.tls_timeout, TimeoutPhase::Tls, tls_fn(stream)).await?;
+        let handshake =
+            crate::ServerBuilder::async_accept(stream, handshake_handler, codec_factory);
+        with_timeout(self.handshake_timeout, TimeoutPhase::Handshake, handshake).await
+    }
+
+    /// async version of [ServerConfig::accept_unix]
+    #[cfg(all(feature = "async", unix))]
+    pub async fn async_accept_unix<F1, F2, T, C>(
+        &self,
+        stream: tokio::net::UnixStream,
+        handshake_handler: F1,
+        codec_factory: F2,
+    ) -> Result<C, WsError>
+    where
+        F1: FnMut(
+            http::Request<()>,
+        )
+            -> Result<(http::Request<()>, http::Response<T>), (http::Response<T>, WsError)>,
+        F2: FnMut(http::Request<()>, tokio::net::UnixStream) -> Result<C, WsError>,
+        T: ToString + std::fmt::Debug,
+    {
+        use crate::codec::with_timeout;
+
+        let handshake =
+            crate::ServerBuilder::async_accept(stream, handshake_handler, codec_factory);
+        with_timeout(self.handshake_timeout, TimeoutPhase::Handshake, handshake).await
+    }
+}
+
+/// cloned socket handle to update read & write timeout of each phase
+#[cfg(feature = "sync")]
+struct PhaseTimeout {
+    socket: socket2::Socket,
+    origin: (Option<Duration>, Option<Duration>),
+}
+
+#[cfg(feature = "sync")]
+impl PhaseTimeout {
+    fn new(socket: impl Into<socket2::Socket>) -> std::io::Result<Self> {
+        let socket = socket.into();
+        let origin = (socket.read_timeout()?, socket.write_timeout()?);
+        Ok(Self { socket, origin })
+    }
+
+    /// set timeout of socket, fallback to original timeout if `None`
+    fn set(&self, read: Option<Duration>, write: Option<Duration>) -> std::io::Result<()> {
+        self.socket.set_read_timeout(read.or(self.origin.0))?;
+        self.socket.set_write_timeout(write.or(self.origin.1))
+    }
+}
+
+#[cfg(all(
+    feature = "sync_tls_rustls",
+    any(
+        feature = "deflate",
+        feature = "deflate_ng",
+        feature = "deflate_static",
+        feature = "deflate_rust"
+    )
+))]
+#[test]
+fn test_phase_timeout() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    // accept connections but never reply
+    let server = std::thread::spawn(move || {
+        (0..2)
+            .map(|_| listener.accept().unwrap().0)
+            .collect::<Vec<_>>()
+    });
+    let mut config = ClientConfig {
+        proxy_from_env: false,
+        tls_timeout: Some(Duration::from_millis(100)),
+        handshake_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let ret = config.connect(format!("ws://{addr}"));
+    assert!(matches!(
+        ret,
+        Err(WsError::Timeout {
+            phase: TimeoutPhase::Handshake
+        })
+    ));
+    let ret = config.connect(format!("wss://{addr}"));
+    assert!(matches!(
+        ret,
+        Err(WsError::Timeout {
+            phase: TimeoutPhase::Tls
+        })
+    ));
+    server.join().unwrap();
+}
+
+#[cfg(all(feature = "sync", unix))]
+#[test]
+fn test_unix_socket() {
+    use crate::codec::StringCodec;
+
+    let dir = std::env::temp_dir().join(format!("ws-tool-unix-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("ws.sock");
+    let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut server = ServerConfig::default()
+            .accept_unix(
+                stream,
+                crate::codec::default_handshake_handler,
+                StringCodec::factory,
+            )
+            .unwrap();
+        let msg = server.receive().unwrap();
+        let data = msg.data.to_string();
+        server.send(&data).unwrap();
+    });
+    let path = path.to_str().unwrap().replace('/', "%2F");
+    let mut client = ClientConfig::default()
+        .connect_with(
+            format!("ws+unix://{path}@localhost/"),
+            StringCodec::check_fn,
+        )
+        .unwrap();
+    client.send("hello").unwrap();
+    let msg = client.receive().unwrap();
+    assert_eq!(msg.data, "hello");
+    server.join().unwrap();
+    std::fs::remove_dir_all(dir).ok();
+}
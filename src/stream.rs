@@ -1,11 +1,22 @@
 #[cfg(feature = "sync")]
 mod blocking {
+    #[cfg(unix)]
+    use std::os::unix::net::UnixStream;
     use std::{
         io::{BufReader, BufWriter, Read, Write},
         net::TcpStream,
+        time::Duration,
     };
 
-    use crate::codec::Split;
+    use crate::codec::{Shutdown, Split};
+
+    /// server side rustls stream
+    #[cfg(feature = "sync_tls_rustls")]
+    pub type RustlsServerStream = rustls_connector::rustls::StreamOwned<
+        rustls_connector::rustls::ServerConnection,
+        TcpStream,
+    >;
+
     #[allow(missing_docs)]
     pub trait RW: Read + Write {}
 
@@ -13,6 +24,10 @@ mod blocking {
 
     #[cfg(any(feature = "sync_tls_rustls", feature = "sync_tls_native"))]
     mod split {
+        #[cfg(feature = "sync_tls_rustls")]
+        use rustls_connector::rustls::{ConnectionCommon, SideData};
+        #[cfg(feature = "sync_tls_rustls")]
+        use std::ops::{Deref, DerefMut};
         use std::{
             io::{ErrorKind, Read, Write},
             sync::{Arc, Mutex},
@@ -20,12 +35,14 @@ mod blocking {
 
         use crate::codec::Split;
 
-        /// reader part of a stream
+        #[cfg(feature = "sync_tls_native")]
+        /// reader part of a stream, inner stream is locked during read
         pub struct ReadHalf<T> {
             /// inner stream
             pub inner: Arc<Mutex<T>>,
         }
 
+        #[cfg(feature = "sync_tls_native")]
         /// writer part of a stream
         pub struct WriteHalf<T> {
             /// inner stream
@@ -46,6 +63,7 @@ mod blocking {
             };
         }
 
+        #[cfg(feature = "sync_tls_native")]
         impl<T: Read> Read for ReadHalf<T> {
             fn read_vectored(
                 &mut self,
@@ -59,6 +77,7 @@ mod blocking {
             }
         }
 
+        #[cfg(feature = "sync_tls_native")]
         impl<T: Write> Write for WriteHalf<T> {
             fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
                 try_lock!(self.inner).write(buf)
@@ -69,16 +88,188 @@ mod blocking {
             }
         }
 
+        /// split by [split_rustls], a thread blocked in read does not block writer
         #[cfg(feature = "sync_tls_rustls")]
-        impl<S: Read + Write> Split for rustls_connector::TlsStream<S> {
-            type R = ReadHalf<rustls_connector::TlsStream<S>>;
+        impl<S> Split for rustls_connector::TlsStream<S>
+        where
+            S: Read + Write,
+            for<'a> &'a S: Read + Write,
+        {
+            type R = RustlsReadHalf<rustls_connector::rustls::ClientConnection, S>;
 
-            type W = WriteHalf<rustls_connector::TlsStream<S>>;
+            type W = RustlsWriteHalf<rustls_connector::rustls::ClientConnection, S>;
 
             fn split(self) -> (Self::R, Self::W) {
-                let inner = Arc::new(Mutex::new(self));
-                let inner_c = inner.clone();
-                (ReadHalf { inner }, WriteHalf { inner: inner_c })
+                split_rustls(self)
+            }
+        }
+
+        /// split by [split_rustls], a thread blocked in read does not block writer
+        #[cfg(feature = "sync_tls_rustls")]
+        impl<S> Split
+            for rustls_connector::rustls::StreamOwned<rustls_connector::rustls::ServerConnection, S>
+        where
+            S: Read + Write,
+            for<'a> &'a S: Read + Write,
+        {
+            type R = RustlsReadHalf<rustls_connector::rustls::ServerConnection, S>;
+
+            type W = RustlsWriteHalf<rustls_connector::rustls::ServerConnection, S>;
+
+            fn split(self) -> (Self::R, Self::W) {
+                split_rustls(self)
+            }
+        }
+
+        /// split rustls stream without locking whole stream during io, a thread
+        /// blocked in read does not block writer
+        #[cfg(feature = "sync_tls_rustls")]
+        pub fn split_rustls<C, D, S>(
+            stream: rustls_connector::rustls::StreamOwned<C, S>,
+        ) -> (RustlsReadHalf<C, S>, RustlsWriteHalf<C, S>)
+        where
+            C: DerefMut + Deref<Target = ConnectionCommon<D>>,
+            D: SideData,
+            S: Read + Write,
+            for<'a> &'a S: Read + Write,
+        {
+            let shared = Arc::new(RustlsShared {
+                conn: Mutex::new(stream.conn),
+                sock: stream.sock,
+                out: Mutex::new(vec![]),
+            });
+            let read = RustlsReadHalf {
+                shared: shared.clone(),
+                buf: vec![0; 16 * 1024],
+                pos: 0,
+                len: 0,
+            };
+            (read, RustlsWriteHalf { shared })
+        }
+
+        /// tls state shared by read and write half, lock of tls state is never held
+        /// during socket io, so a thread blocked in read does not block writer
+        #[cfg(feature = "sync_tls_rustls")]
+        struct RustlsShared<C, S> {
+            conn: Mutex<C>,
+            sock: S,
+            /// encrypted records to write, lock is held during socket write to keep
+            /// order of records
+            out: Mutex<Vec<u8>>,
+        }
+
+        #[cfg(feature = "sync_tls_rustls")]
+        impl<C, D, S> RustlsShared<C, S>
+        where
+            C: DerefMut + Deref<Target = ConnectionCommon<D>>,
+            D: SideData,
+            for<'a> &'a S: Read + Write,
+        {
+            /// encrypt plaintext and write all pending records to socket, return
+            /// number of plaintext bytes accepted
+            fn write(&self, buf: &[u8]) -> std::io::Result<usize> {
+                let mut out = try_lock!(self.out);
+                let num = {
+                    let mut conn = try_lock!(self.conn);
+                    let num = conn.writer().write(buf)?;
+                    while conn.wants_write() {
+                        conn.write_tls(&mut *out)?;
+                    }
+                    num
+                };
+                let ret = (&self.sock).write_all(&out);
+                out.clear();
+                ret.map(|_| num)
+            }
+        }
+
+        /// reader part of a rustls stream, it can read while
+        /// [RustlsWriteHalf] is writing in another thread
+        #[cfg(feature = "sync_tls_rustls")]
+        pub struct RustlsReadHalf<C, S> {
+            shared: Arc<RustlsShared<C, S>>,
+            /// encrypted data read from socket
+            buf: Vec<u8>,
+            pos: usize,
+            len: usize,
+        }
+
+        /// writer part of a rustls stream
+        #[cfg(feature = "sync_tls_rustls")]
+        pub struct RustlsWriteHalf<C, S> {
+            shared: Arc<RustlsShared<C, S>>,
+        }
+
+        #[cfg(feature = "sync_tls_rustls")]
+        impl<C, S> RustlsReadHalf<C, S> {
+            /// get ref of underlying socket
+            pub fn get_ref(&self) -> &S {
+                &self.shared.sock
+            }
+        }
+
+        #[cfg(feature = "sync_tls_rustls")]
+        impl<C, S> RustlsWriteHalf<C, S> {
+            /// get ref of underlying socket
+            pub fn get_ref(&self) -> &S {
+                &self.shared.sock
+            }
+        }
+
+        #[cfg(feature = "sync_tls_rustls")]
+        impl<C, D, S> Read for RustlsReadHalf<C, S>
+        where
+            C: DerefMut + Deref<Target = ConnectionCommon<D>>,
+            D: SideData,
+            for<'a> &'a S: Read + Write,
+        {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+                loop {
+                    let mut conn = try_lock!(self.shared.conn);
+                    match conn.reader().read(buf) {
+                        Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+                        ret => return ret,
+                    }
+                    if self.pos == self.len {
+                        // wait for more records without holding lock
+                        drop(conn);
+                        self.len = (&self.shared.sock).read(&mut self.buf)?;
+                        self.pos = 0;
+                        conn = try_lock!(self.shared.conn);
+                    }
+                    let mut records = &self.buf[self.pos..self.len];
+                    self.pos += conn.read_tls(&mut records)?;
+                    let state = conn.process_new_packets();
+                    let wants_write = conn.wants_write();
+                    drop(conn);
+                    // handshake message or alert need to be sent
+                    if wants_write {
+                        self.shared.write(&[])?;
+                    }
+                    if let Err(e) = state {
+                        return Err(std::io::Error::new(ErrorKind::InvalidData, e));
+                    }
+                }
+            }
+        }
+
+        #[cfg(feature = "sync_tls_rustls")]
+        impl<C, D, S> Write for RustlsWriteHalf<C, S>
+        where
+            C: DerefMut + Deref<Target = ConnectionCommon<D>>,
+            D: SideData,
+            for<'a> &'a S: Read + Write,
+        {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.shared.write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.shared.write(&[])?;
+                (&self.shared.sock).flush()
             }
         }
 
@@ -97,14 +288,20 @@ mod blocking {
     }
 
     macro_rules! def {
-        ($name:ident, $raw:ty, $rustls:ty, $native:ty, $doc:literal) => {
+        ($name:ident, $raw:ty, $unix:ty, $rustls:ty, $rustls_server:ty, $native:ty, $doc:literal) => {
             #[doc=$doc]
             pub enum $name {
                 /// raw tcp stream
                 Raw($raw),
+                /// unix domain socket stream
+                #[cfg(unix)]
+                Unix($unix),
                 /// rustls wrapped stream
                 #[cfg(feature = "sync_tls_rustls")]
                 Rustls($rustls),
+                /// rustls wrapped stream of server side
+                #[cfg(feature = "sync_tls_rustls")]
+                RustlsServer($rustls_server),
                 /// native tls wrapped stream
                 #[cfg(feature = "sync_tls_native")]
                 NativeTls($native),
@@ -114,8 +311,12 @@ mod blocking {
                 fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                     match self {
                         Self::Raw(_) => f.debug_tuple("Raw").finish(),
+                        #[cfg(unix)]
+                        Self::Unix(_) => f.debug_tuple("Unix").finish(),
                         #[cfg(feature = "sync_tls_rustls")]
                         Self::Rustls(_) => f.debug_tuple("Rustls").finish(),
+                        #[cfg(feature = "sync_tls_rustls")]
+                        Self::RustlsServer(_) => f.debug_tuple("RustlsServer").finish(),
                         #[cfg(feature = "sync_tls_native")]
                         Self::NativeTls(_) => f.debug_tuple("NativeTls").finish(),
                     }
@@ -127,7 +328,9 @@ mod blocking {
     def!(
         SyncStreamRead,
         TcpStream,
-        split::ReadHalf<rustls_connector::TlsStream<TcpStream>>,
+        UnixStream,
+        split::RustlsReadHalf<rustls_connector::rustls::ClientConnection, TcpStream>,
+        split::RustlsReadHalf<rustls_connector::rustls::ServerConnection, TcpStream>,
         split::ReadHalf<native_tls::TlsStream<TcpStream>>,
         "a wrapper of most common use raw/ssl tcp based stream"
     );
@@ -135,7 +338,9 @@ mod blocking {
     def!(
         SyncStreamWrite,
         TcpStream,
-        split::WriteHalf<rustls_connector::TlsStream<TcpStream>>,
+        UnixStream,
+        split::RustlsWriteHalf<rustls_connector::rustls::ClientConnection, TcpStream>,
+        split::RustlsWriteHalf<rustls_connector::rustls::ServerConnection, TcpStream>,
         split::WriteHalf<native_tls::TlsStream<TcpStream>>,
         "a wrapper of most common use raw/ssl tcp based stream"
     );
@@ -143,7 +348,9 @@ mod blocking {
     def!(
         SyncStream,
         TcpStream,
+        UnixStream,
         rustls_connector::TlsStream<TcpStream>,
+        RustlsServerStream,
         native_tls::TlsStream<TcpStream>,
         "a wrapper of most common use raw/ssl tcp based stream"
     );
@@ -154,8 +361,12 @@ mod blocking {
                 fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
                     match self {
                         Self::Raw(s) => s.read(buf),
+                        #[cfg(unix)]
+                        Self::Unix(s) => s.read(buf),
                         #[cfg(feature = "sync_tls_rustls")]
                         Self::Rustls(s) => s.read(buf),
+                        #[cfg(feature = "sync_tls_rustls")]
+                        Self::RustlsServer(s) => s.read(buf),
                         #[cfg(feature = "sync_tls_native")]
                         Self::NativeTls(s) => s.read(buf),
                     }
@@ -167,8 +378,12 @@ mod blocking {
                 ) -> std::io::Result<usize> {
                     match self {
                         Self::Raw(s) => s.read_vectored(bufs),
+                        #[cfg(unix)]
+                        Self::Unix(s) => s.read_vectored(bufs),
                         #[cfg(feature = "sync_tls_rustls")]
                         Self::Rustls(s) => s.read_vectored(bufs),
+                        #[cfg(feature = "sync_tls_rustls")]
+                        Self::RustlsServer(s) => s.read_vectored(bufs),
                         #[cfg(feature = "sync_tls_native")]
                         Self::NativeTls(s) => s.read_vectored(bufs),
                     }
@@ -189,8 +404,12 @@ mod blocking {
                 ) -> std::io::Result<usize> {
                     match self {
                         Self::Raw(s) => s.write_vectored(bufs),
+                        #[cfg(unix)]
+                        Self::Unix(s) => s.write_vectored(bufs),
                         #[cfg(feature = "sync_tls_rustls")]
                         Self::Rustls(s) => s.write_vectored(bufs),
+                        #[cfg(feature = "sync_tls_rustls")]
+                        Self::RustlsServer(s) => s.write_vectored(bufs),
                         #[cfg(feature = "sync_tls_native")]
                         Self::NativeTls(s) => s.write_vectored(bufs),
                     }
@@ -199,8 +418,12 @@ mod blocking {
                 fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
                     match self {
                         Self::Raw(s) => s.write(buf),
+                        #[cfg(unix)]
+                        Self::Unix(s) => s.write(buf),
                         #[cfg(feature = "sync_tls_rustls")]
                         Self::Rustls(s) => s.write(buf),
+                        #[cfg(feature = "sync_tls_rustls")]
+                        Self::RustlsServer(s) => s.write(buf),
                         #[cfg(feature = "sync_tls_native")]
                         Self::NativeTls(s) => s.write(buf),
                     }
@@ -209,8 +432,12 @@ mod blocking {
                 fn flush(&mut self) -> std::io::Result<()> {
                     match self {
                         Self::Raw(s) => s.flush(),
+                        #[cfg(unix)]
+                        Self::Unix(s) => s.flush(),
                         #[cfg(feature = "sync_tls_rustls")]
                         Self::Rustls(s) => s.flush(),
+                        #[cfg(feature = "sync_tls_rustls")]
+                        Self::RustlsServer(s) => s.flush(),
                         #[cfg(feature = "sync_tls_native")]
                         Self::NativeTls(s) => s.flush(),
                     }
@@ -222,6 +449,66 @@ mod blocking {
     impl_write!(SyncStream);
     impl_write!(SyncStreamWrite);
 
+    impl SyncStream {
+        /// get ref of underlying tcp stream, can be used to set socket options,
+        /// return None for unix stream
+        pub fn tcp_stream(&self) -> Option<&TcpStream> {
+            match self {
+                Self::Raw(s) => Some(s),
+                #[cfg(unix)]
+                Self::Unix(_) => None,
+                #[cfg(feature = "sync_tls_rustls")]
+                Self::Rustls(s) => Some(s.get_ref()),
+                #[cfg(feature = "sync_tls_rustls")]
+                Self::RustlsServer(s) => Some(s.get_ref()),
+                #[cfg(feature = "sync_tls_native")]
+                Self::NativeTls(s) => Some(s.get_ref()),
+            }
+        }
+
+        /// get ref of underlying unix stream, return None for tcp stream
+        #[cfg(unix)]
+        pub fn unix_stream(&self) -> Option<&UnixStream> {
+            match self {
+                Self::Unix(s) => Some(s),
+                _ => None,
+            }
+        }
+
+        /// set read timeout of underlying socket
+        pub fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+            match self.tcp_stream() {
+                Some(s) => s.set_read_timeout(timeout),
+                #[cfg(unix)]
+                None => self
+                    .unix_stream()
+                    .map(|s| s.set_read_timeout(timeout))
+                    .unwrap_or(Ok(())),
+                #[cfg(not(unix))]
+                None => Ok(()),
+            }
+        }
+    }
+
+    impl Shutdown for SyncStream {
+        fn shutdown(&mut self) -> std::io::Result<()> {
+            match self.tcp_stream() {
+                Some(s) => s.shutdown(std::net::Shutdown::Both),
+                #[cfg(unix)]
+                None => self
+                    .unix_stream()
+                    .map(|s| s.shutdown(std::net::Shutdown::Both))
+                    .unwrap_or(Ok(())),
+                #[cfg(not(unix))]
+                None => Ok(()),
+            }
+        }
+
+        fn set_read_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
+            SyncStream::set_read_timeout(self, timeout)
+        }
+    }
+
     impl Split for SyncStream {
         type R = SyncStreamRead;
 
@@ -233,12 +520,22 @@ mod blocking {
                     let (read, write) = s.split();
                     (SyncStreamRead::Raw(read), SyncStreamWrite::Raw(write))
                 }
+                #[cfg(unix)]
+                Self::Unix(s) => {
+                    let (read, write) = s.split();
+                    (SyncStreamRead::Unix(read), SyncStreamWrite::Unix(write))
+                }
                 #[cfg(feature = "sync_tls_rustls")]
                 Self::Rustls(s) => {
-                    let s = std::sync::Arc::new(std::sync::Mutex::new(s));
+                    let (read, write) = split::split_rustls(s);
+                    (SyncStreamRead::Rustls(read), SyncStreamWrite::Rustls(write))
+                }
+                #[cfg(feature = "sync_tls_rustls")]
+                Self::RustlsServer(s) => {
+                    let (read, write) = split::split_rustls(s);
                     (
-                        SyncStreamRead::Rustls(split::ReadHalf { inner: s.clone() }),
-                        SyncStreamWrite::Rustls(split::WriteHalf { inner: s }),
+                        SyncStreamRead::RustlsServer(read),
+                        SyncStreamWrite::RustlsServer(write),
                     )
                 }
                 #[cfg(feature = "sync_tls_native")]
@@ -275,6 +572,17 @@ mod blocking {
         }
     }
 
+    impl<S: Read + Write + Shutdown> Shutdown for BufStream<S> {
+        fn shutdown(&mut self) -> std::io::Result<()> {
+            self.flush()?;
+            self.get_mut().shutdown()
+        }
+
+        fn set_read_timeout(&mut self, timeout: Option<Duration>) -> std::io::Result<()> {
+            self.get_mut().set_read_timeout(timeout)
+        }
+    }
+
     impl<S: Read + Write> std::fmt::Debug for BufStream<S> {
         fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             f.debug_struct("BufStream").finish()
@@ -383,7 +691,10 @@ mod non_blocking {
     pub enum AsyncStream {
         /// raw tcp stream
         Raw(TcpStream),
-        /// rustls wrapped stream
+        /// unix domain socket stream
+        #[cfg(unix)]
+        Unix(tokio::net::UnixStream),
+        /// rustls wrapped stream, client or server side
         #[cfg(feature = "async_tls_rustls")]
         Rustls(tokio_rustls::TlsStream<TcpStream>),
         /// native tls wrapped stream
@@ -409,6 +720,8 @@ mod non_blocking {
         ) -> std::task::Poll<std::io::Result<()>> {
             match self.get_mut() {
                 AsyncStream::Raw(s) => std::pin::Pin::new(s).poll_read(cx, buf),
+                #[cfg(unix)]
+                AsyncStream::Unix(s) => std::pin::Pin::new(s).poll_read(cx, buf),
                 #[cfg(feature = "async_tls_rustls")]
                 AsyncStream::Rustls(s) => std::pin::Pin::new(s).poll_read(cx, buf),
                 #[cfg(feature = "async_tls_native")]
@@ -425,6 +738,8 @@ mod non_blocking {
         ) -> std::task::Poll<Result<usize, std::io::Error>> {
             match self.get_mut() {
                 AsyncStream::Raw(s) => std::pin::Pin::new(s).poll_write(cx, buf),
+                #[cfg(unix)]
+                AsyncStream::Unix(s) => std::pin::Pin::new(s).poll_write(cx, buf),
                 #[cfg(feature = "async_tls_rustls")]
                 AsyncStream::Rustls(s) => std::pin::Pin::new(s).poll_write(cx, buf),
                 #[cfg(feature = "async_tls_native")]
@@ -438,6 +753,8 @@ mod non_blocking {
         ) -> std::task::Poll<Result<(), std::io::Error>> {
             match self.get_mut() {
                 AsyncStream::Raw(s) => std::pin::Pin::new(s).poll_flush(cx),
+                #[cfg(unix)]
+                AsyncStream::Unix(s) => std::pin::Pin::new(s).poll_flush(cx),
                 #[cfg(feature = "async_tls_rustls")]
                 AsyncStream::Rustls(s) => std::pin::Pin::new(s).poll_flush(cx),
                 #[cfg(feature = "async_tls_native")]
@@ -451,6 +768,8 @@ mod non_blocking {
         ) -> std::task::Poll<Result<(), std::io::Error>> {
             match self.get_mut() {
                 AsyncStream::Raw(s) => std::pin::Pin::new(s).poll_shutdown(cx),
+                #[cfg(unix)]
+                AsyncStream::Unix(s) => std::pin::Pin::new(s).poll_shutdown(cx),
                 #[cfg(feature = "async_tls_rustls")]
                 AsyncStream::Rustls(s) => std::pin::Pin::new(s).poll_shutdown(cx),
                 #[cfg(feature = "async_tls_native")]
@@ -458,7 +777,229 @@ mod non_blocking {
             }
         }
     }
+
+    #[cfg(feature = "h2")]
+    mod http2 {
+        use std::{
+            io,
+            pin::Pin,
+            task::{ready, Context, Poll},
+        };
+
+        use bytes::{Buf, Bytes};
+        use h2::{RecvStream, SendStream};
+        use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+        use crate::codec::Split;
+
+        fn h2_to_io(e: h2::Error) -> io::Error {
+            if e.is_io() {
+                e.into_io().unwrap()
+            } else {
+                io::Error::new(io::ErrorKind::BrokenPipe, e)
+            }
+        }
+
+        /// websocket stream over a http/2 stream opened by extended CONNECT, many
+        /// of them can be multiplexed over one http/2 connection
+        pub struct H2Stream {
+            read: H2ReadHalf,
+            write: H2WriteHalf,
+        }
+
+        impl H2Stream {
+            /// construct from both direction of a http/2 stream
+            pub fn new(send: SendStream<Bytes>, recv: RecvStream) -> Self {
+                Self {
+                    read: H2ReadHalf {
+                        recv,
+                        buf: Bytes::new(),
+                    },
+                    write: H2WriteHalf { send },
+                }
+            }
+        }
+
+        /// read half of [H2Stream]
+        pub struct H2ReadHalf {
+            recv: RecvStream,
+            buf: Bytes,
+        }
+
+        /// write half of [H2Stream]
+        pub struct H2WriteHalf {
+            send: SendStream<Bytes>,
+        }
+
+        impl Split for H2Stream {
+            type R = H2ReadHalf;
+
+            type W = H2WriteHalf;
+
+            fn split(self) -> (Self::R, Self::W) {
+                (self.read, self.write)
+            }
+        }
+
+        impl AsyncRead for H2ReadHalf {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                while self.buf.is_empty() {
+                    match ready!(self.recv.poll_data(cx)) {
+                        Some(Ok(data)) => {
+                            // give back flow control window as soon as data is buffered
+                            self.recv
+                                .flow_control()
+                                .release_capacity(data.len())
+                                .map_err(h2_to_io)?;
+                            self.buf = data;
+                        }
+                        Some(Err(e)) => return Poll::Ready(Err(h2_to_io(e))),
+                        None => return Poll::Ready(Ok(())),
+                    }
+                }
+                let count = self.buf.len().min(buf.remaining());
+                buf.put_slice(&self.buf[..count]);
+                self.buf.advance(count);
+                Poll::Ready(Ok(()))
+            }
+        }
+
+        impl AsyncWrite for H2WriteHalf {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                if buf.is_empty() {
+                    return Poll::Ready(Ok(0));
+                }
+                self.send.reserve_capacity(buf.len());
+                let count = match self.send.capacity() {
+                    0 => match ready!(self.send.poll_capacity(cx)) {
+                        Some(Ok(count)) => count,
+                        Some(Err(e)) => return Poll::Ready(Err(h2_to_io(e))),
+                        None => return Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+                    },
+                    count => count,
+                };
+                let count = count.min(buf.len());
+                self.send
+                    .send_data(Bytes::copy_from_slice(&buf[..count]), false)
+                    .map_err(h2_to_io)?;
+                Poll::Ready(Ok(count))
+            }
+
+            fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                // frames are written by http/2 connection task
+                Poll::Ready(Ok(()))
+            }
+
+            fn poll_shutdown(
+                mut self: Pin<&mut Self>,
+                _cx: &mut Context<'_>,
+            ) -> Poll<io::Result<()>> {
+                Poll::Ready(self.send.send_data(Bytes::new(), true).map_err(h2_to_io))
+            }
+        }
+
+        impl AsyncRead for H2Stream {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.read).poll_read(cx, buf)
+            }
+        }
+
+        impl AsyncWrite for H2Stream {
+            fn poll_write(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+                buf: &[u8],
+            ) -> Poll<io::Result<usize>> {
+                Pin::new(&mut self.write).poll_write(cx, buf)
+            }
+
+            fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.write).poll_flush(cx)
+            }
+
+            fn poll_shutdown(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<io::Result<()>> {
+                Pin::new(&mut self.write).poll_shutdown(cx)
+            }
+        }
+    }
+
+    #[cfg(feature = "h2")]
+    pub use http2::*;
 }
 
 #[cfg(feature = "async")]
 pub use non_blocking::*;
+
+#[cfg(feature = "sync_tls_rustls")]
+#[test]
+fn test_rustls_split() {
+    use crate::{
+        codec::Split,
+        connector::{accept_rustls, rustls_server_config, wrap_rustls},
+    };
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        time::Duration,
+    };
+
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let dir = std::env::temp_dir().join(format!("ws-tool-split-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (cert_path, key_path) = (dir.join("cert.pem"), dir.join("key.pem"));
+    std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+    std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+    let config = rustls_server_config(&cert_path, &key_path, None).unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let stream = SyncStream::RustlsServer(accept_rustls(stream, config).unwrap());
+        let (mut read, mut write) = stream.split();
+        let mut buf = [0; 5];
+        read.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        write.write_all(b"world").unwrap();
+        write.flush().unwrap();
+    });
+
+    let stream = TcpStream::connect(addr).unwrap();
+    // split tls stream directly, server side is split by SyncStream
+    let stream = wrap_rustls(stream, "localhost", vec![cert_path]).unwrap();
+    let (mut read, mut write) = stream.split();
+    let (tx, rx) = std::sync::mpsc::channel();
+    // reader blocks until server replies, which requires writer to send first
+    std::thread::spawn(move || {
+        let mut buf = [0; 5];
+        read.read_exact(&mut buf).unwrap();
+        tx.send(buf).unwrap();
+    });
+    std::thread::sleep(Duration::from_millis(100));
+    // writer must not wait for blocked reader, fail instead of hang if it does
+    let (done_tx, done_rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        write.write_all(b"hello").unwrap();
+        write.flush().unwrap();
+        done_tx.send(()).unwrap();
+    });
+    done_rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    let buf = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(&buf, b"world");
+    std::fs::remove_dir_all(dir).ok();
+}